@@ -1,7 +1,10 @@
-use crate::script::ScriptBuf;
+use crate::constants::MAX_SCRIPT_ELEMENT_SIZE;
+use crate::key::{PublicKey, TweakedPublicKey, XOnlyPublicKey};
+use crate::script::{Script, ScriptBuf};
+use crate::taproot::TapNodeHash;
 use crate::types::{self, SEGWIT_V0_PUBKEY_HASH_LEN, SEGWIT_V0_SCRIPT_HASH_LEN};
-use crate::types::{Network, Payload, ScriptType, WitnessProgram, WitnessVersion};
-use crate::utils::get_script_type_with_payload;
+use crate::types::{Network, Payload, WitnessProgram, WitnessVersion};
+use bitcoin_hashes::Hash;
 use std::str::FromStr;
 
 #[derive(Debug, Clone)]
@@ -80,6 +83,80 @@ impl FromStr for BitcoinAddress {
 }
 
 impl BitcoinAddress {
+    /// Creates a P2PKH address paying to `key`.
+    pub fn p2pkh(key: &PublicKey, network: Network) -> Self {
+        BitcoinAddress {
+            network,
+            payload: Payload::PubkeyHash(key.pubkey_hash().to_byte_array().to_vec()),
+        }
+    }
+
+    /// Creates a P2WPKH address paying to `key`, which must be compressed.
+    pub fn p2wpkh(key: &PublicKey, network: Network) -> Result<Self, &'static str> {
+        let hash = key
+            .wpubkey_hash()
+            .ok_or("segwit doesn't allow uncompressed keys")?;
+        Ok(BitcoinAddress {
+            network,
+            payload: Payload::WitnessProgram(WitnessProgram::new(
+                WitnessVersion::V0,
+                hash.to_byte_array().to_vec(),
+            )),
+        })
+    }
+
+    /// Creates a P2SH address paying to `redeem_script`, which fails if it's longer than
+    /// the [`MAX_SCRIPT_ELEMENT_SIZE`] bytes that can be pushed to spend it.
+    pub fn p2sh(redeem_script: &Script, network: Network) -> Result<Self, &'static str> {
+        if redeem_script.len() > MAX_SCRIPT_ELEMENT_SIZE {
+            return Err("redeem script is too large to be pushed");
+        }
+        Ok(BitcoinAddress {
+            network,
+            payload: Payload::ScriptHash(redeem_script.script_hash().to_byte_array().to_vec()),
+        })
+    }
+
+    /// Creates a P2WSH address paying to `witness_script`.
+    pub fn p2wsh(witness_script: &Script, network: Network) -> Self {
+        BitcoinAddress {
+            network,
+            payload: Payload::WitnessProgram(WitnessProgram::new(
+                WitnessVersion::V0,
+                witness_script.wscript_hash().to_byte_array().to_vec(),
+            )),
+        }
+    }
+
+    /// Creates a P2SH address paying to the P2WSH of `witness_script`, for wallets that
+    /// can't pay to segwit addresses.
+    pub fn p2shwsh(witness_script: &Script, network: Network) -> Self {
+        BitcoinAddress::p2sh(&witness_script.to_p2wsh(), network)
+            .expect("P2WSH scripts are 34 bytes")
+    }
+
+    /// Creates a P2TR address paying to `internal_key` tweaked to commit to the script
+    /// tree with `merkle_root`, or to no scripts at all.
+    pub fn p2tr(
+        internal_key: XOnlyPublicKey,
+        merkle_root: Option<TapNodeHash>,
+        network: Network,
+    ) -> Self {
+        let (output_key, _) = TweakedPublicKey::from_internal_key(internal_key, merkle_root);
+        BitcoinAddress::p2tr_tweaked(output_key, network)
+    }
+
+    /// Creates a P2TR address paying to `output_key`.
+    pub fn p2tr_tweaked(output_key: TweakedPublicKey, network: Network) -> Self {
+        BitcoinAddress {
+            network,
+            payload: Payload::WitnessProgram(WitnessProgram::new(
+                WitnessVersion::V1,
+                output_key.serialize().to_vec(),
+            )),
+        }
+    }
+
     pub fn to_script(&self) -> Result<ScriptBuf, &str> {
         match &self.payload {
             Payload::PubkeyHash(data) => Ok(ScriptBuf::new_p2pkh(data)),
@@ -90,35 +167,34 @@ impl BitcoinAddress {
                     SEGWIT_V0_SCRIPT_HASH_LEN => Ok(ScriptBuf::new_p2wsh(&program.data)),
                     _ => Err("invalid witness program data"),
                 },
-                WitnessVersion::V1 => Ok(ScriptBuf::new_p2tr(&program.data)),
+                version => Ok(ScriptBuf::new_witness_program(version, &program.data)),
             },
         }
     }
 
     pub fn from_script(script: ScriptBuf, network: Network) -> Result<Self, &'static str> {
-        let (script_type, script_data) = get_script_type_with_payload(script.as_bytes())?;
-        let payload = match script_type {
-            ScriptType::P2PKH => Some(Payload::PubkeyHash(script_data)),
-            ScriptType::P2SH => Some(Payload::ScriptHash(script_data)),
-            ScriptType::P2WPKH | ScriptType::P2WSH => Some(Payload::WitnessProgram(
-                WitnessProgram::new(WitnessVersion::V0, script_data),
-            )),
-            ScriptType::P2TR => Some(Payload::WitnessProgram(WitnessProgram::new(
-                WitnessVersion::V1,
-                script_data,
-            ))),
-            ScriptType::OPReturn => None,
-        };
-
-        // no support for OP_RETURN scripts yet
-        if payload.is_none() {
+        let payload = if script.is_p2pkh() {
+            Payload::PubkeyHash(script.as_bytes()[3..23].to_vec())
+        } else if script.is_p2sh() {
+            Payload::ScriptHash(script.as_bytes()[2..22].to_vec())
+        } else if let (Some(version), Some(program)) =
+            (script.witness_version(), script.witness_program())
+        {
+            // like `to_script`, only v0 programs of P2WPKH and P2WSH are valid
+            if version == WitnessVersion::V0
+                && program.len() != SEGWIT_V0_PUBKEY_HASH_LEN
+                && program.len() != SEGWIT_V0_SCRIPT_HASH_LEN
+            {
+                return Err("invalid witness program data");
+            }
+            Payload::WitnessProgram(WitnessProgram::new(version, program.to_vec()))
+        } else {
+            // public keys and bare multisig have no address, and OP_RETURN outputs can't
+            // be paid to: read their data with `Script::op_return_data`
             return Err("script type is invalid");
-        }
+        };
 
-        Ok(BitcoinAddress {
-            network,
-            payload: payload.unwrap(),
-        })
+        Ok(BitcoinAddress { network, payload })
     }
 }
 
@@ -201,4 +277,77 @@ mod test {
         assert_bitcoin_address_to_script(P2TR_TESTNET_ADDRESS, Network::Testnet);
         assert_bitcoin_address_to_script(P2TR_BITCOIN_ADDRESS, Network::Bitcoin);
     }
+
+    #[test]
+    fn bitcoin_address_from_witness_program() {
+        for len in [2, 19, 21, 24, 31, 33, 40] {
+            let script = ScriptBuf::new_witness_program(WitnessVersion::V0, &vec![1; len]);
+            assert_eq!(
+                BitcoinAddress::from_script(script, Network::Bitcoin),
+                Err("invalid witness program data")
+            );
+        }
+
+        let script = ScriptBuf::new_witness_program(WitnessVersion::V2, &[1; 2]);
+        let address = BitcoinAddress::from_script(script.clone(), Network::Bitcoin).unwrap();
+        assert_eq!(address.to_script().unwrap(), script);
+    }
+
+    #[test]
+    fn bitcoin_address_from_public_key() {
+        let secret_key = secp256k1::SecretKey::from_slice(&[0x42; 32]).unwrap();
+        let key = PublicKey::new(secp256k1::PublicKey::from_secret_key(
+            &secp256k1::Secp256k1::new(),
+            &secret_key,
+        ));
+        let bitcoin_key = bitcoin::PublicKey::from_slice(&key.to_bytes()).unwrap();
+
+        let expected = bitcoin::Address::p2pkh(&bitcoin_key, bitcoin::Network::Bitcoin);
+        assert_eq!(
+            BitcoinAddress::p2pkh(&key, Network::Bitcoin),
+            BitcoinAddress::from_str(&expected.to_string()).unwrap()
+        );
+        let expected = bitcoin::Address::p2wpkh(&bitcoin_key, bitcoin::Network::Testnet).unwrap();
+        assert_eq!(
+            BitcoinAddress::p2wpkh(&key, Network::Testnet).unwrap(),
+            BitcoinAddress::from_str(&expected.to_string()).unwrap()
+        );
+        let uncompressed = PublicKey::new_uncompressed(key.inner);
+        assert!(BitcoinAddress::p2wpkh(&uncompressed, Network::Bitcoin).is_err());
+    }
+
+    #[test]
+    fn bitcoin_address_from_taproot_key() {
+        // From the BIP341 test vectors.
+        let cases = [
+            (
+                "d6889cb081036e0faefa3a35157ad71086b123b2b144b649798b494c300a961d",
+                None,
+                "bc1p2wsldez5mud2yam29q22wgfh9439spgduvct83k3pm50fcxa5dps59h4z5",
+            ),
+            (
+                "187791b6f712a8ea41c8ecdd0ee77fab3e85263b37e1ec18a3651926b3a6cf27",
+                Some("5b75adecf53548f3ec6ad7d78383bf84cc57b55a3127c72b9a2481752dd88b21"),
+                "bc1pz37fc4cn9ah8anwm4xqqhvxygjf9rjf2resrw8h8w4tmvcs0863sa2e586",
+            ),
+        ];
+        for (internal_key, merkle_root, expected) in cases {
+            let internal_key =
+                XOnlyPublicKey::from_slice(&hex::decode(internal_key).unwrap()).unwrap();
+            let merkle_root = merkle_root
+                .map(|root| TapNodeHash::from_slice(&hex::decode(root).unwrap()).unwrap());
+            let address = BitcoinAddress::p2tr(internal_key, merkle_root, Network::Bitcoin);
+            assert_eq!(address, BitcoinAddress::from_str(expected).unwrap());
+            assert_eq!(
+                address.to_script().unwrap(),
+                ScriptBuf::new_p2tr(internal_key, merkle_root)
+            );
+
+            let (output_key, _) = TweakedPublicKey::from_internal_key(internal_key, merkle_root);
+            assert_eq!(
+                BitcoinAddress::p2tr_tweaked(output_key, Network::Bitcoin),
+                address
+            );
+        }
+    }
 }
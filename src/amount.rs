@@ -0,0 +1,618 @@
+//! Bitcoin amounts.
+//!
+//! [`Amount`] and [`SignedAmount`] wrap a number of satoshis and convert to and from
+//! strings in any [`Denomination`] using integer arithmetic only, so no value is ever
+//! rounded through a float.
+
+use crate::constants::MAX_MONEY;
+use core::fmt;
+use core::ops::{Add, Neg, Sub};
+use std::str::FromStr;
+
+/// A unit in which amounts can be expressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Denomination {
+    /// BTC
+    Bitcoin,
+    /// cBTC
+    CentiBitcoin,
+    /// mBTC
+    MilliBitcoin,
+    /// uBTC
+    MicroBitcoin,
+    /// bits, an alias of uBTC
+    Bit,
+    /// satoshi
+    Satoshi,
+}
+
+impl Denomination {
+    /// Number of decimal places one unit of this denomination spans in satoshis.
+    fn precision(self) -> u32 {
+        match self {
+            Denomination::Bitcoin => 8,
+            Denomination::CentiBitcoin => 6,
+            Denomination::MilliBitcoin => 5,
+            Denomination::MicroBitcoin | Denomination::Bit => 2,
+            Denomination::Satoshi => 0,
+        }
+    }
+}
+
+impl fmt::Display for Denomination {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Denomination::Bitcoin => "BTC",
+            Denomination::CentiBitcoin => "cBTC",
+            Denomination::MilliBitcoin => "mBTC",
+            Denomination::MicroBitcoin => "uBTC",
+            Denomination::Bit => "bits",
+            Denomination::Satoshi => "sat",
+        })
+    }
+}
+
+impl FromStr for Denomination {
+    type Err = ParseAmountError;
+
+    /// Parses a denomination, ignoring case except for the `m` of `mBTC` which would
+    /// be ambiguous with mega-bitcoin.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "btc" => Ok(Denomination::Bitcoin),
+            "cbtc" => Ok(Denomination::CentiBitcoin),
+            "mbtc" if s.starts_with('m') => Ok(Denomination::MilliBitcoin),
+            "ubtc" | "μbtc" => Ok(Denomination::MicroBitcoin),
+            "bit" | "bits" => Ok(Denomination::Bit),
+            "sat" | "sats" | "satoshi" | "satoshis" => Ok(Denomination::Satoshi),
+            _ => Err(ParseAmountError::UnknownDenomination(s.to_owned())),
+        }
+    }
+}
+
+/// Ways parsing an amount might fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParseAmountError {
+    /// The amount is negative where only positive amounts are allowed.
+    Negative,
+    /// The amount is above [`MAX_MONEY`].
+    TooBig,
+    /// The amount has more decimal places than a satoshi.
+    TooPrecise,
+    /// The number is empty or malformed.
+    InvalidFormat,
+    /// The string has no denomination.
+    MissingDenomination,
+    /// The denomination is not recognized.
+    UnknownDenomination(String),
+}
+
+impl fmt::Display for ParseAmountError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseAmountError::Negative => f.write_str("amount is negative"),
+            ParseAmountError::TooBig => f.write_str("amount is greater than the maximum money"),
+            ParseAmountError::TooPrecise => f.write_str("amount has a fraction of a satoshi"),
+            ParseAmountError::InvalidFormat => f.write_str("invalid number format"),
+            ParseAmountError::MissingDenomination => f.write_str("amount has no denomination"),
+            ParseAmountError::UnknownDenomination(ref d) => {
+                write!(f, "unknown denomination: {}", d)
+            }
+        }
+    }
+}
+
+/// Parses a decimal number of `denom` units into a negative flag and satoshis.
+fn parse_signed_sats(s: &str, denom: Denomination) -> Result<(bool, u64), ParseAmountError> {
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+
+    let (integer, fraction) = match digits.split_once('.') {
+        Some((integer, fraction)) => (integer, fraction),
+        None => (digits, ""),
+    };
+    let all_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+    if (integer.is_empty() && fraction.is_empty()) || !all_digits(integer) || !all_digits(fraction)
+    {
+        return Err(ParseAmountError::InvalidFormat);
+    }
+
+    let precision = denom.precision() as usize;
+    let (fraction, excess) = fraction.split_at(fraction.len().min(precision));
+    if excess.bytes().any(|b| b != b'0') {
+        return Err(ParseAmountError::TooPrecise);
+    }
+
+    let mut sats: u64 = 0;
+    let padding = core::iter::repeat_n(b'0', precision - fraction.len());
+    for digit in integer.bytes().chain(fraction.bytes()).chain(padding) {
+        sats = sats
+            .checked_mul(10)
+            .and_then(|sats| sats.checked_add((digit - b'0') as u64))
+            .ok_or(ParseAmountError::TooBig)?;
+    }
+    if sats > MAX_MONEY {
+        return Err(ParseAmountError::TooBig);
+    }
+
+    Ok((negative && sats != 0, sats))
+}
+
+/// Splits `"<number> <denomination>"`, the space being optional.
+fn split_denomination(s: &str) -> Result<(&str, Denomination), ParseAmountError> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-' || c == '+'))
+        .ok_or(ParseAmountError::MissingDenomination)?;
+    let (number, denom) = s.split_at(split);
+    Ok((number.trim_end(), denom.trim_start().parse()?))
+}
+
+/// Writes `negative` and `sats` as a decimal number of `denom` units, without trailing zeros.
+fn fmt_sats(f: &mut dyn fmt::Write, negative: bool, sats: u64, denom: Denomination) -> fmt::Result {
+    let unit = 10u64.pow(denom.precision());
+    if negative {
+        f.write_char('-')?;
+    }
+    write!(f, "{}", sats / unit)?;
+
+    let fraction = sats % unit;
+    if fraction != 0 {
+        let width = denom.precision() as usize;
+        let digits = format!("{:0width$}", fraction, width = width);
+        write!(f, ".{}", digits.trim_end_matches('0'))?;
+    }
+    Ok(())
+}
+
+/// An amount of bitcoin, stored as a number of satoshis.
+///
+/// Arithmetic is checked: any operation whose result is negative or above [`MAX_MONEY`]
+/// fails, and the operator implementations panic in that case.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Amount(u64);
+
+impl Amount {
+    /// The zero amount.
+    pub const ZERO: Amount = Amount(0);
+    /// Exactly one satoshi.
+    pub const ONE_SAT: Amount = Amount(1);
+    /// Exactly one bitcoin.
+    pub const ONE_BTC: Amount = Amount(100_000_000);
+    /// The maximum amount that can ever exist, [`MAX_MONEY`].
+    pub const MAX_MONEY: Amount = Amount(MAX_MONEY);
+
+    /// Creates an amount from a number of satoshis.
+    ///
+    /// Values above [`MAX_MONEY`] are kept as is so that any `TxOut` value can be
+    /// represented; use [`Amount::is_valid`] to check the range.
+    pub const fn from_sat(sats: u64) -> Amount {
+        Amount(sats)
+    }
+
+    /// Returns the number of satoshis.
+    pub const fn to_sat(self) -> u64 {
+        self.0
+    }
+
+    /// Returns true if the amount is at most [`MAX_MONEY`].
+    pub const fn is_valid(self) -> bool {
+        self.0 <= MAX_MONEY
+    }
+
+    /// Parses a decimal number expressed in `denom`, such as `"0.001"` bitcoin.
+    pub fn from_str_in(s: &str, denom: Denomination) -> Result<Amount, ParseAmountError> {
+        match parse_signed_sats(s, denom)? {
+            (true, _) => Err(ParseAmountError::Negative),
+            (false, sats) => Ok(Amount(sats)),
+        }
+    }
+
+    /// Formats the amount as a decimal number of `denom` units, without the unit.
+    pub fn to_string_in(self, denom: Denomination) -> String {
+        let mut s = String::new();
+        fmt_sats(&mut s, false, self.0, denom).expect("writing to a string can't fail");
+        s
+    }
+
+    /// Formats the amount as a decimal number followed by the `denom` unit.
+    pub fn to_string_with_denomination(self, denom: Denomination) -> String {
+        format!("{} {}", self.to_string_in(denom), denom)
+    }
+
+    /// Adds two amounts, returning `None` if the sum is above [`MAX_MONEY`].
+    pub fn checked_add(self, rhs: Amount) -> Option<Amount> {
+        self.0
+            .checked_add(rhs.0)
+            .map(Amount)
+            .filter(|a| a.is_valid())
+    }
+
+    /// Subtracts an amount, returning `None` if the result would be negative.
+    pub fn checked_sub(self, rhs: Amount) -> Option<Amount> {
+        self.0
+            .checked_sub(rhs.0)
+            .map(Amount)
+            .filter(|a| a.is_valid())
+    }
+
+    /// Multiplies by an integer, returning `None` if the product is above [`MAX_MONEY`].
+    pub fn checked_mul(self, rhs: u64) -> Option<Amount> {
+        self.0.checked_mul(rhs).map(Amount).filter(|a| a.is_valid())
+    }
+
+    /// Divides by an integer, rounding down. Returns `None` if `rhs` is zero.
+    pub fn checked_div(self, rhs: u64) -> Option<Amount> {
+        self.0.checked_div(rhs).map(Amount)
+    }
+
+    /// Converts to a [`SignedAmount`], returning `None` if above [`MAX_MONEY`].
+    pub fn to_signed(self) -> Option<SignedAmount> {
+        if self.is_valid() {
+            Some(SignedAmount(self.0 as i64))
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Amount {
+    /// Formats the amount in bitcoin, e.g. `0.001 BTC`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_sats(f, false, self.0, Denomination::Bitcoin)?;
+        write!(f, " {}", Denomination::Bitcoin)
+    }
+}
+
+impl FromStr for Amount {
+    type Err = ParseAmountError;
+
+    /// Parses an amount with its denomination, e.g. `"0.001 BTC"` or `"1500 sat"`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (number, denom) = split_denomination(s)?;
+        Amount::from_str_in(number, denom)
+    }
+}
+
+impl Add for Amount {
+    type Output = Amount;
+
+    fn add(self, rhs: Amount) -> Amount {
+        self.checked_add(rhs).expect("amount addition out of range")
+    }
+}
+
+impl Sub for Amount {
+    type Output = Amount;
+
+    fn sub(self, rhs: Amount) -> Amount {
+        self.checked_sub(rhs)
+            .expect("amount subtraction out of range")
+    }
+}
+
+impl core::iter::Sum for Amount {
+    fn sum<I: Iterator<Item = Amount>>(iter: I) -> Amount {
+        iter.fold(Amount::ZERO, Add::add)
+    }
+}
+
+impl From<Amount> for u64 {
+    fn from(amount: Amount) -> u64 {
+        amount.0
+    }
+}
+
+/// A signed amount of bitcoin, such as a balance change, stored as satoshis.
+///
+/// Arithmetic is checked: any operation whose magnitude exceeds [`MAX_MONEY`] fails,
+/// and the operator implementations panic in that case.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SignedAmount(i64);
+
+impl SignedAmount {
+    /// The zero amount.
+    pub const ZERO: SignedAmount = SignedAmount(0);
+    /// Exactly one satoshi.
+    pub const ONE_SAT: SignedAmount = SignedAmount(1);
+    /// Exactly one bitcoin.
+    pub const ONE_BTC: SignedAmount = SignedAmount(100_000_000);
+    /// The maximum positive amount, [`MAX_MONEY`].
+    pub const MAX_MONEY: SignedAmount = SignedAmount(MAX_MONEY as i64);
+
+    /// Creates an amount from a number of satoshis.
+    pub const fn from_sat(sats: i64) -> SignedAmount {
+        SignedAmount(sats)
+    }
+
+    /// Returns the number of satoshis.
+    pub const fn to_sat(self) -> i64 {
+        self.0
+    }
+
+    /// Returns true if the magnitude of the amount is at most [`MAX_MONEY`].
+    pub const fn is_valid(self) -> bool {
+        self.0.unsigned_abs() <= MAX_MONEY
+    }
+
+    /// Returns true if the amount is below zero.
+    pub const fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    /// Returns the absolute value of the amount.
+    pub fn abs(self) -> SignedAmount {
+        SignedAmount(self.0.saturating_abs())
+    }
+
+    /// Parses a decimal number expressed in `denom`, such as `"-0.001"` bitcoin.
+    pub fn from_str_in(s: &str, denom: Denomination) -> Result<SignedAmount, ParseAmountError> {
+        let (negative, sats) = parse_signed_sats(s, denom)?;
+        let sats = sats as i64;
+        Ok(SignedAmount(if negative { -sats } else { sats }))
+    }
+
+    /// Formats the amount as a decimal number of `denom` units, without the unit.
+    pub fn to_string_in(self, denom: Denomination) -> String {
+        let mut s = String::new();
+        fmt_sats(&mut s, self.is_negative(), self.0.unsigned_abs(), denom)
+            .expect("writing to a string can't fail");
+        s
+    }
+
+    /// Formats the amount as a decimal number followed by the `denom` unit.
+    pub fn to_string_with_denomination(self, denom: Denomination) -> String {
+        format!("{} {}", self.to_string_in(denom), denom)
+    }
+
+    /// Adds two amounts, returning `None` if the result is out of range.
+    pub fn checked_add(self, rhs: SignedAmount) -> Option<SignedAmount> {
+        self.0
+            .checked_add(rhs.0)
+            .map(SignedAmount)
+            .filter(|a| a.is_valid())
+    }
+
+    /// Subtracts an amount, returning `None` if the result is out of range.
+    pub fn checked_sub(self, rhs: SignedAmount) -> Option<SignedAmount> {
+        self.0
+            .checked_sub(rhs.0)
+            .map(SignedAmount)
+            .filter(|a| a.is_valid())
+    }
+
+    /// Multiplies by an integer, returning `None` if the result is out of range.
+    pub fn checked_mul(self, rhs: i64) -> Option<SignedAmount> {
+        self.0
+            .checked_mul(rhs)
+            .map(SignedAmount)
+            .filter(|a| a.is_valid())
+    }
+
+    /// Divides by an integer, rounding towards zero. Returns `None` if `rhs` is zero.
+    pub fn checked_div(self, rhs: i64) -> Option<SignedAmount> {
+        self.0.checked_div(rhs).map(SignedAmount)
+    }
+
+    /// Converts to an [`Amount`], returning `None` if negative.
+    pub fn to_unsigned(self) -> Option<Amount> {
+        if self.is_negative() {
+            None
+        } else {
+            Some(Amount(self.0 as u64))
+        }
+    }
+}
+
+impl fmt::Display for SignedAmount {
+    /// Formats the amount in bitcoin, e.g. `-0.001 BTC`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_sats(
+            f,
+            self.is_negative(),
+            self.0.unsigned_abs(),
+            Denomination::Bitcoin,
+        )?;
+        write!(f, " {}", Denomination::Bitcoin)
+    }
+}
+
+impl FromStr for SignedAmount {
+    type Err = ParseAmountError;
+
+    /// Parses an amount with its denomination, e.g. `"-0.001 BTC"`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (number, denom) = split_denomination(s)?;
+        SignedAmount::from_str_in(number, denom)
+    }
+}
+
+impl Add for SignedAmount {
+    type Output = SignedAmount;
+
+    fn add(self, rhs: SignedAmount) -> SignedAmount {
+        self.checked_add(rhs).expect("amount addition out of range")
+    }
+}
+
+impl Sub for SignedAmount {
+    type Output = SignedAmount;
+
+    fn sub(self, rhs: SignedAmount) -> SignedAmount {
+        self.checked_sub(rhs)
+            .expect("amount subtraction out of range")
+    }
+}
+
+impl Neg for SignedAmount {
+    type Output = SignedAmount;
+
+    fn neg(self) -> SignedAmount {
+        SignedAmount(-self.0)
+    }
+}
+
+impl core::iter::Sum for SignedAmount {
+    fn sum<I: Iterator<Item = SignedAmount>>(iter: I) -> SignedAmount {
+        iter.fold(SignedAmount::ZERO, Add::add)
+    }
+}
+
+impl From<SignedAmount> for i64 {
+    fn from(amount: SignedAmount) -> i64 {
+        amount.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_with_denomination() {
+        let test_cases = vec![
+            ("0.001 BTC", 100_000),
+            ("1500 sat", 1500),
+            ("2 mBTC", 200_000),
+            ("2mBTC", 200_000),
+            ("1 cBTC", 1_000_000),
+            ("3.5 uBTC", 350),
+            ("12 bits", 1200),
+            (" 21000000 btc ", MAX_MONEY),
+            ("0.00000001 BTC", 1),
+            ("1.10000000000 BTC", 110_000_000),
+            (".5 BTC", 50_000_000),
+            ("5. BTC", 500_000_000),
+        ];
+
+        for (s, sats) in test_cases {
+            assert_eq!(Amount::from_str(s), Ok(Amount::from_sat(sats)), "{}", s);
+            let expected = bitcoin::Amount::from_str(s.trim().replace("bits", "uBTC").as_str());
+            if let Ok(expected) = expected {
+                assert_eq!(expected.to_sat(), sats);
+            }
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        let test_cases = vec![
+            ("0.000000001 BTC", ParseAmountError::TooPrecise),
+            ("0.5 sat", ParseAmountError::TooPrecise),
+            ("21000000.00000001 BTC", ParseAmountError::TooBig),
+            ("99999999999999999999 sat", ParseAmountError::TooBig),
+            ("-1 sat", ParseAmountError::Negative),
+            ("1.2.3 BTC", ParseAmountError::InvalidFormat),
+            (". BTC", ParseAmountError::InvalidFormat),
+            ("1500", ParseAmountError::MissingDenomination),
+            (
+                "1 MBTC",
+                ParseAmountError::UnknownDenomination("MBTC".to_owned()),
+            ),
+            (
+                "1 eth",
+                ParseAmountError::UnknownDenomination("eth".to_owned()),
+            ),
+        ];
+
+        for (s, err) in test_cases {
+            assert_eq!(Amount::from_str(s), Err(err), "{}", s);
+        }
+    }
+
+    #[test]
+    fn format_in_denominations() {
+        let amount = Amount::from_sat(123_456_789);
+        assert_eq!(amount.to_string(), "1.23456789 BTC");
+        assert_eq!(amount.to_string_in(Denomination::Bitcoin), "1.23456789");
+        assert_eq!(
+            amount.to_string_in(Denomination::CentiBitcoin),
+            "123.456789"
+        );
+        assert_eq!(
+            amount.to_string_in(Denomination::MilliBitcoin),
+            "1234.56789"
+        );
+        assert_eq!(
+            amount.to_string_in(Denomination::MicroBitcoin),
+            "1234567.89"
+        );
+        assert_eq!(
+            amount.to_string_with_denomination(Denomination::Satoshi),
+            "123456789 sat"
+        );
+
+        assert_eq!(Amount::from_sat(100_000).to_string(), "0.001 BTC");
+        assert_eq!(Amount::ONE_BTC.to_string(), "1 BTC");
+        assert_eq!(Amount::ZERO.to_string_in(Denomination::Bit), "0");
+        assert_eq!(Amount::MAX_MONEY.to_string(), "21000000 BTC");
+
+        for sats in [0, 1, 10, 999, 100_000, 123_456_789, MAX_MONEY] {
+            let amount = Amount::from_sat(sats);
+            for denom in [
+                Denomination::Bitcoin,
+                Denomination::MilliBitcoin,
+                Denomination::Bit,
+            ] {
+                let s = amount.to_string_with_denomination(denom);
+                assert_eq!(Amount::from_str(&s), Ok(amount));
+            }
+        }
+    }
+
+    #[test]
+    fn checked_arithmetic() {
+        let one = Amount::ONE_BTC;
+        assert_eq!(one.checked_add(one), Some(Amount::from_sat(200_000_000)));
+        assert_eq!(Amount::MAX_MONEY.checked_add(Amount::ONE_SAT), None);
+        assert_eq!(Amount::ZERO.checked_sub(Amount::ONE_SAT), None);
+        assert_eq!(one.checked_mul(21_000_000), Some(Amount::MAX_MONEY));
+        assert_eq!(one.checked_mul(21_000_001), None);
+        assert_eq!(one.checked_div(3), Some(Amount::from_sat(33_333_333)));
+        assert_eq!(one.checked_div(0), None);
+        assert_eq!(
+            vec![one, one, one].into_iter().sum::<Amount>(),
+            one.checked_mul(3).unwrap()
+        );
+        assert!(!Amount::from_sat(u64::MAX).is_valid());
+        assert_eq!(Amount::from_sat(u64::MAX).to_signed(), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn add_panics_above_max_money() {
+        let _ = Amount::MAX_MONEY + Amount::ONE_SAT;
+    }
+
+    #[test]
+    fn signed_amounts() {
+        assert_eq!(
+            SignedAmount::from_str("-0.001 BTC"),
+            Ok(SignedAmount::from_sat(-100_000))
+        );
+        assert_eq!(SignedAmount::from_str("-0 BTC"), Ok(SignedAmount::ZERO));
+        assert_eq!(
+            SignedAmount::from_str("-21000000.1 BTC"),
+            Err(ParseAmountError::TooBig)
+        );
+
+        let amount = SignedAmount::from_sat(-150_000_000);
+        assert_eq!(amount.to_string(), "-1.5 BTC");
+        assert_eq!(amount.to_string_in(Denomination::MilliBitcoin), "-1500");
+        assert_eq!(amount.abs(), SignedAmount::from_sat(150_000_000));
+        assert_eq!(amount.to_unsigned(), None);
+        assert_eq!((-amount).to_unsigned(), Some(Amount::from_sat(150_000_000)));
+
+        let max = SignedAmount::MAX_MONEY;
+        assert_eq!(max.checked_add(SignedAmount::ONE_SAT), None);
+        assert_eq!((-max).checked_sub(SignedAmount::ONE_SAT), None);
+        assert_eq!(
+            SignedAmount::ONE_BTC - SignedAmount::ONE_BTC - SignedAmount::ONE_SAT,
+            SignedAmount::from_sat(-1)
+        );
+    }
+}
@@ -0,0 +1,1126 @@
+//! Hierarchical deterministic keys (BIP32).
+//!
+//! An [`Xpriv`] is derived from a seed and derives a tree of child keys, each chained
+//! to its parent by a chain code. Normal children of an [`Xpub`] can be derived without
+//! the private key, hardened children can't. Extended keys are serialized in base58
+//! with version bytes telling the network and, following SLIP-132, the script type
+//! the keys are meant for.
+
+use bitcoin_hashes::{hmac, sha512, Hash, HashEngine};
+use core::fmt;
+use secp256k1::{Scalar, Secp256k1};
+use std::str::FromStr;
+
+use crate::key::{PrivateKey, PubkeyHash, PublicKey};
+use crate::types::Network;
+
+/// Ways that deriving, parsing or decoding extended keys might fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Bip32Error {
+    /// A hardened child was derived from a public key.
+    CannotDeriveFromHardenedKey,
+    /// The index is not less than 2^31, so it can't be marked hardened or normal.
+    InvalidChildNumber(u32),
+    /// A child number isn't a decimal index, optionally followed by `'` or `h`.
+    InvalidChildNumberFormat,
+    /// A derivation path isn't child numbers separated by `/`, optionally after `m`.
+    InvalidDerivationPathFormat,
+    /// The key isn't valid base58 with a correct checksum.
+    Base58(base58::Error),
+    /// The decoded key isn't 78 bytes.
+    WrongExtendedKeyLength(usize),
+    /// The version bytes are not those of an extended key of the expected kind.
+    UnknownVersion([u8; 4]),
+    /// The key data is not a valid public or private key.
+    InvalidKey,
+    /// A master key, of depth 0, has a parent fingerprint.
+    NonZeroParentFingerprintForMasterKey,
+    /// A master key, of depth 0, has a child number.
+    NonZeroChildNumberForMasterKey,
+    /// A child was derived from a key of depth 255.
+    MaximumDepthExceeded,
+    /// The seed of a master key isn't between 16 and 64 bytes.
+    InvalidSeedLength(usize),
+}
+
+impl fmt::Display for Bip32Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Bip32Error::CannotDeriveFromHardenedKey => {
+                f.write_str("cannot derive hardened child of public key")
+            }
+            Bip32Error::InvalidChildNumber(index) => {
+                write!(f, "child number {} is out of range", index)
+            }
+            Bip32Error::InvalidChildNumberFormat => f.write_str("invalid child number format"),
+            Bip32Error::InvalidDerivationPathFormat => {
+                f.write_str("invalid derivation path format")
+            }
+            Bip32Error::Base58(ref error) => write!(f, "invalid base58: {}", error),
+            Bip32Error::WrongExtendedKeyLength(len) => {
+                write!(f, "invalid extended key length {}", len)
+            }
+            Bip32Error::UnknownVersion(version) => {
+                write!(f, "unknown extended key version {}", hex::encode(version))
+            }
+            Bip32Error::InvalidKey => f.write_str("invalid key data"),
+            Bip32Error::NonZeroParentFingerprintForMasterKey => {
+                f.write_str("master key has a non-zero parent fingerprint")
+            }
+            Bip32Error::NonZeroChildNumberForMasterKey => {
+                f.write_str("master key has a non-zero child number")
+            }
+            Bip32Error::MaximumDepthExceeded => f.write_str("maximum key depth exceeded"),
+            Bip32Error::InvalidSeedLength(len) => write!(f, "invalid seed length {}", len),
+        }
+    }
+}
+
+impl From<base58::Error> for Bip32Error {
+    fn from(error: base58::Error) -> Self {
+        Bip32Error::Base58(error)
+    }
+}
+
+/// The first 4 bytes of the hash160 of a compressed public key, identifying the parent
+/// of an extended key or the origin of a key in a descriptor.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fingerprint([u8; 4]);
+
+impl Fingerprint {
+    /// Returns the fingerprint of `key`.
+    pub fn from_key(key: &secp256k1::PublicKey) -> Self {
+        let hash = PublicKey::new(*key).pubkey_hash();
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&hash[..4]);
+        Fingerprint(bytes)
+    }
+
+    /// Returns the 4 bytes of the fingerprint.
+    pub fn to_bytes(self) -> [u8; 4] {
+        self.0
+    }
+}
+
+impl From<[u8; 4]> for Fingerprint {
+    fn from(bytes: [u8; 4]) -> Self {
+        Fingerprint(bytes)
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&hex::encode(self.0))
+    }
+}
+
+impl FromStr for Fingerprint {
+    type Err = hex::FromHexError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut bytes = [0; 4];
+        hex::decode_to_slice(s, &mut bytes)?;
+        Ok(Fingerprint(bytes))
+    }
+}
+
+/// The 32 bytes mixed into the derivation of every child of an extended key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChainCode([u8; 32]);
+
+impl ChainCode {
+    /// Returns the 32 bytes of the chain code.
+    pub fn to_bytes(self) -> [u8; 32] {
+        self.0
+    }
+}
+
+impl From<[u8; 32]> for ChainCode {
+    fn from(bytes: [u8; 32]) -> Self {
+        ChainCode(bytes)
+    }
+}
+
+/// Index of a child key, hardened children being derived from the private key only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ChildNumber {
+    /// A child derivable from the public key, with an index below 2^31.
+    Normal { index: u32 },
+    /// A child derivable from the private key only, with an index below 2^31.
+    Hardened { index: u32 },
+}
+
+impl ChildNumber {
+    /// Index from which child numbers are hardened when serialized.
+    pub const HARDENED_OFFSET: u32 = 1 << 31;
+
+    /// Returns the normal child with `index`, which must be below 2^31.
+    pub fn from_normal_idx(index: u32) -> Result<Self, Bip32Error> {
+        if index < Self::HARDENED_OFFSET {
+            Ok(ChildNumber::Normal { index })
+        } else {
+            Err(Bip32Error::InvalidChildNumber(index))
+        }
+    }
+
+    /// Returns the hardened child with `index`, which must be below 2^31.
+    pub fn from_hardened_idx(index: u32) -> Result<Self, Bip32Error> {
+        if index < Self::HARDENED_OFFSET {
+            Ok(ChildNumber::Hardened { index })
+        } else {
+            Err(Bip32Error::InvalidChildNumber(index))
+        }
+    }
+
+    /// Returns true for hardened children.
+    pub fn is_hardened(&self) -> bool {
+        matches!(self, ChildNumber::Hardened { .. })
+    }
+
+    /// Returns the number serialized in extended keys, with hardened indexes offset by
+    /// 2^31.
+    pub fn to_u32(self) -> u32 {
+        match self {
+            ChildNumber::Normal { index } => index,
+            ChildNumber::Hardened { index } => index | Self::HARDENED_OFFSET,
+        }
+    }
+}
+
+impl From<u32> for ChildNumber {
+    /// Reads a serialized child number, hardened if it is at least 2^31.
+    fn from(number: u32) -> Self {
+        if number < ChildNumber::HARDENED_OFFSET {
+            ChildNumber::Normal { index: number }
+        } else {
+            ChildNumber::Hardened {
+                index: number ^ ChildNumber::HARDENED_OFFSET,
+            }
+        }
+    }
+}
+
+impl fmt::Display for ChildNumber {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ChildNumber::Normal { index } => write!(f, "{}", index),
+            ChildNumber::Hardened { index } => write!(f, "{}'", index),
+        }
+    }
+}
+
+impl FromStr for ChildNumber {
+    type Err = Bip32Error;
+
+    /// Parses a decimal index, hardened if followed by `'`, `h` or `H`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (index, hardened) = match s.strip_suffix(['\'', 'h', 'H']) {
+            Some(index) => (index, true),
+            None => (s, false),
+        };
+        if index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(Bip32Error::InvalidChildNumberFormat);
+        }
+        let index = index
+            .parse()
+            .map_err(|_| Bip32Error::InvalidChildNumberFormat)?;
+        if hardened {
+            ChildNumber::from_hardened_idx(index)
+        } else {
+            ChildNumber::from_normal_idx(index)
+        }
+    }
+}
+
+/// The child numbers leading from a key to one of its descendants.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DerivationPath(Vec<ChildNumber>);
+
+impl DerivationPath {
+    /// Returns the empty path, leading to the key itself.
+    pub fn master() -> Self {
+        DerivationPath(Vec::new())
+    }
+
+    /// Returns true if the path is empty.
+    pub fn is_master(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the path to the child `number` of the key this path leads to.
+    pub fn child(&self, number: ChildNumber) -> Self {
+        let mut path = self.0.clone();
+        path.push(number);
+        DerivationPath(path)
+    }
+
+    /// Returns the path to the descendant `path` of the key this path leads to.
+    pub fn extend(&self, path: impl AsRef<[ChildNumber]>) -> Self {
+        let mut result = self.0.clone();
+        result.extend_from_slice(path.as_ref());
+        DerivationPath(result)
+    }
+
+    /// Iterates over the child numbers from the key down.
+    pub fn iter(&self) -> core::slice::Iter<'_, ChildNumber> {
+        self.0.iter()
+    }
+
+    /// Returns the number of derivation steps.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns true if the path is empty, like [`is_master`](Self::is_master).
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<Vec<ChildNumber>> for DerivationPath {
+    fn from(path: Vec<ChildNumber>) -> Self {
+        DerivationPath(path)
+    }
+}
+
+impl From<&[ChildNumber]> for DerivationPath {
+    fn from(path: &[ChildNumber]) -> Self {
+        DerivationPath(path.to_vec())
+    }
+}
+
+impl AsRef<[ChildNumber]> for DerivationPath {
+    fn as_ref(&self) -> &[ChildNumber] {
+        &self.0
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("m")?;
+        for number in &self.0 {
+            write!(f, "/{}", number)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = Bip32Error;
+
+    /// Parses child numbers separated by `/`, optionally starting with `m` for the
+    /// master key, like `m/84'/0'/0'/0/5`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let path = match s {
+            "" | "m" => return Ok(DerivationPath::master()),
+            _ => s.strip_prefix("m/").unwrap_or(s),
+        };
+        path.split('/')
+            .map(|number| match number.parse() {
+                Err(Bip32Error::InvalidChildNumberFormat) => {
+                    Err(Bip32Error::InvalidDerivationPathFormat)
+                }
+                result => result,
+            })
+            .collect::<Result<_, _>>()
+            .map(DerivationPath)
+    }
+}
+
+/// The script type extended keys are meant for, which SLIP-132 encodes in their
+/// version bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum KeyFormat {
+    /// xpub and xprv, or tpub and tprv, for P2PKH and anything else.
+    #[default]
+    Legacy,
+    /// ypub and yprv, or upub and uprv, for P2WPKH nested in P2SH.
+    NestedSegwit,
+    /// zpub and zprv, or vpub and vprv, for P2WPKH.
+    Segwit,
+}
+
+/// Version bytes of extended keys, as (network is mainnet, format, private, version).
+const VERSIONS: [(bool, KeyFormat, bool, [u8; 4]); 12] = [
+    (true, KeyFormat::Legacy, false, [0x04, 0x88, 0xb2, 0x1e]),
+    (true, KeyFormat::Legacy, true, [0x04, 0x88, 0xad, 0xe4]),
+    (
+        true,
+        KeyFormat::NestedSegwit,
+        false,
+        [0x04, 0x9d, 0x7c, 0xb2],
+    ),
+    (
+        true,
+        KeyFormat::NestedSegwit,
+        true,
+        [0x04, 0x9d, 0x78, 0x78],
+    ),
+    (true, KeyFormat::Segwit, false, [0x04, 0xb2, 0x47, 0x46]),
+    (true, KeyFormat::Segwit, true, [0x04, 0xb2, 0x43, 0x0c]),
+    (false, KeyFormat::Legacy, false, [0x04, 0x35, 0x87, 0xcf]),
+    (false, KeyFormat::Legacy, true, [0x04, 0x35, 0x83, 0x94]),
+    (
+        false,
+        KeyFormat::NestedSegwit,
+        false,
+        [0x04, 0x4a, 0x52, 0x62],
+    ),
+    (
+        false,
+        KeyFormat::NestedSegwit,
+        true,
+        [0x04, 0x4a, 0x4e, 0x28],
+    ),
+    (false, KeyFormat::Segwit, false, [0x04, 0x5f, 0x1c, 0xf6]),
+    (false, KeyFormat::Segwit, true, [0x04, 0x5f, 0x18, 0xbc]),
+];
+
+fn version(network: Network, format: KeyFormat, private: bool) -> [u8; 4] {
+    let main = network == Network::Bitcoin;
+    VERSIONS
+        .iter()
+        .find(|v| v.0 == main && v.1 == format && v.2 == private)
+        .expect("every version is listed")
+        .3
+}
+
+/// Looks up the network and format of `version`, which must be private or public as
+/// requested. Testnet versions are read as [`Network::Testnet`], since signet and
+/// regtest keys share them.
+fn parse_version(version: [u8; 4], private: bool) -> Result<(Network, KeyFormat), Bip32Error> {
+    VERSIONS
+        .iter()
+        .find(|v| v.3 == version && v.2 == private)
+        .map(|v| {
+            let network = if v.0 {
+                Network::Bitcoin
+            } else {
+                Network::Testnet
+            };
+            (network, v.1)
+        })
+        .ok_or(Bip32Error::UnknownVersion(version))
+}
+
+/// Length of a serialized extended key.
+const EXTENDED_KEY_LEN: usize = 78;
+
+/// Splits HMAC-SHA512 of `data` keyed with `key` into the tweak and the chain code.
+fn hmac_sha512(key: &[u8], data: &[&[u8]]) -> (Scalar, ChainCode) {
+    let mut engine = hmac::HmacEngine::<sha512::Hash>::new(key);
+    for data in data {
+        engine.input(data);
+    }
+    let hash = hmac::Hmac::from_engine(engine).to_byte_array();
+    let mut tweak = [0; 32];
+    tweak.copy_from_slice(&hash[..32]);
+    let mut chain_code = [0; 32];
+    chain_code.copy_from_slice(&hash[32..]);
+    let tweak = Scalar::from_be_bytes(tweak)
+        .expect("hash is below the curve order with overwhelming probability");
+    (tweak, ChainCode(chain_code))
+}
+
+/// Serializes an extended key, whose `key` data takes the last 33 bytes.
+fn encode(
+    version: [u8; 4],
+    depth: u8,
+    parent_fingerprint: Fingerprint,
+    child_number: ChildNumber,
+    chain_code: ChainCode,
+    key: &[u8],
+) -> [u8; EXTENDED_KEY_LEN] {
+    let mut data = [0; EXTENDED_KEY_LEN];
+    data[0..4].copy_from_slice(&version);
+    data[4] = depth;
+    data[5..9].copy_from_slice(&parent_fingerprint.0);
+    data[9..13].copy_from_slice(&child_number.to_u32().to_be_bytes());
+    data[13..45].copy_from_slice(&chain_code.0);
+    data[78 - key.len()..].copy_from_slice(key);
+    data
+}
+
+/// Decoded common fields of an extended key.
+struct Fields<'a> {
+    network: Network,
+    format: KeyFormat,
+    depth: u8,
+    parent_fingerprint: Fingerprint,
+    child_number: ChildNumber,
+    chain_code: ChainCode,
+    key: &'a [u8],
+}
+
+fn decode(data: &[u8], private: bool) -> Result<Fields<'_>, Bip32Error> {
+    if data.len() != EXTENDED_KEY_LEN {
+        return Err(Bip32Error::WrongExtendedKeyLength(data.len()));
+    }
+    let (network, format) = parse_version(data[0..4].try_into().unwrap(), private)?;
+    let fields = Fields {
+        network,
+        format,
+        depth: data[4],
+        parent_fingerprint: Fingerprint(data[5..9].try_into().unwrap()),
+        child_number: u32::from_be_bytes(data[9..13].try_into().unwrap()).into(),
+        chain_code: ChainCode(data[13..45].try_into().unwrap()),
+        key: &data[45..],
+    };
+    if fields.depth == 0 {
+        if fields.parent_fingerprint != Fingerprint::default() {
+            return Err(Bip32Error::NonZeroParentFingerprintForMasterKey);
+        }
+        if fields.child_number.to_u32() != 0 {
+            return Err(Bip32Error::NonZeroChildNumberForMasterKey);
+        }
+    }
+    Ok(fields)
+}
+
+/// An extended private key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Xpriv {
+    pub network: Network,
+    /// The script type the key is meant for, only changing its version bytes.
+    pub format: KeyFormat,
+    /// Number of derivation steps from the master key.
+    pub depth: u8,
+    pub parent_fingerprint: Fingerprint,
+    /// The child number of this key under its parent.
+    pub child_number: ChildNumber,
+    pub private_key: secp256k1::SecretKey,
+    pub chain_code: ChainCode,
+}
+
+impl Xpriv {
+    /// Derives the master key from `seed`, which BIP32 recommends be 32 bytes and
+    /// requires be between 16 and 64 bytes.
+    pub fn new_master(network: Network, seed: &[u8]) -> Result<Self, Bip32Error> {
+        if !(16..=64).contains(&seed.len()) {
+            return Err(Bip32Error::InvalidSeedLength(seed.len()));
+        }
+        let (tweak, chain_code) = hmac_sha512(b"Bitcoin seed", &[seed]);
+        Ok(Xpriv {
+            network,
+            format: KeyFormat::Legacy,
+            depth: 0,
+            parent_fingerprint: Fingerprint::default(),
+            child_number: ChildNumber::Normal { index: 0 },
+            private_key: secp256k1::SecretKey::from_slice(&tweak.to_be_bytes())
+                .expect("seed hash is a valid key with overwhelming probability"),
+            chain_code,
+        })
+    }
+
+    /// Derives the child `number` of this key.
+    pub fn ckd_priv(&self, number: ChildNumber) -> Result<Xpriv, Bip32Error> {
+        let index = number.to_u32().to_be_bytes();
+        let (tweak, chain_code) = if number.is_hardened() {
+            let key = self.private_key.secret_bytes();
+            hmac_sha512(&self.chain_code.0, &[&[0], &key, &index])
+        } else {
+            let key = self.public_key().serialize();
+            hmac_sha512(&self.chain_code.0, &[&key, &index])
+        };
+        Ok(Xpriv {
+            depth: self
+                .depth
+                .checked_add(1)
+                .ok_or(Bip32Error::MaximumDepthExceeded)?,
+            parent_fingerprint: self.fingerprint(),
+            child_number: number,
+            private_key: self
+                .private_key
+                .add_tweak(&tweak)
+                .expect("child key is valid with overwhelming probability"),
+            chain_code,
+            ..*self
+        })
+    }
+
+    /// Derives the descendant of this key at `path`.
+    pub fn derive_priv(&self, path: impl AsRef<[ChildNumber]>) -> Result<Xpriv, Bip32Error> {
+        path.as_ref()
+            .iter()
+            .try_fold(*self, |key, &number| key.ckd_priv(number))
+    }
+
+    fn public_key(&self) -> secp256k1::PublicKey {
+        self.private_key.public_key(&Secp256k1::signing_only())
+    }
+
+    /// Returns the fingerprint of the public key, the parent fingerprint of children.
+    pub fn fingerprint(&self) -> Fingerprint {
+        Fingerprint::from_key(&self.public_key())
+    }
+
+    /// Returns the private key, whose public key is serialized compressed.
+    pub fn to_private_key(&self) -> PrivateKey {
+        PrivateKey::new(self.private_key).with_network(self.network)
+    }
+
+    /// Serializes the key in 78 bytes.
+    pub fn encode(&self) -> [u8; EXTENDED_KEY_LEN] {
+        let mut key = [0; 33];
+        key[1..].copy_from_slice(&self.private_key.secret_bytes());
+        encode(
+            version(self.network, self.format, true),
+            self.depth,
+            self.parent_fingerprint,
+            self.child_number,
+            self.chain_code,
+            &key,
+        )
+    }
+
+    /// Parses a 78-byte key with private version bytes.
+    pub fn decode(data: &[u8]) -> Result<Self, Bip32Error> {
+        let fields = decode(data, true)?;
+        if fields.key[0] != 0 {
+            return Err(Bip32Error::InvalidKey);
+        }
+        Ok(Xpriv {
+            network: fields.network,
+            format: fields.format,
+            depth: fields.depth,
+            parent_fingerprint: fields.parent_fingerprint,
+            child_number: fields.child_number,
+            private_key: secp256k1::SecretKey::from_slice(&fields.key[1..])
+                .map_err(|_| Bip32Error::InvalidKey)?,
+            chain_code: fields.chain_code,
+        })
+    }
+}
+
+impl fmt::Display for Xpriv {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        base58::encode_check_to_fmt(f, &self.encode())
+    }
+}
+
+impl FromStr for Xpriv {
+    type Err = Bip32Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Xpriv::decode(&base58::decode_check(s)?)
+    }
+}
+
+/// An extended public key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Xpub {
+    pub network: Network,
+    /// The script type the key is meant for, only changing its version bytes.
+    pub format: KeyFormat,
+    /// Number of derivation steps from the master key.
+    pub depth: u8,
+    pub parent_fingerprint: Fingerprint,
+    /// The child number of this key under its parent.
+    pub child_number: ChildNumber,
+    pub public_key: secp256k1::PublicKey,
+    pub chain_code: ChainCode,
+}
+
+impl Xpub {
+    /// Returns the public key of `xpriv`, which derives the same normal children.
+    pub fn from_priv(xpriv: &Xpriv) -> Self {
+        Xpub {
+            network: xpriv.network,
+            format: xpriv.format,
+            depth: xpriv.depth,
+            parent_fingerprint: xpriv.parent_fingerprint,
+            child_number: xpriv.child_number,
+            public_key: xpriv.public_key(),
+            chain_code: xpriv.chain_code,
+        }
+    }
+
+    /// Derives the child `number` of this key, which must not be hardened.
+    pub fn ckd_pub(&self, number: ChildNumber) -> Result<Xpub, Bip32Error> {
+        if number.is_hardened() {
+            return Err(Bip32Error::CannotDeriveFromHardenedKey);
+        }
+        let (tweak, chain_code) = hmac_sha512(
+            &self.chain_code.0,
+            &[&self.public_key.serialize(), &number.to_u32().to_be_bytes()],
+        );
+        Ok(Xpub {
+            depth: self
+                .depth
+                .checked_add(1)
+                .ok_or(Bip32Error::MaximumDepthExceeded)?,
+            parent_fingerprint: self.fingerprint(),
+            child_number: number,
+            public_key: self
+                .public_key
+                .add_exp_tweak(&Secp256k1::verification_only(), &tweak)
+                .expect("child key is valid with overwhelming probability"),
+            chain_code,
+            ..*self
+        })
+    }
+
+    /// Derives the descendant of this key at `path`, which must not have hardened
+    /// steps.
+    pub fn derive_pub(&self, path: impl AsRef<[ChildNumber]>) -> Result<Xpub, Bip32Error> {
+        path.as_ref()
+            .iter()
+            .try_fold(*self, |key, &number| key.ckd_pub(number))
+    }
+
+    /// Returns the fingerprint of the key, the parent fingerprint of children.
+    pub fn fingerprint(&self) -> Fingerprint {
+        Fingerprint::from_key(&self.public_key)
+    }
+
+    /// Returns the public key, serialized compressed.
+    pub fn to_public_key(&self) -> PublicKey {
+        PublicKey::new(self.public_key)
+    }
+
+    /// Returns the hash of the key paid to by P2PKH outputs, the key's identifier.
+    pub fn identifier(&self) -> PubkeyHash {
+        self.to_public_key().pubkey_hash()
+    }
+
+    /// Serializes the key in 78 bytes.
+    pub fn encode(&self) -> [u8; EXTENDED_KEY_LEN] {
+        encode(
+            version(self.network, self.format, false),
+            self.depth,
+            self.parent_fingerprint,
+            self.child_number,
+            self.chain_code,
+            &self.public_key.serialize(),
+        )
+    }
+
+    /// Parses a 78-byte key with public version bytes.
+    pub fn decode(data: &[u8]) -> Result<Self, Bip32Error> {
+        let fields = decode(data, false)?;
+        Ok(Xpub {
+            network: fields.network,
+            format: fields.format,
+            depth: fields.depth,
+            parent_fingerprint: fields.parent_fingerprint,
+            child_number: fields.child_number,
+            public_key: secp256k1::PublicKey::from_slice(fields.key)
+                .map_err(|_| Bip32Error::InvalidKey)?,
+            chain_code: fields.chain_code,
+        })
+    }
+}
+
+impl fmt::Display for Xpub {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        base58::encode_check_to_fmt(f, &self.encode())
+    }
+}
+
+impl FromStr for Xpub {
+    type Err = Bip32Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Xpub::decode(&base58::decode_check(s)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn path(s: &str) -> DerivationPath {
+        s.parse().unwrap()
+    }
+
+    /// Checks the keys of a test vector of BIP32, given as path, xpub and xpriv.
+    fn check_test_vector(seed: &str, vectors: &[(&str, &str, &str)]) {
+        let seed = hex::decode(seed).unwrap();
+        let master = Xpriv::new_master(Network::Bitcoin, &seed).unwrap();
+        for &(path_str, xpub, xpriv) in vectors {
+            let derived = master.derive_priv(path(path_str)).unwrap();
+            assert_eq!(derived.to_string(), xpriv, "{}", path_str);
+            assert_eq!(Xpub::from_priv(&derived).to_string(), xpub, "{}", path_str);
+            assert_eq!(xpriv.parse::<Xpriv>(), Ok(derived));
+            assert_eq!(xpub.parse::<Xpub>(), Ok(Xpub::from_priv(&derived)));
+            assert_eq!(derived.depth as usize, path(path_str).len());
+        }
+    }
+
+    #[test]
+    fn bip32_test_vector_1() {
+        let vectors = [
+            (
+                "m",
+                "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8",
+                "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi",
+            ),
+            (
+                "m/0'",
+                "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw",
+                "xprv9uHRZZhk6KAJC1avXpDAp4MDc3sQKNxDiPvvkX8Br5ngLNv1TxvUxt4cV1rGL5hj6KCesnDYUhd7oWgT11eZG7XnxHrnYeSvkzY7d2bhkJ7",
+            ),
+            (
+                "m/0'/1",
+                "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ",
+                "xprv9wTYmMFdV23N2TdNG573QoEsfRrWKQgWeibmLntzniatZvR9BmLnvSxqu53Kw1UmYPxLgboyZQaXwTCg8MSY3H2EU4pWcQDnRnrVA1xe8fs",
+            ),
+            (
+                "m/0'/1/2'",
+                "xpub6D4BDPcP2GT577Vvch3R8wDkScZWzQzMMUm3PWbmWvVJrZwQY4VUNgqFJPMM3No2dFDFGTsxxpG5uJh7n7epu4trkrX7x7DogT5Uv6fcLW5",
+                "xprv9z4pot5VBttmtdRTWfWQmoH1taj2axGVzFqSb8C9xaxKymcFzXBDptWmT7FwuEzG3ryjH4ktypQSAewRiNMjANTtpgP4mLTj34bhnZX7UiM",
+            ),
+            (
+                "m/0'/1/2'/2",
+                "xpub6FHa3pjLCk84BayeJxFW2SP4XRrFd1JYnxeLeU8EqN3vDfZmbqBqaGJAyiLjTAwm6ZLRQUMv1ZACTj37sR62cfN7fe5JnJ7dh8zL4fiyLHV",
+                "xprvA2JDeKCSNNZky6uBCviVfJSKyQ1mDYahRjijr5idH2WwLsEd4Hsb2Tyh8RfQMuPh7f7RtyzTtdrbdqqsunu5Mm3wDvUAKRHSC34sJ7in334",
+            ),
+            (
+                "m/0'/1/2'/2/1000000000",
+                "xpub6H1LXWLaKsWFhvm6RVpEL9P4KfRZSW7abD2ttkWP3SSQvnyA8FSVqNTEcYFgJS2UaFcxupHiYkro49S8yGasTvXEYBVPamhGW6cFJodrTHy",
+                "xprvA41z7zogVVwxVSgdKUHDy1SKmdb533PjDz7J6N6mV6uS3ze1ai8FHa8kmHScGpWmj4WggLyQjgPie1rFSruoUihUZREPSL39UNdE3BBDu76",
+            ),
+        ];
+        check_test_vector("000102030405060708090a0b0c0d0e0f", &vectors);
+    }
+
+    #[test]
+    fn bip32_test_vector_2() {
+        let vectors = [
+            (
+                "m",
+                "xpub661MyMwAqRbcFW31YEwpkMuc5THy2PSt5bDMsktWQcFF8syAmRUapSCGu8ED9W6oDMSgv6Zz8idoc4a6mr8BDzTJY47LJhkJ8UB7WEGuduB",
+                "xprv9s21ZrQH143K31xYSDQpPDxsXRTUcvj2iNHm5NUtrGiGG5e2DtALGdso3pGz6ssrdK4PFmM8NSpSBHNqPqm55Qn3LqFtT2emdEXVYsCzC2U",
+            ),
+            (
+                "m/0",
+                "xpub69H7F5d8KSRgmmdJg2KhpAK8SR3DjMwAdkxj3ZuxV27CprR9LgpeyGmXUbC6wb7ERfvrnKZjXoUmmDznezpbZb7ap6r1D3tgFxHmwMkQTPH",
+                "xprv9vHkqa6EV4sPZHYqZznhT2NPtPCjKuDKGY38FBWLvgaDx45zo9WQRUT3dKYnjwih2yJD9mkrocEZXo1ex8G81dwSM1fwqWpWkeS3v86pgKt",
+            ),
+            (
+                "m/0/2147483647'",
+                "xpub6ASAVgeehLbnwdqV6UKMHVzgqAG8Gr6riv3Fxxpj8ksbH9ebxaEyBLZ85ySDhKiLDBrQSARLq1uNRts8RuJiHjaDMBU4Zn9h8LZNnBC5y4a",
+                "xprv9wSp6B7kry3Vj9m1zSnLvN3xH8RdsPP1Mh7fAaR7aRLcQMKTR2vidYEeEg2mUCTAwCd6vnxVrcjfy2kRgVsFawNzmjuHc2YmYRmagcEPdU9",
+            ),
+            (
+                "m/0/2147483647'/1",
+                "xpub6DF8uhdarytz3FWdA8TvFSvvAh8dP3283MY7p2V4SeE2wyWmG5mg5EwVvmdMVCQcoNJxGoWaU9DCWh89LojfZ537wTfunKau47EL2dhHKon",
+                "xprv9zFnWC6h2cLgpmSA46vutJzBcfJ8yaJGg8cX1e5StJh45BBciYTRXSd25UEPVuesF9yog62tGAQtHjXajPPdbRCHuWS6T8XA2ECKADdw4Ef",
+            ),
+            (
+                "m/0/2147483647'/1/2147483646'",
+                "xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL",
+                "xprvA1RpRA33e1JQ7ifknakTFpgNXPmW2YvmhqLQYMmrj4xJXXWYpDPS3xz7iAxn8L39njGVyuoseXzU6rcxFLJ8HFsTjSyQbLYnMpCqE2VbFWc",
+            ),
+            (
+                "m/0/2147483647'/1/2147483646'/2",
+                "xpub6FnCn6nSzZAw5Tw7cgR9bi15UV96gLZhjDstkXXxvCLsUXBGXPdSnLFbdpq8p9HmGsApME5hQTZ3emM2rnY5agb9rXpVGyy3bdW6EEgAtqt",
+                "xprvA2nrNbFZABcdryreWet9Ea4LvTJcGsqrMzxHx98MMrotbir7yrKCEXw7nadnHM8Dq38EGfSh6dqA9QWTyefMLEcBYJUuekgW4BYPJcr9E7j",
+            ),
+        ];
+        check_test_vector("fffcf9f6f3f0edeae7e4e1dedbd8d5d2cfccc9c6c3c0bdbab7b4b1aeaba8a5a29f9c999693908d8a8784817e7b7875726f6c696663605d5a5754514e4b484542", &vectors);
+    }
+
+    #[test]
+    fn bip32_test_vector_3() {
+        // Retention of leading zeros in private keys.
+        let vectors = [
+            (
+                "m",
+                "xpub661MyMwAqRbcEZVB4dScxMAdx6d4nFc9nvyvH3v4gJL378CSRZiYmhRoP7mBy6gSPSCYk6SzXPTf3ND1cZAceL7SfJ1Z3GC8vBgp2epUt13",
+                "xprv9s21ZrQH143K25QhxbucbDDuQ4naNntJRi4KUfWT7xo4EKsHt2QJDu7KXp1A3u7Bi1j8ph3EGsZ9Xvz9dGuVrtHHs7pXeTzjuxBrCmmhgC6",
+            ),
+            (
+                "m/0'",
+                "xpub68NZiKmJWnxxS6aaHmn81bvJeTESw724CRDs6HbuccFQN9Ku14VQrADWgqbhhTHBaohPX4CjNLf9fq9MYo6oDaPPLPxSb7gwQN3ih19Zm4Y",
+                "xprv9uPDJpEQgRQfDcW7BkF7eTya6RPxXeJCqCJGHuCJ4GiRVLzkTXBAJMu2qaMWPrS7AANYqdq6vcBcBUdJCVVFceUvJFjaPdGZ2y9WACViL4L",
+            ),
+        ];
+        check_test_vector("4b381541583be4423346c643850da4b320e46a87ae3d2a4e6da11eba819cd4acba45d239319ac14f863b8d5ab5a0d0c64d2e8a1e7d1457df2e5a3c51c73235be", &vectors);
+    }
+
+    #[test]
+    fn bip32_test_vector_4() {
+        // Retention of leading zeros in hardened derivation.
+        let vectors = [
+            (
+                "m",
+                "xpub661MyMwAqRbcGczjuMoRm6dXaLDEhW1u34gKenbeYqAix21mdUKJyuyu5F1rzYGVxyL6tmgBUAEPrEz92mBXjByMRiJdba9wpnN37RLLAXa",
+                "xprv9s21ZrQH143K48vGoLGRPxgo2JNkJ3J3fqkirQC2zVdk5Dgd5w14S7fRDyHH4dWNHUgkvsvNDCkvAwcSHNAQwhwgNMgZhLtQC63zxwhQmRv",
+            ),
+            (
+                "m/0'",
+                "xpub69AUMk3qDBi3uW1sXgjCmVjJ2G6WQoYSnNHyzkmdCHEhSZ4tBok37xfFEqHd2AddP56Tqp4o56AePAgCjYdvpW2PU2jbUPFKsav5ut6Ch1m",
+                "xprv9vB7xEWwNp9kh1wQRfCCQMnZUEG21LpbR9NPCNN1dwhiZkjjeGRnaALmPXCX7SgjFTiCTT6bXes17boXtjq3xLpcDjzEuGLQBM5ohqkao9G",
+            ),
+            (
+                "m/0'/1'",
+                "xpub6BJA1jSqiukeaesWfxe6sNK9CCGaujFFSJLomWHprUL9DePQ4JDkM5d88n49sMGJxrhpjazuXYWdMf17C9T5XnxkopaeS7jGk1GyyVziaMt",
+                "xprv9xJocDuwtYCMNAo3Zw76WENQeAS6WGXQ55RCy7tDJ8oALr4FWkuVoHJeHVAcAqiZLE7Je3vZJHxspZdFHfnBEjHqU5hG1Jaj32dVoS6XLT1",
+            ),
+        ];
+        check_test_vector(
+            "3ddd5602285899a946114506157c7997e5444528f3003f6134712147db19b678",
+            &vectors,
+        );
+    }
+
+    #[test]
+    fn derivation_matches_rust_bitcoin() {
+        let seed = [0x5a; 64];
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let expected_master =
+            bitcoin::bip32::Xpriv::new_master(bitcoin::Network::Testnet, &seed).unwrap();
+        let master = Xpriv::new_master(Network::Testnet, &seed).unwrap();
+        for path_str in ["m/84'/1'/0'/0/5", "m/0/1/2147483647'/7", "m/44h/1h/3'"] {
+            let expected_path: bitcoin::bip32::DerivationPath = path_str.parse().unwrap();
+            let expected = expected_master.derive_priv(&secp, &expected_path).unwrap();
+            let derived = master.derive_priv(path(path_str)).unwrap();
+            assert_eq!(derived.to_string(), expected.to_string());
+            assert_eq!(
+                Xpub::from_priv(&derived).to_string(),
+                bitcoin::bip32::Xpub::from_priv(&secp, &expected).to_string()
+            );
+            assert_eq!(
+                derived.fingerprint().to_bytes(),
+                expected.fingerprint(&secp).to_bytes()
+            );
+            assert_eq!(
+                derived.to_private_key().to_wif(),
+                expected.to_priv().to_wif()
+            );
+        }
+    }
+
+    #[test]
+    fn public_derivation() {
+        let master = Xpriv::new_master(Network::Bitcoin, &[0x01; 32]).unwrap();
+        let account = master.derive_priv(path("m/84'/0'/0'")).unwrap();
+        let account_xpub = Xpub::from_priv(&account);
+        let receive = path("m/0/5");
+        assert_eq!(
+            account_xpub.derive_pub(&receive),
+            Ok(Xpub::from_priv(&account.derive_priv(&receive).unwrap()))
+        );
+        assert_eq!(
+            account_xpub.derive_pub(path("m/0/5'")),
+            Err(Bip32Error::CannotDeriveFromHardenedKey)
+        );
+        let child = account_xpub
+            .ckd_pub(ChildNumber::Normal { index: 0 })
+            .unwrap();
+        assert_eq!(child.parent_fingerprint, account_xpub.fingerprint());
+        assert_eq!(child.fingerprint().to_bytes(), child.identifier()[..4]);
+    }
+
+    #[test]
+    fn slip132_formats() {
+        let master = Xpriv::new_master(Network::Bitcoin, &[0x01; 32]).unwrap();
+        let cases = [
+            (Network::Bitcoin, KeyFormat::Legacy, "xpub", "xprv"),
+            (Network::Bitcoin, KeyFormat::NestedSegwit, "ypub", "yprv"),
+            (Network::Bitcoin, KeyFormat::Segwit, "zpub", "zprv"),
+            (Network::Testnet, KeyFormat::Legacy, "tpub", "tprv"),
+            (Network::Testnet, KeyFormat::NestedSegwit, "upub", "uprv"),
+            (Network::Testnet, KeyFormat::Segwit, "vpub", "vprv"),
+        ];
+        for (network, format, xpub_prefix, xpriv_prefix) in cases {
+            let xpriv = Xpriv {
+                network,
+                format,
+                ..master
+            };
+            let xpub = Xpub::from_priv(&xpriv);
+            assert!(xpriv.to_string().starts_with(xpriv_prefix));
+            assert!(xpub.to_string().starts_with(xpub_prefix));
+            assert_eq!(xpriv.to_string().parse(), Ok(xpriv));
+            assert_eq!(xpub.to_string().parse(), Ok(xpub));
+        }
+        let regtest = Xpriv {
+            network: Network::Regtest,
+            ..master
+        };
+        assert_eq!(
+            regtest.to_string().parse::<Xpriv>().map(|key| key.network),
+            Ok(Network::Testnet)
+        );
+    }
+
+    #[test]
+    fn bip32_test_vector_5() {
+        use Bip32Error::*;
+
+        let vectors = [
+            // pubkey version / prvkey mismatch
+            ("xpub661MyMwAqRbcEYS8w7XLSVeEsBXy79zSzH1J8vCdxAZningWLdN3zgtU6LBpB85b3D2yc8sfvZU521AAwdZafEz7mnzBBsz4wKY5fTtTQBm", InvalidKey),
+            // prvkey version / pubkey mismatch
+            ("xprv9s21ZrQH143K24Mfq5zL5MhWK9hUhhGbd45hLXo2Pq2oqzMMo63oStZzFGTQQD3dC4H2D5GBj7vWvSQaaBv5cxi9gafk7NF3pnBju6dwKvH", InvalidKey),
+            // invalid pubkey prefix 04
+            ("xpub661MyMwAqRbcEYS8w7XLSVeEsBXy79zSzH1J8vCdxAZningWLdN3zgtU6Txnt3siSujt9RCVYsx4qHZGc62TG4McvMGcAUjeuwZdduYEvFn", InvalidKey),
+            // invalid prvkey prefix 04
+            ("xprv9s21ZrQH143K24Mfq5zL5MhWK9hUhhGbd45hLXo2Pq2oqzMMo63oStZzFGpWnsj83BHtEy5Zt8CcDr1UiRXuWCmTQLxEK9vbz5gPstX92JQ", InvalidKey),
+            // invalid pubkey prefix 01
+            ("xpub661MyMwAqRbcEYS8w7XLSVeEsBXy79zSzH1J8vCdxAZningWLdN3zgtU6N8ZMMXctdiCjxTNq964yKkwrkBJJwpzZS4HS2fxvyYUA4q2Xe4", InvalidKey),
+            // invalid prvkey prefix 01
+            ("xprv9s21ZrQH143K24Mfq5zL5MhWK9hUhhGbd45hLXo2Pq2oqzMMo63oStZzFAzHGBP2UuGCqWLTAPLcMtD9y5gkZ6Eq3Rjuahrv17fEQ3Qen6J", InvalidKey),
+            // zero depth with non-zero parent fingerprint
+            ("xprv9s2SPatNQ9Vc6GTbVMFPFo7jsaZySyzk7L8n2uqKXJen3KUmvQNTuLh3fhZMBoG3G4ZW1N2kZuHEPY53qmbZzCHshoQnNf4GvELZfqTUrcv", NonZeroParentFingerprintForMasterKey),
+            ("xpub661no6RGEX3uJkY4bNnPcw4URcQTrSibUZ4NqJEw5eBkv7ovTwgiT91XX27VbEXGENhYRCf7hyEbWrR3FewATdCEebj6znwMfQkhRYHRLpJ", NonZeroParentFingerprintForMasterKey),
+            // zero depth with non-zero index
+            ("xprv9s21ZrQH4r4TsiLvyLXqM9P7k1K3EYhA1kkD6xuquB5i39AU8KF42acDyL3qsDbU9NmZn6MsGSUYZEsuoePmjzsB3eFKSUEh3Gu1N3cqVUN", NonZeroChildNumberForMasterKey),
+            ("xpub661MyMwAuDcm6CRQ5N4qiHKrJ39Xe1R1NyfouMKTTWcguwVcfrZJaNvhpebzGerh7gucBvzEQWRugZDuDXjNDRmXzSZe4c7mnTK97pTvGS8", NonZeroChildNumberForMasterKey),
+            // private key 0 not in 1..n-1
+            ("xprv9s21ZrQH143K24Mfq5zL5MhWK9hUhhGbd45hLXo2Pq2oqzMMo63oStZzF93Y5wvzdUayhgkkFoicQZcP3y52uPPxFnfoLZB21Teqt1VvEHx", InvalidKey),
+            // private key n not in 1..n-1
+            ("xprv9s21ZrQH143K24Mfq5zL5MhWK9hUhhGbd45hLXo2Pq2oqzMMo63oStZzFAzHGBP2UuGCqWLTAPLcMtD5SDKr24z3aiUvKr9bJpdrcLg1y3G", InvalidKey),
+            // invalid pubkey 020000000000000000000000000000000000000000000000000000000000000007
+            ("xpub661MyMwAqRbcEYS8w7XLSVeEsBXy79zSzH1J8vCdxAZningWLdN3zgtU6Q5JXayek4PRsn35jii4veMimro1xefsM58PgBMrvdYre8QyULY", InvalidKey),
+        ];
+        for (key, error) in vectors {
+            let result = if key.starts_with("xpub") {
+                key.parse::<Xpub>().map(|_| ())
+            } else {
+                key.parse::<Xpriv>().map(|_| ())
+            };
+            assert_eq!(result, Err(error), "{}", key);
+        }
+
+        // unknown extended key version
+        for key in [
+            "DMwo58pR1QLEFihHiXPVykYB6fJmsTeHvyTp7hRThAtCX8CvYzgPcn8XnmdfHGMQzT7ayAmfo4z3gY5KfbrZWZ6St24UVf2Qgo6oujFktLHdHY4",
+            "DMwo58pR1QLEFihHiXPVykYB6fJmsTeHvyTp7hRThAtCX8CvYzgPcn8XnmdfHPmHJiEDXkTiJTVV9rHEBUem2mwVbbNfvT2MTcAqj3nesx8uBf9",
+        ] {
+            assert!(matches!(key.parse::<Xpub>(), Err(UnknownVersion(_))));
+            assert!(matches!(key.parse::<Xpriv>(), Err(UnknownVersion(_))));
+        }
+        // invalid checksum
+        assert!(matches!(
+            "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHL".parse::<Xpriv>(),
+            Err(Base58(_))
+        ));
+    }
+
+    #[test]
+    fn depth_and_seed_limits() {
+        let master = Xpriv::new_master(Network::Bitcoin, &[0x01; 32]).unwrap();
+        let deepest = Xpriv {
+            depth: u8::MAX,
+            ..master
+        };
+        let child = ChildNumber::Normal { index: 0 };
+        assert_eq!(
+            deepest.ckd_priv(child),
+            Err(Bip32Error::MaximumDepthExceeded)
+        );
+        assert_eq!(
+            Xpub::from_priv(&deepest).ckd_pub(child),
+            Err(Bip32Error::MaximumDepthExceeded)
+        );
+        assert_eq!(
+            master.derive_priv(vec![child; 256]),
+            Err(Bip32Error::MaximumDepthExceeded)
+        );
+
+        for len in [16, 64] {
+            assert!(Xpriv::new_master(Network::Bitcoin, &vec![0x01; len]).is_ok());
+        }
+        for len in [0, 15, 65] {
+            assert_eq!(
+                Xpriv::new_master(Network::Bitcoin, &vec![0x01; len]),
+                Err(Bip32Error::InvalidSeedLength(len))
+            );
+        }
+    }
+
+    #[test]
+    fn decoding_errors() {
+        let xpriv = Xpriv::new_master(Network::Bitcoin, &[0x01; 32]).unwrap();
+        let xpub = Xpub::from_priv(&xpriv);
+        assert_eq!(
+            xpriv.to_string().parse::<Xpub>(),
+            Err(Bip32Error::UnknownVersion([0x04, 0x88, 0xad, 0xe4]))
+        );
+        assert_eq!(
+            xpub.to_string().parse::<Xpriv>(),
+            Err(Bip32Error::UnknownVersion([0x04, 0x88, 0xb2, 0x1e]))
+        );
+        assert_eq!(
+            Xpub::decode(&xpub.encode()[..77]),
+            Err(Bip32Error::WrongExtendedKeyLength(77))
+        );
+        let mut data = xpub.encode();
+        data[45] = 0x04;
+        assert_eq!(Xpub::decode(&data), Err(Bip32Error::InvalidKey));
+        let mut data = xpriv.encode();
+        data[45] = 0x01;
+        assert_eq!(Xpriv::decode(&data), Err(Bip32Error::InvalidKey));
+        let mut data = xpriv.encode();
+        data[46..].copy_from_slice(&[0; 32]);
+        assert_eq!(Xpriv::decode(&data), Err(Bip32Error::InvalidKey));
+        assert!(matches!(
+            xpub.to_string()[1..].parse::<Xpub>(),
+            Err(Bip32Error::Base58(_))
+        ));
+    }
+
+    #[test]
+    fn derivation_paths() {
+        let parsed = path("m/84'/0'/0'/0/5");
+        assert_eq!(
+            parsed.as_ref(),
+            [
+                ChildNumber::Hardened { index: 84 },
+                ChildNumber::Hardened { index: 0 },
+                ChildNumber::Hardened { index: 0 },
+                ChildNumber::Normal { index: 0 },
+                ChildNumber::Normal { index: 5 },
+            ]
+        );
+        assert_eq!(parsed.to_string(), "m/84'/0'/0'/0/5");
+        assert_eq!(path("84h/0H/0'/0/5"), parsed);
+        assert_eq!(path("m/84'/0'").extend(path("m/0'/0/5")), parsed);
+        assert_eq!(
+            path("m/84'/0'/0'/0").child(ChildNumber::Normal { index: 5 }),
+            parsed
+        );
+        assert!(path("m").is_master());
+        assert_eq!(path(""), DerivationPath::master());
+        assert_eq!(DerivationPath::master().to_string(), "m");
+
+        for invalid in [
+            "m/", "m//0", "m/0/", "/0", "m/a", "m/-1", "m/0''", "n/0", "m/ 1",
+        ] {
+            assert_eq!(
+                invalid.parse::<DerivationPath>(),
+                Err(Bip32Error::InvalidDerivationPathFormat),
+                "{}",
+                invalid
+            );
+        }
+        assert_eq!(
+            "m/2147483648".parse::<DerivationPath>(),
+            Err(Bip32Error::InvalidChildNumber(2147483648))
+        );
+        assert_eq!(
+            "4294967296'".parse::<ChildNumber>(),
+            Err(Bip32Error::InvalidChildNumberFormat)
+        );
+
+        let hardened = ChildNumber::from_hardened_idx(7).unwrap();
+        assert_eq!(ChildNumber::from(hardened.to_u32()), hardened);
+        assert_eq!(hardened.to_u32(), 0x8000_0007);
+        assert_eq!(
+            ChildNumber::from_normal_idx(1 << 31),
+            Err(Bip32Error::InvalidChildNumber(1 << 31))
+        );
+        assert_eq!(
+            "12345678".parse(),
+            Ok(Fingerprint::from([0x12, 0x34, 0x56, 0x78]))
+        );
+        assert_eq!(
+            Fingerprint::from([0xde, 0xad, 0xbe, 0xef]).to_string(),
+            "deadbeef"
+        );
+    }
+}
@@ -0,0 +1,279 @@
+//! Mnemonic codes for seeds (BIP39).
+//!
+//! A [`Mnemonic`] encodes 128 to 256 bits of entropy and a checksum of it as 12 to 24
+//! words of the English wordlist, 11 bits per word. Its seed, stretched from the words
+//! and an optional passphrase, is the seed of an [`Xpriv`](crate::bip32::Xpriv)
+//! master key.
+
+mod english;
+
+use bitcoin_hashes::{hmac, sha256, sha512, Hash, HashEngine};
+use core::fmt;
+use std::str::FromStr;
+use unicode_normalization::UnicodeNormalization;
+
+/// Number of PBKDF2 iterations stretching a mnemonic into its seed.
+const PBKDF2_ROUNDS: u32 = 2048;
+
+/// Ways that creating or parsing a mnemonic might fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Bip39Error {
+    /// The entropy isn't 16, 20, 24, 28 or 32 bytes.
+    BadEntropyLength(usize),
+    /// The mnemonic isn't 12, 15, 18, 21 or 24 words.
+    BadWordCount(usize),
+    /// A word isn't in the wordlist.
+    UnknownWord(String),
+    /// The checksum bits don't match the entropy.
+    InvalidChecksum,
+}
+
+impl fmt::Display for Bip39Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Bip39Error::BadEntropyLength(len) => {
+                write!(f, "invalid entropy length of {} bytes", len)
+            }
+            Bip39Error::BadWordCount(count) => write!(f, "invalid mnemonic of {} words", count),
+            Bip39Error::UnknownWord(ref word) => write!(f, "unknown word: {}", word),
+            Bip39Error::InvalidChecksum => f.write_str("invalid mnemonic checksum"),
+        }
+    }
+}
+
+/// A mnemonic of 12, 15, 18, 21 or 24 English words.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Mnemonic {
+    /// Indexes of the words in the wordlist.
+    words: Vec<u16>,
+}
+
+impl Mnemonic {
+    /// Encodes `entropy`, which must be 16, 20, 24, 28 or 32 bytes for 12, 15, 18, 21
+    /// or 24 words.
+    pub fn from_entropy(entropy: &[u8]) -> Result<Self, Bip39Error> {
+        if !(16..=32).contains(&entropy.len()) || !entropy.len().is_multiple_of(4) {
+            return Err(Bip39Error::BadEntropyLength(entropy.len()));
+        }
+        let checksum = sha256::Hash::hash(entropy).to_byte_array()[0];
+        let word_count = entropy.len() * 3 / 4;
+        let bit = |i: usize| {
+            let byte = entropy.get(i / 8).copied().unwrap_or(checksum);
+            (byte >> (7 - i % 8)) & 1
+        };
+        let words = (0..word_count)
+            .map(|word| (0..11).fold(0, |index, i| index << 1 | bit(word * 11 + i) as u16))
+            .collect();
+        Ok(Mnemonic { words })
+    }
+
+    /// Decodes the entropy the mnemonic encodes, dropping the checksum bits after it.
+    pub fn to_entropy(&self) -> Vec<u8> {
+        let mut bytes = vec![0; (self.words.len() * 11).div_ceil(8)];
+        for (word, &index) in self.words.iter().enumerate() {
+            for i in 0..11 {
+                if index >> (10 - i) & 1 == 1 {
+                    let bit = word * 11 + i;
+                    bytes[bit / 8] |= 0x80 >> (bit % 8);
+                }
+            }
+        }
+        bytes.truncate(self.words.len() * 4 / 3);
+        bytes
+    }
+
+    /// Returns the number of words.
+    pub fn word_count(&self) -> usize {
+        self.words.len()
+    }
+
+    /// Iterates over the words.
+    pub fn words(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.words
+            .iter()
+            .map(|&index| english::WORDS[index as usize])
+    }
+
+    /// Stretches the mnemonic and `passphrase` into a 64-byte seed with
+    /// PBKDF2-HMAC-SHA512, as the seed of a BIP32 master key.
+    ///
+    /// The passphrase is normalized to Unicode NFKD first as BIP39 requires, so that
+    /// differently composed forms of the same text give the same seed.
+    pub fn to_seed(&self, passphrase: &str) -> [u8; 64] {
+        let password = self.to_string();
+        let mut salt = b"mnemonic".to_vec();
+        salt.extend(passphrase.nfkd().collect::<String>().bytes());
+        pbkdf2_hmac_sha512(password.as_bytes(), &salt, PBKDF2_ROUNDS)
+    }
+}
+
+/// Derives the first 64-byte block of PBKDF2 with HMAC-SHA512, all of a BIP39 seed.
+fn pbkdf2_hmac_sha512(password: &[u8], salt: &[u8], rounds: u32) -> [u8; 64] {
+    let keyed = hmac::HmacEngine::<sha512::Hash>::new(password);
+    let mut engine = keyed.clone();
+    engine.input(salt);
+    engine.input(&1u32.to_be_bytes());
+    let mut block = hmac::Hmac::from_engine(engine).to_byte_array();
+    let mut result = block;
+    for _ in 1..rounds {
+        let mut engine = keyed.clone();
+        engine.input(&block);
+        block = hmac::Hmac::from_engine(engine).to_byte_array();
+        for (result, byte) in result.iter_mut().zip(block) {
+            *result ^= byte;
+        }
+    }
+    result
+}
+
+impl fmt::Display for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, word) in self.words().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            f.write_str(word)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Mnemonic {
+    type Err = Bip39Error;
+
+    /// Parses words separated by whitespace, checking their checksum.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words = s
+            .split_whitespace()
+            .map(|word| {
+                english::WORDS
+                    .binary_search(&word)
+                    .map(|index| index as u16)
+                    .map_err(|_| Bip39Error::UnknownWord(word.to_owned()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if !(12..=24).contains(&words.len()) || !words.len().is_multiple_of(3) {
+            return Err(Bip39Error::BadWordCount(words.len()));
+        }
+        let mnemonic = Mnemonic { words };
+        // Encoding the entropy again gives the same words only if the checksum matches.
+        if Mnemonic::from_entropy(&mnemonic.to_entropy())? != mnemonic {
+            return Err(Bip39Error::InvalidChecksum);
+        }
+        Ok(mnemonic)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bip32::{DerivationPath, KeyFormat, Xpriv, Xpub};
+    use crate::types::Network;
+
+    #[test]
+    fn bip39_vectors() {
+        let vectors: serde_json::Value =
+            serde_json::from_str(include_str!("../../tests/data/bip39_vectors.json")).unwrap();
+        for vector in vectors["english"].as_array().unwrap() {
+            let field = |i: usize| vector[i].as_str().unwrap();
+            let entropy = hex::decode(field(0)).unwrap();
+            let mnemonic = Mnemonic::from_entropy(&entropy).unwrap();
+            assert_eq!(mnemonic.to_string(), field(1));
+            assert_eq!(field(1).parse(), Ok(mnemonic.clone()));
+            assert_eq!(mnemonic.to_entropy(), entropy);
+            assert_eq!(mnemonic.word_count(), field(1).split(' ').count());
+
+            let seed = mnemonic.to_seed("TREZOR");
+            assert_eq!(hex::encode(seed), field(2));
+            assert_eq!(
+                Xpriv::new_master(Network::Bitcoin, &seed)
+                    .unwrap()
+                    .to_string(),
+                field(3)
+            );
+        }
+    }
+
+    #[test]
+    fn non_ascii_passphrase() {
+        let mnemonic: Mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about".parse().unwrap();
+        // Composed characters and compatibility characters, which NFKD decomposes into
+        // "A\u{30a}ngstro\u{308}m メートル fi".
+        let seed = mnemonic.to_seed("Ångström ㍍ ﬁ");
+        assert_eq!(hex::encode(seed), "025d03f06d0dcbf9f6d6b1ab46dc9010df8f6594c61ad476972344af4bf1470057e3c9e56fb7156949ad8345e1843b62c8c8b31129d2211c078174b8bdb098b1");
+        assert_eq!(
+            mnemonic.to_seed("A\u{30a}ngstro\u{308}m \u{30e1}\u{30fc}\u{30c8}\u{30eb} fi"),
+            seed
+        );
+    }
+
+    #[test]
+    fn bip84_account() {
+        let mnemonic: Mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about".parse().unwrap();
+        let master = Xpriv {
+            format: KeyFormat::Segwit,
+            ..Xpriv::new_master(Network::Bitcoin, &mnemonic.to_seed("")).unwrap()
+        };
+        assert_eq!(master.to_string(), "zprvAWgYBBk7JR8Gjrh4UJQ2uJdG1r3WNRRfURiABBE3RvMXYSrRJL62XuezvGdPvG6GFBZduosCc1YP5wixPox7zhZLfiUm8aunE96BBa4Kei5");
+        let path: DerivationPath = "m/84'/0'/0'".parse().unwrap();
+        let account = master.derive_priv(path).unwrap();
+        assert_eq!(Xpub::from_priv(&account).to_string(), "zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYs");
+    }
+
+    #[test]
+    fn all_lengths() {
+        for len in [16, 20, 24, 28, 32] {
+            let entropy: Vec<u8> = (0..len as u8).map(|i| i.wrapping_mul(37)).collect();
+            let mnemonic = Mnemonic::from_entropy(&entropy).unwrap();
+            assert_eq!(mnemonic.word_count(), len * 3 / 4);
+            assert_eq!(mnemonic.to_string().parse(), Ok(mnemonic.clone()));
+            assert_eq!(mnemonic.to_entropy(), entropy);
+        }
+        for len in [0, 12, 15, 17, 36] {
+            assert_eq!(
+                Mnemonic::from_entropy(&vec![0; len]),
+                Err(Bip39Error::BadEntropyLength(len))
+            );
+        }
+    }
+
+    #[test]
+    fn parsing_errors() {
+        let abandon = |count: usize| vec!["abandon"; count].join(" ");
+        assert_eq!(
+            format!("{} about", abandon(11))
+                .parse::<Mnemonic>()
+                .map(|m| m.to_entropy()),
+            Ok(vec![0; 16])
+        );
+        assert_eq!(
+            format!("  {}\n about ", abandon(11))
+                .parse::<Mnemonic>()
+                .map(|m| m.word_count()),
+            Ok(12)
+        );
+        assert_eq!(
+            abandon(12).parse::<Mnemonic>(),
+            Err(Bip39Error::InvalidChecksum)
+        );
+        assert_eq!(
+            format!("{} zoo", abandon(11)).parse::<Mnemonic>(),
+            Err(Bip39Error::InvalidChecksum)
+        );
+        for count in [0, 9, 11, 13, 27] {
+            assert_eq!(
+                abandon(count).parse::<Mnemonic>(),
+                Err(Bip39Error::BadWordCount(count))
+            );
+        }
+        assert_eq!(
+            format!("{} Abandon", abandon(11)).parse::<Mnemonic>(),
+            Err(Bip39Error::UnknownWord("Abandon".to_owned()))
+        );
+        assert_eq!(
+            format!("{} bitcoin", abandon(11)).parse::<Mnemonic>(),
+            Err(Bip39Error::UnknownWord("bitcoin".to_owned()))
+        );
+    }
+}
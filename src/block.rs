@@ -0,0 +1,188 @@
+//! Bitcoin block headers.
+
+use crate::pow::{Target, Work};
+use crate::types::Network;
+use bitcoin_hashes::{hash_newtype, sha256d, Hash};
+use std::str::FromStr;
+
+hash_newtype! {
+    /// A bitcoin block hash.
+    pub struct BlockHash(sha256d::Hash);
+    /// The merkle root of the transactions in a block.
+    pub struct TxMerkleNode(sha256d::Hash);
+}
+
+/// Size of a serialized block header in bytes.
+pub const BLOCK_HEADER_SIZE: usize = 80;
+
+/// A bitcoin block header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockHeader {
+    /// Block version, now repurposed for soft fork signalling.
+    pub version: i32,
+    /// Hash of the previous block header.
+    pub prev_blockhash: BlockHash,
+    /// Merkle root of the block's transactions.
+    pub merkle_root: TxMerkleNode,
+    /// Block timestamp in seconds since the unix epoch.
+    pub time: u32,
+    /// The target the block hash must not exceed, in compact form.
+    pub bits: u32,
+    /// Nonce chosen by the miner.
+    pub nonce: u32,
+}
+
+impl BlockHeader {
+    /// Returns the genesis block header of `network`.
+    pub fn genesis(network: Network) -> BlockHeader {
+        let merkle_root = TxMerkleNode::from_str(
+            "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b",
+        )
+        .expect("valid hash");
+        let (time, bits, nonce) = match network {
+            Network::Bitcoin => (1231006505, 0x1d00ffff, 2083236893),
+            Network::Testnet => (1296688602, 0x1d00ffff, 414098458),
+            Network::Signet => (1598918400, 0x1e0377ae, 52613770),
+            Network::Regtest => (1296688602, 0x207fffff, 2),
+        };
+
+        BlockHeader {
+            version: 1,
+            prev_blockhash: BlockHash::all_zeros(),
+            merkle_root,
+            time,
+            bits,
+            nonce,
+        }
+    }
+
+    /// Decodes a header from its 80 byte consensus serialization.
+    pub fn from_bytes(bytes: &[u8]) -> Result<BlockHeader, &'static str> {
+        if bytes.len() != BLOCK_HEADER_SIZE {
+            return Err("invalid block header length");
+        }
+
+        let u32_at = |pos: usize| u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap());
+
+        Ok(BlockHeader {
+            version: u32_at(0) as i32,
+            prev_blockhash: BlockHash::from_slice(&bytes[4..36]).unwrap(),
+            merkle_root: TxMerkleNode::from_slice(&bytes[36..68]).unwrap(),
+            time: u32_at(68),
+            bits: u32_at(72),
+            nonce: u32_at(76),
+        })
+    }
+
+    /// Decodes a header from a hex string.
+    pub fn from_hex(hex_header: &str) -> Result<BlockHeader, &'static str> {
+        let bytes = hex::decode(hex_header).map_err(|_| "invalid hex")?;
+        BlockHeader::from_bytes(&bytes)
+    }
+
+    /// Returns the 80 byte consensus serialization of the header.
+    pub fn to_bytes(&self) -> [u8; BLOCK_HEADER_SIZE] {
+        let mut bytes = [0u8; BLOCK_HEADER_SIZE];
+        bytes[0..4].copy_from_slice(&self.version.to_le_bytes());
+        bytes[4..36].copy_from_slice(self.prev_blockhash.as_byte_array());
+        bytes[36..68].copy_from_slice(self.merkle_root.as_byte_array());
+        bytes[68..72].copy_from_slice(&self.time.to_le_bytes());
+        bytes[72..76].copy_from_slice(&self.bits.to_le_bytes());
+        bytes[76..80].copy_from_slice(&self.nonce.to_le_bytes());
+        bytes
+    }
+
+    /// Encodes the header as a hex string.
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.to_bytes())
+    }
+
+    /// Returns the hash of the header.
+    pub fn block_hash(&self) -> BlockHash {
+        BlockHash::hash(&self.to_bytes())
+    }
+
+    /// Returns the target encoded in `bits`, or `None` if the encoding is invalid.
+    pub fn target(&self) -> Option<Target> {
+        Target::from_compact(self.bits)
+    }
+
+    /// Returns the work this header contributes to its chain.
+    pub fn work(&self) -> Work {
+        self.target().unwrap_or(Target::ZERO).to_work()
+    }
+
+    /// Returns true if the header hash meets the target encoded in `bits`.
+    pub fn has_valid_proof_of_work(&self) -> bool {
+        match self.target() {
+            Some(target) if target != Target::ZERO => {
+                target.is_met_by(self.block_hash().to_byte_array())
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const BLOCK_1_HEADER: &str = "010000006fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000982051fd1e4ba744bbbe680e1fee14677ba1a3c3540bf7b1cdb606e857233e0e61bc6649ffff001d01e36299";
+
+    #[test]
+    fn genesis_block_hashes() {
+        let test_cases = vec![
+            (
+                Network::Bitcoin,
+                "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f",
+            ),
+            (
+                Network::Testnet,
+                "000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943",
+            ),
+            (
+                Network::Signet,
+                "00000008819873e925422c1ff0f99f7cc9bbb232af63a077a480a3633bee1ef6",
+            ),
+            (
+                Network::Regtest,
+                "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206",
+            ),
+        ];
+
+        for (network, expected_hash) in test_cases {
+            let genesis = BlockHeader::genesis(network);
+            assert_eq!(genesis.block_hash().to_string(), expected_hash);
+            assert!(genesis.has_valid_proof_of_work());
+        }
+    }
+
+    #[test]
+    fn decode_encode_hex() {
+        let header = BlockHeader::from_hex(BLOCK_1_HEADER).unwrap();
+
+        assert_eq!(header.version, 1);
+        assert_eq!(
+            header.prev_blockhash,
+            BlockHeader::genesis(Network::Bitcoin).block_hash()
+        );
+        assert_eq!(header.time, 1231469665);
+        assert_eq!(header.bits, 0x1d00ffff);
+        assert_eq!(
+            header.block_hash().to_string(),
+            "00000000839a8e6886ab5951d76f411475428afc90947ee320161bbf18eb6048"
+        );
+        assert_eq!(header.to_hex(), BLOCK_1_HEADER);
+
+        assert!(BlockHeader::from_hex(&BLOCK_1_HEADER[2..]).is_err());
+    }
+
+    #[test]
+    fn invalid_proof_of_work() {
+        let mut header = BlockHeader::from_hex(BLOCK_1_HEADER).unwrap();
+        assert!(header.has_valid_proof_of_work());
+
+        header.nonce += 1;
+        assert!(!header.has_valid_proof_of_work());
+    }
+}
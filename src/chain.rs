@@ -0,0 +1,510 @@
+//! Header chain validation.
+//!
+//! [`HeaderChain`] accepts block headers on top of a genesis header, checking that each
+//! one links to a known header, carries valid proof of work, is newer than the median
+//! time of the previous eleven blocks and commits to the expected difficulty. Forks
+//! are kept and the tip with the most cumulative work is selected as the best chain.
+
+use crate::block::{BlockHash, BlockHeader};
+use crate::constants::{DIFFCHANGE_INTERVAL, TARGET_BLOCK_SPACING};
+use crate::params::Params;
+use crate::pow::{calculate_next_work_required, Target, Work};
+use bitcoin_hashes::Hash;
+use core::fmt;
+use std::collections::HashMap;
+
+/// Number of previous blocks whose median timestamp a new block must exceed.
+const MEDIAN_TIME_SPAN: usize = 11;
+
+/// A validated header together with its position in the chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainEntry {
+    /// The block header.
+    pub header: BlockHeader,
+    /// Height of the block, the genesis block being at height zero.
+    pub height: u32,
+    /// Total work of the chain up to and including this block.
+    pub chainwork: Work,
+}
+
+/// Ways header validation might fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HeaderChainError {
+    /// The header's previous block is not part of the chain.
+    UnknownPrevBlock(BlockHash),
+    /// The `bits` field does not encode a valid target within the proof-of-work limit.
+    BadTarget(u32),
+    /// The header hash does not meet its target.
+    HighHash(BlockHash),
+    /// The `bits` field differs from the difficulty required at this height.
+    BadDifficultyBits { expected: u32, actual: u32 },
+    /// The timestamp is not greater than the median time past of the previous block.
+    TimeTooOld { time: u32, median_time_past: u32 },
+}
+
+impl fmt::Display for HeaderChainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HeaderChainError::UnknownPrevBlock(ref hash) => {
+                write!(f, "previous block {} is unknown", hash)
+            }
+            HeaderChainError::BadTarget(bits) => write!(f, "invalid target bits {:#010x}", bits),
+            HeaderChainError::HighHash(ref hash) => {
+                write!(f, "block hash {} does not meet its target", hash)
+            }
+            HeaderChainError::BadDifficultyBits { expected, actual } => write!(
+                f,
+                "incorrect difficulty bits {:#010x}, expected {:#010x}",
+                actual, expected
+            ),
+            HeaderChainError::TimeTooOld {
+                time,
+                median_time_past,
+            } => write!(
+                f,
+                "block time {} is not after median time past {}",
+                time, median_time_past
+            ),
+        }
+    }
+}
+
+/// A tree of validated block headers rooted at a genesis header.
+#[derive(Debug, Clone)]
+pub struct HeaderChain {
+    params: Params,
+    entries: HashMap<BlockHash, ChainEntry>,
+    best_tip: BlockHash,
+}
+
+impl HeaderChain {
+    /// Creates a chain starting at the genesis block of the network in `params`.
+    pub fn new(params: Params) -> Self {
+        let genesis = BlockHeader::genesis(params.network);
+        HeaderChain::with_genesis(params, genesis)
+    }
+
+    /// Creates a chain starting at a custom genesis header, which is trusted as is.
+    pub fn with_genesis(params: Params, genesis: BlockHeader) -> Self {
+        let hash = genesis.block_hash();
+        let entry = ChainEntry {
+            header: genesis,
+            height: 0,
+            chainwork: genesis.work(),
+        };
+
+        let mut entries = HashMap::new();
+        entries.insert(hash, entry);
+
+        HeaderChain {
+            params,
+            entries,
+            best_tip: hash,
+        }
+    }
+
+    /// Returns the consensus parameters the chain is validated against.
+    pub fn params(&self) -> &Params {
+        &self.params
+    }
+
+    /// Returns the entry of a known header.
+    pub fn get(&self, hash: &BlockHash) -> Option<&ChainEntry> {
+        self.entries.get(hash)
+    }
+
+    /// Returns true if the header is part of the chain, on any branch.
+    pub fn contains(&self, hash: &BlockHash) -> bool {
+        self.entries.contains_key(hash)
+    }
+
+    /// Returns the tip of the chain with the most cumulative work.
+    pub fn best_tip(&self) -> &ChainEntry {
+        &self.entries[&self.best_tip]
+    }
+
+    /// Returns the hash of the tip of the best chain.
+    pub fn best_hash(&self) -> BlockHash {
+        self.best_tip
+    }
+
+    /// Returns the height of the best chain.
+    pub fn best_height(&self) -> u32 {
+        self.best_tip().height
+    }
+
+    /// Returns the number of headers known, across all branches. This includes the
+    /// genesis header, so it is never zero.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if only the genesis header is known.
+    pub fn has_only_genesis(&self) -> bool {
+        self.entries.len() == 1
+    }
+
+    /// Returns the ancestor of `hash` at `height` following its own branch.
+    pub fn ancestor(&self, hash: &BlockHash, height: u32) -> Option<&ChainEntry> {
+        let mut entry = self.entries.get(hash)?;
+        if height > entry.height {
+            return None;
+        }
+        while entry.height > height {
+            entry = &self.entries[&entry.header.prev_blockhash];
+        }
+        Some(entry)
+    }
+
+    /// Returns the median timestamp of the eleven blocks ending at `hash`.
+    pub fn median_time_past(&self, hash: &BlockHash) -> Option<u32> {
+        let mut entry = self.entries.get(hash)?;
+        let mut times = Vec::with_capacity(MEDIAN_TIME_SPAN);
+        loop {
+            times.push(entry.header.time);
+            if times.len() == MEDIAN_TIME_SPAN || entry.height == 0 {
+                break;
+            }
+            entry = &self.entries[&entry.header.prev_blockhash];
+        }
+        times.sort_unstable();
+        Some(times[times.len() / 2])
+    }
+
+    /// Returns the compact target required for a block with timestamp `time` built on `prev`.
+    pub fn next_work_required(&self, prev: &BlockHash, time: u32) -> Option<u32> {
+        let last = self.entries.get(prev)?;
+        let pow_limit_bits = self.params.pow_limit.to_compact_lossy();
+        let interval = DIFFCHANGE_INTERVAL;
+
+        if (last.height + 1) % interval != 0 {
+            if !self.params.allow_min_difficulty_blocks {
+                return Some(last.header.bits);
+            }
+
+            // A block arriving more than 20 minutes after its predecessor may be mined
+            // at the minimum difficulty.
+            if time as i64 > last.header.time as i64 + 2 * TARGET_BLOCK_SPACING as i64 {
+                return Some(pow_limit_bits);
+            }
+
+            // Otherwise use the difficulty of the last block that wasn't a minimum
+            // difficulty exception.
+            let mut entry = last;
+            while entry.height % interval != 0 && entry.header.bits == pow_limit_bits {
+                entry = &self.entries[&entry.header.prev_blockhash];
+            }
+            return Some(entry.header.bits);
+        }
+
+        if self.params.no_pow_retargeting {
+            return Some(last.header.bits);
+        }
+
+        let first = self.ancestor(prev, last.height + 1 - interval)?;
+        Some(calculate_next_work_required(
+            last.header.bits,
+            first.header.time,
+            last.header.time,
+            &self.params,
+        ))
+    }
+
+    /// Validates a header and adds it to the chain, returning its hash.
+    ///
+    /// Headers that are already known are accepted again without any change. If the
+    /// header extends a branch past the current best tip's cumulative work it becomes
+    /// the new best tip.
+    pub fn accept_header(&mut self, header: BlockHeader) -> Result<BlockHash, HeaderChainError> {
+        let hash = header.block_hash();
+        if self.entries.contains_key(&hash) {
+            return Ok(hash);
+        }
+
+        let prev = self
+            .entries
+            .get(&header.prev_blockhash)
+            .ok_or(HeaderChainError::UnknownPrevBlock(header.prev_blockhash))?;
+        let (prev_height, prev_chainwork) = (prev.height, prev.chainwork);
+
+        let target = match header.target() {
+            Some(target) if target != Target::ZERO && target <= self.params.pow_limit => target,
+            _ => return Err(HeaderChainError::BadTarget(header.bits)),
+        };
+        if !target.is_met_by(hash.to_byte_array()) {
+            return Err(HeaderChainError::HighHash(hash));
+        }
+
+        let expected = self
+            .next_work_required(&header.prev_blockhash, header.time)
+            .expect("previous block is known");
+        if header.bits != expected {
+            return Err(HeaderChainError::BadDifficultyBits {
+                expected,
+                actual: header.bits,
+            });
+        }
+
+        let median_time_past = self
+            .median_time_past(&header.prev_blockhash)
+            .expect("previous block is known");
+        if header.time <= median_time_past {
+            return Err(HeaderChainError::TimeTooOld {
+                time: header.time,
+                median_time_past,
+            });
+        }
+
+        let entry = ChainEntry {
+            header,
+            height: prev_height + 1,
+            chainwork: prev_chainwork + target.to_work(),
+        };
+        if entry.chainwork > self.best_tip().chainwork {
+            self.best_tip = hash;
+        }
+        self.entries.insert(hash, entry);
+
+        Ok(hash)
+    }
+
+    /// Validates and adds a sequence of headers, stopping at the first invalid one.
+    pub fn accept_headers(&mut self, headers: &[BlockHeader]) -> Result<(), HeaderChainError> {
+        for header in headers {
+            self.accept_header(*header)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::TxMerkleNode;
+    use crate::types::Network;
+
+    const BLOCK_1_HEADER: &str = "010000006fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000982051fd1e4ba744bbbe680e1fee14677ba1a3c3540bf7b1cdb606e857233e0e61bc6649ffff001d01e36299";
+    const BLOCK_2_HEADER: &str = "010000004860eb18bf1b1620e37e9490fc8a427514416fd75159ab86688e9a8300000000d5fdcc541e25de1c7a5addedf24858b8bb665c9f36ef744ee42c316022c90f9bb0bc6649ffff001d08d2bd61";
+
+    fn mine(prev: BlockHash, time: u32, bits: u32) -> BlockHeader {
+        let mut header = BlockHeader {
+            version: 4,
+            prev_blockhash: prev,
+            merkle_root: TxMerkleNode::all_zeros(),
+            time,
+            bits,
+            nonce: 0,
+        };
+        while !header.has_valid_proof_of_work() {
+            header.nonce += 1;
+        }
+        header
+    }
+
+    fn extend(chain: &mut HeaderChain, from: BlockHash, count: u32, spacing: u32) -> BlockHash {
+        let mut tip = from;
+        for _ in 0..count {
+            let prev = chain.get(&tip).unwrap().header;
+            let time = prev.time + spacing;
+            let bits = chain.next_work_required(&tip, time).unwrap();
+            tip = chain.accept_header(mine(tip, time, bits)).unwrap();
+        }
+        tip
+    }
+
+    /// Regtest-like parameters that still retarget, so difficulty changes can be
+    /// exercised without real proof of work.
+    fn retargeting_params(allow_min_difficulty_blocks: bool) -> Params {
+        Params {
+            network: Network::Regtest,
+            pow_limit: Target::MAX_ATTAINABLE_REGTEST,
+            allow_min_difficulty_blocks,
+            no_pow_retargeting: false,
+            subsidy_halving_interval: 150,
+        }
+    }
+
+    #[test]
+    fn accepts_mainnet_headers() {
+        let mut chain = HeaderChain::new(Params::new(Network::Bitcoin));
+        assert_eq!(chain.len(), 1);
+        assert!(chain.has_only_genesis());
+        let headers = vec![
+            BlockHeader::from_hex(BLOCK_1_HEADER).unwrap(),
+            BlockHeader::from_hex(BLOCK_2_HEADER).unwrap(),
+        ];
+
+        chain.accept_headers(&headers).unwrap();
+        assert_eq!(chain.best_height(), 2);
+        assert_eq!(
+            chain.best_hash().to_string(),
+            "000000006a625f06636b8bb6ac7b960a8d03705d1ace08b1a19da3fdcc99ddbd"
+        );
+        let mut expected_work = [0u8; 32];
+        expected_work[27] = 3;
+        expected_work[29] = 3;
+        expected_work[31] = 3;
+        assert_eq!(chain.best_tip().chainwork.to_be_bytes(), expected_work);
+
+        // accepting known headers again is a no-op
+        chain.accept_headers(&headers).unwrap();
+        assert_eq!(chain.len(), 3);
+        assert!(!chain.has_only_genesis());
+    }
+
+    #[test]
+    fn rejects_unlinked_header() {
+        let mut chain = HeaderChain::new(Params::new(Network::Bitcoin));
+        let block_2 = BlockHeader::from_hex(BLOCK_2_HEADER).unwrap();
+
+        assert_eq!(
+            chain.accept_header(block_2),
+            Err(HeaderChainError::UnknownPrevBlock(block_2.prev_blockhash))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_proof_of_work() {
+        let mut chain = HeaderChain::new(Params::new(Network::Bitcoin));
+        let mut block_1 = BlockHeader::from_hex(BLOCK_1_HEADER).unwrap();
+        block_1.nonce += 1;
+        assert_eq!(
+            chain.accept_header(block_1),
+            Err(HeaderChainError::HighHash(block_1.block_hash()))
+        );
+
+        // a target easier than the proof-of-work limit
+        let genesis = chain.best_hash();
+        let header = mine(genesis, 1231006506, 0x207fffff);
+        assert_eq!(
+            chain.accept_header(header),
+            Err(HeaderChainError::BadTarget(0x207fffff))
+        );
+    }
+
+    #[test]
+    fn rejects_time_before_median_time_past() {
+        let mut chain = HeaderChain::new(Params::new(Network::Regtest));
+        let genesis = chain.best_hash();
+        let tip = extend(&mut chain, genesis, 11, 600);
+
+        let median_time_past = chain.median_time_past(&tip).unwrap();
+        assert_eq!(
+            median_time_past,
+            chain.ancestor(&tip, 6).unwrap().header.time
+        );
+
+        let header = mine(tip, median_time_past, 0x207fffff);
+        assert_eq!(
+            chain.accept_header(header),
+            Err(HeaderChainError::TimeTooOld {
+                time: median_time_past,
+                median_time_past
+            })
+        );
+        chain
+            .accept_header(mine(tip, median_time_past + 1, 0x207fffff))
+            .unwrap();
+    }
+
+    #[test]
+    fn regtest_never_retargets() {
+        let mut chain = HeaderChain::new(Params::new(Network::Regtest));
+        let genesis = chain.best_hash();
+        let tip = extend(&mut chain, genesis, DIFFCHANGE_INTERVAL + 1, 1);
+
+        assert_eq!(chain.best_height(), DIFFCHANGE_INTERVAL + 1);
+        assert_eq!(chain.best_tip().header.bits, 0x207fffff);
+        assert_eq!(chain.best_hash(), tip);
+    }
+
+    #[test]
+    fn retargets_at_period_boundary() {
+        let mut chain = HeaderChain::new(retargeting_params(false));
+        let genesis = chain.best_hash();
+        // blocks twice as fast as expected
+        let tip = extend(
+            &mut chain,
+            genesis,
+            DIFFCHANGE_INTERVAL - 1,
+            TARGET_BLOCK_SPACING / 2,
+        );
+
+        let time = chain.get(&tip).unwrap().header.time + 300;
+        let expected = chain.next_work_required(&tip, time).unwrap();
+        let first_time = chain.ancestor(&tip, 0).unwrap().header.time;
+        assert_eq!(
+            expected,
+            calculate_next_work_required(0x207fffff, first_time, time - 300, chain.params())
+        );
+        assert_eq!(expected, 0x203ff7de);
+
+        let header = mine(tip, time, 0x207fffff);
+        assert_eq!(
+            chain.accept_header(header),
+            Err(HeaderChainError::BadDifficultyBits {
+                expected,
+                actual: 0x207fffff
+            })
+        );
+
+        chain.accept_header(mine(tip, time, expected)).unwrap();
+        assert_eq!(chain.best_height(), DIFFCHANGE_INTERVAL);
+    }
+
+    #[test]
+    fn min_difficulty_blocks() {
+        let mut chain = HeaderChain::new(retargeting_params(true));
+        let genesis = chain.best_hash();
+        let tip = extend(
+            &mut chain,
+            genesis,
+            DIFFCHANGE_INTERVAL,
+            TARGET_BLOCK_SPACING / 2,
+        );
+        let retarget_bits = chain.get(&tip).unwrap().header.bits;
+        assert_ne!(retarget_bits, 0x207fffff);
+
+        // more than 20 minutes later the minimum difficulty is allowed
+        let time = chain.get(&tip).unwrap().header.time + 2 * TARGET_BLOCK_SPACING + 1;
+        assert_eq!(chain.next_work_required(&tip, time), Some(0x207fffff));
+        let tip = chain.accept_header(mine(tip, time, 0x207fffff)).unwrap();
+
+        // the next block returns to the last real difficulty
+        let time = time + 1;
+        assert_eq!(chain.next_work_required(&tip, time), Some(retarget_bits));
+        let header = mine(tip, time, 0x207fffff);
+        assert_eq!(
+            chain.accept_header(header),
+            Err(HeaderChainError::BadDifficultyBits {
+                expected: retarget_bits,
+                actual: 0x207fffff
+            })
+        );
+        chain.accept_header(mine(tip, time, retarget_bits)).unwrap();
+    }
+
+    #[test]
+    fn selects_chain_with_most_work() {
+        let mut chain = HeaderChain::new(Params::new(Network::Regtest));
+        let genesis = chain.best_hash();
+
+        let short_tip = extend(&mut chain, genesis, 3, 600);
+        assert_eq!(chain.best_hash(), short_tip);
+
+        let fork_tip = extend(&mut chain, genesis, 3, 601);
+        // equal work keeps the first seen tip
+        assert_eq!(chain.best_hash(), short_tip);
+
+        let fork_tip = extend(&mut chain, fork_tip, 1, 601);
+        assert_eq!(chain.best_hash(), fork_tip);
+        assert_eq!(chain.best_height(), 4);
+        assert!(chain.contains(&short_tip));
+        assert_eq!(
+            chain.get(&fork_tip).unwrap().chainwork,
+            chain.get(&short_tip).unwrap().chainwork + Target::MAX_ATTAINABLE_REGTEST.to_work()
+        );
+    }
+}
@@ -24,6 +24,20 @@ pub const PUBKEY_ADDRESS_PREFIX_TEST: u8 = 111; // 0x6f
 pub const SCRIPT_ADDRESS_PREFIX_TEST: u8 = 196; // 0xc4
 /// The maximum allowed script size.
 pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+/// The maximum allowed size of a legacy or segwit version 0 script.
+pub const MAX_SCRIPT_SIZE: usize = 10_000;
+/// The maximum number of non-push operations per legacy or segwit version 0 script.
+pub const MAX_OPS_PER_SCRIPT: usize = 201;
+/// The maximum number of public keys per `OP_CHECKMULTISIG`.
+pub const MAX_PUBKEYS_PER_MULTISIG: usize = 20;
+/// The maximum number of elements on the stack and alt stack combined.
+pub const MAX_STACK_SIZE: usize = 1000;
+/// The validation weight consumed by each executed tapscript signature check (BIP342).
+pub const VALIDATION_WEIGHT_PER_SIGOP_PASSED: i64 = 50;
+/// The validation weight a tapscript gets on top of the size of its witness (BIP342).
+pub const VALIDATION_WEIGHT_OFFSET: i64 = 50;
+/// Lock times below this are block heights, lock times at or above it are unix timestamps.
+pub const LOCKTIME_THRESHOLD: u32 = 500_000_000;
 /// How may blocks between halvings.
 pub const SUBSIDY_HALVING_INTERVAL: u32 = 210_000;
 /// Maximum allowed value for an integer in Script.
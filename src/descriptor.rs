@@ -0,0 +1,1114 @@
+//! Output script descriptors (BIP380 to BIP386).
+//!
+//! A [`Descriptor`] describes the output scripts of a wallet in a form Bitcoin Core
+//! imports and exports, like `wpkh([d34db33f/84'/0'/0']xpub.../0/*)#checksum`. Keys
+//! may be extended keys ending in a `/*` wildcard, which are derived at an index to
+//! give one script of a range.
+//!
+//! Supported are `pkh`, `wpkh`, `sh(wpkh)`, `tr` with a key path only, and `multi` and
+//! `sortedmulti` inside `sh`, `wsh` or `sh(wsh)`.
+
+use bitcoin_hashes::Hash;
+use core::fmt;
+use secp256k1::Parity;
+use std::str::FromStr;
+
+use crate::address::BitcoinAddress;
+use crate::bip32::{Bip32Error, ChildNumber, DerivationPath, Fingerprint, Xpub};
+use crate::constants::MAX_SCRIPT_ELEMENT_SIZE;
+use crate::key::{PublicKey, XOnlyPublicKey};
+use crate::script::{MultisigError, ScriptBuf, MAX_MULTISIG_KEYS};
+use crate::types::Network;
+
+/// Characters a descriptor may contain, ordered so that the checksum catches common
+/// mistakes.
+const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+
+/// Characters of the checksum, as in bech32.
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// Length of a descriptor checksum.
+const CHECKSUM_LEN: usize = 8;
+
+/// Ways that parsing or deriving a descriptor might fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DescriptorError {
+    /// The descriptor contains a character that can't be checksummed.
+    InvalidCharacter(char),
+    /// The checksum after `#` doesn't match the descriptor.
+    InvalidChecksum { expected: String, found: String },
+    /// The descriptor isn't a function with balanced parentheses, or an argument is
+    /// missing or malformed.
+    InvalidSyntax,
+    /// The function isn't supported, or not at this position.
+    UnsupportedFunction(String),
+    /// `tr` has a script tree, which isn't supported.
+    TaprootScriptTree,
+    /// The key isn't a hex public key or an extended public key.
+    InvalidKey(String),
+    /// The key origin isn't `[fingerprint/path]`.
+    InvalidKeyOrigin,
+    /// An uncompressed key is used in segwit or multisig.
+    UncompressedKey,
+    /// An x-only key is used outside of `tr`.
+    XOnlyKey,
+    /// Parsing or deriving an extended key failed, including deriving hardened steps.
+    Bip32(Bip32Error),
+    /// The multisig threshold or number of keys is invalid.
+    Multisig(MultisigError),
+    /// The redeem script of `sh` is longer than the [`MAX_SCRIPT_ELEMENT_SIZE`] bytes
+    /// that can be pushed to spend it.
+    RedeemScriptSize(usize),
+}
+
+impl fmt::Display for DescriptorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DescriptorError::InvalidCharacter(c) => {
+                write!(f, "invalid character {:?} in descriptor", c)
+            }
+            DescriptorError::InvalidChecksum {
+                ref expected,
+                ref found,
+            } => write!(
+                f,
+                "invalid descriptor checksum {}, expected {}",
+                found, expected
+            ),
+            DescriptorError::InvalidSyntax => f.write_str("invalid descriptor syntax"),
+            DescriptorError::UnsupportedFunction(ref name) => {
+                write!(f, "unsupported descriptor function: {}", name)
+            }
+            DescriptorError::TaprootScriptTree => {
+                f.write_str("taproot script trees are not supported")
+            }
+            DescriptorError::InvalidKey(ref key) => write!(f, "invalid key: {}", key),
+            DescriptorError::InvalidKeyOrigin => f.write_str("invalid key origin"),
+            DescriptorError::UncompressedKey => {
+                f.write_str("uncompressed keys are not allowed in segwit or multisig")
+            }
+            DescriptorError::XOnlyKey => f.write_str("x-only keys are only allowed in tr"),
+            DescriptorError::Bip32(ref error) => write!(f, "{}", error),
+            DescriptorError::Multisig(ref error) => write!(f, "{}", error),
+            DescriptorError::RedeemScriptSize(size) => write!(
+                f,
+                "P2SH redeem script of {} bytes is larger than {} bytes",
+                size, MAX_SCRIPT_ELEMENT_SIZE
+            ),
+        }
+    }
+}
+
+impl From<Bip32Error> for DescriptorError {
+    fn from(error: Bip32Error) -> Self {
+        DescriptorError::Bip32(error)
+    }
+}
+
+impl From<MultisigError> for DescriptorError {
+    fn from(error: MultisigError) -> Self {
+        DescriptorError::Multisig(error)
+    }
+}
+
+/// Computes the 40-bit BCH code of the descriptor checksum over 5-bit symbols.
+fn polymod(symbols: impl IntoIterator<Item = u64>) -> u64 {
+    const GENERATOR: [u64; 5] = [
+        0xf5dee51989,
+        0xa9fdca3312,
+        0x1bab10e32d,
+        0x3706b1677a,
+        0x644d626ffd,
+    ];
+    let mut chk = 1;
+    for value in symbols {
+        let top = chk >> 35;
+        chk = ((chk & 0x7ffffffff) << 5) ^ value;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= generator;
+            }
+        }
+    }
+    chk
+}
+
+/// Computes the 8-character checksum of `desc`, which is written after a `#`.
+pub fn checksum(desc: &str) -> Result<String, DescriptorError> {
+    // Each character is the low 5 bits of its position in the charset, and every 3
+    // characters are followed by a symbol of their groups, the high bits.
+    let mut symbols = Vec::with_capacity(desc.len() * 4 / 3 + CHECKSUM_LEN + 1);
+    let mut groups = Vec::with_capacity(3);
+    for c in desc.chars() {
+        let position = INPUT_CHARSET
+            .find(c)
+            .ok_or(DescriptorError::InvalidCharacter(c))? as u64;
+        symbols.push(position & 31);
+        groups.push(position >> 5);
+        if groups.len() == 3 {
+            symbols.push(groups[0] * 9 + groups[1] * 3 + groups[2]);
+            groups.clear();
+        }
+    }
+    match *groups.as_slice() {
+        [group] => symbols.push(group),
+        [first, second] => symbols.push(first * 3 + second),
+        _ => {}
+    }
+    symbols.extend([0; CHECKSUM_LEN]);
+    let checksum = polymod(symbols) ^ 1;
+    Ok((0..CHECKSUM_LEN)
+        .map(|i| CHECKSUM_CHARSET[((checksum >> (5 * (7 - i))) & 31) as usize] as char)
+        .collect())
+}
+
+/// The master key fingerprint and derivation path a key was derived with.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KeyOrigin {
+    pub fingerprint: Fingerprint,
+    pub path: DerivationPath,
+}
+
+impl fmt::Display for KeyOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}", self.fingerprint)?;
+        for number in self.path.iter() {
+            write!(f, "/{}", number)?;
+        }
+        f.write_str("]")
+    }
+}
+
+impl FromStr for KeyOrigin {
+    type Err = DescriptorError;
+
+    /// Parses `[fingerprint/path]`, like `[d34db33f/84'/0'/0']`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let origin = s
+            .strip_prefix('[')
+            .and_then(|s| s.strip_suffix(']'))
+            .ok_or(DescriptorError::InvalidKeyOrigin)?;
+        let (fingerprint, path) = match origin.split_once('/') {
+            Some((fingerprint, path)) => (fingerprint, path),
+            None => (origin, ""),
+        };
+        if fingerprint.len() != 8 || path.starts_with('m') || origin.ends_with('/') {
+            return Err(DescriptorError::InvalidKeyOrigin);
+        }
+        Ok(KeyOrigin {
+            fingerprint: fingerprint
+                .parse()
+                .map_err(|_| DescriptorError::InvalidKeyOrigin)?,
+            path: path.parse()?,
+        })
+    }
+}
+
+/// Whether and how an extended key is derived at the index of a range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Wildcard {
+    /// The key is not ranged.
+    None,
+    /// The key ends in `/*`, the normal child at the index.
+    Unhardened,
+    /// The key ends in `/*'`, the hardened child at the index, which can't be derived
+    /// from a public key.
+    Hardened,
+}
+
+/// A public key of a descriptor, without the key origin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DescriptorKey {
+    /// A hex public key, compressed or not.
+    Single(PublicKey),
+    /// A hex x-only public key, only allowed in `tr`.
+    XOnly(XOnlyPublicKey),
+    /// An extended public key, derived along `path` and then at the index of the range
+    /// if it has a wildcard.
+    Extended {
+        xpub: Xpub,
+        path: DerivationPath,
+        wildcard: Wildcard,
+    },
+}
+
+/// A public key of a descriptor along with where it comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DescriptorPublicKey {
+    pub origin: Option<KeyOrigin>,
+    pub key: DescriptorKey,
+}
+
+impl DescriptorPublicKey {
+    /// Returns true if the key is an extended key with a wildcard.
+    pub fn is_ranged(&self) -> bool {
+        matches!(
+            self.key,
+            DescriptorKey::Extended {
+                wildcard: Wildcard::Unhardened | Wildcard::Hardened,
+                ..
+            }
+        )
+    }
+
+    /// Derives the public key at `index`, which is ignored unless the key is ranged.
+    ///
+    /// X-only keys are returned with an even y coordinate, which they imply.
+    pub fn derive_public_key(&self, index: u32) -> Result<PublicKey, DescriptorError> {
+        match self.key {
+            DescriptorKey::Single(key) => Ok(key),
+            DescriptorKey::XOnly(key) => Ok(PublicKey::new(key.public_key(Parity::Even))),
+            DescriptorKey::Extended {
+                ref xpub,
+                ref path,
+                wildcard,
+            } => {
+                let xpub = xpub.derive_pub(path)?;
+                let xpub = match wildcard {
+                    Wildcard::None => xpub,
+                    Wildcard::Unhardened => xpub.ckd_pub(ChildNumber::from_normal_idx(index)?)?,
+                    Wildcard::Hardened => {
+                        return Err(Bip32Error::CannotDeriveFromHardenedKey.into())
+                    }
+                };
+                Ok(xpub.to_public_key())
+            }
+        }
+    }
+
+    /// Checks the key is allowed in a context that requires compressed keys, or in
+    /// `tr`, the only context allowing x-only keys.
+    fn check(&self, compressed: bool, taproot: bool) -> Result<(), DescriptorError> {
+        match self.key {
+            DescriptorKey::Single(key) if compressed && !key.compressed => {
+                Err(DescriptorError::UncompressedKey)
+            }
+            DescriptorKey::XOnly(_) if !taproot => Err(DescriptorError::XOnlyKey),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for DescriptorPublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref origin) = self.origin {
+            write!(f, "{}", origin)?;
+        }
+        match self.key {
+            DescriptorKey::Single(key) => f.write_str(&hex::encode(key.to_bytes())),
+            DescriptorKey::XOnly(key) => f.write_str(&hex::encode(key.serialize())),
+            DescriptorKey::Extended {
+                ref xpub,
+                ref path,
+                wildcard,
+            } => {
+                write!(f, "{}", xpub)?;
+                for number in path.iter() {
+                    write!(f, "/{}", number)?;
+                }
+                match wildcard {
+                    Wildcard::None => Ok(()),
+                    Wildcard::Unhardened => f.write_str("/*"),
+                    Wildcard::Hardened => f.write_str("/*'"),
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for DescriptorPublicKey {
+    type Err = DescriptorError;
+
+    /// Parses a key with an optional origin: a hex compressed, uncompressed or x-only
+    /// key, or an extended public key followed by a path and optionally `/*`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (origin, key) = match s.find(']') {
+            Some(end) if s.starts_with('[') => (Some(s[..=end].parse()?), &s[end + 1..]),
+            _ => (None, s),
+        };
+        let invalid_key = || DescriptorError::InvalidKey(key.to_owned());
+        let is_hex = key.bytes().all(|b| b.is_ascii_hexdigit());
+        let key = match key.len() {
+            66 | 130 if is_hex => DescriptorKey::Single(
+                PublicKey::from_slice(&hex::decode(key).map_err(|_| invalid_key())?)
+                    .map_err(|_| invalid_key())?,
+            ),
+            64 if is_hex => DescriptorKey::XOnly(
+                XOnlyPublicKey::from_slice(&hex::decode(key).map_err(|_| invalid_key())?)
+                    .map_err(|_| invalid_key())?,
+            ),
+            _ => {
+                let mut steps = key.split('/');
+                let xpub: Xpub =
+                    steps
+                        .next()
+                        .unwrap_or_default()
+                        .parse()
+                        .map_err(|error| match error {
+                            Bip32Error::UnknownVersion(_) | Bip32Error::Base58(_) => invalid_key(),
+                            error => DescriptorError::Bip32(error),
+                        })?;
+                let mut steps: Vec<&str> = steps.collect();
+                let wildcard = match steps.last() {
+                    Some(&"*") => Wildcard::Unhardened,
+                    Some(&"*'") | Some(&"*h") => Wildcard::Hardened,
+                    _ => Wildcard::None,
+                };
+                if wildcard != Wildcard::None {
+                    steps.pop();
+                }
+                let path = steps
+                    .into_iter()
+                    .map(ChildNumber::from_str)
+                    .collect::<Result<Vec<_>, _>>()?;
+                DescriptorKey::Extended {
+                    xpub,
+                    path: path.into(),
+                    wildcard,
+                }
+            }
+        };
+        Ok(DescriptorPublicKey { origin, key })
+    }
+}
+
+/// A multisig script requiring `required` signatures from `keys`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Multi {
+    pub required: usize,
+    pub keys: Vec<DescriptorPublicKey>,
+    /// Whether the derived keys are sorted as in BIP67, for `sortedmulti`.
+    pub sorted: bool,
+}
+
+impl Multi {
+    /// Derives the multisig script at `index`.
+    pub fn derive_script(&self, index: u32) -> Result<ScriptBuf, DescriptorError> {
+        let keys = self
+            .keys
+            .iter()
+            .map(|key| key.derive_public_key(index))
+            .collect::<Result<Vec<_>, _>>()?;
+        let script = if self.sorted {
+            ScriptBuf::new_sorted_multisig(self.required, &keys)?
+        } else {
+            ScriptBuf::new_multisig(self.required, &keys)?
+        };
+        Ok(script)
+    }
+
+    /// Returns the length of the multisig script.
+    fn script_len(&self) -> usize {
+        // Numbers up to 16 are pushed with a single opcode, and 17 to 20 in two bytes.
+        let int_len = |n: usize| if n <= 16 { 1 } else { 2 };
+        let keys_len: usize = self
+            .keys
+            .iter()
+            .map(|key| match key.key {
+                DescriptorKey::Single(key) if !key.compressed => 66,
+                _ => 34,
+            })
+            .sum();
+        int_len(self.required) + keys_len + int_len(self.keys.len()) + 1
+    }
+}
+
+impl fmt::Display for Multi {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = if self.sorted { "sortedmulti" } else { "multi" };
+        write!(f, "{}({}", name, self.required)?;
+        for key in &self.keys {
+            write!(f, ",{}", key)?;
+        }
+        f.write_str(")")
+    }
+}
+
+/// An output script descriptor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Descriptor {
+    /// `pkh(KEY)`, P2PKH.
+    Pkh(DescriptorPublicKey),
+    /// `wpkh(KEY)`, P2WPKH.
+    Wpkh(DescriptorPublicKey),
+    /// `sh(wpkh(KEY))`, P2WPKH nested in P2SH.
+    ShWpkh(DescriptorPublicKey),
+    /// `sh(multi(...))` or `sh(sortedmulti(...))`, P2SH multisig.
+    Sh(Multi),
+    /// `wsh(multi(...))` or `wsh(sortedmulti(...))`, P2WSH multisig.
+    Wsh(Multi),
+    /// `sh(wsh(multi(...)))` or `sh(wsh(sortedmulti(...)))`, P2WSH multisig nested in
+    /// P2SH.
+    ShWsh(Multi),
+    /// `tr(KEY)`, P2TR committing to no scripts.
+    Tr(DescriptorPublicKey),
+}
+
+impl Descriptor {
+    /// Returns true if a key has a wildcard, so that scripts depend on the index.
+    pub fn is_ranged(&self) -> bool {
+        match *self {
+            Descriptor::Pkh(ref key)
+            | Descriptor::Wpkh(ref key)
+            | Descriptor::ShWpkh(ref key)
+            | Descriptor::Tr(ref key) => key.is_ranged(),
+            Descriptor::Sh(ref multi)
+            | Descriptor::Wsh(ref multi)
+            | Descriptor::ShWsh(ref multi) => multi.keys.iter().any(DescriptorPublicKey::is_ranged),
+        }
+    }
+
+    /// Derives the output script at `index`, which is ignored unless the descriptor is
+    /// ranged.
+    pub fn script_pubkey(&self, index: u32) -> Result<ScriptBuf, DescriptorError> {
+        let script = match *self {
+            Descriptor::Pkh(ref key) => {
+                ScriptBuf::new_p2pkh(&key.derive_public_key(index)?.pubkey_hash().to_byte_array())
+            }
+            Descriptor::Wpkh(ref key) => ScriptBuf::new_p2wpkh(&wpubkey_hash(key, index)?),
+            Descriptor::ShWpkh(ref key) => ScriptBuf::new_p2wpkh(&wpubkey_hash(key, index)?)
+                .to_p2sh()
+                .expect("P2WPKH scripts are 22 bytes"),
+            Descriptor::Sh(ref multi) => {
+                let redeem_script = multi.derive_script(index)?;
+                redeem_script
+                    .to_p2sh()
+                    .map_err(|_| DescriptorError::RedeemScriptSize(redeem_script.len()))?
+            }
+            Descriptor::Wsh(ref multi) => multi.derive_script(index)?.to_p2wsh(),
+            Descriptor::ShWsh(ref multi) => multi.derive_script(index)?.to_p2sh_p2wsh(),
+            Descriptor::Tr(ref key) => {
+                let (internal_key, _) = key.derive_public_key(index)?.inner.x_only_public_key();
+                ScriptBuf::new_p2tr(internal_key, None)
+            }
+        };
+        Ok(script)
+    }
+
+    /// Derives the address at `index` on `network`, which is ignored unless the
+    /// descriptor is ranged.
+    pub fn address(&self, index: u32, network: Network) -> Result<BitcoinAddress, DescriptorError> {
+        let address = match *self {
+            Descriptor::Pkh(ref key) => {
+                BitcoinAddress::p2pkh(&key.derive_public_key(index)?, network)
+            }
+            Descriptor::Wpkh(ref key) => {
+                BitcoinAddress::p2wpkh(&key.derive_public_key(index)?, network)
+                    .map_err(|_| DescriptorError::UncompressedKey)?
+            }
+            Descriptor::ShWpkh(ref key) => {
+                BitcoinAddress::p2sh(&ScriptBuf::new_p2wpkh(&wpubkey_hash(key, index)?), network)
+                    .expect("P2WPKH scripts are 22 bytes")
+            }
+            Descriptor::Sh(ref multi) => {
+                let redeem_script = multi.derive_script(index)?;
+                BitcoinAddress::p2sh(&redeem_script, network)
+                    .map_err(|_| DescriptorError::RedeemScriptSize(redeem_script.len()))?
+            }
+            Descriptor::Wsh(ref multi) => {
+                BitcoinAddress::p2wsh(&multi.derive_script(index)?, network)
+            }
+            Descriptor::ShWsh(ref multi) => {
+                BitcoinAddress::p2shwsh(&multi.derive_script(index)?, network)
+            }
+            Descriptor::Tr(ref key) => {
+                let (internal_key, _) = key.derive_public_key(index)?.inner.x_only_public_key();
+                BitcoinAddress::p2tr(internal_key, None, network)
+            }
+        };
+        Ok(address)
+    }
+
+    /// Writes the descriptor without its checksum.
+    fn write_body(&self, f: &mut impl fmt::Write) -> fmt::Result {
+        match *self {
+            Descriptor::Pkh(ref key) => write!(f, "pkh({})", key),
+            Descriptor::Wpkh(ref key) => write!(f, "wpkh({})", key),
+            Descriptor::ShWpkh(ref key) => write!(f, "sh(wpkh({}))", key),
+            Descriptor::Sh(ref multi) => write!(f, "sh({})", multi),
+            Descriptor::Wsh(ref multi) => write!(f, "wsh({})", multi),
+            Descriptor::ShWsh(ref multi) => write!(f, "sh(wsh({}))", multi),
+            Descriptor::Tr(ref key) => write!(f, "tr({})", key),
+        }
+    }
+}
+
+/// Derives the hash of the key at `index` paid to by P2WPKH outputs.
+fn wpubkey_hash(key: &DescriptorPublicKey, index: u32) -> Result<[u8; 20], DescriptorError> {
+    key.derive_public_key(index)?
+        .wpubkey_hash()
+        .map(|hash| hash.to_byte_array())
+        .ok_or(DescriptorError::UncompressedKey)
+}
+
+impl fmt::Display for Descriptor {
+    /// Writes the descriptor followed by `#` and its checksum.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut body = String::new();
+        self.write_body(&mut body)?;
+        let checksum = checksum(&body).map_err(|_| fmt::Error)?;
+        write!(f, "{}#{}", body, checksum)
+    }
+}
+
+/// Splits `name(args)` into its name and arguments.
+fn function(s: &str) -> Result<(&str, &str), DescriptorError> {
+    let (name, args) = s
+        .strip_suffix(')')
+        .and_then(|s| s.split_once('('))
+        .ok_or(DescriptorError::InvalidSyntax)?;
+    let mut depth = 0usize;
+    for c in args.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.checked_sub(1).ok_or(DescriptorError::InvalidSyntax)?,
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return Err(DescriptorError::InvalidSyntax);
+    }
+    Ok((name, args))
+}
+
+/// Parses a key allowed in a context requiring compressed keys, or in `tr`.
+fn parse_key(
+    s: &str,
+    compressed: bool,
+    taproot: bool,
+) -> Result<DescriptorPublicKey, DescriptorError> {
+    let key: DescriptorPublicKey = s.parse()?;
+    key.check(compressed, taproot)?;
+    Ok(key)
+}
+
+/// Parses the arguments of `multi` or `sortedmulti`, whose keys must be compressed
+/// unless they are in the top level `sh`.
+fn parse_multi(s: &str, compressed: bool) -> Result<Multi, DescriptorError> {
+    let (name, args) = function(s)?;
+    let sorted = match name {
+        "multi" => false,
+        "sortedmulti" => true,
+        _ => return Err(DescriptorError::UnsupportedFunction(name.to_owned())),
+    };
+    let mut args = args.split(',');
+    let required = args.next().unwrap_or_default();
+    if required.is_empty() || !required.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(DescriptorError::InvalidSyntax);
+    }
+    let required = required
+        .parse()
+        .map_err(|_| DescriptorError::InvalidSyntax)?;
+    let keys = args
+        .map(|key| parse_key(key, compressed, false))
+        .collect::<Result<Vec<_>, _>>()?;
+    if keys.len() > MAX_MULTISIG_KEYS {
+        return Err(MultisigError::TooManyKeys { keys: keys.len() }.into());
+    }
+    if required == 0 || required > keys.len() {
+        return Err(MultisigError::InvalidThreshold {
+            required,
+            keys: keys.len(),
+        }
+        .into());
+    }
+    Ok(Multi {
+        required,
+        keys,
+        sorted,
+    })
+}
+
+impl FromStr for Descriptor {
+    type Err = DescriptorError;
+
+    /// Parses a descriptor, checking its checksum if it is followed by one.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let desc = match s.split_once('#') {
+            Some((desc, found)) => {
+                let expected = checksum(desc)?;
+                if found != expected {
+                    return Err(DescriptorError::InvalidChecksum {
+                        expected,
+                        found: found.to_owned(),
+                    });
+                }
+                desc
+            }
+            None => {
+                checksum(s)?;
+                s
+            }
+        };
+
+        let (name, args) = function(desc)?;
+        match name {
+            "pkh" => Ok(Descriptor::Pkh(parse_key(args, false, false)?)),
+            "wpkh" => Ok(Descriptor::Wpkh(parse_key(args, true, false)?)),
+            "sh" => match function(args)? {
+                ("wpkh", key) => Ok(Descriptor::ShWpkh(parse_key(key, true, false)?)),
+                ("wsh", multi) => Ok(Descriptor::ShWsh(parse_multi(multi, true)?)),
+                _ => {
+                    // Bitcoin Core rejects redeem scripts that couldn't be pushed to spend
+                    // them, limiting `sh` to 15 keys.
+                    let multi = parse_multi(args, false)?;
+                    if multi.script_len() > MAX_SCRIPT_ELEMENT_SIZE {
+                        return Err(DescriptorError::RedeemScriptSize(multi.script_len()));
+                    }
+                    Ok(Descriptor::Sh(multi))
+                }
+            },
+            "wsh" => Ok(Descriptor::Wsh(parse_multi(args, true)?)),
+            "tr" if args.contains(',') => Err(DescriptorError::TaprootScriptTree),
+            "tr" => Ok(Descriptor::Tr(parse_key(args, true, true)?)),
+            _ => Err(DescriptorError::UnsupportedFunction(name.to_owned())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bip32::Xpriv;
+    use crate::bip39::Mnemonic;
+    use crate::script::Script;
+
+    fn master() -> Xpriv {
+        let mnemonic: Mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about".parse().unwrap();
+        Xpriv::new_master(Network::Bitcoin, &mnemonic.to_seed("")).unwrap()
+    }
+
+    /// Returns the account key at `path` of [`master`] with its key origin.
+    fn account(path: &str) -> String {
+        let master = master();
+        let path: DerivationPath = path.parse().unwrap();
+        let origin = KeyOrigin {
+            fingerprint: master.fingerprint(),
+            path: path.clone(),
+        };
+        format!(
+            "{}{}",
+            origin,
+            Xpub::from_priv(&master.derive_priv(&path).unwrap())
+        )
+    }
+
+    fn parse(desc: &str) -> Descriptor {
+        desc.parse().unwrap()
+    }
+
+    #[test]
+    fn checksums() {
+        let cases = [
+            ("raw(deadbeef)", "89f8spxm"),
+            (
+                "pkh(02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5)",
+                "8fhd9pwu",
+            ),
+            (
+                "wpkh(02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9)",
+                "8zl0zxma",
+            ),
+            (
+                "sh(wpkh(03fff97bd5755eeea420453a14355235d382f6472f8568a18b2f057a1460297556))",
+                "qkrrc7je",
+            ),
+            ("addr(mkmZxiEcEd8ZqjQWVZuC6so5dFMKEFpN2j)", "02wpgw69"),
+        ];
+        for (desc, expected) in cases {
+            assert_eq!(checksum(desc), Ok(expected.to_owned()));
+        }
+        assert_eq!(
+            checksum("raw(deadbeef)\u{e9}"),
+            Err(DescriptorError::InvalidCharacter('\u{e9}'))
+        );
+
+        let desc = cases[2].0;
+        assert_eq!(parse(&format!("{}#8zl0zxma", desc)), parse(desc));
+        for found in ["", "8zl0zxm", "8zl0zxmaa", "8zl0zxmq"] {
+            assert_eq!(
+                format!("{}#{}", desc, found).parse::<Descriptor>(),
+                Err(DescriptorError::InvalidChecksum {
+                    expected: "8zl0zxma".to_owned(),
+                    found: found.to_owned(),
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn single_key_scripts() {
+        let cases = [
+            (
+                "pkh(02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5)",
+                "76a91406afd46bcdfd22ef94ac122aa11f241244a37ecc88ac",
+            ),
+            (
+                "wpkh(02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9)",
+                "00147dd65592d0ab2fe0d0257d571abf032cd9db93dc",
+            ),
+            (
+                "sh(wpkh(03fff97bd5755eeea420453a14355235d382f6472f8568a18b2f057a1460297556))",
+                "a914cc6ffbc0bf31af759451068f90ba7a0272b6b33287",
+            ),
+            (
+                "tr(a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd)",
+                "512077aab6e066f8a7419c5ab714c12c67d25007ed55a43cadcacb4d7a970a093f11",
+            ),
+        ];
+        for (desc, script) in cases {
+            let descriptor = parse(desc);
+            assert!(!descriptor.is_ranged());
+            let script_pubkey = descriptor.script_pubkey(0).unwrap();
+            assert_eq!(hex::encode(script_pubkey.as_bytes()), script, "{}", desc);
+            assert_eq!(descriptor.script_pubkey(7), Ok(script_pubkey.clone()));
+            assert_eq!(
+                descriptor
+                    .address(0, Network::Bitcoin)
+                    .unwrap()
+                    .to_script()
+                    .unwrap(),
+                script_pubkey
+            );
+            assert_eq!(
+                descriptor.to_string(),
+                format!("{}#{}", desc, checksum(desc).unwrap())
+            );
+        }
+
+        let uncompressed = "pkh(04a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd5b8dec5235a0fa8722476c7709c02559e3aa73aa03918ba2d492eea75abea235)";
+        let expected =
+            bitcoin::PublicKey::from_slice(&hex::decode(&uncompressed[4..134]).unwrap()).unwrap();
+        assert_eq!(
+            parse(uncompressed).script_pubkey(0).unwrap().as_bytes(),
+            bitcoin::ScriptBuf::new_p2pkh(&expected.pubkey_hash()).as_bytes()
+        );
+    }
+
+    #[test]
+    fn bip84_bip86_bip49_addresses() {
+        let wpkh = parse(&format!("wpkh({}/0/*)", account("84'/0'/0'")));
+        assert!(wpkh
+            .to_string()
+            .starts_with("wpkh([73c5da0a/84'/0'/0']xpub"));
+        assert!(wpkh.is_ranged());
+        let address = |desc: &Descriptor, index, network| {
+            let address = desc.address(index, network).unwrap();
+            let script = desc.script_pubkey(index).unwrap();
+            let expected = bitcoin::Address::from_script(
+                bitcoin::Script::from_bytes(script.as_bytes()),
+                match network {
+                    Network::Bitcoin => bitcoin::Network::Bitcoin,
+                    _ => bitcoin::Network::Testnet,
+                },
+            )
+            .unwrap();
+            assert_eq!(address.to_script(), Ok(script));
+            expected.to_string()
+        };
+        assert_eq!(
+            address(&wpkh, 0, Network::Bitcoin),
+            "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu"
+        );
+        assert_eq!(
+            address(&wpkh, 1, Network::Bitcoin),
+            "bc1qnjg0jd8228aq7egyzacy8cys3knf9xvrerkf9g"
+        );
+        let change = parse(&format!("wpkh({}/1/*)", account("84'/0'/0'")));
+        assert_eq!(
+            address(&change, 0, Network::Bitcoin),
+            "bc1q8c6fshw2dlwun7ekn9qwf37cu2rn755upcp6el"
+        );
+
+        let tr = parse(&format!("tr({}/0/*)", account("86'/0'/0'")));
+        assert_eq!(
+            address(&tr, 0, Network::Bitcoin),
+            "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr"
+        );
+
+        let master = Xpriv {
+            network: Network::Testnet,
+            ..master()
+        };
+        let account = master
+            .derive_priv("m/49'/1'/0'".parse::<DerivationPath>().unwrap())
+            .unwrap();
+        let sh_wpkh = parse(&format!("sh(wpkh({}/0/*))", Xpub::from_priv(&account)));
+        assert_eq!(
+            address(&sh_wpkh, 0, Network::Testnet),
+            "2Mww8dCYPUpKHofjgcXcBCEGmniw9CoaiD2"
+        );
+    }
+
+    #[test]
+    fn multisig() {
+        let sorted = parse("sh(sortedmulti(2,03acd484e2f0c7f65309ad178a9f559abde09796974c57e714c35f110dfc27ccbe,022f01e5e15cca351daff3843fb70f3c2f0a1bdd05e5af888a67784ef3e10a2a01))");
+        let multi = parse("sh(multi(2,022f01e5e15cca351daff3843fb70f3c2f0a1bdd05e5af888a67784ef3e10a2a01,03acd484e2f0c7f65309ad178a9f559abde09796974c57e714c35f110dfc27ccbe))");
+        let reversed = parse("sh(multi(2,03acd484e2f0c7f65309ad178a9f559abde09796974c57e714c35f110dfc27ccbe,022f01e5e15cca351daff3843fb70f3c2f0a1bdd05e5af888a67784ef3e10a2a01))");
+        assert_eq!(
+            hex::encode(sorted.script_pubkey(0).unwrap().as_bytes()),
+            "a914a6a8b030a38762f4c1f5cbe387b61a3c5da5cd2687"
+        );
+        assert_eq!(sorted.script_pubkey(0), multi.script_pubkey(0));
+        assert_ne!(sorted.script_pubkey(0), reversed.script_pubkey(0));
+
+        let first = account("48'/0'/0'/2'");
+        let second = Xpub::from_priv(&Xpriv::new_master(Network::Bitcoin, &[0x02; 32]).unwrap());
+        let keys = format!("{}/0/*,{}/0/*", first, second);
+        for desc in [
+            format!("wsh(multi(2,{}))", keys),
+            format!("sh(wsh(sortedmulti(1,{})))", keys),
+            format!("sh(multi(1,{}))", keys),
+        ] {
+            let descriptor = parse(&desc);
+            assert!(descriptor.is_ranged());
+            assert_eq!(parse(&descriptor.to_string()), descriptor);
+            let (multi, wrap): (_, fn(&Script) -> ScriptBuf) = match descriptor {
+                Descriptor::Wsh(ref multi) => (multi, Script::to_p2wsh),
+                Descriptor::ShWsh(ref multi) => (multi, Script::to_p2sh_p2wsh),
+                Descriptor::Sh(ref multi) => (multi, |script| script.to_p2sh().unwrap()),
+                _ => panic!("{}", desc),
+            };
+            for index in [0, 5] {
+                let keys: Vec<_> = multi
+                    .keys
+                    .iter()
+                    .map(|key| key.derive_public_key(index).unwrap())
+                    .collect();
+                let script = if multi.sorted {
+                    ScriptBuf::new_sorted_multisig(multi.required, &keys)
+                } else {
+                    ScriptBuf::new_multisig(multi.required, &keys)
+                }
+                .unwrap();
+                assert_eq!(descriptor.script_pubkey(index), Ok(wrap(&script)));
+                assert_eq!(
+                    descriptor
+                        .address(index, Network::Bitcoin)
+                        .unwrap()
+                        .to_script(),
+                    Ok(wrap(&script))
+                );
+            }
+        }
+
+        let key = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+        assert_eq!(
+            format!("wsh(multi(3,{},{}))", key, key).parse::<Descriptor>(),
+            Err(MultisigError::InvalidThreshold {
+                required: 3,
+                keys: 2
+            }
+            .into())
+        );
+        assert_eq!(
+            format!("wsh(multi(0,{}))", key).parse::<Descriptor>(),
+            Err(MultisigError::InvalidThreshold {
+                required: 0,
+                keys: 1
+            }
+            .into())
+        );
+        assert_eq!(
+            format!("wsh(multi(x,{}))", key).parse::<Descriptor>(),
+            Err(DescriptorError::InvalidSyntax)
+        );
+    }
+
+    #[test]
+    fn multisig_key_limits() {
+        // Bitcoin Core's descriptor_tests: `sh` takes redeem scripts of up to 520 bytes,
+        // so 15 compressed keys, and `wsh` up to 20 keys.
+        let key = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+        let multi = |required: usize, keys: usize| {
+            format!("multi({}{})", required, format!(",{}", key).repeat(keys))
+        };
+        let sh = parse(&format!("sh({})", multi(1, 15)));
+        let redeem_script = match sh {
+            Descriptor::Sh(ref multi) => multi.derive_script(0).unwrap(),
+            _ => unreachable!(),
+        };
+        assert_eq!(redeem_script.len(), 513);
+        assert_eq!(sh.script_pubkey(0), Ok(redeem_script.to_p2sh().unwrap()));
+        assert_eq!(
+            format!("sh({})", multi(1, 16)).parse::<Descriptor>(),
+            Err(DescriptorError::RedeemScriptSize(547))
+        );
+
+        let witness_script = ScriptBuf::new_multisig(
+            20,
+            &[PublicKey::from_slice(&hex::decode(key).unwrap()).unwrap(); 20],
+        )
+        .unwrap();
+        assert_eq!(
+            parse(&format!("wsh({})", multi(20, 20))).script_pubkey(0),
+            Ok(witness_script.to_p2wsh())
+        );
+        assert_eq!(
+            parse(&format!("sh(wsh({}))", multi(20, 20))).script_pubkey(0),
+            Ok(witness_script.to_p2sh_p2wsh())
+        );
+        for desc in [
+            format!("wsh({})", multi(1, 21)),
+            format!("sh(wsh({}))", multi(1, 21)),
+            format!("sh({})", multi(1, 21)),
+        ] {
+            assert_eq!(
+                desc.parse::<Descriptor>(),
+                Err(MultisigError::TooManyKeys { keys: 21 }.into())
+            );
+        }
+
+        // Only the top level `sh` allows uncompressed keys, which take 66 bytes each.
+        let uncompressed = "04a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd5b8dec5235a0fa8722476c7709c02559e3aa73aa03918ba2d492eea75abea235";
+        let keys = [
+            PublicKey::from_slice(&hex::decode(key).unwrap()).unwrap(),
+            PublicKey::from_slice(&hex::decode(uncompressed).unwrap()).unwrap(),
+        ];
+        assert!(!keys[1].compressed);
+        let sh = parse(&format!("sh(multi(1,{},{}))", key, uncompressed));
+        let redeem_script = ScriptBuf::new_multisig(1, &keys).unwrap();
+        assert_eq!(redeem_script.len(), 1 + 34 + 66 + 1 + 1);
+        assert_eq!(sh.script_pubkey(0), Ok(redeem_script.to_p2sh().unwrap()));
+        assert_eq!(parse(&sh.to_string()), sh);
+        let sh = parse(&format!("sh(sortedmulti(1,{},{}))", uncompressed, key));
+        assert_eq!(
+            sh.script_pubkey(0),
+            Ok(ScriptBuf::new_sorted_multisig(1, &keys)
+                .unwrap()
+                .to_p2sh()
+                .unwrap())
+        );
+        // Seven uncompressed keys are a 465-byte redeem script, eight are 531 bytes.
+        let uncompressed_multi =
+            |keys: usize| format!("sh(multi(1{}))", format!(",{}", uncompressed).repeat(keys));
+        assert!(uncompressed_multi(7).parse::<Descriptor>().is_ok());
+        assert_eq!(
+            uncompressed_multi(8).parse::<Descriptor>(),
+            Err(DescriptorError::RedeemScriptSize(531))
+        );
+    }
+
+    #[test]
+    fn keys() {
+        let xpub = Xpub::from_priv(&master());
+        let key: DescriptorPublicKey = format!("[d34db33f/44h/0'/0H]{}/1/2h/*h", xpub)
+            .parse()
+            .unwrap();
+        assert_eq!(
+            key.origin,
+            Some(KeyOrigin {
+                fingerprint: Fingerprint::from([0xd3, 0x4d, 0xb3, 0x3f]),
+                path: "m/44'/0'/0'".parse().unwrap(),
+            })
+        );
+        assert_eq!(
+            key.to_string(),
+            format!("[d34db33f/44'/0'/0']{}/1/2'/*'", xpub)
+        );
+        assert!(key.is_ranged());
+        assert_eq!(
+            key.derive_public_key(0),
+            Err(Bip32Error::CannotDeriveFromHardenedKey.into())
+        );
+
+        let key: DescriptorPublicKey = format!("{}/1/*", xpub).parse().unwrap();
+        assert_eq!(
+            key.derive_public_key(3),
+            Ok(xpub
+                .derive_pub("m/1/3".parse::<DerivationPath>().unwrap())
+                .unwrap()
+                .to_public_key())
+        );
+        assert_eq!(
+            key.derive_public_key(1 << 31),
+            Err(Bip32Error::InvalidChildNumber(1 << 31).into())
+        );
+        let key: DescriptorPublicKey = format!("[d34db33f]{}", xpub).parse().unwrap();
+        assert!(!key.is_ranged());
+        assert_eq!(key.derive_public_key(9), Ok(xpub.to_public_key()));
+
+        for origin in [
+            "[d34db33f/]",
+            "[d34db33/0]",
+            "[m/0]",
+            "[d34db33f/m/0]",
+            "[zz4db33f]",
+        ] {
+            assert!(
+                format!("{}{}", origin, xpub)
+                    .parse::<DescriptorPublicKey>()
+                    .is_err(),
+                "{}",
+                origin
+            );
+        }
+        assert_eq!(
+            "02c6".parse::<DescriptorPublicKey>(),
+            Err(DescriptorError::InvalidKey("02c6".to_owned()))
+        );
+        assert_eq!(
+            format!("{}/a", xpub).parse::<DescriptorPublicKey>(),
+            Err(Bip32Error::InvalidChildNumberFormat.into())
+        );
+    }
+
+    #[test]
+    fn parsing_errors() {
+        let key = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+        let x_only = &key[2..];
+        let uncompressed = "04a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd5b8dec5235a0fa8722476c7709c02559e3aa73aa03918ba2d492eea75abea235";
+        let cases = [
+            (
+                format!("wpkh({})", uncompressed),
+                DescriptorError::UncompressedKey,
+            ),
+            (
+                format!("sh(wpkh({}))", uncompressed),
+                DescriptorError::UncompressedKey,
+            ),
+            (
+                format!("tr({})", uncompressed),
+                DescriptorError::UncompressedKey,
+            ),
+            (
+                format!("wsh(multi(1,{}))", uncompressed),
+                DescriptorError::UncompressedKey,
+            ),
+            (
+                format!("sh(wsh(sortedmulti(1,{},{})))", key, uncompressed),
+                DescriptorError::UncompressedKey,
+            ),
+            (format!("pkh({})", x_only), DescriptorError::XOnlyKey),
+            (format!("wpkh({})", x_only), DescriptorError::XOnlyKey),
+            (
+                format!("tr({},pk({}))", x_only, x_only),
+                DescriptorError::TaprootScriptTree,
+            ),
+            (
+                format!("combo({})", key),
+                DescriptorError::UnsupportedFunction("combo".to_owned()),
+            ),
+            (
+                format!("sh(pkh({}))", key),
+                DescriptorError::UnsupportedFunction("pkh".to_owned()),
+            ),
+            (
+                format!("wsh(wpkh({}))", key),
+                DescriptorError::UnsupportedFunction("wpkh".to_owned()),
+            ),
+            (format!("pkh({}", key), DescriptorError::InvalidSyntax),
+            (format!("pkh({}))", key), DescriptorError::InvalidSyntax),
+            (format!("pkh){}(", key), DescriptorError::InvalidSyntax),
+            ("".to_owned(), DescriptorError::InvalidSyntax),
+        ];
+        for (desc, error) in cases {
+            assert_eq!(desc.parse::<Descriptor>(), Err(error), "{}", desc);
+        }
+        assert_eq!(
+            parse(&format!("tr({})", x_only)).script_pubkey(0),
+            parse(&format!("tr({})", key)).script_pubkey(0)
+        );
+    }
+}
@@ -0,0 +1,221 @@
+//! ECDSA signatures.
+//!
+//! Legacy and segwit version 0 scripts take DER encoded signatures followed by the
+//! sighash type they commit to, see [`SighashCache`](crate::sighash::SighashCache).
+
+use core::fmt;
+use secp256k1::{Message, Secp256k1};
+
+#[cfg(feature = "signing")]
+use crate::key::PrivateKey;
+use crate::key::PublicKey;
+use crate::sighash::{SIGHASH_ALL, SIGHASH_ANYONECANPAY};
+
+/// Ways that parsing a signature might fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SignatureError {
+    /// The signature is empty, so it has no sighash type.
+    Empty,
+    /// The signature isn't strictly DER encoded, or a compact signature isn't valid.
+    InvalidEncoding,
+    /// The sighash type isn't one of `SIGHASH_ALL`, `SIGHASH_NONE` or `SIGHASH_SINGLE`,
+    /// optionally with `SIGHASH_ANYONECANPAY`.
+    InvalidSighashType(u32),
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SignatureError::Empty => f.write_str("empty signature"),
+            SignatureError::InvalidEncoding => f.write_str("invalid signature encoding"),
+            SignatureError::InvalidSighashType(sighash_type) => {
+                write!(f, "invalid sighash type {:#x}", sighash_type)
+            }
+        }
+    }
+}
+
+/// A signature along with the sighash type it commits to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signature {
+    pub signature: secp256k1::ecdsa::Signature,
+    pub sighash_type: u32,
+}
+
+impl Signature {
+    /// Signs `sighash` with `key`, with a nonce derived from both as in RFC6979 and a
+    /// low S value as required by standardness.
+    #[cfg(feature = "signing")]
+    pub fn sign(sighash: &[u8; 32], key: &PrivateKey, sighash_type: u32) -> Self {
+        let signature =
+            Secp256k1::signing_only().sign_ecdsa(&Message::from_digest(*sighash), &key.inner);
+        Signature {
+            signature,
+            sighash_type,
+        }
+    }
+
+    /// Wraps a signature committing to `SIGHASH_ALL`.
+    pub fn sighash_all(signature: secp256k1::ecdsa::Signature) -> Self {
+        Signature {
+            signature,
+            sighash_type: SIGHASH_ALL,
+        }
+    }
+
+    /// Parses a strictly DER encoded signature followed by its sighash type, as pushed
+    /// by scripts.
+    ///
+    /// High S values are accepted, see [`normalize_s`](Self::normalize_s).
+    pub fn from_slice(data: &[u8]) -> Result<Self, SignatureError> {
+        let (&sighash_type, der) = data.split_last().ok_or(SignatureError::Empty)?;
+        let sighash_type = sighash_type as u32;
+        if !(1..=3).contains(&(sighash_type & !SIGHASH_ANYONECANPAY)) {
+            return Err(SignatureError::InvalidSighashType(sighash_type));
+        }
+        let signature = secp256k1::ecdsa::Signature::from_der(der)
+            .map_err(|_| SignatureError::InvalidEncoding)?;
+        Ok(Signature {
+            signature,
+            sighash_type,
+        })
+    }
+
+    /// Parses a 64-byte compact signature, the 32-byte R and S values.
+    pub fn from_compact(data: &[u8], sighash_type: u32) -> Result<Self, SignatureError> {
+        let signature = secp256k1::ecdsa::Signature::from_compact(data)
+            .map_err(|_| SignatureError::InvalidEncoding)?;
+        Ok(Signature {
+            signature,
+            sighash_type,
+        })
+    }
+
+    /// Serializes the signature in DER followed by its sighash type, as pushed by
+    /// scripts.
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut result = self.signature.serialize_der().to_vec();
+        result.push(self.sighash_type as u8);
+        result
+    }
+
+    /// Serializes the signature in 64 bytes, without its sighash type.
+    pub fn serialize_compact(&self) -> [u8; 64] {
+        self.signature.serialize_compact()
+    }
+
+    /// Returns true if S is at most half the curve order, which standardness requires
+    /// to prevent third parties from changing the signature.
+    pub fn is_low_s(&self) -> bool {
+        let mut normalized = self.signature;
+        normalized.normalize_s();
+        normalized == self.signature
+    }
+
+    /// Replaces a high S value with its negation, which is valid for the same message.
+    pub fn normalize_s(&mut self) {
+        self.signature.normalize_s();
+    }
+
+    /// Returns true if the signature is valid for `sighash` and `key`.
+    ///
+    /// Like consensus, this accepts high S values, which secp256k1 itself rejects, so
+    /// check [`is_low_s`](Self::is_low_s) as well to require a standard signature.
+    pub fn verify(&self, sighash: &[u8; 32], key: &PublicKey) -> bool {
+        let mut signature = self.signature;
+        signature.normalize_s();
+        Secp256k1::verification_only()
+            .verify_ecdsa(&Message::from_digest(*sighash), &signature, &key.inner)
+            .is_ok()
+    }
+}
+
+#[cfg(all(test, feature = "signing"))]
+mod test {
+    use super::*;
+    use crate::sighash::SIGHASH_SINGLE;
+
+    fn key() -> PrivateKey {
+        PrivateKey::from_slice(&[0x42; 32]).unwrap()
+    }
+
+    #[test]
+    fn sign_matches_rust_bitcoin() {
+        let sighash = [0x17; 32];
+        let sig = Signature::sign(&sighash, &key(), SIGHASH_ALL);
+        assert!(sig.verify(&sighash, &key().public_key()));
+        assert!(!sig.verify(&[0x18; 32], &key().public_key()));
+        assert!(sig.is_low_s());
+
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let expected = secp.sign_ecdsa(
+            &bitcoin::secp256k1::Message::from_digest(sighash),
+            &bitcoin::secp256k1::SecretKey::from_slice(&key().to_bytes()).unwrap(),
+        );
+        let expected = bitcoin::ecdsa::Signature::sighash_all(expected);
+        assert_eq!(sig.to_vec(), expected.to_vec());
+        assert_eq!(sig.serialize_compact(), expected.sig.serialize_compact());
+    }
+
+    #[test]
+    fn encoding() {
+        let sig = Signature::sign(&[0x17; 32], &key(), SIGHASH_SINGLE | SIGHASH_ANYONECANPAY);
+        let bytes = sig.to_vec();
+        assert_eq!(bytes.last(), Some(&0x83));
+        assert_eq!(Signature::from_slice(&bytes), Ok(sig));
+        assert_eq!(
+            Signature::from_compact(&sig.serialize_compact(), sig.sighash_type),
+            Ok(sig)
+        );
+        assert_eq!(
+            Signature::sighash_all(sig.signature).to_vec().last(),
+            Some(&0x01)
+        );
+
+        assert_eq!(Signature::from_slice(&[]), Err(SignatureError::Empty));
+        let mut undefined = bytes.clone();
+        *undefined.last_mut().unwrap() = 0x04;
+        assert_eq!(
+            Signature::from_slice(&undefined),
+            Err(SignatureError::InvalidSighashType(0x04))
+        );
+        let mut not_der = bytes.clone();
+        not_der[0] = 0x31;
+        assert_eq!(
+            Signature::from_slice(&not_der),
+            Err(SignatureError::InvalidEncoding)
+        );
+        assert_eq!(
+            Signature::from_compact(&[0xff; 64], SIGHASH_ALL),
+            Err(SignatureError::InvalidEncoding)
+        );
+    }
+
+    #[test]
+    fn low_s() {
+        let sighash = [0x17; 32];
+        let mut sig = Signature::sign(&sighash, &key(), SIGHASH_ALL);
+        // Negate S, giving the other signature valid for the same message.
+        let mut compact = sig.serialize_compact();
+        let order = secp256k1::constants::CURVE_ORDER;
+        let mut borrow = 0i16;
+        for i in (32..64).rev() {
+            let diff = order[i - 32] as i16 - compact[i] as i16 - borrow;
+            compact[i] = diff.rem_euclid(256) as u8;
+            borrow = (diff < 0) as i16;
+        }
+        let mut high = Signature::from_compact(&compact, SIGHASH_ALL).unwrap();
+        assert!(!high.is_low_s());
+        assert_eq!(
+            Signature::from_slice(&high.to_vec()).map(|sig| sig.is_low_s()),
+            Ok(false)
+        );
+        assert!(high.verify(&sighash, &key().public_key()));
+        assert!(!high.verify(&[0x18; 32], &key().public_key()));
+        high.normalize_s();
+        assert_eq!(high, sig);
+        sig.normalize_s();
+        assert_eq!(sig, high);
+    }
+}
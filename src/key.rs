@@ -0,0 +1,428 @@
+//! Keys.
+//!
+//! secp256k1 private and public keys along with whether the public key is serialized
+//! compressed, which changes its hash and so the outputs paying to it, and the x-only
+//! keys of taproot.
+
+use bitcoin_hashes::{hash160, hash_newtype, Hash};
+use core::fmt;
+#[cfg(feature = "signing")]
+use secp256k1::Keypair;
+use secp256k1::{Parity, Secp256k1};
+
+use crate::taproot::{TapNodeHash, TapTweakHash};
+use crate::types::Network;
+
+/// A key serialized as the 32 bytes of its x coordinate, implying an even y coordinate,
+/// as used by taproot (BIP340).
+///
+/// Parsing one checks that its bytes are the x coordinate of a point on the curve.
+pub use secp256k1::XOnlyPublicKey;
+
+hash_newtype! {
+    /// Hash160 of a public key, as paid to by P2PKH outputs.
+    pub struct PubkeyHash(hash160::Hash);
+    /// Hash160 of a compressed public key, as paid to by P2WPKH outputs.
+    pub struct WPubkeyHash(hash160::Hash);
+}
+
+/// A public key, compressed or not.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PublicKey {
+    /// Whether the key is serialized in 33 rather than 65 bytes.
+    pub compressed: bool,
+    pub inner: secp256k1::PublicKey,
+}
+
+impl PublicKey {
+    /// Wraps a key to be serialized compressed.
+    pub fn new(key: secp256k1::PublicKey) -> Self {
+        PublicKey {
+            compressed: true,
+            inner: key,
+        }
+    }
+
+    /// Wraps a key to be serialized uncompressed.
+    pub fn new_uncompressed(key: secp256k1::PublicKey) -> Self {
+        PublicKey {
+            compressed: false,
+            inner: key,
+        }
+    }
+
+    /// Parses a compressed or uncompressed key, keeping its compression.
+    pub fn from_slice(data: &[u8]) -> Result<Self, secp256k1::Error> {
+        Ok(PublicKey {
+            compressed: data.len() == secp256k1::constants::PUBLIC_KEY_SIZE,
+            inner: secp256k1::PublicKey::from_slice(data)?,
+        })
+    }
+
+    /// Serializes the key, in 33 bytes if it is compressed and 65 bytes otherwise.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        if self.compressed {
+            self.inner.serialize().to_vec()
+        } else {
+            self.inner.serialize_uncompressed().to_vec()
+        }
+    }
+
+    /// Returns the hash of the key paid to by P2PKH outputs.
+    pub fn pubkey_hash(&self) -> PubkeyHash {
+        PubkeyHash::from_raw_hash(hash160::Hash::hash(&self.to_bytes()))
+    }
+
+    /// Returns the hash of the key paid to by P2WPKH outputs, or `None` if the key is
+    /// uncompressed, which segwit doesn't allow.
+    pub fn wpubkey_hash(&self) -> Option<WPubkeyHash> {
+        if self.compressed {
+            Some(WPubkeyHash::from_raw_hash(hash160::Hash::hash(
+                &self.inner.serialize(),
+            )))
+        } else {
+            None
+        }
+    }
+}
+
+impl From<secp256k1::PublicKey> for PublicKey {
+    fn from(key: secp256k1::PublicKey) -> Self {
+        PublicKey::new(key)
+    }
+}
+
+/// First byte of the WIF encoding of a mainnet private key.
+pub const WIF_PREFIX_MAIN: u8 = 0x80;
+/// First byte of the WIF encoding of a testnet, signet or regtest private key.
+pub const WIF_PREFIX_TEST: u8 = 0xef;
+
+/// Ways that decoding a WIF private key might fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FromWifError {
+    /// The key isn't valid base58 with a correct checksum.
+    Base58(base58::Error),
+    /// The decoded key isn't 33 bytes, or 34 with a compression flag.
+    InvalidLength(usize),
+    /// The first byte is neither the mainnet nor the testnet prefix.
+    InvalidPrefix(u8),
+    /// The byte after the key isn't the `0x01` compression flag.
+    InvalidCompressionFlag(u8),
+    /// The key is zero or not less than the curve order.
+    InvalidSecretKey,
+}
+
+impl fmt::Display for FromWifError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FromWifError::Base58(ref error) => write!(f, "invalid base58: {}", error),
+            FromWifError::InvalidLength(len) => write!(f, "invalid WIF length {}", len),
+            FromWifError::InvalidPrefix(prefix) => {
+                write!(f, "invalid WIF prefix {:#04x}", prefix)
+            }
+            FromWifError::InvalidCompressionFlag(flag) => {
+                write!(f, "invalid WIF compression flag {:#04x}", flag)
+            }
+            FromWifError::InvalidSecretKey => f.write_str("invalid secret key"),
+        }
+    }
+}
+
+impl From<base58::Error> for FromWifError {
+    fn from(error: base58::Error) -> Self {
+        FromWifError::Base58(error)
+    }
+}
+
+/// A private key, along with whether its public key is serialized compressed and the
+/// network it is used on, which are part of its WIF encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrivateKey {
+    /// Whether the public key is serialized in 33 rather than 65 bytes.
+    pub compressed: bool,
+    pub network: Network,
+    pub inner: secp256k1::SecretKey,
+}
+
+impl PrivateKey {
+    /// Wraps a key whose public key is serialized compressed, used on [`Network::Bitcoin`].
+    pub fn new(key: secp256k1::SecretKey) -> Self {
+        PrivateKey {
+            compressed: true,
+            network: Network::Bitcoin,
+            inner: key,
+        }
+    }
+
+    /// Wraps a key whose public key is serialized uncompressed, used on
+    /// [`Network::Bitcoin`].
+    pub fn new_uncompressed(key: secp256k1::SecretKey) -> Self {
+        PrivateKey {
+            compressed: false,
+            network: Network::Bitcoin,
+            inner: key,
+        }
+    }
+
+    /// Parses a 32-byte key, whose public key is serialized compressed, used on
+    /// [`Network::Bitcoin`].
+    pub fn from_slice(data: &[u8]) -> Result<Self, secp256k1::Error> {
+        Ok(PrivateKey::new(secp256k1::SecretKey::from_slice(data)?))
+    }
+
+    /// Returns the key used on `network`, which only changes its WIF encoding.
+    pub fn with_network(self, network: Network) -> Self {
+        PrivateKey { network, ..self }
+    }
+
+    /// Decodes a key in the Wallet Import Format, as exported by Bitcoin Core's
+    /// `dumpprivkey`.
+    ///
+    /// Keys with the testnet prefix are decoded as [`Network::Testnet`] keys, since
+    /// signet and regtest keys share it.
+    pub fn from_wif(wif: &str) -> Result<Self, FromWifError> {
+        let data = base58::decode_check(wif)?;
+        let compressed = match data.len() {
+            33 => false,
+            34 if data[33] == 1 => true,
+            34 => return Err(FromWifError::InvalidCompressionFlag(data[33])),
+            len => return Err(FromWifError::InvalidLength(len)),
+        };
+        let network = match data[0] {
+            WIF_PREFIX_MAIN => Network::Bitcoin,
+            WIF_PREFIX_TEST => Network::Testnet,
+            prefix => return Err(FromWifError::InvalidPrefix(prefix)),
+        };
+        let inner = secp256k1::SecretKey::from_slice(&data[1..33])
+            .map_err(|_| FromWifError::InvalidSecretKey)?;
+        Ok(PrivateKey {
+            compressed,
+            network,
+            inner,
+        })
+    }
+
+    /// Encodes the key in the Wallet Import Format.
+    pub fn to_wif(&self) -> String {
+        let mut data = Vec::with_capacity(34);
+        data.push(match self.network {
+            Network::Bitcoin => WIF_PREFIX_MAIN,
+            _ => WIF_PREFIX_TEST,
+        });
+        data.extend_from_slice(&self.inner.secret_bytes());
+        if self.compressed {
+            data.push(1);
+        }
+        base58::encode_check(&data)
+    }
+
+    /// Serializes the key in 32 bytes.
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.inner.secret_bytes()
+    }
+
+    /// Returns the public key, with the same compression.
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey {
+            compressed: self.compressed,
+            inner: self.inner.public_key(&Secp256k1::signing_only()),
+        }
+    }
+
+    /// Tweaks the key like [`TweakedPublicKey::from_internal_key`] tweaks its x-only
+    /// public key, giving the key signing for the output key of a P2TR key path spend.
+    #[cfg(feature = "signing")]
+    pub fn tap_tweak(&self, merkle_root: Option<TapNodeHash>) -> PrivateKey {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_secret_key(&secp, &self.inner);
+        let (internal_key, _) = keypair.x_only_public_key();
+        let tweak = TapTweakHash::from_key_and_tweak(&internal_key, merkle_root).to_scalar();
+        let keypair = keypair
+            .add_xonly_tweak(&secp, &tweak)
+            .expect("tweaked key is valid with overwhelming probability");
+        PrivateKey {
+            inner: keypair.secret_key(),
+            ..*self
+        }
+    }
+}
+
+/// An x-only key tweaked to commit to a script tree, or to no scripts, as paid to by
+/// P2TR outputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TweakedPublicKey(XOnlyPublicKey);
+
+impl TweakedPublicKey {
+    /// Tweaks `internal_key` to commit to the script tree with `merkle_root`, or to no
+    /// scripts at all, returning the output key and the parity of its full point.
+    pub fn from_internal_key(
+        internal_key: XOnlyPublicKey,
+        merkle_root: Option<TapNodeHash>,
+    ) -> (TweakedPublicKey, Parity) {
+        let tweak = TapTweakHash::from_key_and_tweak(&internal_key, merkle_root).to_scalar();
+        let (output_key, parity) = internal_key
+            .add_tweak(&Secp256k1::verification_only(), &tweak)
+            .expect("tweaked key is valid with overwhelming probability");
+        (TweakedPublicKey(output_key), parity)
+    }
+
+    /// Wraps a key known to be tweaked already, such as the output key of a P2TR script.
+    pub fn dangerous_assume_tweaked(key: XOnlyPublicKey) -> Self {
+        TweakedPublicKey(key)
+    }
+
+    /// Returns the tweaked key.
+    pub fn to_inner(self) -> XOnlyPublicKey {
+        self.0
+    }
+
+    /// Serializes the key in 32 bytes.
+    pub fn serialize(&self) -> [u8; 32] {
+        self.0.serialize()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use secp256k1::{Secp256k1, SecretKey};
+
+    fn key() -> PublicKey {
+        let secret_key = SecretKey::from_slice(&[0x42; 32]).unwrap();
+        PublicKey::new(secp256k1::PublicKey::from_secret_key(
+            &Secp256k1::new(),
+            &secret_key,
+        ))
+    }
+
+    #[test]
+    fn hashes_match_rust_bitcoin() {
+        for key in [key(), PublicKey::new_uncompressed(key().inner)] {
+            let expected = bitcoin::PublicKey::from_slice(&key.to_bytes()).unwrap();
+            assert_eq!(PublicKey::from_slice(&key.to_bytes()), Ok(key));
+            assert_eq!(key.compressed, expected.compressed);
+            assert_eq!(
+                key.pubkey_hash().to_byte_array(),
+                expected.pubkey_hash().to_byte_array()
+            );
+            assert_eq!(
+                key.wpubkey_hash().map(|hash| hash.to_byte_array()),
+                expected.wpubkey_hash().map(|hash| hash.to_byte_array())
+            );
+        }
+    }
+
+    #[test]
+    fn compression() {
+        let key = key();
+        let uncompressed = PublicKey::new_uncompressed(key.inner);
+        assert_eq!(PublicKey::from(key.inner), key);
+        assert_eq!(key.to_bytes().len(), 33);
+        assert_eq!(uncompressed.to_bytes().len(), 65);
+        assert_ne!(uncompressed.pubkey_hash(), key.pubkey_hash());
+        assert_eq!(uncompressed.wpubkey_hash(), None);
+        assert!(PublicKey::from_slice(&[0x02; 32]).is_err());
+    }
+
+    #[test]
+    fn private_key() {
+        let private_key = PrivateKey::from_slice(&[0x42; 32]).unwrap();
+        assert_eq!(private_key.public_key(), key());
+        assert_eq!(private_key.to_bytes(), [0x42; 32]);
+        assert_eq!(private_key.network, Network::Bitcoin);
+        let uncompressed = PrivateKey::new_uncompressed(private_key.inner);
+        assert_eq!(
+            uncompressed.public_key(),
+            PublicKey::new_uncompressed(key().inner)
+        );
+        assert!(PrivateKey::from_slice(&[0; 32]).is_err());
+        assert!(PrivateKey::from_slice(&[0x42; 31]).is_err());
+    }
+
+    #[test]
+    fn wif() {
+        let key = PrivateKey::from_slice(&[0x42; 32]).unwrap();
+        let keys = [
+            key,
+            PrivateKey::new_uncompressed(key.inner),
+            key.with_network(Network::Testnet),
+            PrivateKey::new_uncompressed(key.inner).with_network(Network::Testnet),
+        ];
+        for key in keys {
+            let wif = key.to_wif();
+            let expected = bitcoin::PrivateKey::from_wif(&wif).unwrap();
+            assert_eq!(expected.to_bytes(), key.to_bytes());
+            assert_eq!(expected.compressed, key.compressed);
+            assert_eq!(expected.to_wif(), wif);
+            assert_eq!(PrivateKey::from_wif(&wif), Ok(key));
+        }
+        let regtest = key.with_network(Network::Regtest);
+        assert_eq!(
+            PrivateKey::from_wif(&regtest.to_wif()),
+            Ok(key.with_network(Network::Testnet))
+        );
+
+        // From Bitcoin Core's key_tests.
+        let wif = "5HxWvvfubhXpYYpS3tJkw6fq9jE9j18THftkZjHHfmFiWtmAbrj";
+        let key = PrivateKey::from_wif(wif).unwrap();
+        assert!(!key.compressed);
+        assert_eq!(key.network, Network::Bitcoin);
+        assert_eq!(key.to_wif(), wif);
+
+        let encode = |data: &[u8]| base58::encode_check(data);
+        let mut data = vec![WIF_PREFIX_MAIN];
+        data.extend_from_slice(&[0x42; 32]);
+        assert!(matches!(
+            PrivateKey::from_wif(&encode(&data)[1..]),
+            Err(FromWifError::Base58(_))
+        ));
+        assert_eq!(
+            PrivateKey::from_wif(&encode(&data[..32])),
+            Err(FromWifError::InvalidLength(32))
+        );
+        data.push(0x02);
+        assert_eq!(
+            PrivateKey::from_wif(&encode(&data)),
+            Err(FromWifError::InvalidCompressionFlag(0x02))
+        );
+        data[0] = 0x00;
+        data[33] = 0x01;
+        assert_eq!(
+            PrivateKey::from_wif(&encode(&data)),
+            Err(FromWifError::InvalidPrefix(0x00))
+        );
+        let mut zero = vec![WIF_PREFIX_TEST];
+        zero.extend_from_slice(&[0; 32]);
+        assert_eq!(
+            PrivateKey::from_wif(&encode(&zero)),
+            Err(FromWifError::InvalidSecretKey)
+        );
+    }
+
+    #[test]
+    fn tap_tweak_matches_rust_bitcoin() {
+        use bitcoin::key::TapTweak;
+
+        let (internal_key, _) = key().inner.x_only_public_key();
+        let expected_key =
+            bitcoin::key::UntweakedPublicKey::from_slice(&internal_key.serialize()).unwrap();
+        let secp = bitcoin::secp256k1::Secp256k1::verification_only();
+        for merkle_root in [None, Some(TapNodeHash::from_byte_array([0x01; 32]))] {
+            let (output_key, parity) =
+                TweakedPublicKey::from_internal_key(internal_key, merkle_root);
+            let (expected, expected_parity) = expected_key.tap_tweak(
+                &secp,
+                merkle_root.map(|root| {
+                    bitcoin::taproot::TapNodeHash::from_byte_array(root.to_byte_array())
+                }),
+            );
+            assert_eq!(output_key.serialize(), expected.serialize());
+            assert_eq!(parity.to_u8(), expected_parity.to_u8());
+            assert_eq!(
+                TweakedPublicKey::dangerous_assume_tweaked(output_key.to_inner()),
+                output_key
+            );
+        }
+    }
+}
@@ -1,6 +1,19 @@
 pub mod address;
+pub mod amount;
+pub mod bip32;
+pub mod bip39;
+pub mod block;
+pub mod chain;
 pub mod constants;
+pub mod descriptor;
+pub mod ecdsa;
+pub mod key;
+pub mod params;
+pub mod pow;
 pub mod script;
+pub mod sighash;
+pub mod subsidy;
+pub mod taproot;
 pub mod transaction;
 pub mod types;
 pub mod utils;
@@ -0,0 +1,59 @@
+//! Consensus parameters.
+//!
+//! Per-network values used when validating block headers and block contents.
+
+use crate::constants::SUBSIDY_HALVING_INTERVAL;
+use crate::pow::Target;
+use crate::types::Network;
+
+/// Consensus parameters of a network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Params {
+    /// Network these parameters belong to.
+    pub network: Network,
+    /// Easiest target any block may have.
+    pub pow_limit: Target,
+    /// Whether blocks more than twice the target spacing apart may be mined at
+    /// the minimum difficulty (the testnet 20-minute rule).
+    pub allow_min_difficulty_blocks: bool,
+    /// Whether the difficulty is never adjusted (regtest).
+    pub no_pow_retargeting: bool,
+    /// Number of blocks between block subsidy halvings.
+    pub subsidy_halving_interval: u32,
+}
+
+impl Params {
+    /// Returns the consensus parameters of `network`.
+    pub fn new(network: Network) -> Self {
+        match network {
+            Network::Bitcoin => Params {
+                network,
+                pow_limit: Target::MAX_ATTAINABLE_MAINNET,
+                allow_min_difficulty_blocks: false,
+                no_pow_retargeting: false,
+                subsidy_halving_interval: SUBSIDY_HALVING_INTERVAL,
+            },
+            Network::Testnet => Params {
+                network,
+                pow_limit: Target::MAX_ATTAINABLE_TESTNET,
+                allow_min_difficulty_blocks: true,
+                no_pow_retargeting: false,
+                subsidy_halving_interval: SUBSIDY_HALVING_INTERVAL,
+            },
+            Network::Signet => Params {
+                network,
+                pow_limit: Target::MAX_ATTAINABLE_SIGNET,
+                allow_min_difficulty_blocks: false,
+                no_pow_retargeting: false,
+                subsidy_halving_interval: SUBSIDY_HALVING_INTERVAL,
+            },
+            Network::Regtest => Params {
+                network,
+                pow_limit: Target::MAX_ATTAINABLE_REGTEST,
+                allow_min_difficulty_blocks: true,
+                no_pow_retargeting: true,
+                subsidy_halving_interval: 150,
+            },
+        }
+    }
+}
@@ -0,0 +1,434 @@
+//! Proof-of-work related types.
+//!
+//! Provides the [`Target`] a block hash must not exceed, the [`Work`] a block
+//! contributes to its chain and the difficulty retargeting calculation.
+
+use crate::constants::DIFFCHANGE_TIMESPAN;
+use crate::params::Params;
+use core::cmp::Ordering;
+use core::fmt;
+use core::ops::Add;
+
+/// A proof-of-work target threshold.
+///
+/// A block is valid if its hash, interpreted as a little-endian 256-bit
+/// integer, is less than or equal to the target encoded in its `bits` field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Target(U256);
+
+impl Target {
+    /// The target of zero, never met by any block.
+    pub const ZERO: Target = Target(U256::ZERO);
+    /// The proof-of-work limit on mainnet.
+    pub const MAX_ATTAINABLE_MAINNET: Target = Target(U256(u128::MAX >> 32, u128::MAX));
+    /// The proof-of-work limit on testnet.
+    pub const MAX_ATTAINABLE_TESTNET: Target = Target(U256(u128::MAX >> 32, u128::MAX));
+    /// The proof-of-work limit on signet.
+    pub const MAX_ATTAINABLE_SIGNET: Target = Target(U256(0x0377ae << 88, 0));
+    /// The proof-of-work limit on regtest.
+    pub const MAX_ATTAINABLE_REGTEST: Target = Target(U256(u128::MAX >> 1, u128::MAX));
+
+    /// Decodes a target from its compact ("nBits") representation.
+    ///
+    /// Returns `None` if the encoding is negative or does not fit in 256 bits.
+    pub fn from_compact(bits: u32) -> Option<Target> {
+        let size = bits >> 24;
+        let mut word = bits & 0x007f_ffff;
+
+        let value = if size <= 3 {
+            word >>= 8 * (3 - size);
+            U256::from(word as u64)
+        } else {
+            let overflow = word != 0
+                && (size > 34 || (word > 0xff && size > 33) || (word > 0xffff && size > 32));
+            if overflow {
+                return None;
+            }
+            U256::from(word as u64).shl(8 * (size - 3))
+        };
+
+        if word != 0 && (bits & 0x0080_0000) != 0 {
+            return None;
+        }
+
+        Some(Target(value))
+    }
+
+    /// Encodes the target in compact form, dropping any precision beyond 23 bits.
+    pub fn to_compact_lossy(self) -> u32 {
+        let mut size = self.0.bits().div_ceil(8);
+        let mut compact = if size <= 3 {
+            (self.0.low_u64() << (8 * (3 - size))) as u32
+        } else {
+            self.0.shr(8 * (size - 3)).low_u64() as u32
+        };
+
+        // The 0x00800000 bit denotes the sign, so if it is already set divide the
+        // mantissa by 256 and increase the exponent.
+        if compact & 0x0080_0000 != 0 {
+            compact >>= 8;
+            size += 1;
+        }
+
+        compact | (size << 24)
+    }
+
+    /// Creates a target from its big-endian byte representation.
+    pub fn from_be_bytes(bytes: [u8; 32]) -> Target {
+        Target(U256::from_be_bytes(bytes))
+    }
+
+    /// Returns the big-endian byte representation of the target.
+    pub fn to_be_bytes(self) -> [u8; 32] {
+        self.0.to_be_bytes()
+    }
+
+    /// Returns true if a block hash in internal byte order meets this target.
+    pub fn is_met_by(&self, hash: [u8; 32]) -> bool {
+        U256::from_le_bytes(hash) <= self.0
+    }
+
+    /// Returns the expected number of hashes needed to find a block meeting
+    /// this target, `2**256 / (target + 1)`.
+    pub fn to_work(self) -> Work {
+        if self.0 == U256::MAX {
+            return Work(U256::ONE);
+        }
+        // 2**256 doesn't fit in 256 bits, but (2**256 - target - 1) / (target + 1) + 1 is
+        // the same value.
+        let divisor = self.0.wrapping_add(U256::ONE);
+        Work(self.0.not().div_rem(divisor).0.wrapping_add(U256::ONE))
+    }
+}
+
+impl fmt::LowerHex for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.0, f)
+    }
+}
+
+/// The amount of work represented by one or more blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Work(U256);
+
+impl Work {
+    /// No work at all.
+    pub const ZERO: Work = Work(U256::ZERO);
+
+    /// Creates a work value from its big-endian byte representation.
+    pub fn from_be_bytes(bytes: [u8; 32]) -> Work {
+        Work(U256::from_be_bytes(bytes))
+    }
+
+    /// Returns the big-endian byte representation of the work.
+    pub fn to_be_bytes(self) -> [u8; 32] {
+        self.0.to_be_bytes()
+    }
+}
+
+impl Add for Work {
+    type Output = Work;
+
+    fn add(self, rhs: Work) -> Work {
+        Work(self.0.wrapping_add(rhs.0))
+    }
+}
+
+impl fmt::LowerHex for Work {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.0, f)
+    }
+}
+
+/// Computes the compact target of the first block of a new difficulty period.
+///
+/// `last_bits` and `last_block_time` belong to the last block of the period that just
+/// ended and `first_block_time` to the first block of that period. The timespan is
+/// clamped to a factor of four in either direction and the result never exceeds the
+/// network's proof-of-work limit.
+pub fn calculate_next_work_required(
+    last_bits: u32,
+    first_block_time: u32,
+    last_block_time: u32,
+    params: &Params,
+) -> u32 {
+    if params.no_pow_retargeting {
+        return last_bits;
+    }
+
+    let timespan = (last_block_time as i64 - first_block_time as i64).clamp(
+        DIFFCHANGE_TIMESPAN as i64 / 4,
+        DIFFCHANGE_TIMESPAN as i64 * 4,
+    );
+
+    // Multiply before dividing to match Core's rounding, splitting the target into
+    // quotient and remainder so the intermediate product can't overflow.
+    let last_target = Target::from_compact(last_bits).unwrap_or(Target::ZERO);
+    let divisor = U256::from(DIFFCHANGE_TIMESPAN as u64);
+    let (quotient, remainder) = last_target.0.div_rem(divisor);
+    let (scaled, overflow) = quotient.mul_u64(timespan as u64);
+    let fraction = remainder.mul_u64(timespan as u64).0.div_rem(divisor).0;
+    let new_target = match scaled.checked_add(fraction) {
+        Some(target) if !overflow => Target(target),
+        _ => params.pow_limit,
+    };
+
+    new_target.min(params.pow_limit).to_compact_lossy()
+}
+
+/// Unsigned 256-bit integer stored as (high, low) halves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+struct U256(u128, u128);
+
+impl U256 {
+    const ZERO: U256 = U256(0, 0);
+    const ONE: U256 = U256(0, 1);
+    const MAX: U256 = U256(u128::MAX, u128::MAX);
+
+    fn from_be_bytes(bytes: [u8; 32]) -> U256 {
+        let mut hi = [0u8; 16];
+        let mut lo = [0u8; 16];
+        hi.copy_from_slice(&bytes[..16]);
+        lo.copy_from_slice(&bytes[16..]);
+        U256(u128::from_be_bytes(hi), u128::from_be_bytes(lo))
+    }
+
+    fn from_le_bytes(mut bytes: [u8; 32]) -> U256 {
+        bytes.reverse();
+        U256::from_be_bytes(bytes)
+    }
+
+    fn to_be_bytes(self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        bytes[..16].copy_from_slice(&self.0.to_be_bytes());
+        bytes[16..].copy_from_slice(&self.1.to_be_bytes());
+        bytes
+    }
+
+    fn low_u64(self) -> u64 {
+        self.1 as u64
+    }
+
+    /// Returns the number of bits needed to represent the value.
+    fn bits(self) -> u32 {
+        if self.0 != 0 {
+            256 - self.0.leading_zeros()
+        } else {
+            128 - self.1.leading_zeros()
+        }
+    }
+
+    fn bit(self, index: u32) -> bool {
+        if index >= 128 {
+            (self.0 >> (index - 128)) & 1 == 1
+        } else {
+            (self.1 >> index) & 1 == 1
+        }
+    }
+
+    fn not(self) -> U256 {
+        U256(!self.0, !self.1)
+    }
+
+    fn shl(self, shift: u32) -> U256 {
+        match shift {
+            0 => self,
+            1..=127 => U256(
+                (self.0 << shift) | (self.1 >> (128 - shift)),
+                self.1 << shift,
+            ),
+            128..=255 => U256(self.1 << (shift - 128), 0),
+            _ => U256::ZERO,
+        }
+    }
+
+    fn shr(self, shift: u32) -> U256 {
+        match shift {
+            0 => self,
+            1..=127 => U256(
+                self.0 >> shift,
+                (self.1 >> shift) | (self.0 << (128 - shift)),
+            ),
+            128..=255 => U256(0, self.0 >> (shift - 128)),
+            _ => U256::ZERO,
+        }
+    }
+
+    fn wrapping_add(self, rhs: U256) -> U256 {
+        let (lo, carry) = self.1.overflowing_add(rhs.1);
+        U256(self.0.wrapping_add(rhs.0).wrapping_add(carry as u128), lo)
+    }
+
+    fn checked_add(self, rhs: U256) -> Option<U256> {
+        let (lo, carry) = self.1.overflowing_add(rhs.1);
+        let (hi, overflow_a) = self.0.overflowing_add(rhs.0);
+        let (hi, overflow_b) = hi.overflowing_add(carry as u128);
+        if overflow_a || overflow_b {
+            None
+        } else {
+            Some(U256(hi, lo))
+        }
+    }
+
+    fn wrapping_sub(self, rhs: U256) -> U256 {
+        let (lo, borrow) = self.1.overflowing_sub(rhs.1);
+        U256(self.0.wrapping_sub(rhs.0).wrapping_sub(borrow as u128), lo)
+    }
+
+    /// Multiplies by a 64-bit value, returning the wrapped result and whether it overflowed.
+    fn mul_u64(self, rhs: u64) -> (U256, bool) {
+        const MASK: u128 = u64::MAX as u128;
+        let rhs = rhs as u128;
+
+        let p0 = (self.1 & MASK) * rhs;
+        let p1 = (self.1 >> 64) * rhs + (p0 >> 64);
+        let q0 = (self.0 & MASK) * rhs + (p1 >> 64);
+        let q1 = (self.0 >> 64) * rhs + (q0 >> 64);
+
+        let result = U256((q1 << 64) | (q0 & MASK), (p1 << 64) | (p0 & MASK));
+        (result, q1 >> 64 != 0)
+    }
+
+    /// Returns the quotient and remainder of `self / rhs`.
+    ///
+    /// # Panics
+    ///
+    /// If `rhs` is zero.
+    fn div_rem(self, rhs: U256) -> (U256, U256) {
+        assert!(rhs != U256::ZERO, "attempt to divide by zero");
+
+        let mut quotient = U256::ZERO;
+        let mut remainder = U256::ZERO;
+        for i in (0..self.bits()).rev() {
+            remainder = remainder.shl(1);
+            if self.bit(i) {
+                remainder.1 |= 1;
+            }
+            if remainder >= rhs {
+                remainder = remainder.wrapping_sub(rhs);
+                quotient = quotient.wrapping_add(U256::ONE.shl(i));
+            }
+        }
+        (quotient, remainder)
+    }
+}
+
+impl From<u64> for U256 {
+    fn from(value: u64) -> U256 {
+        U256(0, value as u128)
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &U256) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for U256 {
+    fn cmp(&self, other: &U256) -> Ordering {
+        self.0.cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
+
+impl fmt::LowerHex for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:032x}{:032x}", self.0, self.1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::Network;
+
+    #[test]
+    fn compact_roundtrip() {
+        for bits in [0x1d00ffff, 0x1b0404cb, 0x207fffff, 0x1e0377ae, 0x170331db] {
+            let target = Target::from_compact(bits).unwrap();
+            assert_eq!(target.to_compact_lossy(), bits);
+        }
+
+        let target = Target::from_compact(0x1d00ffff).unwrap();
+        let mut expected = [0u8; 32];
+        expected[4] = 0xff;
+        expected[5] = 0xff;
+        assert_eq!(target.to_be_bytes(), expected);
+
+        assert_eq!(
+            Target::MAX_ATTAINABLE_MAINNET.to_compact_lossy(),
+            0x1d00ffff
+        );
+        assert_eq!(Target::MAX_ATTAINABLE_SIGNET.to_compact_lossy(), 0x1e0377ae);
+        assert_eq!(
+            Target::MAX_ATTAINABLE_REGTEST.to_compact_lossy(),
+            0x207fffff
+        );
+    }
+
+    #[test]
+    fn compact_rejects_negative_and_overflow() {
+        assert_eq!(Target::from_compact(0x04923456), None);
+        assert_eq!(Target::from_compact(0xff123456), None);
+        assert_eq!(Target::from_compact(0x01003456), Some(Target::ZERO));
+        assert_eq!(
+            Target::from_compact(0x01123456).unwrap().to_be_bytes()[31],
+            0x12
+        );
+    }
+
+    #[test]
+    fn work_from_target() {
+        let work = Target::from_compact(0x1d00ffff).unwrap().to_work();
+        let mut expected = [0u8; 32];
+        expected[27] = 0x01;
+        expected[29] = 0x01;
+        expected[31] = 0x01;
+        // 2**256 / (0xffff * 2**208 + 1) rounded down is 0x0100010001
+        assert_eq!(work.to_be_bytes(), expected);
+
+        let regtest = Target::MAX_ATTAINABLE_REGTEST.to_work();
+        assert_eq!(regtest.to_be_bytes()[31], 2);
+        assert_eq!(
+            regtest + regtest,
+            Work::from_be_bytes({
+                let mut bytes = [0u8; 32];
+                bytes[31] = 4;
+                bytes
+            })
+        );
+    }
+
+    // Vectors from Bitcoin Core's `pow_tests.cpp`.
+    #[test]
+    fn next_work_required() {
+        let params = Params::new(Network::Bitcoin);
+
+        // block #32255, retargeting from block #30240
+        assert_eq!(
+            calculate_next_work_required(0x1d00ffff, 1261130161, 1262152739, &params),
+            0x1d00d86a
+        );
+        // target can't go above the proof-of-work limit
+        assert_eq!(
+            calculate_next_work_required(0x1d00ffff, 1231006505, 1233061996, &params),
+            0x1d00ffff
+        );
+        // timespan clamped to a quarter of the expected one
+        assert_eq!(
+            calculate_next_work_required(0x1c05a3f4, 1279008237, 1279297671, &params),
+            0x1c0168fd
+        );
+        // timespan clamped to four times the expected one
+        assert_eq!(
+            calculate_next_work_required(0x1c387f6f, 1263163443, 1269211443, &params),
+            0x1d00e1fd
+        );
+
+        let regtest = Params::new(Network::Regtest);
+        assert_eq!(
+            calculate_next_work_required(0x207fffff, 0, 1, &regtest),
+            0x207fffff
+        );
+    }
+}
@@ -0,0 +1,392 @@
+//! Parsing of script ASM.
+
+use super::interpreter::push_encoding;
+use super::num::ScriptNum;
+use super::script::ScriptBuf;
+use core::fmt;
+use core::str::FromStr;
+use opcodes::all::*;
+use opcodes::Opcode;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Largest magnitude of a decimal number in ASM, as in Bitcoin Core's `ParseScript`.
+const MAX_ASM_NUMBER: i64 = 0xffff_ffff;
+
+/// Ways that parsing ASM might fail, with the byte position of the offending token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParseAsmError {
+    /// The token is neither an opcode, a number, `<hex>` data nor a quoted string.
+    UnknownToken { pos: usize, token: String },
+    /// A decimal number is outside of `-0xffffffff..=0xffffffff`.
+    NumberOutOfRange { pos: usize, token: String },
+    /// A `0x` or `<...>` token, or the data of a push opcode, isn't valid hex.
+    InvalidHex { pos: usize, token: String },
+    /// A push opcode isn't followed by its data.
+    MissingPushData { pos: usize },
+    /// The data following a push opcode has a different length than the opcode pushes.
+    PushLengthMismatch {
+        pos: usize,
+        expected: usize,
+        actual: usize,
+    },
+}
+
+impl ParseAsmError {
+    /// Returns the byte position of the token that couldn't be parsed.
+    pub fn pos(&self) -> usize {
+        match *self {
+            ParseAsmError::UnknownToken { pos, .. }
+            | ParseAsmError::NumberOutOfRange { pos, .. }
+            | ParseAsmError::InvalidHex { pos, .. }
+            | ParseAsmError::MissingPushData { pos }
+            | ParseAsmError::PushLengthMismatch { pos, .. } => pos,
+        }
+    }
+}
+
+impl fmt::Display for ParseAsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseAsmError::UnknownToken { pos, ref token } => {
+                write!(f, "unknown token `{}` at position {}", token, pos)
+            }
+            ParseAsmError::NumberOutOfRange { pos, ref token } => write!(
+                f,
+                "number `{}` at position {} is out of range, use <hex> for data",
+                token, pos
+            ),
+            ParseAsmError::InvalidHex { pos, ref token } => {
+                write!(f, "invalid hex `{}` at position {}", token, pos)
+            }
+            ParseAsmError::MissingPushData { pos } => {
+                write!(f, "push opcode at position {} is missing its data", pos)
+            }
+            ParseAsmError::PushLengthMismatch {
+                pos,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "push at position {} expects {} bytes of data, found {}",
+                pos, expected, actual
+            ),
+        }
+    }
+}
+
+impl ScriptBuf {
+    /// Parses a script from ASM.
+    ///
+    /// Tokens are separated by whitespace and can be:
+    ///
+    /// - opcode names, with or without the `OP_` prefix, as displayed by the `opcodes`
+    ///   crate or named by Bitcoin Core, e.g. `OP_CLTV` or `CHECKLOCKTIMEVERIFY`;
+    /// - decimal numbers, pushed as minimal script numbers;
+    /// - hex data in angle brackets, e.g. `<abcd>`, pushed with the smallest push opcode;
+    /// - `'strings'` without whitespace, pushed like hex data;
+    /// - `0x` followed by hex, inserted as raw bytes like in Bitcoin Core's test vectors;
+    /// - push opcodes followed by their data, e.g. `OP_PUSHBYTES_2 abcd` or
+    ///   `OP_PUSHDATA1 <>`, which are kept as they are, so that the output of
+    ///   [`Display`](fmt::Display) parses back.
+    ///
+    /// Like in Bitcoin Core, a token of digits is always a decimal number, so `1234` is
+    /// the number 1234 and `<1234>` the two bytes `0x12 0x34`.
+    pub fn from_asm(asm: &str) -> Result<ScriptBuf, ParseAsmError> {
+        let mut script = Vec::new();
+        let mut tokens = tokens(asm);
+        while let Some((pos, token)) = tokens.next() {
+            if let Some(hex) = token.strip_prefix("0x") {
+                let bytes = hex::decode(hex).map_err(|_| ParseAsmError::InvalidHex {
+                    pos,
+                    token: token.to_string(),
+                })?;
+                script.extend(bytes);
+            } else if token.len() >= 2 && token.starts_with('\'') && token.ends_with('\'') {
+                script.extend(push_encoding(&token.as_bytes()[1..token.len() - 1]));
+            } else if let Some(data) = bracketed(token) {
+                let data = hex::decode(data).map_err(|_| ParseAsmError::InvalidHex {
+                    pos,
+                    token: token.to_string(),
+                })?;
+                script.extend(push_encoding(&data));
+            } else if let Some(number) = parse_number(pos, token)? {
+                script.extend(number);
+            } else if let Some(opcode) = opcode_by_name(token) {
+                script.push(opcode.to_u8());
+                if let Some(len_size) = push_len_size(opcode) {
+                    let (data_pos, data) = tokens
+                        .next()
+                        .ok_or(ParseAsmError::MissingPushData { pos })?;
+                    let hex = bracketed(data).unwrap_or(data);
+                    let data = hex::decode(hex).map_err(|_| ParseAsmError::InvalidHex {
+                        pos: data_pos,
+                        token: data.to_string(),
+                    })?;
+                    if len_size == 0 {
+                        let expected = opcode.to_u8() as usize;
+                        if data.len() != expected {
+                            return Err(ParseAsmError::PushLengthMismatch {
+                                pos: data_pos,
+                                expected,
+                                actual: data.len(),
+                            });
+                        }
+                    } else {
+                        let len = (data.len() as u32).to_le_bytes();
+                        if len[len_size..].iter().any(|&byte| byte != 0) {
+                            return Err(ParseAsmError::PushLengthMismatch {
+                                pos: data_pos,
+                                expected: (1 << (8 * len_size)) - 1,
+                                actual: data.len(),
+                            });
+                        }
+                        script.extend_from_slice(&len[..len_size]);
+                    }
+                    script.extend(data);
+                }
+            } else {
+                return Err(ParseAsmError::UnknownToken {
+                    pos,
+                    token: token.to_string(),
+                });
+            }
+        }
+        Ok(ScriptBuf(script))
+    }
+}
+
+impl FromStr for ScriptBuf {
+    type Err = ParseAsmError;
+
+    fn from_str(asm: &str) -> Result<Self, Self::Err> {
+        ScriptBuf::from_asm(asm)
+    }
+}
+
+/// Splits `asm` on whitespace, with the byte position of each token.
+fn tokens(asm: &str) -> impl Iterator<Item = (usize, &str)> {
+    asm.split(char::is_whitespace)
+        .filter(|token| !token.is_empty())
+        .map(move |token| (token.as_ptr() as usize - asm.as_ptr() as usize, token))
+}
+
+/// Returns the hex between the angle brackets of a `<...>` token.
+fn bracketed(token: &str) -> Option<&str> {
+    token.strip_prefix('<')?.strip_suffix('>')
+}
+
+/// Parses a decimal number into its minimal push, or returns `None` if `token` isn't one.
+fn parse_number(pos: usize, token: &str) -> Result<Option<Vec<u8>>, ParseAsmError> {
+    let digits = token.strip_prefix('-').unwrap_or(token);
+    if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Ok(None);
+    }
+    let value = match token.parse::<i64>() {
+        Ok(value) if (-MAX_ASM_NUMBER..=MAX_ASM_NUMBER).contains(&value) => value,
+        _ => {
+            return Err(ParseAsmError::NumberOutOfRange {
+                pos,
+                token: token.to_string(),
+            })
+        }
+    };
+    let push = match value {
+        0 => vec![OP_PUSHBYTES_0.to_u8()],
+        -1 => vec![OP_PUSHNUM_NEG1.to_u8()],
+        1..=16 => vec![OP_PUSHNUM_1.to_u8() + value as u8 - 1],
+        _ => push_encoding(&ScriptNum::new(value).to_bytes()),
+    };
+    Ok(Some(push))
+}
+
+/// Returns the opcode named `name`, with or without its `OP_` prefix.
+fn opcode_by_name(name: &str) -> Option<Opcode> {
+    static OPCODES: OnceLock<HashMap<String, Opcode>> = OnceLock::new();
+    let opcodes = OPCODES.get_or_init(|| {
+        // In reverse, so that the lowest of opcodes displayed alike is kept.
+        let mut opcodes: HashMap<_, _> = (0..=u8::MAX)
+            .rev()
+            .map(Opcode::from)
+            .filter_map(|opcode| {
+                Some((opcode.to_string().strip_prefix("OP_")?.to_string(), opcode))
+            })
+            .collect();
+        // Names used by Bitcoin Core but not displayed by the opcodes crate.
+        let aliases = [
+            ("0", OP_PUSHBYTES_0),
+            ("FALSE", OP_PUSHBYTES_0),
+            ("TRUE", OP_PUSHNUM_1),
+            ("1NEGATE", OP_PUSHNUM_NEG1),
+            ("NOP2", OP_CLTV),
+            ("CHECKLOCKTIMEVERIFY", OP_CLTV),
+            ("NOP3", OP_CSV),
+            ("CHECKSEQUENCEVERIFY", OP_CSV),
+        ];
+        opcodes.extend(aliases.map(|(name, opcode)| (name.to_string(), opcode)));
+        for n in 1..=16 {
+            let opcode = Opcode::from(OP_PUSHNUM_1.to_u8() + n - 1);
+            opcodes.insert(n.to_string(), opcode);
+        }
+        opcodes
+    });
+    opcodes
+        .get(name.strip_prefix("OP_").unwrap_or(name))
+        .copied()
+}
+
+/// Returns the size of the length prefix of a push opcode followed by data, 0 for the
+/// `OP_PUSHBYTES_n` that encode the length themselves.
+fn push_len_size(opcode: Opcode) -> Option<usize> {
+    match opcode.to_u8() {
+        0x01..=0x4b => Some(0),
+        0x4c => Some(1),
+        0x4d => Some(2),
+        0x4e => Some(4),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_templates() {
+        let hash = [0x42; 20];
+        let asm = format!(
+            "OP_DUP OP_HASH160 <{}> OP_EQUALVERIFY OP_CHECKSIG",
+            hex::encode(hash)
+        );
+        assert_eq!(ScriptBuf::from_asm(&asm), Ok(ScriptBuf::new_p2pkh(&hash)));
+        assert_eq!(
+            format!("HASH160 <{}> EQUAL", hex::encode(hash)).parse(),
+            Ok(ScriptBuf::new_p2sh(&hash))
+        );
+        assert_eq!(
+            ScriptBuf::from_asm(&format!("0 <{}>", hex::encode([7; 32]))),
+            Ok(ScriptBuf::new_p2wsh(&[7; 32]))
+        );
+    }
+
+    #[test]
+    fn parse_display_round_trip() {
+        let scripts = [
+            vec![0x00, 0x4f, 0x51, 0x60, 0xb1, 0xba, 0xff],
+            vec![0x02, 0xab, 0xcd, 0x87],
+            // Non-minimal pushes keep their opcode.
+            vec![0x4c, 0x01, 0x05],
+            vec![0x4d, 0x02, 0x00, 0xab, 0xcd],
+            vec![0x4e, 0x01, 0x00, 0x00, 0x00, 0xee],
+            [vec![0x4c, 0xff], vec![0x11; 0xff]].concat(),
+            // Empty pushes other than OP_0.
+            vec![0x4c, 0x00],
+            vec![0x4d, 0x00, 0x00, 0x4e, 0x00, 0x00, 0x00, 0x00],
+        ];
+        for bytes in scripts {
+            let script = ScriptBuf::from(bytes);
+            assert_eq!(ScriptBuf::from_asm(&script.to_string()), Ok(script));
+        }
+    }
+
+    #[test]
+    fn parse_numbers_and_data() {
+        let parse = |asm: &str| ScriptBuf::from_asm(asm).unwrap().into_bytes();
+        assert_eq!(
+            parse("0 -1 1 16 TRUE FALSE"),
+            [0x00, 0x4f, 0x51, 0x60, 0x51, 0x00]
+        );
+        assert_eq!(
+            parse("17 -2 1000"),
+            [0x01, 0x11, 0x01, 0x82, 0x02, 0xe8, 0x03]
+        );
+        assert_eq!(parse("4294967295"), [0x05, 0xff, 0xff, 0xff, 0xff, 0x00]);
+        assert_eq!(
+            parse("NOP2 CHECKSEQUENCEVERIFY OP_CLTV"),
+            [0xb1, 0xb2, 0xb1]
+        );
+        assert_eq!(parse("0x4c01 0x07"), [0x4c, 0x01, 0x07]);
+        assert_eq!(parse("'ab' ''"), [0x02, b'a', b'b', 0x00]);
+        assert_eq!(parse("<abcd> <>"), [0x02, 0xab, 0xcd, 0x00]);
+        // Digits are always decimal numbers, and data is bracketed.
+        assert_eq!(parse("00 1234"), [0x00, 0x02, 0xd2, 0x04]);
+        assert_eq!(parse("12345678"), [0x04, 0x4e, 0x61, 0xbc, 0x00]);
+        assert_eq!(parse("<00> <1234>"), [0x01, 0x00, 0x02, 0x12, 0x34]);
+        assert_eq!(
+            parse("<12345678901234>"),
+            [0x07, 0x12, 0x34, 0x56, 0x78, 0x90, 0x12, 0x34]
+        );
+        assert_eq!(parse(&format!("<{}>", "ab".repeat(76)))[..2], [0x4c, 76]);
+        assert_eq!(
+            parse("OP_PUSHDATA1 <> OP_PUSHBYTES_1 <07>"),
+            [0x4c, 0x00, 0x01, 0x07]
+        );
+        assert!(parse("  \n\t").is_empty());
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = |asm: &str| ScriptBuf::from_asm(asm).unwrap_err();
+        assert_eq!(
+            err("OP_DUP OP_BOGUS"),
+            ParseAsmError::UnknownToken {
+                pos: 7,
+                token: "OP_BOGUS".to_string()
+            }
+        );
+        assert_eq!(
+            err("1 12345678901"),
+            ParseAsmError::NumberOutOfRange {
+                pos: 2,
+                token: "12345678901".to_string()
+            }
+        );
+        assert_eq!(err("-4294967296").pos(), 0);
+        assert_eq!(
+            err("OP_DUP 12345678901234"),
+            ParseAsmError::NumberOutOfRange {
+                pos: 7,
+                token: "12345678901234".to_string()
+            }
+        );
+        assert_eq!(
+            err("1 <abc>"),
+            ParseAsmError::InvalidHex {
+                pos: 2,
+                token: "<abc>".to_string()
+            }
+        );
+        assert_eq!(
+            err("0xabc"),
+            ParseAsmError::InvalidHex {
+                pos: 0,
+                token: "0xabc".to_string()
+            }
+        );
+        assert_eq!(
+            err("OP_1  OP_PUSHDATA1"),
+            ParseAsmError::MissingPushData { pos: 6 }
+        );
+        assert_eq!(
+            err("OP_PUSHBYTES_3 abcd"),
+            ParseAsmError::PushLengthMismatch {
+                pos: 15,
+                expected: 3,
+                actual: 2
+            }
+        );
+        assert_eq!(
+            err(&format!("OP_PUSHDATA1 {}", "00".repeat(256))).to_string(),
+            "push at position 13 expects 255 bytes of data, found 256"
+        );
+        assert_eq!(err("abc").to_string(), "unknown token `abc` at position 0");
+        assert_eq!(
+            err("OP_DUP abcd"),
+            ParseAsmError::UnknownToken {
+                pos: 7,
+                token: "abcd".to_string()
+            }
+        );
+    }
+}
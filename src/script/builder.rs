@@ -1,9 +1,19 @@
 use opcodes::Opcode;
 
+use super::interpreter::push_encoding;
+use super::num::ScriptNum;
 use super::script::ScriptBuf;
+use crate::constants::MAX_SCRIPT_ELEMENT_SIZE;
+use crate::key::{PublicKey, XOnlyPublicKey};
 
 pub struct ScriptBuilder(ScriptBuf);
 
+impl Default for ScriptBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ScriptBuilder {
     pub fn new() -> Self {
         ScriptBuilder(ScriptBuf(Vec::new()))
@@ -14,20 +24,17 @@ impl ScriptBuilder {
         self
     }
 
-    // push_int only supports 0, -1 and 1 to 16 for now
+    /// Pushes `data` as a minimally encoded script number: `OP_0`, `OP_1NEGATE` and
+    /// `OP_1` to `OP_16` for the small values, and a push of its bytes otherwise.
     pub fn push_int(&mut self, data: i64) -> &mut Self {
-        if data == 0 {
-            self.push_opcode(opcodes::OP_0);
-            return self;
-        }
-
-        if data == -1 || (1..=16).contains(&data) {
-            let opcode = Opcode::from((data - 1 + opcodes::OP_TRUE.to_u8() as i64) as u8);
-            self.push_opcode(opcode);
-            return self;
+        match data {
+            0 => self.push_opcode(opcodes::OP_0),
+            -1 | 1..=16 => {
+                let opcode = Opcode::from((data - 1 + opcodes::OP_TRUE.to_u8() as i64) as u8);
+                self.push_opcode(opcode)
+            }
+            _ => self.push_slice_with_size(&ScriptNum::new(data).to_bytes()),
         }
-
-        panic!("invalid integer to push");
     }
 
     pub fn push_slice_only(&mut self, data: &[u8]) -> &mut Self {
@@ -35,15 +42,31 @@ impl ScriptBuilder {
         self
     }
 
+    /// Pushes `data` with the smallest push opcode: `OP_PUSHBYTES_n` up to 75 bytes and
+    /// `OP_PUSHDATA1`, `OP_PUSHDATA2` or `OP_PUSHDATA4` with a length prefix above.
+    ///
+    /// # Panics
+    ///
+    /// If `data` is longer than [`MAX_SCRIPT_ELEMENT_SIZE`], which no script could push.
     pub fn push_slice_with_size(&mut self, data: &[u8]) -> &mut Self {
-        // TODO(chinonso): Handle large streams of data
-        self.push_opcode(Opcode::from(data.len() as u8));
-        self.0.push_slice(data);
+        assert!(
+            data.len() <= MAX_SCRIPT_ELEMENT_SIZE,
+            "cannot push {} bytes, the maximum is {}",
+            data.len(),
+            MAX_SCRIPT_ELEMENT_SIZE
+        );
+        self.0.push_slice(&push_encoding(data));
         self
     }
 
-    pub fn push_x_only_key(&mut self, x_only_key: &[u8]) -> &mut Self {
-        todo!()
+    /// Pushes a public key in 33 or 65 bytes, depending on whether it's compressed.
+    pub fn push_key(&mut self, key: &PublicKey) -> &mut Self {
+        self.push_slice_with_size(&key.to_bytes())
+    }
+
+    /// Pushes the 32 bytes of an x-only public key, as used by taproot.
+    pub fn push_x_only_key(&mut self, x_only_key: &XOnlyPublicKey) -> &mut Self {
+        self.push_slice_with_size(&x_only_key.serialize())
     }
 
     pub fn into_script(self) -> ScriptBuf {
@@ -88,10 +111,34 @@ mod test {
     }
 
     #[test]
-    #[should_panic]
-    fn push_int_panics() {
+    fn push_int_numbers() {
         let mut builder = ScriptBuilder::new();
-        builder.push_int(17);
+        builder
+            .push_int(17)
+            .push_int(-2)
+            .push_int(128)
+            .push_int(-255)
+            .push_int(0x7fff_ffff)
+            .push_int(i64::MIN);
+        assert_eq!(
+            builder.into_script().into_bytes(),
+            [
+                vec![1, 0x11, 1, 0x82, 2, 0x80, 0x00, 2, 0xff, 0x80],
+                vec![4, 0xff, 0xff, 0xff, 0x7f],
+                vec![9, 0, 0, 0, 0, 0, 0, 0, 0x80, 0x80],
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn push_int_matches_rust_bitcoin() {
+        for n in [-1_000_000, -129, -16, 16, 17, 75, 76, 1_000_000, i64::MAX] {
+            let mut builder = ScriptBuilder::new();
+            builder.push_int(n);
+            let expected = bitcoin::script::Builder::new().push_int(n).into_script();
+            assert_eq!(builder.into_script().into_bytes(), expected.into_bytes());
+        }
     }
 
     #[test]
@@ -113,4 +160,49 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn push_slice_large() {
+        for (len, prefix) in [
+            (75, vec![75]),
+            (76, vec![OP_PUSHDATA1.to_u8(), 76]),
+            (255, vec![OP_PUSHDATA1.to_u8(), 255]),
+            (256, vec![OP_PUSHDATA2.to_u8(), 0, 1]),
+            (520, vec![OP_PUSHDATA2.to_u8(), 8, 2]),
+        ] {
+            let data = vec![0xaa; len];
+            let mut builder = ScriptBuilder::new();
+            builder.push_slice_with_size(&data);
+            assert_eq!(builder.into_script().into_bytes(), [prefix, data].concat());
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn push_slice_too_large() {
+        ScriptBuilder::new().push_slice_with_size(&[0; MAX_SCRIPT_ELEMENT_SIZE + 1]);
+    }
+
+    #[test]
+    fn push_keys() {
+        let secp = secp256k1::Secp256k1::new();
+        let secret_key = secp256k1::SecretKey::from_slice(&[0x11; 32]).unwrap();
+        let key = secp256k1::PublicKey::from_secret_key(&secp, &secret_key);
+        let (x_only_key, _) = key.x_only_public_key();
+
+        let mut builder = ScriptBuilder::new();
+        builder
+            .push_key(&PublicKey::new(key))
+            .push_key(&PublicKey::new_uncompressed(key))
+            .push_x_only_key(&x_only_key)
+            .push_opcode(OP_CHECKSIG);
+        let script = builder.into_script().into_bytes();
+        assert_eq!(script[0], 33);
+        assert_eq!(script[1..34], key.serialize());
+        assert_eq!(script[34], 65);
+        assert_eq!(script[35..100], key.serialize_uncompressed());
+        assert_eq!(script[100], 32);
+        assert_eq!(script[101..133], x_only_key.serialize());
+        assert_eq!(script[133..], [OP_CHECKSIG.to_u8()]);
+    }
 }
@@ -1,7 +1,10 @@
+use borsh::{BorshDeserialize, BorshSerialize};
 use core::fmt;
 
 /// Ways that a script might fail.
-#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
+#[derive(
+    PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy, BorshDeserialize, BorshSerialize,
+)]
 #[non_exhaustive]
 pub enum ScriptError {
     /// Something did a non-minimal push; for more information see
@@ -11,6 +14,109 @@ pub enum ScriptError {
     EarlyEndOfScript,
     /// Tried to read an array off the stack as a number when it was more than 4 bytes.
     NumericOverflow,
+    /// Tried to read an array off the stack as a number when it was not minimally encoded.
+    NonMinimalNumber,
+    /// Script evaluated without error but finished with a false or empty top stack element.
+    EvalFalse,
+    /// `OP_RETURN` was executed.
+    OpReturn,
+    /// The script is larger than `MAX_SCRIPT_SIZE`.
+    ScriptSize,
+    /// A pushed element is larger than `MAX_SCRIPT_ELEMENT_SIZE`.
+    PushSize,
+    /// The script executes more than `MAX_OPS_PER_SCRIPT` non-push operations.
+    OpCount,
+    /// The stack and alt stack together hold more than `MAX_STACK_SIZE` elements.
+    StackSize,
+    /// `OP_CHECKMULTISIG` was given a negative signature count or more signatures than keys.
+    SigCount,
+    /// `OP_CHECKMULTISIG` was given a negative key count or more than `MAX_PUBKEYS_PER_MULTISIG` keys.
+    PubkeyCount,
+    /// `OP_VERIFY` failed.
+    Verify,
+    /// `OP_EQUALVERIFY` failed.
+    EqualVerify,
+    /// `OP_CHECKMULTISIGVERIFY` failed.
+    CheckMultisigVerify,
+    /// `OP_CHECKSIGVERIFY` failed.
+    CheckSigVerify,
+    /// `OP_NUMEQUALVERIFY` failed.
+    NumEqualVerify,
+    /// An opcode that is invalid in this position was executed.
+    BadOpcode,
+    /// One of the opcodes disabled in 2010 appears in the script.
+    DisabledOpcode,
+    /// An operation needed more stack elements than were available.
+    InvalidStackOperation,
+    /// `OP_FROMALTSTACK` was executed with an empty alt stack.
+    InvalidAltstackOperation,
+    /// `OP_IF`, `OP_NOTIF`, `OP_ELSE` and `OP_ENDIF` are not balanced.
+    UnbalancedConditional,
+    /// `OP_CHECKLOCKTIMEVERIFY` or `OP_CHECKSEQUENCEVERIFY` was given a negative value.
+    NegativeLocktime,
+    /// The lock time or sequence required by the script is not met by the transaction.
+    UnsatisfiedLocktime,
+    /// A signature has an undefined sighash type.
+    SigHashType,
+    /// A signature is not strictly DER encoded.
+    SigDer,
+    /// The argument of `OP_IF` or `OP_NOTIF` is not exactly empty or `0x01`.
+    MinimalIf,
+    /// The extra element consumed by `OP_CHECKMULTISIG` is not empty.
+    SigNullDummy,
+    /// A public key is neither compressed nor uncompressed.
+    PubkeyType,
+    /// The stack doesn't hold exactly one element after execution.
+    CleanStack,
+    /// A failing signature check was given a non-empty signature.
+    SigNullFail,
+    /// A signature has an S value above half the curve order.
+    SigHighS,
+    /// The scriptSig contains non-push operations.
+    SigPushOnly,
+    /// One of the NOPs reserved for soft forks was executed.
+    DiscourageUpgradableNops,
+    /// An output uses a witness version reserved for soft forks.
+    DiscourageUpgradableWitnessProgram,
+    /// A witness version 0 program is neither 20 nor 32 bytes long.
+    WitnessProgramWrongLength,
+    /// A P2WSH output was spent with an empty witness.
+    WitnessProgramWitnessEmpty,
+    /// The witness doesn't match the witness program.
+    WitnessProgramMismatch,
+    /// A native witness program was spent with a non-empty scriptSig.
+    WitnessMalleated,
+    /// A P2SH-wrapped witness program was spent with a scriptSig other than a single push
+    /// of the redeem script.
+    WitnessMalleatedP2sh,
+    /// Witness data was provided for an input that doesn't use it.
+    WitnessUnexpected,
+    /// A segwit script used an uncompressed public key.
+    WitnessPubkeyType,
+    /// `OP_CODESEPARATOR` appears in a legacy script while the script code must stay constant.
+    OpCodeSeparator,
+    /// A signature was found in the script code while the script code must stay constant.
+    SigFindAndDelete,
+    /// A Schnorr signature is neither 64 nor 65 bytes long.
+    SchnorrSigSize,
+    /// A Schnorr signature has an invalid or explicitly default sighash type.
+    SchnorrSigHashType,
+    /// A Schnorr signature is invalid.
+    SchnorrSig,
+    /// A taproot control block has an invalid size.
+    TaprootWrongControlSize,
+    /// A tapscript ran out of its signature validation weight budget.
+    TapscriptValidationWeight,
+    /// `OP_CHECKMULTISIG` or `OP_CHECKMULTISIGVERIFY` was executed in a tapscript.
+    TapscriptCheckMultisig,
+    /// The argument of `OP_IF` or `OP_NOTIF` in a tapscript isn't empty or `0x01`.
+    TapscriptMinimalIf,
+    /// A taproot leaf version is reserved for soft forks.
+    DiscourageUpgradableTaprootVersion,
+    /// A tapscript contains an `OP_SUCCESSx` opcode, reserved for soft forks.
+    DiscourageOpSuccess,
+    /// A tapscript public key type is reserved for soft forks.
+    DiscourageUpgradablePubkeyType,
 }
 
 impl fmt::Display for ScriptError {
@@ -19,6 +125,57 @@ impl fmt::Display for ScriptError {
             ScriptError::NonMinimalPush => f.write_str("non-minimal datapush"),
             ScriptError::EarlyEndOfScript => f.write_str("unexpected end of script"),
             ScriptError::NumericOverflow => f.write_str("numeric overflow (number on stack larger than 4 bytes)"),
+            ScriptError::NonMinimalNumber => f.write_str("non-minimally encoded script number"),
+            ScriptError::EvalFalse => f.write_str("script evaluated without error but finished with a false/empty top stack element"),
+            ScriptError::OpReturn => f.write_str("OP_RETURN was encountered"),
+            ScriptError::ScriptSize => f.write_str("script is too big"),
+            ScriptError::PushSize => f.write_str("push value size limit exceeded"),
+            ScriptError::OpCount => f.write_str("operation limit exceeded"),
+            ScriptError::StackSize => f.write_str("stack size limit exceeded"),
+            ScriptError::SigCount => f.write_str("signature count negative or greater than pubkey count"),
+            ScriptError::PubkeyCount => f.write_str("pubkey count negative or limit exceeded"),
+            ScriptError::Verify => f.write_str("script failed an OP_VERIFY operation"),
+            ScriptError::EqualVerify => f.write_str("script failed an OP_EQUALVERIFY operation"),
+            ScriptError::CheckMultisigVerify => f.write_str("script failed an OP_CHECKMULTISIGVERIFY operation"),
+            ScriptError::CheckSigVerify => f.write_str("script failed an OP_CHECKSIGVERIFY operation"),
+            ScriptError::NumEqualVerify => f.write_str("script failed an OP_NUMEQUALVERIFY operation"),
+            ScriptError::BadOpcode => f.write_str("opcode missing or not understood"),
+            ScriptError::DisabledOpcode => f.write_str("attempted to use a disabled opcode"),
+            ScriptError::InvalidStackOperation => f.write_str("operation not valid with the current stack size"),
+            ScriptError::InvalidAltstackOperation => f.write_str("operation not valid with the current altstack size"),
+            ScriptError::UnbalancedConditional => f.write_str("invalid OP_IF construction"),
+            ScriptError::NegativeLocktime => f.write_str("negative locktime"),
+            ScriptError::UnsatisfiedLocktime => f.write_str("locktime requirement not satisfied"),
+            ScriptError::SigHashType => f.write_str("signature hash type missing or not understood"),
+            ScriptError::SigDer => f.write_str("non-canonical DER signature"),
+            ScriptError::MinimalIf => f.write_str("OP_IF/NOTIF argument must be minimal"),
+            ScriptError::SigNullDummy => f.write_str("dummy CHECKMULTISIG argument must be zero"),
+            ScriptError::PubkeyType => f.write_str("public key is neither compressed or uncompressed"),
+            ScriptError::CleanStack => f.write_str("stack size must be exactly one after execution"),
+            ScriptError::SigNullFail => f.write_str("signature must be zero for failed CHECK(MULTI)SIG operation"),
+            ScriptError::SigHighS => f.write_str("non-canonical signature: S value is unnecessarily high"),
+            ScriptError::SigPushOnly => f.write_str("only push operators allowed in signatures"),
+            ScriptError::DiscourageUpgradableNops => f.write_str("NOPx reserved for soft-fork upgrades"),
+            ScriptError::DiscourageUpgradableWitnessProgram => f.write_str("witness version reserved for soft-fork upgrades"),
+            ScriptError::WitnessProgramWrongLength => f.write_str("witness program has incorrect length"),
+            ScriptError::WitnessProgramWitnessEmpty => f.write_str("witness program was passed an empty witness"),
+            ScriptError::WitnessProgramMismatch => f.write_str("witness program hash mismatch"),
+            ScriptError::WitnessMalleated => f.write_str("witness requires empty scriptSig"),
+            ScriptError::WitnessMalleatedP2sh => f.write_str("witness requires only-redeemscript scriptSig"),
+            ScriptError::WitnessUnexpected => f.write_str("witness provided for non-witness script"),
+            ScriptError::WitnessPubkeyType => f.write_str("using non-compressed keys in segwit"),
+            ScriptError::OpCodeSeparator => f.write_str("using OP_CODESEPARATOR in non-witness script"),
+            ScriptError::SigFindAndDelete => f.write_str("signature is found in scriptCode"),
+            ScriptError::SchnorrSigSize => f.write_str("invalid Schnorr signature size"),
+            ScriptError::SchnorrSigHashType => f.write_str("invalid Schnorr signature hash type"),
+            ScriptError::SchnorrSig => f.write_str("invalid Schnorr signature"),
+            ScriptError::TaprootWrongControlSize => f.write_str("invalid Taproot control block size"),
+            ScriptError::TapscriptValidationWeight => f.write_str("too much signature validation relative to witness weight"),
+            ScriptError::TapscriptCheckMultisig => f.write_str("OP_CHECKMULTISIG(VERIFY) is not available in tapscript"),
+            ScriptError::TapscriptMinimalIf => f.write_str("OP_IF/NOTIF argument must be minimal in tapscript"),
+            ScriptError::DiscourageUpgradableTaprootVersion => f.write_str("taproot version reserved for soft-fork upgrades"),
+            ScriptError::DiscourageOpSuccess => f.write_str("OP_SUCCESSx reserved for soft-fork upgrades"),
+            ScriptError::DiscourageUpgradablePubkeyType => f.write_str("public key version reserved for soft-fork upgrades"),
         }
     }
 }
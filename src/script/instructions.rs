@@ -1,5 +1,7 @@
-use crate::script::{error::ScriptError, ScriptBuf};
+use crate::script::{error::ScriptError, ScriptBuf, ScriptNum};
 use core::convert::TryInto;
+use opcodes::all::*;
+use opcodes::Opcode;
 
 /// A "parsed opcode" which allows iterating over a [`Script`] in a more sensible way.
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
@@ -26,6 +28,36 @@ impl<'a> Instruction<'a> {
             Instruction::PushBytes(bytes) => Some(bytes),
         }
     }
+
+    /// Returns the number pushed by the instruction, if it pushes a minimally encoded
+    /// number of at most 4 bytes or is one of `OP_1NEGATE` and `OP_1` to `OP_16`.
+    pub fn script_num(&self) -> Option<ScriptNum> {
+        match *self {
+            Instruction::PushBytes(bytes) => ScriptNum::from_slice(bytes, true).ok(),
+            Instruction::Op(OP_PUSHNUM_NEG1) => Some(ScriptNum::new(-1)),
+            Instruction::Op(op) => decode_pushnum(op).map(|n| ScriptNum::new(n as i64)),
+        }
+    }
+
+    /// Returns the number pushed by `OP_0` to `OP_16`, as used for the key and signature
+    /// counts of multisig scripts.
+    pub fn small_int(&self) -> Option<u8> {
+        match *self {
+            Instruction::PushBytes([]) => Some(0),
+            Instruction::Op(op) => decode_pushnum(op),
+            Instruction::PushBytes(_) => None,
+        }
+    }
+}
+
+/// Returns the number pushed by `OP_PUSHNUM_1` to `OP_PUSHNUM_16`.
+pub(crate) fn decode_pushnum(op: Opcode) -> Option<u8> {
+    const START: u8 = OP_PUSHNUM_1.to_u8();
+    const END: u8 = OP_PUSHNUM_16.to_u8();
+    match op.to_u8() {
+        n @ START..=END => Some(n - START + 1),
+        _ => None,
+    }
 }
 
 /// Iterator over a script returning parsed opcodes.
@@ -59,9 +91,7 @@ impl<'a> Instructions<'a> {
                 self.data.nth(len - 1);
             }
 
-            Ok(slice
-                .try_into()
-                .expect("len was created from u32, so can't happen"))
+            Ok(slice)
         } else {
             self.kill();
             Err(ScriptError::EarlyEndOfScript)
@@ -109,7 +139,7 @@ impl<'a> From<&'a [u8]> for Instructions<'a> {
 fn read_uint_iter(data: &mut core::slice::Iter<'_, u8>, size: usize) -> Result<usize, ScriptError> {
     if data.len() < size {
         Err(ScriptError::EarlyEndOfScript)
-    } else if size > usize::from(u16::max_value() / 8) {
+    } else if size > usize::from(u16::MAX / 8) {
         // Casting to u32 would overflow
         Err(ScriptError::NumericOverflow)
     } else {
@@ -255,4 +285,36 @@ mod test {
             assert_eq!(actual_payload, expected_payload);
         }
     }
+
+    #[test]
+    fn instruction_numbers() {
+        let script = [
+            0x00, 0x4f, 0x51, 0x60, 0x01, 0x11, 0x02, 0x01, 0x00, 0x61, 0x05, 1, 2, 3, 4, 5,
+        ];
+        let instructions: Vec<Instruction> = Instructions::from(&script[..])
+            .map(Result::unwrap)
+            .collect();
+        let nums: Vec<Option<i64>> = instructions
+            .iter()
+            .map(|instruction| instruction.script_num().map(i64::from))
+            .collect();
+        assert_eq!(
+            nums,
+            [
+                Some(0),
+                Some(-1),
+                Some(1),
+                Some(16),
+                Some(17),
+                None,
+                None,
+                None
+            ]
+        );
+        let small_ints: Vec<Option<u8>> = instructions.iter().map(Instruction::small_int).collect();
+        assert_eq!(
+            small_ints,
+            [Some(0), None, Some(1), Some(16), None, None, None, None]
+        );
+    }
 }
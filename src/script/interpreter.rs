@@ -0,0 +1,3459 @@
+//! Script execution.
+//!
+//! A port of Bitcoin Core's script interpreter, evaluating legacy, segwit version 0 and
+//! taproot scripts with the same results and errors as Core.
+
+use crate::constants::{
+    LOCKTIME_THRESHOLD, MAX_OPS_PER_SCRIPT, MAX_PUBKEYS_PER_MULTISIG, MAX_SCRIPT_ELEMENT_SIZE,
+    MAX_SCRIPT_SIZE, MAX_STACK_SIZE, VALIDATION_WEIGHT_OFFSET, VALIDATION_WEIGHT_PER_SIGOP_PASSED,
+};
+use crate::script::error::ScriptError;
+use crate::script::instructions::{Instruction, Instructions};
+use crate::script::num::ScriptNum;
+use crate::script::trace::{ExecutionTrace, ScriptTrace, TraceStep};
+use crate::script::{Script, ScriptBuf};
+use crate::sighash::{SighashCache, SIGHASH_DEFAULT};
+use crate::taproot::{
+    annex, ControlBlockError, ScriptPathSpend, TapLeafHash, TAPROOT_LEAF_TAPSCRIPT,
+};
+use crate::transaction::{encode_compact_size, TxOut};
+use bitcoin_hashes::{hash160, ripemd160, sha1, sha256, sha256d, Hash};
+use borsh::{BorshDeserialize, BorshSerialize};
+use core::ops::{BitOr, BitOrAssign};
+use opcodes::all::*;
+use opcodes::{Class, ClassifyContext, Opcode};
+use secp256k1::{ecdsa, schnorr, Message, PublicKey, Secp256k1, VerifyOnly, XOnlyPublicKey};
+
+/// Sequence flag disabling the relative lock time of an input (BIP68).
+const SEQUENCE_LOCKTIME_DISABLE_FLAG: i64 = 1 << 31;
+/// Sequence flag making the relative lock time of an input time based (BIP68).
+const SEQUENCE_LOCKTIME_TYPE_FLAG: i64 = 1 << 22;
+/// Mask of the relative lock time value in a sequence (BIP68).
+const SEQUENCE_LOCKTIME_MASK: i64 = 0x0000ffff;
+
+/// Sighash type bit signing only the input being spent.
+const SIGHASH_ANYONECANPAY: u8 = 0x80;
+
+/// The stack of a script: a list of byte vectors, with the top at the end.
+pub type Stack = Vec<Vec<u8>>;
+
+/// Flags selecting which rules are enforced while verifying scripts.
+///
+/// These match the `SCRIPT_VERIFY_*` flags of Bitcoin Core and can be combined with `|`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct VerifyFlags(u32);
+
+impl VerifyFlags {
+    /// No additional rules.
+    pub const NONE: VerifyFlags = VerifyFlags(0);
+    /// Evaluate P2SH redeem scripts (BIP16).
+    pub const P2SH: VerifyFlags = VerifyFlags(1 << 0);
+    /// Require signatures and public keys to be strictly encoded, with a defined sighash type.
+    pub const STRICTENC: VerifyFlags = VerifyFlags(1 << 1);
+    /// Require signatures to be strict DER (BIP66).
+    pub const DERSIG: VerifyFlags = VerifyFlags(1 << 2);
+    /// Require signatures to have a low S value (BIP146).
+    pub const LOW_S: VerifyFlags = VerifyFlags(1 << 3);
+    /// Require the extra element consumed by `OP_CHECKMULTISIG` to be empty (BIP147).
+    pub const NULLDUMMY: VerifyFlags = VerifyFlags(1 << 4);
+    /// Require the scriptSig to only contain pushes.
+    pub const SIGPUSHONLY: VerifyFlags = VerifyFlags(1 << 5);
+    /// Require pushes and script numbers to be minimally encoded.
+    pub const MINIMALDATA: VerifyFlags = VerifyFlags(1 << 6);
+    /// Fail on the NOPs reserved for soft forks.
+    pub const DISCOURAGE_UPGRADABLE_NOPS: VerifyFlags = VerifyFlags(1 << 7);
+    /// Require exactly one element on the stack after execution. Needs `P2SH` and `WITNESS`.
+    pub const CLEANSTACK: VerifyFlags = VerifyFlags(1 << 8);
+    /// Enforce `OP_CHECKLOCKTIMEVERIFY` (BIP65).
+    pub const CHECKLOCKTIMEVERIFY: VerifyFlags = VerifyFlags(1 << 9);
+    /// Enforce `OP_CHECKSEQUENCEVERIFY` (BIP112).
+    pub const CHECKSEQUENCEVERIFY: VerifyFlags = VerifyFlags(1 << 10);
+    /// Evaluate witness programs (BIP141). Needs `P2SH`.
+    pub const WITNESS: VerifyFlags = VerifyFlags(1 << 11);
+    /// Fail on witness versions reserved for soft forks.
+    pub const DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM: VerifyFlags = VerifyFlags(1 << 12);
+    /// Require the argument of `OP_IF` and `OP_NOTIF` in segwit scripts to be empty or `0x01`.
+    pub const MINIMALIF: VerifyFlags = VerifyFlags(1 << 13);
+    /// Require the signatures of failing signature checks to be empty.
+    pub const NULLFAIL: VerifyFlags = VerifyFlags(1 << 14);
+    /// Require public keys in segwit scripts to be compressed.
+    pub const WITNESS_PUBKEYTYPE: VerifyFlags = VerifyFlags(1 << 15);
+    /// Fail on `OP_CODESEPARATOR` and on signatures found in the script code of legacy scripts.
+    pub const CONST_SCRIPTCODE: VerifyFlags = VerifyFlags(1 << 16);
+    /// Evaluate taproot spends and tapscripts (BIP341 and BIP342). Needs `WITNESS`.
+    pub const TAPROOT: VerifyFlags = VerifyFlags(1 << 17);
+    /// Fail on taproot leaf versions reserved for soft forks.
+    pub const DISCOURAGE_UPGRADABLE_TAPROOT_VERSION: VerifyFlags = VerifyFlags(1 << 18);
+    /// Fail on the `OP_SUCCESSx` opcodes of tapscript reserved for soft forks.
+    pub const DISCOURAGE_OP_SUCCESS: VerifyFlags = VerifyFlags(1 << 19);
+    /// Fail on tapscript public key types reserved for soft forks.
+    pub const DISCOURAGE_UPGRADABLE_PUBKEYTYPE: VerifyFlags = VerifyFlags(1 << 20);
+
+    /// The rules enforced by consensus on all blocks today.
+    pub const CONSENSUS: VerifyFlags = VerifyFlags(
+        Self::P2SH.0
+            | Self::DERSIG.0
+            | Self::NULLDUMMY.0
+            | Self::CHECKLOCKTIMEVERIFY.0
+            | Self::CHECKSEQUENCEVERIFY.0
+            | Self::WITNESS.0
+            | Self::TAPROOT.0,
+    );
+    /// The rules Bitcoin Core enforces on transactions it relays.
+    pub const STANDARD: VerifyFlags = VerifyFlags(
+        Self::CONSENSUS.0
+            | Self::STRICTENC.0
+            | Self::MINIMALDATA.0
+            | Self::DISCOURAGE_UPGRADABLE_NOPS.0
+            | Self::CLEANSTACK.0
+            | Self::MINIMALIF.0
+            | Self::NULLFAIL.0
+            | Self::LOW_S.0
+            | Self::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM.0
+            | Self::WITNESS_PUBKEYTYPE.0
+            | Self::CONST_SCRIPTCODE.0
+            | Self::DISCOURAGE_UPGRADABLE_TAPROOT_VERSION.0
+            | Self::DISCOURAGE_OP_SUCCESS.0
+            | Self::DISCOURAGE_UPGRADABLE_PUBKEYTYPE.0,
+    );
+
+    /// Creates flags from their bit representation, which matches Bitcoin Core's.
+    pub const fn from_bits(bits: u32) -> Self {
+        VerifyFlags(bits)
+    }
+
+    /// Returns the bit representation of the flags.
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Returns true if all of `other` is set in `self`.
+    pub const fn contains(self, other: VerifyFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns false if `CLEANSTACK` is set without both `P2SH` and `WITNESS`, or
+    /// `WITNESS` without `P2SH`. Such combinations can't be enabled as soft forks and
+    /// [`verify_script`] doesn't support them.
+    pub const fn is_supported(self) -> bool {
+        let p2sh_and_witness = VerifyFlags(Self::P2SH.0 | Self::WITNESS.0);
+        (!self.contains(Self::CLEANSTACK) || self.contains(p2sh_and_witness))
+            && (!self.contains(Self::WITNESS) || self.contains(Self::P2SH))
+    }
+}
+
+impl BitOr for VerifyFlags {
+    type Output = VerifyFlags;
+
+    fn bitor(self, rhs: VerifyFlags) -> VerifyFlags {
+        VerifyFlags(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for VerifyFlags {
+    fn bitor_assign(&mut self, rhs: VerifyFlags) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// The kind of script being executed, which selects the signature algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, BorshDeserialize, BorshSerialize)]
+pub enum SigVersion {
+    /// Legacy scripts: scriptSig, scriptPubKey and P2SH redeem scripts.
+    Base,
+    /// Segwit version 0 scripts (BIP143).
+    WitnessV0,
+    /// Taproot key path spends (BIP341).
+    Taproot,
+    /// Taproot script path spends of tapscript leaves (BIP342).
+    Tapscript,
+}
+
+/// Data about a taproot spend, committed to by its signatures along with the transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecutionData {
+    /// The hash of the executed leaf, for script path spends.
+    pub tapleaf_hash: Option<TapLeafHash>,
+    /// The opcode position of the last executed `OP_CODESEPARATOR`, or `u32::MAX`.
+    pub codeseparator_pos: u32,
+    /// The annex of the witness, including its `0x50` prefix.
+    pub annex: Option<Vec<u8>>,
+    /// What's left of the signature validation budget of a tapscript.
+    pub validation_weight_left: i64,
+}
+
+impl Default for ExecutionData {
+    fn default() -> Self {
+        ExecutionData {
+            tapleaf_hash: None,
+            codeseparator_pos: u32::MAX,
+            annex: None,
+            validation_weight_left: 0,
+        }
+    }
+}
+
+/// Checks the parts of a script that depend on the spending transaction.
+///
+/// Every check fails by default, which is what [`BaseSignatureChecker`] does.
+pub trait SignatureChecker {
+    /// Returns true if `sig`, a DER signature followed by its sighash type, is valid for
+    /// `pubkey` over `script_code`.
+    fn check_ecdsa_signature(
+        &self,
+        _sig: &[u8],
+        _pubkey: &[u8],
+        _script_code: &[u8],
+        _sig_version: SigVersion,
+    ) -> bool {
+        false
+    }
+
+    /// Checks `sig`, a BIP340 signature optionally followed by its sighash type, for the
+    /// x-only `pubkey` of a taproot spend.
+    fn check_schnorr_signature(
+        &self,
+        _sig: &[u8],
+        _pubkey: &[u8],
+        _sig_version: SigVersion,
+        _exec_data: &ExecutionData,
+    ) -> Result<(), ScriptError> {
+        Err(ScriptError::SchnorrSig)
+    }
+
+    /// Returns true if the transaction satisfies the absolute `lock_time` of `OP_CHECKLOCKTIMEVERIFY`.
+    fn check_lock_time(&self, _lock_time: i64) -> bool {
+        false
+    }
+
+    /// Returns true if the input satisfies the relative `sequence` of `OP_CHECKSEQUENCEVERIFY`.
+    fn check_sequence(&self, _sequence: i64) -> bool {
+        false
+    }
+}
+
+/// A checker without a transaction, for which every check fails.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BaseSignatureChecker;
+
+impl SignatureChecker for BaseSignatureChecker {}
+
+/// Checks signatures and lock times against an input of a transaction.
+#[derive(Debug)]
+pub struct TransactionSignatureChecker<'a> {
+    cache: &'a SighashCache<'a>,
+    input_index: usize,
+    amount: u64,
+    spent_outputs: Option<&'a [TxOut]>,
+    secp: Secp256k1<VerifyOnly>,
+}
+
+impl<'a> TransactionSignatureChecker<'a> {
+    /// Creates a checker for input `input_index` of the transaction in `cache`, which spends
+    /// an output worth `amount` satoshis.
+    ///
+    /// # Panics
+    ///
+    /// If `input_index` is out of range.
+    pub fn new(cache: &'a SighashCache<'a>, input_index: usize, amount: u64) -> Self {
+        assert!(input_index < cache.transaction().input.len());
+        TransactionSignatureChecker {
+            cache,
+            input_index,
+            amount,
+            spent_outputs: None,
+            secp: Secp256k1::verification_only(),
+        }
+    }
+
+    /// Creates a checker for input `input_index` of the transaction in `cache`, given the
+    /// outputs spent by all its inputs. Taproot signatures commit to all of them, so they
+    /// can only be checked this way.
+    ///
+    /// # Panics
+    ///
+    /// If `input_index` is out of range, or if `spent_outputs` doesn't have one output
+    /// per input.
+    pub fn with_spent_outputs(
+        cache: &'a SighashCache<'a>,
+        input_index: usize,
+        spent_outputs: &'a [TxOut],
+    ) -> Self {
+        assert_eq!(spent_outputs.len(), cache.transaction().input.len());
+        TransactionSignatureChecker {
+            spent_outputs: Some(spent_outputs),
+            ..TransactionSignatureChecker::new(
+                cache,
+                input_index,
+                spent_outputs[input_index].amount,
+            )
+        }
+    }
+}
+
+impl<'a> SignatureChecker for TransactionSignatureChecker<'a> {
+    fn check_ecdsa_signature(
+        &self,
+        sig: &[u8],
+        pubkey: &[u8],
+        script_code: &[u8],
+        sig_version: SigVersion,
+    ) -> bool {
+        let (&sighash_type, sig) = match sig.split_last() {
+            Some(split) => split,
+            None => return false,
+        };
+        let pubkey = match PublicKey::from_slice(pubkey) {
+            Ok(pubkey) => pubkey,
+            Err(_) => return false,
+        };
+        // Like Core, accept any BER-ish encoding and high S values here; the stricter
+        // rules are enforced by the interpreter depending on the flags.
+        let mut sig = match ecdsa::Signature::from_der_lax(sig) {
+            Ok(sig) => sig,
+            Err(_) => return false,
+        };
+        sig.normalize_s();
+
+        let sighash = match sig_version {
+            SigVersion::Base => self
+                .cache
+                .legacy_signature_hash(self.input_index, script_code, sighash_type as u32)
+                .to_byte_array(),
+            SigVersion::WitnessV0 => self
+                .cache
+                .segwit_v0_signature_hash(
+                    self.input_index,
+                    script_code,
+                    self.amount,
+                    sighash_type as u32,
+                )
+                .to_byte_array(),
+            SigVersion::Taproot | SigVersion::Tapscript => return false,
+        };
+
+        self.secp
+            .verify_ecdsa(&Message::from_digest(sighash), &sig, &pubkey)
+            .is_ok()
+    }
+
+    fn check_schnorr_signature(
+        &self,
+        sig: &[u8],
+        pubkey: &[u8],
+        sig_version: SigVersion,
+        exec_data: &ExecutionData,
+    ) -> Result<(), ScriptError> {
+        let (sig, sighash_type) = match sig.len() {
+            64 => (sig, SIGHASH_DEFAULT),
+            // An explicit default type would make the signature malleable.
+            65 if sig[64] as u32 == SIGHASH_DEFAULT => return Err(ScriptError::SchnorrSigHashType),
+            65 => (&sig[..64], sig[64] as u32),
+            _ => return Err(ScriptError::SchnorrSigSize),
+        };
+        let spent_outputs = self.spent_outputs.ok_or(ScriptError::SchnorrSig)?;
+
+        let leaf_hash_code_separator = match sig_version {
+            SigVersion::Taproot => None,
+            SigVersion::Tapscript => {
+                let leaf_hash = exec_data.tapleaf_hash.ok_or(ScriptError::SchnorrSig)?;
+                Some((leaf_hash, exec_data.codeseparator_pos))
+            }
+            SigVersion::Base | SigVersion::WitnessV0 => return Err(ScriptError::SchnorrSig),
+        };
+        let sighash = self
+            .cache
+            .taproot_signature_hash(
+                self.input_index,
+                spent_outputs,
+                exec_data.annex.as_deref(),
+                leaf_hash_code_separator,
+                sighash_type,
+            )
+            .ok_or(ScriptError::SchnorrSigHashType)?;
+
+        let valid = match (
+            schnorr::Signature::from_slice(sig),
+            XOnlyPublicKey::from_slice(pubkey),
+        ) {
+            (Ok(sig), Ok(pubkey)) => self
+                .secp
+                .verify_schnorr(
+                    &sig,
+                    &Message::from_digest(sighash.to_byte_array()),
+                    &pubkey,
+                )
+                .is_ok(),
+            _ => false,
+        };
+        if !valid {
+            return Err(ScriptError::SchnorrSig);
+        }
+        Ok(())
+    }
+
+    fn check_lock_time(&self, lock_time: i64) -> bool {
+        let tx = self.cache.transaction();
+        let tx_lock_time = tx.lock_time as i64;
+        let threshold = LOCKTIME_THRESHOLD as i64;
+
+        // Both lock times must be of the same kind: a block height or a timestamp.
+        if (tx_lock_time < threshold) != (lock_time < threshold) {
+            return false;
+        }
+        if lock_time > tx_lock_time {
+            return false;
+        }
+        // A final input would disable the transaction's lock time altogether.
+        tx.input[self.input_index].sequence != u32::MAX
+    }
+
+    fn check_sequence(&self, sequence: i64) -> bool {
+        let tx = self.cache.transaction();
+        let tx_sequence = tx.input[self.input_index].sequence as i64;
+
+        // Relative lock times only apply from version 2 onwards (BIP68).
+        if tx.version < 2 {
+            return false;
+        }
+        if tx_sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+            return false;
+        }
+
+        let mask = SEQUENCE_LOCKTIME_TYPE_FLAG | SEQUENCE_LOCKTIME_MASK;
+        let tx_sequence = tx_sequence & mask;
+        let sequence = sequence & mask;
+        if (tx_sequence < SEQUENCE_LOCKTIME_TYPE_FLAG) != (sequence < SEQUENCE_LOCKTIME_TYPE_FLAG) {
+            return false;
+        }
+        sequence <= tx_sequence
+    }
+}
+
+/// Returns the truth value of a stack element: false for any encoding of zero,
+/// including negative zero.
+pub fn cast_to_bool(bytes: &[u8]) -> bool {
+    match bytes.split_last() {
+        Some((&last, rest)) => rest.iter().any(|&b| b != 0) || (last != 0 && last != 0x80),
+        None => false,
+    }
+}
+
+/// Returns true if `data` was pushed with the smallest possible opcode.
+fn is_minimal_push(data: &[u8], opcode: u8) -> bool {
+    match data.len() {
+        0 => opcode == OP_PUSHBYTES_0.to_u8(),
+        1 if (1..=16).contains(&data[0]) => false,
+        1 if data[0] == 0x81 => false,
+        len if len <= 75 => opcode as usize == len,
+        len if len <= 255 => opcode == OP_PUSHDATA1.to_u8(),
+        len if len <= 65535 => opcode == OP_PUSHDATA2.to_u8(),
+        _ => true,
+    }
+}
+
+/// Returns true if `sig` follows the strict DER rules of BIP66, followed by a sighash byte.
+fn is_valid_signature_encoding(sig: &[u8]) -> bool {
+    // Format: 0x30 [total-length] 0x02 [R-length] [R] 0x02 [S-length] [S] [sighash]
+    if sig.len() < 9 || sig.len() > 73 {
+        return false;
+    }
+    if sig[0] != 0x30 || sig[1] as usize != sig.len() - 3 {
+        return false;
+    }
+    let len_r = sig[3] as usize;
+    if 5 + len_r >= sig.len() {
+        return false;
+    }
+    let len_s = sig[5 + len_r] as usize;
+    if len_r + len_s + 7 != sig.len() {
+        return false;
+    }
+
+    // R must be a positive integer without excess padding.
+    if sig[2] != 0x02 || len_r == 0 || sig[4] & 0x80 != 0 {
+        return false;
+    }
+    if len_r > 1 && sig[4] == 0x00 && sig[5] & 0x80 == 0 {
+        return false;
+    }
+
+    // Same for S.
+    if sig[len_r + 4] != 0x02 || len_s == 0 || sig[len_r + 6] & 0x80 != 0 {
+        return false;
+    }
+    if len_s > 1 && sig[len_r + 6] == 0x00 && sig[len_r + 7] & 0x80 == 0 {
+        return false;
+    }
+
+    true
+}
+
+fn is_low_s_signature(sig: &[u8]) -> bool {
+    match ecdsa::Signature::from_der_lax(&sig[..sig.len() - 1]) {
+        Ok(parsed) => {
+            let mut normalized = parsed;
+            normalized.normalize_s();
+            normalized == parsed
+        }
+        Err(_) => false,
+    }
+}
+
+fn is_defined_hashtype_signature(sig: &[u8]) -> bool {
+    match sig.last() {
+        Some(&sighash_type) => (1..=3).contains(&(sighash_type & !SIGHASH_ANYONECANPAY)),
+        None => false,
+    }
+}
+
+fn check_signature_encoding(sig: &[u8], flags: VerifyFlags) -> Result<(), ScriptError> {
+    // An empty signature is a compact way to provide an invalid signature.
+    if sig.is_empty() {
+        return Ok(());
+    }
+    let strict = VerifyFlags::DERSIG.0 | VerifyFlags::LOW_S.0 | VerifyFlags::STRICTENC.0;
+    if flags.0 & strict != 0 && !is_valid_signature_encoding(sig) {
+        return Err(ScriptError::SigDer);
+    }
+    if flags.contains(VerifyFlags::LOW_S) && !is_low_s_signature(sig) {
+        return Err(ScriptError::SigHighS);
+    }
+    if flags.contains(VerifyFlags::STRICTENC) && !is_defined_hashtype_signature(sig) {
+        return Err(ScriptError::SigHashType);
+    }
+    Ok(())
+}
+
+fn check_pubkey_encoding(
+    pubkey: &[u8],
+    flags: VerifyFlags,
+    sig_version: SigVersion,
+) -> Result<(), ScriptError> {
+    let is_compressed = pubkey.len() == 33 && (pubkey[0] == 0x02 || pubkey[0] == 0x03);
+    let is_uncompressed = pubkey.len() == 65 && pubkey[0] == 0x04;
+
+    if flags.contains(VerifyFlags::STRICTENC) && !is_compressed && !is_uncompressed {
+        return Err(ScriptError::PubkeyType);
+    }
+    if flags.contains(VerifyFlags::WITNESS_PUBKEYTYPE)
+        && sig_version == SigVersion::WitnessV0
+        && !is_compressed
+    {
+        return Err(ScriptError::WitnessPubkeyType);
+    }
+    Ok(())
+}
+
+/// Returns the push of `data` as it would be encoded by Core's `CScript() << data`.
+pub(crate) fn push_encoding(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len() + 5);
+    match data.len() {
+        len if len < OP_PUSHDATA1.to_u8() as usize => result.push(len as u8),
+        len if len <= 0xff => {
+            result.push(OP_PUSHDATA1.to_u8());
+            result.push(len as u8);
+        }
+        len if len <= 0xffff => {
+            result.push(OP_PUSHDATA2.to_u8());
+            result.extend_from_slice(&(len as u16).to_le_bytes());
+        }
+        len => {
+            result.push(OP_PUSHDATA4.to_u8());
+            result.extend_from_slice(&(len as u32).to_le_bytes());
+        }
+    }
+    result.extend_from_slice(data);
+    result
+}
+
+/// Removes all occurrences of `pattern` that start at an opcode boundary of `script`,
+/// returning how many were removed.
+fn find_and_delete(script: &mut Vec<u8>, pattern: &[u8]) -> usize {
+    if pattern.is_empty() {
+        return 0;
+    }
+
+    let mut found = 0;
+    let mut result = Vec::with_capacity(script.len());
+    let mut pos = 0;
+    let mut copied_to = 0;
+    loop {
+        result.extend_from_slice(&script[copied_to..pos]);
+        while script[pos..].starts_with(pattern) {
+            pos += pattern.len();
+            found += 1;
+        }
+        copied_to = pos;
+
+        let mut instructions = Instructions::from(&script[pos..]);
+        match instructions.next() {
+            Some(Ok(_)) => pos = script.len() - instructions.data.len(),
+            Some(Err(_)) | None => break,
+        }
+    }
+
+    if found > 0 {
+        result.extend_from_slice(&script[copied_to..]);
+        *script = result;
+    }
+    found
+}
+
+/// Tracks which branches of nested `OP_IF`s are executed, without storing every level.
+struct ConditionStack {
+    size: usize,
+    /// Position of the first false value, if any.
+    first_false: Option<usize>,
+}
+
+impl ConditionStack {
+    fn new() -> Self {
+        ConditionStack {
+            size: 0,
+            first_false: None,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    fn all_true(&self) -> bool {
+        self.first_false.is_none()
+    }
+
+    fn push(&mut self, value: bool) {
+        if self.first_false.is_none() && !value {
+            self.first_false = Some(self.size);
+        }
+        self.size += 1;
+    }
+
+    fn pop(&mut self) {
+        self.size -= 1;
+        if self.first_false == Some(self.size) {
+            self.first_false = None;
+        }
+    }
+
+    /// Returns whether each level is executed, innermost last.
+    fn branches(&self) -> Vec<bool> {
+        (0..self.size)
+            .map(|level| self.first_false.is_none_or(|pos| level < pos))
+            .collect()
+    }
+
+    fn toggle_top(&mut self) {
+        match self.first_false {
+            // Everything is true, so the top becomes the first false value.
+            None => self.first_false = Some(self.size - 1),
+            // The top is the first false value, so everything becomes true.
+            Some(pos) if pos == self.size - 1 => self.first_false = None,
+            // A false value below the top stays in effect.
+            Some(_) => {}
+        }
+    }
+}
+
+fn top(stack: &Stack, depth: usize) -> &Vec<u8> {
+    &stack[stack.len() - depth]
+}
+
+fn require(stack: &Stack, len: usize) -> Result<(), ScriptError> {
+    if stack.len() < len {
+        Err(ScriptError::InvalidStackOperation)
+    } else {
+        Ok(())
+    }
+}
+
+fn bool_bytes(value: bool) -> Vec<u8> {
+    if value {
+        vec![1]
+    } else {
+        vec![]
+    }
+}
+
+fn is_disabled(opcode: Opcode) -> bool {
+    matches!(
+        opcode,
+        OP_CAT
+            | OP_SUBSTR
+            | OP_LEFT
+            | OP_RIGHT
+            | OP_INVERT
+            | OP_AND
+            | OP_OR
+            | OP_XOR
+            | OP_2MUL
+            | OP_2DIV
+            | OP_MUL
+            | OP_DIV
+            | OP_MOD
+            | OP_LSHIFT
+            | OP_RSHIFT
+    )
+}
+
+/// Checks a signature for `OP_CHECKSIG`, `OP_CHECKSIGVERIFY` and `OP_CHECKSIGADD`,
+/// returning whether it is valid.
+fn eval_checksig<C: SignatureChecker + ?Sized>(
+    sig: &[u8],
+    pubkey: &[u8],
+    script_code: &[u8],
+    exec_data: &mut ExecutionData,
+    flags: VerifyFlags,
+    checker: &C,
+    sig_version: SigVersion,
+) -> Result<bool, ScriptError> {
+    match sig_version {
+        SigVersion::Base | SigVersion::WitnessV0 => {
+            let mut script_code = script_code.to_vec();
+            // Drop the signature in legacy scripts, it can't sign itself.
+            if sig_version == SigVersion::Base {
+                let found = find_and_delete(&mut script_code, &push_encoding(sig));
+                if found > 0 && flags.contains(VerifyFlags::CONST_SCRIPTCODE) {
+                    return Err(ScriptError::SigFindAndDelete);
+                }
+            }
+            check_signature_encoding(sig, flags)?;
+            check_pubkey_encoding(pubkey, flags, sig_version)?;
+            let success = checker.check_ecdsa_signature(sig, pubkey, &script_code, sig_version);
+            if !success && flags.contains(VerifyFlags::NULLFAIL) && !sig.is_empty() {
+                return Err(ScriptError::SigNullFail);
+            }
+            Ok(success)
+        }
+        SigVersion::Taproot | SigVersion::Tapscript => {
+            // An empty signature fails the check without failing the script.
+            let success = !sig.is_empty();
+            if success {
+                // Bound the signature checks by the size of the witness.
+                exec_data.validation_weight_left -= VALIDATION_WEIGHT_PER_SIGOP_PASSED;
+                if exec_data.validation_weight_left < 0 {
+                    return Err(ScriptError::TapscriptValidationWeight);
+                }
+            }
+            match pubkey.len() {
+                0 => return Err(ScriptError::PubkeyType),
+                32 => {
+                    if success {
+                        checker.check_schnorr_signature(sig, pubkey, sig_version, exec_data)?;
+                    }
+                }
+                // Other sizes are left for new public key types, and always succeed.
+                _ => {
+                    if flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_PUBKEYTYPE) {
+                        return Err(ScriptError::DiscourageUpgradablePubkeyType);
+                    }
+                }
+            }
+            Ok(success)
+        }
+    }
+}
+
+/// Executes `script` on `stack`.
+///
+/// This doesn't check the final stack; use [`verify_script`] to validate a spend.
+/// Tapscripts need the execution data set up by [`verify_script`] to check signatures.
+pub fn eval_script<C: SignatureChecker + ?Sized>(
+    stack: &mut Stack,
+    script: &Script,
+    flags: VerifyFlags,
+    checker: &C,
+    sig_version: SigVersion,
+) -> Result<(), ScriptError> {
+    let mut exec_data = ExecutionData::default();
+    eval(
+        stack,
+        script.as_bytes(),
+        flags,
+        checker,
+        sig_version,
+        &mut exec_data,
+        None,
+    )
+}
+
+/// Executes `script` on `stack` like [`eval_script`], recording every step in `trace`.
+pub fn eval_script_with_trace<C: SignatureChecker + ?Sized>(
+    stack: &mut Stack,
+    script: &Script,
+    flags: VerifyFlags,
+    checker: &C,
+    sig_version: SigVersion,
+    trace: &mut ExecutionTrace,
+) -> Result<(), ScriptError> {
+    let mut exec_data = ExecutionData::default();
+    eval(
+        stack,
+        script.as_bytes(),
+        flags,
+        checker,
+        sig_version,
+        &mut exec_data,
+        Some(trace),
+    )
+}
+
+fn eval<C: SignatureChecker + ?Sized>(
+    stack: &mut Stack,
+    script: &[u8],
+    flags: VerifyFlags,
+    checker: &C,
+    sig_version: SigVersion,
+    exec_data: &mut ExecutionData,
+    trace: Option<&mut ExecutionTrace>,
+) -> Result<(), ScriptError> {
+    let trace = match trace {
+        Some(trace) => trace,
+        None => return execute(stack, script, flags, checker, sig_version, exec_data, None),
+    };
+    let mut script_trace = ScriptTrace {
+        script: script.to_vec(),
+        sig_version,
+        steps: Vec::new(),
+        stack: Vec::new(),
+        result: Ok(()),
+    };
+    let result = execute(
+        stack,
+        script,
+        flags,
+        checker,
+        sig_version,
+        exec_data,
+        Some(&mut script_trace),
+    );
+    script_trace.stack = stack.clone();
+    script_trace.result = result;
+    trace.scripts.push(script_trace);
+    result
+}
+
+fn execute<C: SignatureChecker + ?Sized>(
+    stack: &mut Stack,
+    script: &[u8],
+    flags: VerifyFlags,
+    checker: &C,
+    sig_version: SigVersion,
+    exec_data: &mut ExecutionData,
+    mut trace: Option<&mut ScriptTrace>,
+) -> Result<(), ScriptError> {
+    // Tapscripts are limited by the block weight and their validation weight instead.
+    let is_tapscript = matches!(sig_version, SigVersion::Taproot | SigVersion::Tapscript);
+    if !is_tapscript && script.len() > MAX_SCRIPT_SIZE {
+        return Err(ScriptError::ScriptSize);
+    }
+
+    let require_minimal = flags.contains(VerifyFlags::MINIMALDATA);
+    let mut instructions = Instructions::from(script);
+    let mut exec = ConditionStack::new();
+    let mut altstack: Stack = Vec::new();
+    let mut op_count = 0;
+    // Start of the script code hashed by signature checks, moved by OP_CODESEPARATOR.
+    let mut code_start = 0;
+    let mut opcode_pos: u32 = 0;
+
+    loop {
+        let pos = script.len() - instructions.data.len();
+        let instruction = match instructions.next() {
+            Some(Ok(instruction)) => instruction,
+            Some(Err(_)) => return Err(ScriptError::BadOpcode),
+            None => break,
+        };
+        let executing = exec.all_true();
+        let opcode = Opcode::from(script[pos]);
+        if let Some(trace) = trace.as_deref_mut() {
+            trace.steps.push(TraceStep {
+                pos,
+                opcode,
+                push: instruction.push_bytes().map(<[u8]>::to_vec),
+                executed: executing,
+                stack: stack.clone(),
+                altstack: altstack.clone(),
+                conditions: exec.branches(),
+            });
+        }
+
+        if let Instruction::PushBytes(data) = instruction {
+            if data.len() > MAX_SCRIPT_ELEMENT_SIZE {
+                return Err(ScriptError::PushSize);
+            }
+        }
+        // Note how OP_RESERVED does not count towards the opcode limit.
+        if !is_tapscript && opcode.to_u8() > OP_PUSHNUM_16.to_u8() {
+            op_count += 1;
+            if op_count > MAX_OPS_PER_SCRIPT {
+                return Err(ScriptError::OpCount);
+            }
+        }
+        if is_disabled(opcode) {
+            return Err(ScriptError::DisabledOpcode);
+        }
+        if opcode == OP_CODESEPARATOR
+            && sig_version == SigVersion::Base
+            && flags.contains(VerifyFlags::CONST_SCRIPTCODE)
+        {
+            return Err(ScriptError::OpCodeSeparator);
+        }
+
+        let is_conditional = (OP_IF.to_u8()..=OP_ENDIF.to_u8()).contains(&opcode.to_u8());
+        match instruction {
+            Instruction::PushBytes(data) if executing => {
+                if require_minimal && !is_minimal_push(data, opcode.to_u8()) {
+                    return Err(ScriptError::NonMinimalPush);
+                }
+                stack.push(data.to_vec());
+            }
+            Instruction::Op(op) if executing || is_conditional => match op {
+                OP_PUSHNUM_NEG1 | OP_PUSHNUM_1 | OP_PUSHNUM_2 | OP_PUSHNUM_3 | OP_PUSHNUM_4
+                | OP_PUSHNUM_5 | OP_PUSHNUM_6 | OP_PUSHNUM_7 | OP_PUSHNUM_8 | OP_PUSHNUM_9
+                | OP_PUSHNUM_10 | OP_PUSHNUM_11 | OP_PUSHNUM_12 | OP_PUSHNUM_13 | OP_PUSHNUM_14
+                | OP_PUSHNUM_15 | OP_PUSHNUM_16 => {
+                    let value = op.to_u8() as i64 - (OP_PUSHNUM_1.to_u8() as i64 - 1);
+                    stack.push(ScriptNum::new(value).to_bytes());
+                }
+
+                // Control
+                OP_NOP => {}
+                OP_CLTV => {
+                    // Without the flag this is still OP_NOP2.
+                    if flags.contains(VerifyFlags::CHECKLOCKTIMEVERIFY) {
+                        require(stack, 1)?;
+                        // Five byte numbers allow lock times up to 2^39 - 1.
+                        let lock_time = ScriptNum::from_slice_with_max_size(
+                            top(stack, 1),
+                            require_minimal,
+                            ScriptNum::LOCKTIME_MAX_SIZE,
+                        )?
+                        .to_i64();
+                        if lock_time < 0 {
+                            return Err(ScriptError::NegativeLocktime);
+                        }
+                        if !checker.check_lock_time(lock_time) {
+                            return Err(ScriptError::UnsatisfiedLocktime);
+                        }
+                    }
+                }
+                OP_CSV => {
+                    // Without the flag this is still OP_NOP3.
+                    if flags.contains(VerifyFlags::CHECKSEQUENCEVERIFY) {
+                        require(stack, 1)?;
+                        let sequence = ScriptNum::from_slice_with_max_size(
+                            top(stack, 1),
+                            require_minimal,
+                            ScriptNum::LOCKTIME_MAX_SIZE,
+                        )?
+                        .to_i64();
+                        if sequence < 0 {
+                            return Err(ScriptError::NegativeLocktime);
+                        }
+                        // With the disable flag set, the operand is left for future soft forks.
+                        if sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG == 0
+                            && !checker.check_sequence(sequence)
+                        {
+                            return Err(ScriptError::UnsatisfiedLocktime);
+                        }
+                    }
+                }
+                OP_NOP1 | OP_NOP4 | OP_NOP5 | OP_NOP6 | OP_NOP7 | OP_NOP8 | OP_NOP9 | OP_NOP10 => {
+                    if flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_NOPS) {
+                        return Err(ScriptError::DiscourageUpgradableNops);
+                    }
+                }
+                OP_IF | OP_NOTIF => {
+                    let mut value = false;
+                    if executing {
+                        let condition = stack.last().ok_or(ScriptError::UnbalancedConditional)?;
+                        let is_minimal = condition.is_empty() || condition[..] == [1];
+                        // Consensus in tapscript, only policy in segwit version 0.
+                        if sig_version == SigVersion::Tapscript && !is_minimal {
+                            return Err(ScriptError::TapscriptMinimalIf);
+                        }
+                        if sig_version == SigVersion::WitnessV0
+                            && flags.contains(VerifyFlags::MINIMALIF)
+                            && !is_minimal
+                        {
+                            return Err(ScriptError::MinimalIf);
+                        }
+                        value = cast_to_bool(condition) != (op == OP_NOTIF);
+                        stack.pop();
+                    }
+                    exec.push(value);
+                }
+                OP_ELSE => {
+                    if exec.is_empty() {
+                        return Err(ScriptError::UnbalancedConditional);
+                    }
+                    exec.toggle_top();
+                }
+                OP_ENDIF => {
+                    if exec.is_empty() {
+                        return Err(ScriptError::UnbalancedConditional);
+                    }
+                    exec.pop();
+                }
+                OP_VERIFY => {
+                    require(stack, 1)?;
+                    if !cast_to_bool(top(stack, 1)) {
+                        return Err(ScriptError::Verify);
+                    }
+                    stack.pop();
+                }
+                OP_RETURN => return Err(ScriptError::OpReturn),
+
+                // Stack operations
+                OP_TOALTSTACK => {
+                    require(stack, 1)?;
+                    altstack.push(stack.pop().unwrap());
+                }
+                OP_FROMALTSTACK => {
+                    let value = altstack
+                        .pop()
+                        .ok_or(ScriptError::InvalidAltstackOperation)?;
+                    stack.push(value);
+                }
+                OP_2DROP => {
+                    require(stack, 2)?;
+                    stack.truncate(stack.len() - 2);
+                }
+                OP_2DUP => {
+                    require(stack, 2)?;
+                    stack.extend_from_within(stack.len() - 2..);
+                }
+                OP_3DUP => {
+                    require(stack, 3)?;
+                    stack.extend_from_within(stack.len() - 3..);
+                }
+                OP_2OVER => {
+                    require(stack, 4)?;
+                    let len = stack.len();
+                    stack.extend_from_within(len - 4..len - 2);
+                }
+                OP_2ROT => {
+                    require(stack, 6)?;
+                    let len = stack.len();
+                    let moved: Vec<_> = stack.drain(len - 6..len - 4).collect();
+                    stack.extend(moved);
+                }
+                OP_2SWAP => {
+                    require(stack, 4)?;
+                    let len = stack.len();
+                    stack.swap(len - 4, len - 2);
+                    stack.swap(len - 3, len - 1);
+                }
+                OP_IFDUP => {
+                    require(stack, 1)?;
+                    if cast_to_bool(top(stack, 1)) {
+                        stack.push(top(stack, 1).clone());
+                    }
+                }
+                OP_DEPTH => stack.push(ScriptNum::new(stack.len() as i64).to_bytes()),
+                OP_DROP => {
+                    require(stack, 1)?;
+                    stack.pop();
+                }
+                OP_DUP => {
+                    require(stack, 1)?;
+                    stack.push(top(stack, 1).clone());
+                }
+                OP_NIP => {
+                    require(stack, 2)?;
+                    stack.remove(stack.len() - 2);
+                }
+                OP_OVER => {
+                    require(stack, 2)?;
+                    stack.push(top(stack, 2).clone());
+                }
+                OP_PICK | OP_ROLL => {
+                    require(stack, 2)?;
+                    let n = ScriptNum::from_slice(top(stack, 1), require_minimal)?.to_i64();
+                    stack.pop();
+                    if n < 0 || n as usize >= stack.len() {
+                        return Err(ScriptError::InvalidStackOperation);
+                    }
+                    let index = stack.len() - 1 - n as usize;
+                    let value = if op == OP_ROLL {
+                        stack.remove(index)
+                    } else {
+                        stack[index].clone()
+                    };
+                    stack.push(value);
+                }
+                OP_ROT => {
+                    require(stack, 3)?;
+                    let len = stack.len();
+                    stack[len - 3..].rotate_left(1);
+                }
+                OP_SWAP => {
+                    require(stack, 2)?;
+                    let len = stack.len();
+                    stack.swap(len - 2, len - 1);
+                }
+                OP_TUCK => {
+                    require(stack, 2)?;
+                    let value = top(stack, 1).clone();
+                    stack.insert(stack.len() - 2, value);
+                }
+                OP_SIZE => {
+                    require(stack, 1)?;
+                    stack.push(ScriptNum::new(top(stack, 1).len() as i64).to_bytes());
+                }
+
+                // Bitwise logic
+                OP_EQUAL | OP_EQUALVERIFY => {
+                    require(stack, 2)?;
+                    let equal = stack.pop().unwrap() == stack.pop().unwrap();
+                    if op == OP_EQUALVERIFY {
+                        if !equal {
+                            return Err(ScriptError::EqualVerify);
+                        }
+                    } else {
+                        stack.push(bool_bytes(equal));
+                    }
+                }
+
+                // Numeric
+                OP_1ADD | OP_1SUB | OP_NEGATE | OP_ABS | OP_NOT | OP_0NOTEQUAL => {
+                    require(stack, 1)?;
+                    let n = ScriptNum::from_slice(top(stack, 1), require_minimal)?.to_i64();
+                    let result = match op {
+                        OP_1ADD => n + 1,
+                        OP_1SUB => n - 1,
+                        OP_NEGATE => -n,
+                        OP_ABS => n.abs(),
+                        OP_NOT => (n == 0) as i64,
+                        _ => (n != 0) as i64,
+                    };
+                    stack.pop();
+                    stack.push(ScriptNum::new(result).to_bytes());
+                }
+                OP_ADD
+                | OP_SUB
+                | OP_BOOLAND
+                | OP_BOOLOR
+                | OP_NUMEQUAL
+                | OP_NUMEQUALVERIFY
+                | OP_NUMNOTEQUAL
+                | OP_LESSTHAN
+                | OP_GREATERTHAN
+                | OP_LESSTHANOREQUAL
+                | OP_GREATERTHANOREQUAL
+                | OP_MIN
+                | OP_MAX => {
+                    require(stack, 2)?;
+                    let a = ScriptNum::from_slice(top(stack, 2), require_minimal)?.to_i64();
+                    let b = ScriptNum::from_slice(top(stack, 1), require_minimal)?.to_i64();
+                    let result = match op {
+                        OP_ADD => a + b,
+                        OP_SUB => a - b,
+                        OP_BOOLAND => (a != 0 && b != 0) as i64,
+                        OP_BOOLOR => (a != 0 || b != 0) as i64,
+                        OP_NUMEQUAL | OP_NUMEQUALVERIFY => (a == b) as i64,
+                        OP_NUMNOTEQUAL => (a != b) as i64,
+                        OP_LESSTHAN => (a < b) as i64,
+                        OP_GREATERTHAN => (a > b) as i64,
+                        OP_LESSTHANOREQUAL => (a <= b) as i64,
+                        OP_GREATERTHANOREQUAL => (a >= b) as i64,
+                        OP_MIN => a.min(b),
+                        _ => a.max(b),
+                    };
+                    stack.truncate(stack.len() - 2);
+                    if op == OP_NUMEQUALVERIFY {
+                        if result == 0 {
+                            return Err(ScriptError::NumEqualVerify);
+                        }
+                    } else {
+                        stack.push(ScriptNum::new(result).to_bytes());
+                    }
+                }
+                OP_WITHIN => {
+                    require(stack, 3)?;
+                    let x = ScriptNum::from_slice(top(stack, 3), require_minimal)?.to_i64();
+                    let min = ScriptNum::from_slice(top(stack, 2), require_minimal)?.to_i64();
+                    let max = ScriptNum::from_slice(top(stack, 1), require_minimal)?.to_i64();
+                    stack.truncate(stack.len() - 3);
+                    stack.push(bool_bytes(min <= x && x < max));
+                }
+
+                // Crypto
+                OP_RIPEMD160 | OP_SHA1 | OP_SHA256 | OP_HASH160 | OP_HASH256 => {
+                    require(stack, 1)?;
+                    let data = stack.pop().unwrap();
+                    let hash = match op {
+                        OP_RIPEMD160 => ripemd160::Hash::hash(&data).to_byte_array().to_vec(),
+                        OP_SHA1 => sha1::Hash::hash(&data).to_byte_array().to_vec(),
+                        OP_SHA256 => sha256::Hash::hash(&data).to_byte_array().to_vec(),
+                        OP_HASH160 => hash160::Hash::hash(&data).to_byte_array().to_vec(),
+                        _ => sha256d::Hash::hash(&data).to_byte_array().to_vec(),
+                    };
+                    stack.push(hash);
+                }
+                OP_CODESEPARATOR => {
+                    code_start = pos + 1;
+                    exec_data.codeseparator_pos = opcode_pos;
+                }
+                OP_CHECKSIG | OP_CHECKSIGVERIFY => {
+                    require(stack, 2)?;
+                    let success = eval_checksig(
+                        top(stack, 2),
+                        top(stack, 1),
+                        &script[code_start..],
+                        exec_data,
+                        flags,
+                        checker,
+                        sig_version,
+                    )?;
+
+                    stack.truncate(stack.len() - 2);
+                    if op == OP_CHECKSIGVERIFY {
+                        if !success {
+                            return Err(ScriptError::CheckSigVerify);
+                        }
+                    } else {
+                        stack.push(bool_bytes(success));
+                    }
+                }
+                OP_CHECKSIGADD => {
+                    // Still OP_RETURN outside of tapscript.
+                    if !is_tapscript {
+                        return Err(ScriptError::BadOpcode);
+                    }
+                    // (sig num pubkey -- num)
+                    require(stack, 3)?;
+                    let num = ScriptNum::from_slice(top(stack, 2), require_minimal)?.to_i64();
+                    let success = eval_checksig(
+                        top(stack, 3),
+                        top(stack, 1),
+                        &script[code_start..],
+                        exec_data,
+                        flags,
+                        checker,
+                        sig_version,
+                    )?;
+
+                    stack.truncate(stack.len() - 3);
+                    stack.push(ScriptNum::new(num + success as i64).to_bytes());
+                }
+                OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => {
+                    if sig_version == SigVersion::Tapscript {
+                        return Err(ScriptError::TapscriptCheckMultisig);
+                    }
+                    // ([sig ...] num_of_signatures [pubkey ...] num_of_pubkeys -- bool)
+                    let mut i = 1;
+                    require(stack, i)?;
+                    let mut keys_count =
+                        ScriptNum::from_slice(top(stack, i), require_minimal)?.to_i64();
+                    if keys_count < 0 || keys_count > MAX_PUBKEYS_PER_MULTISIG as i64 {
+                        return Err(ScriptError::PubkeyCount);
+                    }
+                    op_count += keys_count as usize;
+                    if op_count > MAX_OPS_PER_SCRIPT {
+                        return Err(ScriptError::OpCount);
+                    }
+                    i += 1;
+                    let mut ikey = i;
+                    // Position of the last key, used to tell signatures apart when cleaning up.
+                    let mut ikey2 = keys_count as usize + 2;
+                    i += keys_count as usize;
+                    require(stack, i)?;
+                    let mut sigs_count =
+                        ScriptNum::from_slice(top(stack, i), require_minimal)?.to_i64();
+                    if sigs_count < 0 || sigs_count > keys_count {
+                        return Err(ScriptError::SigCount);
+                    }
+                    i += 1;
+                    let mut isig = i;
+                    i += sigs_count as usize;
+                    require(stack, i)?;
+
+                    let mut script_code = script[code_start..].to_vec();
+                    if sig_version == SigVersion::Base {
+                        for k in 0..sigs_count as usize {
+                            let sig = top(stack, isig + k);
+                            let found = find_and_delete(&mut script_code, &push_encoding(sig));
+                            if found > 0 && flags.contains(VerifyFlags::CONST_SCRIPTCODE) {
+                                return Err(ScriptError::SigFindAndDelete);
+                            }
+                        }
+                    }
+
+                    let mut success = true;
+                    while success && sigs_count > 0 {
+                        let sig = top(stack, isig);
+                        let pubkey = top(stack, ikey);
+                        // The order of these checks makes the evaluation order of keys and
+                        // signatures observable with STRICTENC.
+                        check_signature_encoding(sig, flags)?;
+                        check_pubkey_encoding(pubkey, flags, sig_version)?;
+
+                        if checker.check_ecdsa_signature(sig, pubkey, &script_code, sig_version) {
+                            isig += 1;
+                            sigs_count -= 1;
+                        }
+                        ikey += 1;
+                        keys_count -= 1;
+
+                        // Give up once there are more signatures left than keys.
+                        if sigs_count > keys_count {
+                            success = false;
+                        }
+                    }
+
+                    // Clean up the arguments, which must be empty signatures if we failed.
+                    while i > 1 {
+                        i -= 1;
+                        if !success
+                            && flags.contains(VerifyFlags::NULLFAIL)
+                            && ikey2 == 0
+                            && !top(stack, 1).is_empty()
+                        {
+                            return Err(ScriptError::SigNullFail);
+                        }
+                        ikey2 = ikey2.saturating_sub(1);
+                        stack.pop();
+                    }
+
+                    // A bug consumes one extra element, which must be empty with NULLDUMMY.
+                    require(stack, 1)?;
+                    if flags.contains(VerifyFlags::NULLDUMMY) && !top(stack, 1).is_empty() {
+                        return Err(ScriptError::SigNullDummy);
+                    }
+                    stack.pop();
+
+                    if op == OP_CHECKMULTISIGVERIFY {
+                        if !success {
+                            return Err(ScriptError::CheckMultisigVerify);
+                        }
+                    } else {
+                        stack.push(bool_bytes(success));
+                    }
+                }
+
+                _ => return Err(ScriptError::BadOpcode),
+            },
+            _ => {}
+        }
+
+        if stack.len() + altstack.len() > MAX_STACK_SIZE {
+            return Err(ScriptError::StackSize);
+        }
+        opcode_pos += 1;
+    }
+
+    if !exec.is_empty() {
+        return Err(ScriptError::UnbalancedConditional);
+    }
+    Ok(())
+}
+
+fn verify_witness_program<C: SignatureChecker + ?Sized>(
+    witness: &[Vec<u8>],
+    version: u8,
+    program: &[u8],
+    flags: VerifyFlags,
+    checker: &C,
+    is_p2sh: bool,
+    trace: Option<&mut ExecutionTrace>,
+) -> Result<(), ScriptError> {
+    let mut exec_data = ExecutionData::default();
+    match (version, program.len()) {
+        // P2WSH: the last witness element is the script, committed to by its SHA256.
+        (0, 32) => {
+            let (script, stack) = witness
+                .split_last()
+                .ok_or(ScriptError::WitnessProgramWitnessEmpty)?;
+            if sha256::Hash::hash(script).as_byte_array()[..] != program[..] {
+                return Err(ScriptError::WitnessProgramMismatch);
+            }
+            execute_witness_script(
+                stack.to_vec(),
+                script,
+                flags,
+                checker,
+                SigVersion::WitnessV0,
+                &mut exec_data,
+                trace,
+            )
+        }
+        // P2WPKH: a signature and public key spending the implied P2PKH script.
+        (0, 20) => {
+            if witness.len() != 2 {
+                return Err(ScriptError::WitnessProgramMismatch);
+            }
+            execute_witness_script(
+                witness.to_vec(),
+                ScriptBuf::new_p2pkh(program).as_bytes(),
+                flags,
+                checker,
+                SigVersion::WitnessV0,
+                &mut exec_data,
+                trace,
+            )
+        }
+        (0, _) => Err(ScriptError::WitnessProgramWrongLength),
+        // P2TR, which can't be nested in P2SH.
+        (1, 32) if !is_p2sh => {
+            // Before taproot activates these spends succeed, even if upgradable witness
+            // programs are discouraged.
+            if !flags.contains(VerifyFlags::TAPROOT) {
+                return Ok(());
+            }
+            verify_taproot(witness, program, flags, checker, trace)
+        }
+        // Other versions are left for future soft forks.
+        _ => {
+            if flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM) {
+                return Err(ScriptError::DiscourageUpgradableWitnessProgram);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Verifies a spend of the taproot output key `program`.
+fn verify_taproot<C: SignatureChecker + ?Sized>(
+    witness: &[Vec<u8>],
+    program: &[u8],
+    flags: VerifyFlags,
+    checker: &C,
+    trace: Option<&mut ExecutionTrace>,
+) -> Result<(), ScriptError> {
+    if witness.is_empty() {
+        return Err(ScriptError::WitnessProgramWitnessEmpty);
+    }
+    let mut exec_data = ExecutionData {
+        annex: annex(witness).map(<[u8]>::to_vec),
+        ..ExecutionData::default()
+    };
+
+    // Key path: a single signature for the output key.
+    let spend = match ScriptPathSpend::from_witness(witness) {
+        Ok(Some(spend)) => spend,
+        Ok(None) => {
+            return checker.check_schnorr_signature(
+                &witness[0],
+                program,
+                SigVersion::Taproot,
+                &exec_data,
+            )
+        }
+        Err(ControlBlockError::InvalidSize { .. }) => {
+            return Err(ScriptError::TaprootWrongControlSize)
+        }
+        Err(_) => return Err(ScriptError::WitnessProgramMismatch),
+    };
+
+    // Script path: a script and a control block proving the output key commits to it.
+    let leaf_version = spend.control_block.leaf_version;
+    let tapleaf_hash = spend.leaf_hash();
+    let committed = XOnlyPublicKey::from_slice(program).is_ok_and(|output_key| {
+        spend
+            .control_block
+            .verify_leaf_hash(&output_key, tapleaf_hash)
+    });
+    if !committed {
+        return Err(ScriptError::WitnessProgramMismatch);
+    }
+    exec_data.tapleaf_hash = Some(tapleaf_hash);
+
+    if leaf_version != TAPROOT_LEAF_TAPSCRIPT {
+        // Other leaf versions are left for future soft forks.
+        if flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_TAPROOT_VERSION) {
+            return Err(ScriptError::DiscourageUpgradableTaprootVersion);
+        }
+        return Ok(());
+    }
+    exec_data.validation_weight_left = witness_size(witness) as i64 + VALIDATION_WEIGHT_OFFSET;
+    execute_witness_script(
+        spend.stack.to_vec(),
+        spend.script.as_bytes(),
+        flags,
+        checker,
+        SigVersion::Tapscript,
+        &mut exec_data,
+        trace,
+    )
+}
+
+/// Returns the serialized size of `witness`.
+fn witness_size(witness: &[Vec<u8>]) -> usize {
+    witness
+        .iter()
+        .fold(encode_compact_size(witness.len()).len(), |size, elem| {
+            size + encode_compact_size(elem.len()).len() + elem.len()
+        })
+}
+
+/// Runs a segwit version 0 script or tapscript on the rest of its witness.
+fn execute_witness_script<C: SignatureChecker + ?Sized>(
+    mut stack: Stack,
+    script: &[u8],
+    flags: VerifyFlags,
+    checker: &C,
+    sig_version: SigVersion,
+    exec_data: &mut ExecutionData,
+    trace: Option<&mut ExecutionTrace>,
+) -> Result<(), ScriptError> {
+    if sig_version == SigVersion::Tapscript {
+        // Any OP_SUCCESSx makes the script succeed, before any other rule applies.
+        for instruction in Instructions::from(script) {
+            match instruction {
+                Ok(Instruction::Op(op))
+                    if op.classify(ClassifyContext::TapScript) == Class::SuccessOp =>
+                {
+                    if flags.contains(VerifyFlags::DISCOURAGE_OP_SUCCESS) {
+                        return Err(ScriptError::DiscourageOpSuccess);
+                    }
+                    return Ok(());
+                }
+                Ok(_) => {}
+                Err(_) => return Err(ScriptError::BadOpcode),
+            }
+        }
+        // The stack limit also applies to the initial stack.
+        if stack.len() > MAX_STACK_SIZE {
+            return Err(ScriptError::StackSize);
+        }
+    }
+
+    if stack
+        .iter()
+        .any(|elem| elem.len() > MAX_SCRIPT_ELEMENT_SIZE)
+    {
+        return Err(ScriptError::PushSize);
+    }
+    eval(
+        &mut stack,
+        script,
+        flags,
+        checker,
+        sig_version,
+        exec_data,
+        trace,
+    )?;
+
+    // Witness scripts implicitly require a clean stack.
+    if stack.len() != 1 {
+        return Err(ScriptError::CleanStack);
+    }
+    if !cast_to_bool(&stack[0]) {
+        return Err(ScriptError::EvalFalse);
+    }
+    Ok(())
+}
+
+/// Verifies that `script_sig` and `witness` satisfy `script_pubkey`.
+///
+/// # Panics
+///
+/// If `flags` contains `CLEANSTACK` without both `P2SH` and `WITNESS`, or `WITNESS`
+/// without `P2SH`. Such combinations can't be enabled as soft forks and aren't supported.
+pub fn verify_script<C: SignatureChecker + ?Sized>(
+    script_sig: &Script,
+    script_pubkey: &Script,
+    witness: &[Vec<u8>],
+    flags: VerifyFlags,
+    checker: &C,
+) -> Result<(), ScriptError> {
+    verify_spend(script_sig, script_pubkey, witness, flags, checker, None)
+}
+
+/// Verifies a spend like [`verify_script`], recording every step of the scripts it runs
+/// in `trace`.
+///
+/// # Panics
+///
+/// Under the same conditions as [`verify_script`].
+pub fn verify_script_with_trace<C: SignatureChecker + ?Sized>(
+    script_sig: &Script,
+    script_pubkey: &Script,
+    witness: &[Vec<u8>],
+    flags: VerifyFlags,
+    checker: &C,
+    trace: &mut ExecutionTrace,
+) -> Result<(), ScriptError> {
+    verify_spend(
+        script_sig,
+        script_pubkey,
+        witness,
+        flags,
+        checker,
+        Some(trace),
+    )
+}
+
+fn verify_spend<C: SignatureChecker + ?Sized>(
+    script_sig: &Script,
+    script_pubkey: &Script,
+    witness: &[Vec<u8>],
+    flags: VerifyFlags,
+    checker: &C,
+    mut trace: Option<&mut ExecutionTrace>,
+) -> Result<(), ScriptError> {
+    assert!(
+        flags.is_supported(),
+        "unsupported verification flags {:#x}",
+        flags.bits()
+    );
+
+    if flags.contains(VerifyFlags::SIGPUSHONLY) && !script_sig.is_push_only() {
+        return Err(ScriptError::SigPushOnly);
+    }
+
+    // The scriptSig and scriptPubKey are evaluated one after another on the same
+    // stack, rather than concatenated (CVE-2010-5141).
+    let mut stack = Vec::new();
+    eval_legacy(&mut stack, script_sig, flags, checker, trace.as_deref_mut())?;
+    let p2sh_stack = if flags.contains(VerifyFlags::P2SH) {
+        stack.clone()
+    } else {
+        Vec::new()
+    };
+    eval_legacy(
+        &mut stack,
+        script_pubkey,
+        flags,
+        checker,
+        trace.as_deref_mut(),
+    )?;
+    if !stack.last().is_some_and(|top| cast_to_bool(top)) {
+        return Err(ScriptError::EvalFalse);
+    }
+
+    let mut had_witness = false;
+    if flags.contains(VerifyFlags::WITNESS) {
+        if let (Some(version), Some(program)) = (
+            script_pubkey.witness_version(),
+            script_pubkey.witness_program(),
+        ) {
+            had_witness = true;
+            // Anything in the scriptSig would make the transaction malleable.
+            if !script_sig.as_bytes().is_empty() {
+                return Err(ScriptError::WitnessMalleated);
+            }
+            verify_witness_program(
+                witness,
+                version.to_num(),
+                program,
+                flags,
+                checker,
+                false,
+                trace.as_deref_mut(),
+            )?;
+            // The stack isn't clean for witness programs, so skip that check.
+            stack.truncate(1);
+        }
+    }
+
+    if flags.contains(VerifyFlags::P2SH) && script_pubkey.is_p2sh() {
+        if !script_sig.is_push_only() {
+            return Err(ScriptError::SigPushOnly);
+        }
+
+        // Can't be empty: the scriptPubKey would have failed on an empty stack.
+        stack = p2sh_stack;
+        let redeem_script = ScriptBuf::from(stack.pop().unwrap());
+        eval_legacy(
+            &mut stack,
+            &redeem_script,
+            flags,
+            checker,
+            trace.as_deref_mut(),
+        )?;
+        if !stack.last().is_some_and(|top| cast_to_bool(top)) {
+            return Err(ScriptError::EvalFalse);
+        }
+
+        if flags.contains(VerifyFlags::WITNESS) {
+            if let (Some(version), Some(program)) = (
+                redeem_script.witness_version(),
+                redeem_script.witness_program(),
+            ) {
+                had_witness = true;
+                // The scriptSig must be exactly a single push of the redeem script.
+                if script_sig.as_bytes() != push_encoding(redeem_script.as_bytes()) {
+                    return Err(ScriptError::WitnessMalleatedP2sh);
+                }
+                verify_witness_program(
+                    witness,
+                    version.to_num(),
+                    program,
+                    flags,
+                    checker,
+                    true,
+                    trace,
+                )?;
+                stack.truncate(1);
+            }
+        }
+    }
+
+    // Only checked after P2SH, which leaves the redeem script's inputs in the
+    // non-P2SH evaluation.
+    if flags.contains(VerifyFlags::CLEANSTACK) && stack.len() != 1 {
+        return Err(ScriptError::CleanStack);
+    }
+
+    if flags.contains(VerifyFlags::WITNESS) && !had_witness && !witness.is_empty() {
+        return Err(ScriptError::WitnessUnexpected);
+    }
+
+    Ok(())
+}
+
+/// Executes a legacy script: a scriptSig, scriptPubKey or P2SH redeem script.
+fn eval_legacy<C: SignatureChecker + ?Sized>(
+    stack: &mut Stack,
+    script: &Script,
+    flags: VerifyFlags,
+    checker: &C,
+    trace: Option<&mut ExecutionTrace>,
+) -> Result<(), ScriptError> {
+    let mut exec_data = ExecutionData::default();
+    eval(
+        stack,
+        script.as_bytes(),
+        flags,
+        checker,
+        SigVersion::Base,
+        &mut exec_data,
+        trace,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::key::TweakedPublicKey;
+    use crate::sighash::{SIGHASH_ALL, SIGHASH_ANYONECANPAY, SIGHASH_SINGLE};
+    use crate::taproot::{TapNodeHash, TapTweakHash, TAPROOT_ANNEX_PREFIX};
+    use crate::transaction::{Transaction, TxIn, TxOut};
+    use secp256k1::{Keypair, Parity, Scalar, SecretKey};
+    use serde_json::Value;
+
+    /// Parses the script notation of Bitcoin Core's test vectors.
+    ///
+    /// The vectors are from Core v0.17.1, whose parser still pushed decimal numbers
+    /// outside of -0xffffffff..=0xffffffff as script numbers of up to eight bytes, which
+    /// [`ScriptBuf::from_asm`] rejects like later Core versions do, so those are rewritten
+    /// to the data they pushed.
+    fn parse_script(asm: &str) -> ScriptBuf {
+        let asm = asm
+            .split_whitespace()
+            .map(|token| match token.parse::<i64>() {
+                Ok(value) if value.unsigned_abs() > 0xffff_ffff => {
+                    format!("<{}>", hex::encode(ScriptNum::new(value).to_bytes()))
+                }
+                _ => token.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        ScriptBuf::from_asm(&asm).unwrap()
+    }
+
+    fn parse_flags(names: &str) -> VerifyFlags {
+        names
+            .split(',')
+            .filter(|name| !name.is_empty())
+            .fold(VerifyFlags::NONE, |flags, name| {
+                flags
+                    | match name {
+                        "NONE" => VerifyFlags::NONE,
+                        "P2SH" => VerifyFlags::P2SH,
+                        "STRICTENC" => VerifyFlags::STRICTENC,
+                        "DERSIG" => VerifyFlags::DERSIG,
+                        "LOW_S" => VerifyFlags::LOW_S,
+                        "NULLDUMMY" => VerifyFlags::NULLDUMMY,
+                        "SIGPUSHONLY" => VerifyFlags::SIGPUSHONLY,
+                        "MINIMALDATA" => VerifyFlags::MINIMALDATA,
+                        "DISCOURAGE_UPGRADABLE_NOPS" => VerifyFlags::DISCOURAGE_UPGRADABLE_NOPS,
+                        "CLEANSTACK" => VerifyFlags::CLEANSTACK,
+                        "CHECKLOCKTIMEVERIFY" => VerifyFlags::CHECKLOCKTIMEVERIFY,
+                        "CHECKSEQUENCEVERIFY" => VerifyFlags::CHECKSEQUENCEVERIFY,
+                        "WITNESS" => VerifyFlags::WITNESS,
+                        "DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM" => {
+                            VerifyFlags::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM
+                        }
+                        "MINIMALIF" => VerifyFlags::MINIMALIF,
+                        "NULLFAIL" => VerifyFlags::NULLFAIL,
+                        "WITNESS_PUBKEYTYPE" => VerifyFlags::WITNESS_PUBKEYTYPE,
+                        "CONST_SCRIPTCODE" => VerifyFlags::CONST_SCRIPTCODE,
+                        "TAPROOT" => VerifyFlags::TAPROOT,
+                        "DISCOURAGE_UPGRADABLE_TAPROOT_VERSION" => {
+                            VerifyFlags::DISCOURAGE_UPGRADABLE_TAPROOT_VERSION
+                        }
+                        "DISCOURAGE_OP_SUCCESS" => VerifyFlags::DISCOURAGE_OP_SUCCESS,
+                        "DISCOURAGE_UPGRADABLE_PUBKEYTYPE" => {
+                            VerifyFlags::DISCOURAGE_UPGRADABLE_PUBKEYTYPE
+                        }
+                        _ => panic!("unknown flag {}", name),
+                    }
+            })
+    }
+
+    /// Returns the name Bitcoin Core's test vectors use for a verification result.
+    fn error_name(result: Result<(), ScriptError>) -> &'static str {
+        let err = match result {
+            Ok(()) => return "OK",
+            Err(err) => err,
+        };
+        match err {
+            ScriptError::NonMinimalPush => "MINIMALDATA",
+            ScriptError::EarlyEndOfScript
+            | ScriptError::NumericOverflow
+            | ScriptError::NonMinimalNumber => "UNKNOWN_ERROR",
+            ScriptError::EvalFalse => "EVAL_FALSE",
+            ScriptError::OpReturn => "OP_RETURN",
+            ScriptError::ScriptSize => "SCRIPT_SIZE",
+            ScriptError::PushSize => "PUSH_SIZE",
+            ScriptError::OpCount => "OP_COUNT",
+            ScriptError::StackSize => "STACK_SIZE",
+            ScriptError::SigCount => "SIG_COUNT",
+            ScriptError::PubkeyCount => "PUBKEY_COUNT",
+            ScriptError::Verify => "VERIFY",
+            ScriptError::EqualVerify => "EQUALVERIFY",
+            ScriptError::CheckMultisigVerify => "CHECKMULTISIGVERIFY",
+            ScriptError::CheckSigVerify => "CHECKSIGVERIFY",
+            ScriptError::NumEqualVerify => "NUMEQUALVERIFY",
+            ScriptError::BadOpcode => "BAD_OPCODE",
+            ScriptError::DisabledOpcode => "DISABLED_OPCODE",
+            ScriptError::InvalidStackOperation => "INVALID_STACK_OPERATION",
+            ScriptError::InvalidAltstackOperation => "INVALID_ALTSTACK_OPERATION",
+            ScriptError::UnbalancedConditional => "UNBALANCED_CONDITIONAL",
+            ScriptError::NegativeLocktime => "NEGATIVE_LOCKTIME",
+            ScriptError::UnsatisfiedLocktime => "UNSATISFIED_LOCKTIME",
+            ScriptError::SigHashType => "SIG_HASHTYPE",
+            ScriptError::SigDer => "SIG_DER",
+            ScriptError::MinimalIf => "MINIMALIF",
+            ScriptError::SigNullDummy => "SIG_NULLDUMMY",
+            ScriptError::PubkeyType => "PUBKEYTYPE",
+            ScriptError::CleanStack => "CLEANSTACK",
+            ScriptError::SigNullFail => "NULLFAIL",
+            ScriptError::SigHighS => "SIG_HIGH_S",
+            ScriptError::SigPushOnly => "SIG_PUSHONLY",
+            ScriptError::DiscourageUpgradableNops => "DISCOURAGE_UPGRADABLE_NOPS",
+            ScriptError::DiscourageUpgradableWitnessProgram => {
+                "DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM"
+            }
+            ScriptError::WitnessProgramWrongLength => "WITNESS_PROGRAM_WRONG_LENGTH",
+            ScriptError::WitnessProgramWitnessEmpty => "WITNESS_PROGRAM_WITNESS_EMPTY",
+            ScriptError::WitnessProgramMismatch => "WITNESS_PROGRAM_MISMATCH",
+            ScriptError::WitnessMalleated => "WITNESS_MALLEATED",
+            ScriptError::WitnessMalleatedP2sh => "WITNESS_MALLEATED_P2SH",
+            ScriptError::WitnessUnexpected => "WITNESS_UNEXPECTED",
+            ScriptError::WitnessPubkeyType => "WITNESS_PUBKEYTYPE",
+            ScriptError::OpCodeSeparator => "OP_CODESEPARATOR",
+            ScriptError::SigFindAndDelete => "SIG_FINDANDDELETE",
+            ScriptError::SchnorrSigSize => "SCHNORR_SIG_SIZE",
+            ScriptError::SchnorrSigHashType => "SCHNORR_SIG_HASHTYPE",
+            ScriptError::SchnorrSig => "SCHNORR_SIG",
+            ScriptError::TaprootWrongControlSize => "TAPROOT_WRONG_CONTROL_SIZE",
+            ScriptError::TapscriptValidationWeight => "TAPSCRIPT_VALIDATION_WEIGHT",
+            ScriptError::TapscriptCheckMultisig => "TAPSCRIPT_CHECKMULTISIG",
+            ScriptError::TapscriptMinimalIf => "TAPSCRIPT_MINIMALIF",
+            ScriptError::DiscourageUpgradableTaprootVersion => {
+                "DISCOURAGE_UPGRADABLE_TAPROOT_VERSION"
+            }
+            ScriptError::DiscourageOpSuccess => "DISCOURAGE_OP_SUCCESS",
+            ScriptError::DiscourageUpgradablePubkeyType => "DISCOURAGE_UPGRADABLE_PUBKEYTYPE",
+        }
+    }
+
+    /// Builds the transaction spending the output of a coinbase-like transaction paying
+    /// `amount` to `script_pubkey`, as Core's script tests do.
+    fn spending_transaction(script_pubkey: &ScriptBuf, amount: u64) -> Transaction {
+        let credit = Transaction {
+            version: 1,
+            input: vec![TxIn {
+                txid: "00".repeat(32),
+                vout: u32::MAX,
+                script_sig: vec![0x00, 0x00],
+                sequence: u32::MAX,
+                witness: vec![],
+            }],
+            output: vec![TxOut {
+                amount,
+                script_pubkey: script_pubkey.as_bytes().to_vec(),
+            }],
+            lock_time: 0,
+        };
+
+        Transaction {
+            version: 1,
+            input: vec![TxIn {
+                txid: credit.txid(),
+                vout: 0,
+                script_sig: vec![],
+                sequence: u32::MAX,
+                witness: vec![],
+            }],
+            output: vec![TxOut {
+                amount,
+                script_pubkey: vec![],
+            }],
+            lock_time: 0,
+        }
+    }
+
+    fn verify(
+        tx: &Transaction,
+        script_pubkey: &ScriptBuf,
+        amount: u64,
+        flags: VerifyFlags,
+    ) -> Result<(), ScriptError> {
+        let cache = SighashCache::new(tx).unwrap();
+        let checker = TransactionSignatureChecker::new(&cache, 0, amount);
+        let input = &tx.input[0];
+        verify_script(
+            &ScriptBuf::from(input.script_sig.clone()),
+            script_pubkey,
+            &input.witness,
+            flags,
+            &checker,
+        )
+    }
+
+    /// Runs a row in the format of Bitcoin Core's script_tests.json, returning false for
+    /// comments.
+    fn run_script_test(vector: &Value) -> bool {
+        let mut fields = vector.as_array().unwrap().as_slice();
+        if fields.len() == 1 {
+            return false;
+        }
+
+        let mut witness = vec![];
+        let mut amount = 0;
+        if let Some(items) = fields[0].as_array() {
+            let (value, items) = items.split_last().unwrap();
+            witness = items
+                .iter()
+                .map(|item| hex::decode(item.as_str().unwrap()).unwrap())
+                .collect();
+            amount = (value.as_f64().unwrap() * 100_000_000.0).round() as u64;
+            fields = &fields[1..];
+        }
+
+        let script_sig = parse_script(fields[0].as_str().unwrap());
+        let script_pubkey = parse_script(fields[1].as_str().unwrap());
+        let mut flags = parse_flags(fields[2].as_str().unwrap());
+        // Like Core's test runner, as CLEANSTACK is only defined together with these.
+        if flags.contains(VerifyFlags::CLEANSTACK) {
+            flags |= VerifyFlags::P2SH | VerifyFlags::WITNESS;
+        }
+
+        let mut tx = spending_transaction(&script_pubkey, amount);
+        tx.input[0].script_sig = script_sig.as_bytes().to_vec();
+        tx.input[0].witness = witness;
+
+        let result = verify(&tx, &script_pubkey, amount, flags);
+        assert_eq!(
+            error_name(result),
+            fields[3].as_str().unwrap(),
+            "{}",
+            vector
+        );
+        true
+    }
+
+    #[test]
+    fn script_tests() {
+        // src/test/data/script_tests.json of Bitcoin Core v0.17.1, unmodified. Every row
+        // is run. It has no rows using CONST_SCRIPTCODE, which
+        // codeseparator_and_find_and_delete covers instead.
+        let vectors: Vec<Value> =
+            serde_json::from_str(include_str!("../../tests/data/script_tests.json")).unwrap();
+        assert_eq!(vectors.len(), 1253);
+        let tested = vectors
+            .iter()
+            .filter(|vector| run_script_test(vector))
+            .count();
+        assert_eq!(tested, 1203);
+    }
+
+    #[test]
+    fn cast_to_bool_ignores_negative_zero() {
+        assert!(!cast_to_bool(&[]));
+        assert!(!cast_to_bool(&[0x00, 0x00]));
+        assert!(!cast_to_bool(&[0x00, 0x80]));
+        assert!(cast_to_bool(&[0x80, 0x00]));
+        assert!(cast_to_bool(&[0x01]));
+    }
+
+    #[test]
+    fn find_and_delete_matches_core() {
+        // Cases from Core's script_FindAndDelete test.
+        let cases = [
+            ("0302ff030302ff03", "0302ff03", "", 2),
+            ("0302ff030302ff03", "02", "0302ff030302ff03", 0),
+            ("0302ff030302ff03", "ff", "0302ff030302ff03", 0),
+            ("0003feed", "03feed", "00", 1),
+            ("0003feed", "00", "03feed", 1),
+            ("02feed51", "feed", "02feed51", 0),
+            ("02feed51", "02feed", "51", 1),
+            ("00005151", "0051", "0051", 1),
+            // Strips the push-three-bytes prefix, leaving two push-two-bytes.
+            ("0302ff030302ff03", "03", "02ff0302ff03", 2),
+            ("0302ff030302ff03", "0302ff0303", "02ff03", 1),
+            ("ab", "ab", "", 1),
+        ];
+        for (script, pattern, expected, found) in cases {
+            let mut script = hex::decode(script).unwrap();
+            let pattern = hex::decode(pattern).unwrap();
+            assert_eq!(find_and_delete(&mut script, &pattern), found);
+            assert_eq!(hex::encode(script), expected);
+        }
+    }
+
+    #[test]
+    fn minimal_pushes() {
+        assert!(is_minimal_push(&[], 0x00));
+        assert!(!is_minimal_push(&[], OP_PUSHDATA1.to_u8()));
+        assert!(!is_minimal_push(&[0x05], 0x01));
+        assert!(!is_minimal_push(&[0x81], 0x01));
+        assert!(is_minimal_push(&[0x00], 0x01));
+        assert!(is_minimal_push(&[0x42; 75], 75));
+        assert!(is_minimal_push(&[0x42; 76], OP_PUSHDATA1.to_u8()));
+        assert!(!is_minimal_push(&[0x42; 255], OP_PUSHDATA2.to_u8()));
+        assert!(is_minimal_push(&[0x42; 256], OP_PUSHDATA2.to_u8()));
+    }
+
+    fn key(byte: u8) -> (SecretKey, PublicKey) {
+        let secret = SecretKey::from_slice(&[byte; 32]).unwrap();
+        (secret, secret.public_key(&Secp256k1::new()))
+    }
+
+    fn sign(
+        tx: &Transaction,
+        secret: &SecretKey,
+        script_code: &[u8],
+        amount: u64,
+        sig_version: SigVersion,
+        sighash_type: u32,
+    ) -> Vec<u8> {
+        let cache = SighashCache::new(tx).unwrap();
+        let sighash = match sig_version {
+            SigVersion::Base => cache
+                .legacy_signature_hash(0, script_code, sighash_type)
+                .to_byte_array(),
+            SigVersion::WitnessV0 => cache
+                .segwit_v0_signature_hash(0, script_code, amount, sighash_type)
+                .to_byte_array(),
+            SigVersion::Taproot | SigVersion::Tapscript => unreachable!("use sign_schnorr"),
+        };
+        let sig = Secp256k1::new().sign_ecdsa(&Message::from_digest(sighash), secret);
+        let mut sig = sig.serialize_der().to_vec();
+        sig.push(sighash_type as u8);
+        sig
+    }
+
+    /// Returns `sig` with its S value replaced by `n - S`, which is equally valid but high.
+    fn high_s(sig: &[u8]) -> Vec<u8> {
+        const ORDER: [u8; 32] = [
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            0xff, 0xfe, 0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c,
+            0xd0, 0x36, 0x41, 0x41,
+        ];
+        let (&sighash_type, der) = sig.split_last().unwrap();
+        let mut compact = ecdsa::Signature::from_der(der).unwrap().serialize_compact();
+        let mut borrow = 0i16;
+        for i in (0..32).rev() {
+            let diff = ORDER[i] as i16 - compact[32 + i] as i16 - borrow;
+            compact[32 + i] = diff.rem_euclid(256) as u8;
+            borrow = (diff < 0) as i16;
+        }
+        let mut sig = ecdsa::Signature::from_compact(&compact)
+            .unwrap()
+            .serialize_der()
+            .to_vec();
+        sig.push(sighash_type);
+        sig
+    }
+
+    fn script_sig(pushes: &[&[u8]]) -> Vec<u8> {
+        pushes.iter().flat_map(|push| push_encoding(push)).collect()
+    }
+
+    #[test]
+    fn p2pk_and_p2pkh() {
+        let (secret, pubkey) = key(1);
+        let (other_secret, _) = key(2);
+        let flags = VerifyFlags::STANDARD;
+
+        let p2pk = parse_script(&format!("0x21 0x{} CHECKSIG", pubkey));
+        let mut tx = spending_transaction(&p2pk, 1000);
+        let sig = sign(
+            &tx,
+            &secret,
+            p2pk.as_bytes(),
+            0,
+            SigVersion::Base,
+            SIGHASH_ALL,
+        );
+        tx.input[0].script_sig = script_sig(&[&sig]);
+        assert_eq!(verify(&tx, &p2pk, 1000, flags), Ok(()));
+
+        let wrong = sign(
+            &tx,
+            &other_secret,
+            p2pk.as_bytes(),
+            0,
+            SigVersion::Base,
+            SIGHASH_ALL,
+        );
+        tx.input[0].script_sig = script_sig(&[&wrong]);
+        assert_eq!(
+            verify(&tx, &p2pk, 1000, VerifyFlags::P2SH),
+            Err(ScriptError::EvalFalse)
+        );
+        assert_eq!(
+            verify(&tx, &p2pk, 1000, flags),
+            Err(ScriptError::SigNullFail)
+        );
+
+        let p2pkh = ScriptBuf::new_p2pkh(&hash160::Hash::hash(&pubkey.serialize()).to_byte_array());
+        let mut tx = spending_transaction(&p2pkh, 1000);
+        let sig = sign(
+            &tx,
+            &secret,
+            p2pkh.as_bytes(),
+            0,
+            SigVersion::Base,
+            SIGHASH_ALL,
+        );
+        tx.input[0].script_sig = script_sig(&[&sig, &pubkey.serialize()]);
+        assert_eq!(verify(&tx, &p2pkh, 1000, flags), Ok(()));
+
+        // Changing the transaction after signing invalidates the signature.
+        tx.output[0].amount = 999;
+        assert_eq!(
+            verify(&tx, &p2pkh, 1000, VerifyFlags::P2SH),
+            Err(ScriptError::EvalFalse)
+        );
+    }
+
+    #[test]
+    fn signature_encoding() {
+        let (secret, pubkey) = key(1);
+        let p2pk = parse_script(&format!("0x21 0x{} CHECKSIG", pubkey));
+        let mut tx = spending_transaction(&p2pk, 1000);
+        let sig = sign(
+            &tx,
+            &secret,
+            p2pk.as_bytes(),
+            0,
+            SigVersion::Base,
+            SIGHASH_ALL,
+        );
+
+        tx.input[0].script_sig = script_sig(&[&high_s(&sig)]);
+        assert_eq!(verify(&tx, &p2pk, 1000, VerifyFlags::P2SH), Ok(()));
+        assert_eq!(
+            verify(&tx, &p2pk, 1000, VerifyFlags::LOW_S),
+            Err(ScriptError::SigHighS)
+        );
+
+        let mut undefined = sig.clone();
+        *undefined.last_mut().unwrap() = 0x00;
+        tx.input[0].script_sig = script_sig(&[&undefined]);
+        assert_eq!(
+            verify(&tx, &p2pk, 1000, VerifyFlags::STRICTENC),
+            Err(ScriptError::SigHashType)
+        );
+
+        let mut padded = sig.clone();
+        padded.insert(padded.len() - 1, 0x00);
+        tx.input[0].script_sig = script_sig(&[&padded]);
+        assert_eq!(
+            verify(&tx, &p2pk, 1000, VerifyFlags::DERSIG),
+            Err(ScriptError::SigDer)
+        );
+
+        let uncompressed = parse_script(&format!(
+            "0x41 0x{} CHECKSIG",
+            hex::encode(pubkey.serialize_uncompressed())
+        ));
+        let mut tx = spending_transaction(&uncompressed, 1000);
+        let sig = sign(
+            &tx,
+            &secret,
+            uncompressed.as_bytes(),
+            0,
+            SigVersion::Base,
+            SIGHASH_ALL,
+        );
+        tx.input[0].script_sig = script_sig(&[&sig]);
+        assert_eq!(
+            verify(&tx, &uncompressed, 1000, VerifyFlags::STANDARD),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn multisig() {
+        let keys = [key(1), key(2), key(3)];
+        let script_pubkey = parse_script(&format!(
+            "2 0x21 0x{} 0x21 0x{} 0x21 0x{} 3 CHECKMULTISIG",
+            keys[0].1, keys[1].1, keys[2].1
+        ));
+        let mut tx = spending_transaction(&script_pubkey, 1000);
+        let sigs: Vec<_> = keys
+            .iter()
+            .map(|(secret, _)| {
+                sign(
+                    &tx,
+                    secret,
+                    script_pubkey.as_bytes(),
+                    0,
+                    SigVersion::Base,
+                    SIGHASH_ALL,
+                )
+            })
+            .collect();
+
+        tx.input[0].script_sig = script_sig(&[&[], &sigs[0], &sigs[2]]);
+        assert_eq!(
+            verify(&tx, &script_pubkey, 1000, VerifyFlags::STANDARD),
+            Ok(())
+        );
+
+        // Signatures must be in the same order as the keys.
+        tx.input[0].script_sig = script_sig(&[&[], &sigs[2], &sigs[0]]);
+        assert_eq!(
+            verify(&tx, &script_pubkey, 1000, VerifyFlags::P2SH),
+            Err(ScriptError::EvalFalse)
+        );
+
+        tx.input[0].script_sig = script_sig(&[&[0x01], &sigs[0], &sigs[1]]);
+        assert_eq!(verify(&tx, &script_pubkey, 1000, VerifyFlags::P2SH), Ok(()));
+        assert_eq!(
+            verify(&tx, &script_pubkey, 1000, VerifyFlags::NULLDUMMY),
+            Err(ScriptError::SigNullDummy)
+        );
+
+        // The same script behind P2SH.
+        let redeem_script = script_pubkey;
+        let p2sh =
+            ScriptBuf::new_p2sh(&hash160::Hash::hash(redeem_script.as_bytes()).to_byte_array());
+        let mut tx = spending_transaction(&p2sh, 1000);
+        let sigs: Vec<_> = keys
+            .iter()
+            .map(|(secret, _)| {
+                sign(
+                    &tx,
+                    secret,
+                    redeem_script.as_bytes(),
+                    0,
+                    SigVersion::Base,
+                    SIGHASH_ALL,
+                )
+            })
+            .collect();
+        tx.input[0].script_sig = script_sig(&[&[], &sigs[1], &sigs[2], redeem_script.as_bytes()]);
+        assert_eq!(verify(&tx, &p2sh, 1000, VerifyFlags::STANDARD), Ok(()));
+        // Without P2SH only the hash of the redeem script is checked.
+        tx.input[0].script_sig = script_sig(&[&[], redeem_script.as_bytes()]);
+        assert_eq!(verify(&tx, &p2sh, 1000, VerifyFlags::NONE), Ok(()));
+        assert_eq!(
+            verify(&tx, &p2sh, 1000, VerifyFlags::P2SH),
+            Err(ScriptError::InvalidStackOperation)
+        );
+    }
+
+    #[test]
+    fn segwit_v0() {
+        let (secret, pubkey) = key(1);
+        let amount = 100_000;
+        let flags = VerifyFlags::STANDARD;
+
+        let pubkey_hash = hash160::Hash::hash(&pubkey.serialize()).to_byte_array();
+        let p2wpkh = ScriptBuf::new_p2wpkh(&pubkey_hash);
+        let script_code = ScriptBuf::new_p2pkh(&pubkey_hash);
+        let mut tx = spending_transaction(&p2wpkh, amount);
+        let sig = sign(
+            &tx,
+            &secret,
+            script_code.as_bytes(),
+            amount,
+            SigVersion::WitnessV0,
+            SIGHASH_ALL,
+        );
+        tx.input[0].witness = vec![sig, pubkey.serialize().to_vec()];
+        assert_eq!(verify(&tx, &p2wpkh, amount, flags), Ok(()));
+        // The amount is committed to by the signature.
+        assert_eq!(
+            verify(&tx, &p2wpkh, amount + 1, flags),
+            Err(ScriptError::SigNullFail)
+        );
+
+        let uncompressed = pubkey.serialize_uncompressed();
+        let pubkey_hash = hash160::Hash::hash(&uncompressed).to_byte_array();
+        let p2wpkh = ScriptBuf::new_p2wpkh(&pubkey_hash);
+        let script_code = ScriptBuf::new_p2pkh(&pubkey_hash);
+        let mut tx = spending_transaction(&p2wpkh, amount);
+        let sig = sign(
+            &tx,
+            &secret,
+            script_code.as_bytes(),
+            amount,
+            SigVersion::WitnessV0,
+            SIGHASH_ALL,
+        );
+        tx.input[0].witness = vec![sig, uncompressed.to_vec()];
+        assert_eq!(verify(&tx, &p2wpkh, amount, VerifyFlags::CONSENSUS), Ok(()));
+        assert_eq!(
+            verify(&tx, &p2wpkh, amount, flags),
+            Err(ScriptError::WitnessPubkeyType)
+        );
+
+        let witness_script = parse_script(&format!("0x21 0x{} CHECKSIG", pubkey));
+        let p2wsh =
+            ScriptBuf::new_p2wsh(&sha256::Hash::hash(witness_script.as_bytes()).to_byte_array());
+        let mut tx = spending_transaction(&p2wsh, amount);
+        let sig = sign(
+            &tx,
+            &secret,
+            witness_script.as_bytes(),
+            amount,
+            SigVersion::WitnessV0,
+            SIGHASH_SINGLE,
+        );
+        tx.input[0].witness = vec![sig.clone(), witness_script.as_bytes().to_vec()];
+        assert_eq!(verify(&tx, &p2wsh, amount, flags), Ok(()));
+
+        // Nested in P2SH.
+        let p2sh = ScriptBuf::new_p2sh(&hash160::Hash::hash(p2wsh.as_bytes()).to_byte_array());
+        let mut tx = spending_transaction(&p2sh, amount);
+        let sig = sign(
+            &tx,
+            &secret,
+            witness_script.as_bytes(),
+            amount,
+            SigVersion::WitnessV0,
+            SIGHASH_ALL,
+        );
+        tx.input[0].script_sig = script_sig(&[p2wsh.as_bytes()]);
+        tx.input[0].witness = vec![sig, witness_script.as_bytes().to_vec()];
+        assert_eq!(verify(&tx, &p2sh, amount, flags), Ok(()));
+    }
+
+    /// Returns `sig` with a zero byte padding R, which only lax DER parsing accepts.
+    fn pad_r(sig: &[u8]) -> Vec<u8> {
+        let mut padded = sig.to_vec();
+        padded[1] += 1;
+        padded[3] += 1;
+        padded.insert(4, 0x00);
+        padded
+    }
+
+    /// Flips a bit of R, like `DamagePush(10)` in Core's script_tests.cpp.
+    fn damage(sig: &[u8]) -> Vec<u8> {
+        let mut damaged = sig.to_vec();
+        damaged[10] ^= 1;
+        damaged
+    }
+
+    /// Returns a row of script_tests.json spending `script_pubkey`, with the scriptSig
+    /// and witness `unlock` makes for the spending transaction.
+    fn signature_vector(
+        script_pubkey: &ScriptBuf,
+        amount: u64,
+        unlock: impl Fn(&Transaction) -> (Vec<u8>, Vec<Vec<u8>>),
+        flags: &str,
+        expected: &str,
+        comment: &str,
+    ) -> Value {
+        let (script_sig, witness) = unlock(&spending_transaction(script_pubkey, amount));
+        let raw = |bytes: &[u8]| match bytes.is_empty() {
+            true => String::new(),
+            false => format!("0x{}", hex::encode(bytes)),
+        };
+        let mut row = vec![];
+        if !witness.is_empty() {
+            let mut items: Vec<Value> = witness
+                .iter()
+                .map(|item| hex::encode(item).into())
+                .collect();
+            items.push((amount as f64 / 100_000_000.0).into());
+            row.push(Value::Array(items));
+        }
+        row.extend([
+            raw(&script_sig).into(),
+            raw(script_pubkey.as_bytes()).into(),
+            flags.into(),
+            expected.into(),
+            comment.into(),
+        ]);
+        Value::Array(row)
+    }
+
+    #[test]
+    fn core_signature_vectors() {
+        // The cases script_tests.cpp generates the signature rows of
+        // tests/data/script_tests.json from, signed with the keys of these tests instead
+        // of Core's and run as rows of the same format.
+        let keys = [key(1), key(2), key(3)];
+        let (secret0, secret1, secret2) = (&keys[0].0, &keys[1].0, &keys[2].0);
+        let pubkeys: Vec<_> = keys.iter().map(|(_, pubkey)| pubkey.serialize()).collect();
+        let uncompressed = keys[0].1.serialize_uncompressed();
+        let mut hybrid = uncompressed;
+        hybrid[0] = 0x06 | (uncompressed[64] & 1);
+
+        let p2pk = |pubkey: &[u8], tail: &str| {
+            parse_script(&format!(
+                "0x{:02x} 0x{} {}",
+                pubkey.len(),
+                hex::encode(pubkey),
+                tail
+            ))
+        };
+        let multisig = |required: u8, pubkeys: &[[u8; 33]], tail: &str| {
+            let pushes: Vec<_> = pubkeys
+                .iter()
+                .map(|pubkey| format!("0x21 0x{}", hex::encode(pubkey)))
+                .collect();
+            parse_script(&format!(
+                "{} {} {} CHECKMULTISIG {}",
+                required,
+                pushes.join(" "),
+                pubkeys.len(),
+                tail
+            ))
+        };
+        let legacy =
+            |tx: &Transaction, secret: &SecretKey, script_code: &ScriptBuf, sighash_type| {
+                sign(
+                    tx,
+                    secret,
+                    script_code.as_bytes(),
+                    0,
+                    SigVersion::Base,
+                    sighash_type,
+                )
+            };
+        let segwit = |tx: &Transaction, secret: &SecretKey, script_code: &ScriptBuf, amount| {
+            sign(
+                tx,
+                secret,
+                script_code.as_bytes(),
+                amount,
+                SigVersion::WitnessV0,
+                SIGHASH_ALL,
+            )
+        };
+        let mut vectors = vec![];
+
+        let p2pk0 = p2pk(&uncompressed, "CHECKSIG");
+        let sig = |tx: &Transaction| legacy(tx, secret0, &p2pk0, SIGHASH_ALL);
+        vectors.extend([
+            signature_vector(
+                &p2pk0,
+                0,
+                |tx| (script_sig(&[&sig(tx)]), vec![]),
+                "",
+                "OK",
+                "P2PK",
+            ),
+            signature_vector(
+                &p2pk0,
+                0,
+                |tx| (script_sig(&[&damage(&sig(tx))]), vec![]),
+                "",
+                "EVAL_FALSE",
+                "P2PK, bad sig",
+            ),
+        ]);
+
+        let p2pkh = ScriptBuf::new_p2pkh(&hash160::Hash::hash(&pubkeys[1]).to_byte_array());
+        let sig = |tx: &Transaction| legacy(tx, secret1, &p2pkh, SIGHASH_ALL);
+        vectors.extend([
+            signature_vector(
+                &p2pkh,
+                0,
+                |tx| (script_sig(&[&sig(tx), &pubkeys[1]]), vec![]),
+                "",
+                "OK",
+                "P2PKH",
+            ),
+            signature_vector(
+                &p2pkh,
+                0,
+                |tx| (script_sig(&[&sig(tx), &pubkeys[2]]), vec![]),
+                "",
+                "EQUALVERIFY",
+                "P2PKH, bad pubkey",
+            ),
+        ]);
+
+        let p2pk1 = p2pk(&pubkeys[1], "CHECKSIG");
+        let sig = |tx: &Transaction, sighash_type| legacy(tx, secret1, &p2pk1, sighash_type);
+        let anyonecanpay = SIGHASH_ALL | SIGHASH_ANYONECANPAY;
+        let with_hashtype = |mut sig: Vec<u8>, sighash_type: u8| {
+            *sig.last_mut().unwrap() = sighash_type;
+            sig
+        };
+        vectors.extend([
+            signature_vector(
+                &p2pk1,
+                0,
+                |tx| (script_sig(&[&sig(tx, anyonecanpay)]), vec![]),
+                "",
+                "OK",
+                "P2PK anyonecanpay",
+            ),
+            signature_vector(
+                &p2pk1,
+                0,
+                |tx| {
+                    (
+                        script_sig(&[&with_hashtype(sig(tx, anyonecanpay), 0x01)]),
+                        vec![],
+                    )
+                },
+                "",
+                "EVAL_FALSE",
+                "P2PK anyonecanpay marked with normal hashtype",
+            ),
+        ]);
+
+        let p2sh = ScriptBuf::new_p2sh(&hash160::Hash::hash(p2pk0.as_bytes()).to_byte_array());
+        let sig = |tx: &Transaction| legacy(tx, secret0, &p2pk0, SIGHASH_ALL);
+        vectors.extend([
+            signature_vector(
+                &p2sh,
+                0,
+                |tx| (script_sig(&[&sig(tx), p2pk0.as_bytes()]), vec![]),
+                "P2SH",
+                "OK",
+                "P2SH(P2PK)",
+            ),
+            signature_vector(
+                &p2sh,
+                0,
+                |tx| (script_sig(&[&sig(tx), &damage(p2pk0.as_bytes())]), vec![]),
+                "P2SH",
+                "EVAL_FALSE",
+                "P2SH(P2PK), bad redeemscript",
+            ),
+        ]);
+
+        let three_of_three = multisig(3, &[pubkeys[0], pubkeys[1], pubkeys[2]], "");
+        let sigs = |tx: &Transaction| {
+            [secret0, secret1, secret2]
+                .map(|secret| legacy(tx, secret, &three_of_three, SIGHASH_ALL))
+        };
+        vectors.extend([
+            signature_vector(
+                &three_of_three,
+                0,
+                |tx| {
+                    let [sig0, sig1, sig2] = sigs(tx);
+                    (script_sig(&[&[], &sig0, &sig1, &sig2]), vec![])
+                },
+                "",
+                "OK",
+                "3-of-3",
+            ),
+            signature_vector(
+                &three_of_three,
+                0,
+                |tx| {
+                    let [sig0, sig1, _] = sigs(tx);
+                    (script_sig(&[&[], &sig0, &sig1, &[]]), vec![])
+                },
+                "",
+                "EVAL_FALSE",
+                "3-of-3, 2 sigs",
+            ),
+        ]);
+        for (flags, expected, comment) in [
+            ("", "OK", "3-of-3 with nonzero dummy but no NULLDUMMY"),
+            ("NULLDUMMY", "SIG_NULLDUMMY", "3-of-3 with nonzero dummy"),
+        ] {
+            vectors.push(signature_vector(
+                &three_of_three,
+                0,
+                |tx| {
+                    let [sig0, sig1, sig2] = sigs(tx);
+                    (script_sig(&[&[0x01], &sig0, &sig1, &sig2]), vec![])
+                },
+                flags,
+                expected,
+                comment,
+            ));
+        }
+
+        let redeem_script = multisig(2, &[pubkeys[0], pubkeys[1], pubkeys[2]], "");
+        let p2sh =
+            ScriptBuf::new_p2sh(&hash160::Hash::hash(redeem_script.as_bytes()).to_byte_array());
+        let sig = |tx: &Transaction, secret| legacy(tx, secret, &redeem_script, SIGHASH_ALL);
+        vectors.extend([
+            signature_vector(
+                &p2sh,
+                0,
+                |tx| {
+                    let pushes: [&[u8]; 4] = [
+                        &[],
+                        &sig(tx, secret1),
+                        &sig(tx, secret2),
+                        redeem_script.as_bytes(),
+                    ];
+                    (script_sig(&pushes), vec![])
+                },
+                "P2SH",
+                "OK",
+                "P2SH(2-of-3)",
+            ),
+            signature_vector(
+                &p2sh,
+                0,
+                |tx| {
+                    let pushes: [&[u8]; 4] =
+                        [&[], &sig(tx, secret1), &[], redeem_script.as_bytes()];
+                    (script_sig(&pushes), vec![])
+                },
+                "P2SH",
+                "EVAL_FALSE",
+                "P2SH(2-of-3), 1 sig",
+            ),
+        ]);
+
+        // Encodings of the signature and key that STRICTENC, DERSIG and LOW_S forbid.
+        let sig = |tx: &Transaction, sighash_type| legacy(tx, secret1, &p2pk1, sighash_type);
+        let p2pk_hybrid = p2pk(&hybrid, "CHECKSIG");
+        type Sign<'a> = &'a dyn Fn(&Transaction) -> Vec<u8>;
+        let encodings: [(&str, &ScriptBuf, Sign, &str, &str); 4] = [
+            (
+                "too much R padding",
+                &p2pk1,
+                &|tx| pad_r(&sig(tx, SIGHASH_ALL)),
+                "DERSIG",
+                "SIG_DER",
+            ),
+            (
+                "high S",
+                &p2pk1,
+                &|tx| high_s(&sig(tx, SIGHASH_ALL)),
+                "LOW_S",
+                "SIG_HIGH_S",
+            ),
+            (
+                "hybrid pubkey",
+                &p2pk_hybrid,
+                &|tx| legacy(tx, secret0, &p2pk_hybrid, SIGHASH_ALL),
+                "STRICTENC",
+                "PUBKEYTYPE",
+            ),
+            (
+                "undefined hashtype",
+                &p2pk1,
+                &|tx| sig(tx, 5),
+                "STRICTENC",
+                "SIG_HASHTYPE",
+            ),
+        ];
+        for (encoding, script_pubkey, sig, flag, error) in encodings {
+            vectors.extend([
+                signature_vector(
+                    script_pubkey,
+                    0,
+                    |tx| (script_sig(&[&sig(tx)]), vec![]),
+                    "",
+                    "OK",
+                    &format!("P2PK with {} but no {}", encoding, flag),
+                ),
+                signature_vector(
+                    script_pubkey,
+                    0,
+                    |tx| (script_sig(&[&sig(tx)]), vec![]),
+                    flag,
+                    error,
+                    &format!("P2PK with {}", encoding),
+                ),
+            ]);
+        }
+
+        // Failed signature checks that NULLFAIL requires to be given empty signatures.
+        let p2pk_not = p2pk(&pubkeys[1], "CHECKSIG NOT");
+        let two_of_two_not = multisig(2, &[pubkeys[1], pubkeys[2]], "NOT");
+        for (flags, expected) in [("", "OK"), ("NULLFAIL", "NULLFAIL")] {
+            vectors.extend([
+                signature_vector(
+                    &p2pk_not,
+                    0,
+                    |tx| {
+                        let sig = legacy(tx, secret1, &p2pk_not, SIGHASH_ALL);
+                        (script_sig(&[&damage(&sig)]), vec![])
+                    },
+                    flags,
+                    expected,
+                    "P2PK NOT with invalid sig",
+                ),
+                signature_vector(
+                    &two_of_two_not,
+                    0,
+                    |tx| {
+                        let sig1 = legacy(tx, secret1, &two_of_two_not, SIGHASH_ALL);
+                        let sig2 = legacy(tx, secret2, &two_of_two_not, SIGHASH_ALL);
+                        (script_sig(&[&[], &damage(&sig1), &damage(&sig2)]), vec![])
+                    },
+                    flags,
+                    expected,
+                    "2-of-2 CHECKMULTISIG NOT with both signatures invalid",
+                ),
+            ]);
+        }
+        vectors.extend([
+            signature_vector(
+                &p2pk_not,
+                0,
+                |_| (script_sig(&[&[]]), vec![]),
+                "NULLFAIL",
+                "OK",
+                "P2PK NOT with empty sig",
+            ),
+            signature_vector(
+                &two_of_two_not,
+                0,
+                |_| (script_sig(&[&[], &[], &[]]), vec![]),
+                "NULLFAIL",
+                "OK",
+                "2-of-2 CHECKMULTISIG NOT with both signatures empty",
+            ),
+        ]);
+
+        let p2wpkh = ScriptBuf::new_p2wpkh(&hash160::Hash::hash(&pubkeys[0]).to_byte_array());
+        let script_code = ScriptBuf::new_p2pkh(&hash160::Hash::hash(&pubkeys[0]).to_byte_array());
+        let witness = |tx: &Transaction, secret, amount| {
+            vec![
+                segwit(tx, secret, &script_code, amount),
+                pubkeys[0].to_vec(),
+            ]
+        };
+        let nested = ScriptBuf::new_p2sh(&hash160::Hash::hash(p2wpkh.as_bytes()).to_byte_array());
+        vectors.extend([
+            signature_vector(
+                &p2wpkh,
+                1,
+                |tx| (vec![], witness(tx, secret0, 1)),
+                "P2SH,WITNESS",
+                "OK",
+                "Basic P2WPKH",
+            ),
+            signature_vector(
+                &p2wpkh,
+                1,
+                |tx| (vec![], witness(tx, secret1, 1)),
+                "P2SH,WITNESS",
+                "EVAL_FALSE",
+                "Basic P2WPKH with the wrong key",
+            ),
+            signature_vector(
+                &p2wpkh,
+                1,
+                |tx| (vec![], witness(tx, secret0, 0)),
+                "P2SH,WITNESS",
+                "EVAL_FALSE",
+                "Basic P2WPKH with wrong value",
+            ),
+            signature_vector(
+                &nested,
+                1,
+                |tx| (script_sig(&[p2wpkh.as_bytes()]), witness(tx, secret0, 1)),
+                "P2SH,WITNESS",
+                "OK",
+                "Basic P2SH(P2WPKH)",
+            ),
+        ]);
+
+        let witness_script = p2pk(&pubkeys[0], "CHECKSIG");
+        let p2wsh =
+            ScriptBuf::new_p2wsh(&sha256::Hash::hash(witness_script.as_bytes()).to_byte_array());
+        vectors.push(signature_vector(
+            &p2wsh,
+            1,
+            |tx| {
+                let sig = segwit(tx, secret0, &witness_script, 1);
+                (vec![], vec![sig, witness_script.as_bytes().to_vec()])
+            },
+            "P2SH,WITNESS",
+            "OK",
+            "Basic P2WSH",
+        ));
+
+        let pubkey_hash = hash160::Hash::hash(&uncompressed).to_byte_array();
+        let p2wpkh = ScriptBuf::new_p2wpkh(&pubkey_hash);
+        let script_code = ScriptBuf::new_p2pkh(&pubkey_hash);
+        let witness_script = p2pk(&uncompressed, "CHECKSIG");
+        let p2wsh =
+            ScriptBuf::new_p2wsh(&sha256::Hash::hash(witness_script.as_bytes()).to_byte_array());
+        for (flags, expected) in [
+            ("P2SH,WITNESS", "OK"),
+            ("P2SH,WITNESS,WITNESS_PUBKEYTYPE", "WITNESS_PUBKEYTYPE"),
+        ] {
+            vectors.extend([
+                signature_vector(
+                    &p2wpkh,
+                    1,
+                    |tx| {
+                        let sig = segwit(tx, secret0, &script_code, 1);
+                        (vec![], vec![sig, uncompressed.to_vec()])
+                    },
+                    flags,
+                    expected,
+                    "Basic P2WPKH with uncompressed key",
+                ),
+                signature_vector(
+                    &p2wsh,
+                    1,
+                    |tx| {
+                        let sig = segwit(tx, secret0, &witness_script, 1);
+                        (vec![], vec![sig, witness_script.as_bytes().to_vec()])
+                    },
+                    flags,
+                    expected,
+                    "Basic P2WSH with uncompressed key",
+                ),
+            ]);
+        }
+
+        for vector in &vectors {
+            assert!(run_script_test(vector));
+        }
+        assert_eq!(vectors.len(), 37);
+    }
+
+    #[test]
+    fn lock_times() {
+        let cltv = parse_script("500 CHECKLOCKTIMEVERIFY DROP 1");
+        let mut tx = spending_transaction(&cltv, 0);
+        let flags = VerifyFlags::CHECKLOCKTIMEVERIFY;
+        tx.lock_time = 500;
+        assert_eq!(
+            verify(&tx, &cltv, 0, flags),
+            Err(ScriptError::UnsatisfiedLocktime)
+        );
+        tx.input[0].sequence = 0xfffffffe;
+        assert_eq!(verify(&tx, &cltv, 0, flags), Ok(()));
+        tx.lock_time = 499;
+        assert_eq!(
+            verify(&tx, &cltv, 0, flags),
+            Err(ScriptError::UnsatisfiedLocktime)
+        );
+        // Timestamps can't satisfy a height.
+        tx.lock_time = LOCKTIME_THRESHOLD + 1;
+        assert_eq!(
+            verify(&tx, &cltv, 0, flags),
+            Err(ScriptError::UnsatisfiedLocktime)
+        );
+
+        let csv = parse_script("10 CHECKSEQUENCEVERIFY DROP 1");
+        let mut tx = spending_transaction(&csv, 0);
+        let flags = VerifyFlags::CHECKSEQUENCEVERIFY;
+        tx.version = 2;
+        tx.input[0].sequence = 10;
+        assert_eq!(verify(&tx, &csv, 0, flags), Ok(()));
+        tx.input[0].sequence = 9;
+        assert_eq!(
+            verify(&tx, &csv, 0, flags),
+            Err(ScriptError::UnsatisfiedLocktime)
+        );
+        tx.input[0].sequence = 10 | SEQUENCE_LOCKTIME_TYPE_FLAG as u32;
+        assert_eq!(
+            verify(&tx, &csv, 0, flags),
+            Err(ScriptError::UnsatisfiedLocktime)
+        );
+        tx.input[0].sequence = 10 | SEQUENCE_LOCKTIME_DISABLE_FLAG as u32;
+        assert_eq!(
+            verify(&tx, &csv, 0, flags),
+            Err(ScriptError::UnsatisfiedLocktime)
+        );
+    }
+
+    #[test]
+    fn codeseparator_and_find_and_delete() {
+        let (secret, pubkey) = key(1);
+
+        // The signature only commits to the script after the last executed separator.
+        let script_pubkey = parse_script(&format!("NOP CODESEPARATOR 0x21 0x{} CHECKSIG", pubkey));
+        let mut tx = spending_transaction(&script_pubkey, 0);
+        let script_code = &script_pubkey.as_bytes()[2..];
+        let sig = sign(&tx, &secret, script_code, 0, SigVersion::Base, SIGHASH_ALL);
+        tx.input[0].script_sig = script_sig(&[&sig]);
+        assert_eq!(verify(&tx, &script_pubkey, 0, VerifyFlags::P2SH), Ok(()));
+        assert_eq!(
+            verify(&tx, &script_pubkey, 0, VerifyFlags::CONST_SCRIPTCODE),
+            Err(ScriptError::OpCodeSeparator)
+        );
+
+        // A signature included in the script it signs is removed from the script code.
+        let (other_secret, _) = key(2);
+        let placeholder = sign(&tx, &other_secret, &[], 0, SigVersion::Base, SIGHASH_ALL);
+        let script_pubkey = ScriptBuf::from(
+            [
+                script_sig(&[&placeholder]),
+                parse_script(&format!("DROP 0x21 0x{} CHECKSIG", pubkey))
+                    .as_bytes()
+                    .to_vec(),
+            ]
+            .concat(),
+        );
+        let mut tx = spending_transaction(&script_pubkey, 0);
+        let mut script_code = script_pubkey.as_bytes().to_vec();
+        let sig = sign(&tx, &secret, &[], 0, SigVersion::Base, SIGHASH_ALL);
+        find_and_delete(&mut script_code, &push_encoding(&sig));
+        let sig = sign(&tx, &secret, &script_code, 0, SigVersion::Base, SIGHASH_ALL);
+        tx.input[0].script_sig = script_sig(&[&sig]);
+        assert_eq!(verify(&tx, &script_pubkey, 0, VerifyFlags::P2SH), Ok(()));
+    }
+
+    /// Verifies the first input of `tx`, which spends the first of `spent_outputs`.
+    fn verify_spent(
+        tx: &Transaction,
+        spent_outputs: &[TxOut],
+        flags: VerifyFlags,
+    ) -> Result<(), ScriptError> {
+        let cache = SighashCache::new(tx).unwrap();
+        let checker = TransactionSignatureChecker::with_spent_outputs(&cache, 0, spent_outputs);
+        let input = &tx.input[0];
+        verify_script(
+            &ScriptBuf::from(input.script_sig.clone()),
+            &ScriptBuf::from(spent_outputs[0].script_pubkey.clone()),
+            &input.witness,
+            flags,
+            &checker,
+        )
+    }
+
+    fn x_only_key(byte: u8) -> (Keypair, XOnlyPublicKey) {
+        let keypair = Keypair::from_seckey_slice(&Secp256k1::new(), &[byte; 32]).unwrap();
+        let (x_only, _) = keypair.x_only_public_key();
+        (keypair, x_only)
+    }
+
+    /// Returns the output committing to `merkle_root` with `internal_key`, and the parity
+    /// of its key.
+    fn taproot_output(
+        internal_key: &XOnlyPublicKey,
+        merkle_root: Option<TapNodeHash>,
+    ) -> (TxOut, Parity) {
+        let tweak = TapTweakHash::from_key_and_tweak(internal_key, merkle_root);
+        let tweak = Scalar::from_be_bytes(tweak.to_byte_array()).unwrap();
+        let (output_key, parity) = internal_key.add_tweak(&Secp256k1::new(), &tweak).unwrap();
+        let txout = TxOut {
+            amount: 100_000,
+            script_pubkey: ScriptBuf::new_p2tr_tweaked(TweakedPublicKey::dangerous_assume_tweaked(
+                output_key,
+            ))
+            .as_bytes()
+            .to_vec(),
+        };
+        (txout, parity)
+    }
+
+    /// Returns the output committing to `script` and the optional `sibling` node, and the
+    /// control block spending it.
+    fn script_path(
+        script: &ScriptBuf,
+        leaf_version: u8,
+        sibling: Option<TapNodeHash>,
+    ) -> (TxOut, Vec<u8>) {
+        let (_, internal_key) = x_only_key(0x10);
+        let leaf = TapNodeHash::from(TapLeafHash::from_script(script.as_bytes(), leaf_version));
+        let merkle_root = match sibling {
+            Some(sibling) => TapNodeHash::from_node_hashes(leaf, sibling),
+            None => leaf,
+        };
+        let (txout, parity) = taproot_output(&internal_key, Some(merkle_root));
+        let mut control = vec![leaf_version | parity.to_u8()];
+        control.extend_from_slice(&internal_key.serialize());
+        if let Some(sibling) = sibling {
+            control.extend_from_slice(sibling.as_byte_array());
+        }
+        (txout, control)
+    }
+
+    fn sign_schnorr(
+        tx: &Transaction,
+        spent_outputs: &[TxOut],
+        keypair: &Keypair,
+        annex: Option<&[u8]>,
+        leaf_hash_code_separator: Option<(TapLeafHash, u32)>,
+        sighash_type: u32,
+    ) -> Vec<u8> {
+        let sighash = SighashCache::new(tx)
+            .unwrap()
+            .taproot_signature_hash(
+                0,
+                spent_outputs,
+                annex,
+                leaf_hash_code_separator,
+                sighash_type,
+            )
+            .unwrap();
+        let sig = Secp256k1::new()
+            .sign_schnorr_no_aux_rand(&Message::from_digest(sighash.to_byte_array()), keypair);
+        let mut sig = sig.as_ref().to_vec();
+        if sighash_type != SIGHASH_DEFAULT {
+            sig.push(sighash_type as u8);
+        }
+        sig
+    }
+
+    #[test]
+    fn bip341_key_path_spends() {
+        let vectors: Value =
+            serde_json::from_str(include_str!("../../tests/data/bip341_tests.json")).unwrap();
+
+        for vector in vectors["keyPathSpending"].as_array().unwrap() {
+            let tx = Transaction::from_hex(vector["auxiliary"]["fullySignedTx"].as_str().unwrap());
+            let spent_outputs: Vec<TxOut> = vector["given"]["utxosSpent"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|utxo| TxOut {
+                    amount: utxo["amountSats"].as_u64().unwrap(),
+                    script_pubkey: hex::decode(utxo["scriptPubKey"].as_str().unwrap()).unwrap(),
+                })
+                .collect();
+
+            let cache = SighashCache::new(&tx).unwrap();
+            for (input_index, input) in tx.input.iter().enumerate() {
+                let checker = TransactionSignatureChecker::with_spent_outputs(
+                    &cache,
+                    input_index,
+                    &spent_outputs,
+                );
+                let script_pubkey =
+                    ScriptBuf::from(spent_outputs[input_index].script_pubkey.clone());
+                let script_sig = ScriptBuf::from(input.script_sig.clone());
+                assert_eq!(
+                    verify_script(
+                        &script_sig,
+                        &script_pubkey,
+                        &input.witness,
+                        VerifyFlags::STANDARD,
+                        &checker,
+                    ),
+                    Ok(())
+                );
+
+                // Every taproot signature commits to the amount of its own input.
+                if spent_outputs[input_index].script_pubkey[0] == 0x51 {
+                    let mut other_outputs = spent_outputs.clone();
+                    other_outputs[input_index].amount += 1;
+                    // The cache keeps the hashes of the first spent outputs it's given.
+                    let other_cache = SighashCache::new(&tx).unwrap();
+                    let checker = TransactionSignatureChecker::with_spent_outputs(
+                        &other_cache,
+                        input_index,
+                        &other_outputs,
+                    );
+                    assert_eq!(
+                        verify_script(
+                            &script_sig,
+                            &script_pubkey,
+                            &input.witness,
+                            VerifyFlags::STANDARD,
+                            &checker,
+                        ),
+                        Err(ScriptError::SchnorrSig)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn taproot_key_path() {
+        let secp = Secp256k1::new();
+        let flags = VerifyFlags::STANDARD;
+        let (keypair, internal_key) = x_only_key(1);
+        let (spent, _) = taproot_output(&internal_key, None);
+        let spent_outputs = [spent];
+        let tweak = TapTweakHash::from_key_and_tweak(&internal_key, None);
+        let tweaked = keypair
+            .add_xonly_tweak(
+                &secp,
+                &Scalar::from_be_bytes(tweak.to_byte_array()).unwrap(),
+            )
+            .unwrap();
+
+        let script_pubkey = ScriptBuf::from(spent_outputs[0].script_pubkey.clone());
+        let mut tx = spending_transaction(&script_pubkey, 100_000);
+        for sighash_type in [SIGHASH_DEFAULT, SIGHASH_ALL, SIGHASH_SINGLE] {
+            let sig = sign_schnorr(&tx, &spent_outputs, &tweaked, None, None, sighash_type);
+            tx.input[0].witness = vec![sig];
+            assert_eq!(verify_spent(&tx, &spent_outputs, flags), Ok(()));
+        }
+
+        // Signatures must be 64 bytes, or 65 with an explicit non-default sighash type.
+        let sig = sign_schnorr(&tx, &spent_outputs, &tweaked, None, None, SIGHASH_DEFAULT);
+        tx.input[0].witness = vec![[&sig[..], &[0x00]].concat()];
+        assert_eq!(
+            verify_spent(&tx, &spent_outputs, flags),
+            Err(ScriptError::SchnorrSigHashType)
+        );
+        tx.input[0].witness = vec![[&sig[..], &[0x04]].concat()];
+        assert_eq!(
+            verify_spent(&tx, &spent_outputs, flags),
+            Err(ScriptError::SchnorrSigHashType)
+        );
+        tx.input[0].witness = vec![sig[..63].to_vec()];
+        assert_eq!(
+            verify_spent(&tx, &spent_outputs, flags),
+            Err(ScriptError::SchnorrSigSize)
+        );
+
+        // The untweaked key can't spend the output.
+        let sig = sign_schnorr(&tx, &spent_outputs, &keypair, None, None, SIGHASH_DEFAULT);
+        tx.input[0].witness = vec![sig];
+        assert_eq!(
+            verify_spent(&tx, &spent_outputs, flags),
+            Err(ScriptError::SchnorrSig)
+        );
+
+        // The annex is committed to by the signature.
+        let annex = vec![TAPROOT_ANNEX_PREFIX, 0x01];
+        let sig = sign_schnorr(
+            &tx,
+            &spent_outputs,
+            &tweaked,
+            Some(&annex),
+            None,
+            SIGHASH_DEFAULT,
+        );
+        tx.input[0].witness = vec![sig.clone(), annex];
+        assert_eq!(verify_spent(&tx, &spent_outputs, flags), Ok(()));
+        tx.input[0].witness = vec![sig];
+        assert_eq!(
+            verify_spent(&tx, &spent_outputs, flags),
+            Err(ScriptError::SchnorrSig)
+        );
+
+        tx.input[0].witness = vec![];
+        assert_eq!(
+            verify_spent(&tx, &spent_outputs, flags),
+            Err(ScriptError::WitnessProgramWitnessEmpty)
+        );
+        // Without the taproot rules, the output is anyone-can-spend.
+        assert_eq!(
+            verify_spent(
+                &tx,
+                &spent_outputs,
+                VerifyFlags::P2SH | VerifyFlags::WITNESS
+            ),
+            Ok(())
+        );
+        // Even when upgradable witness programs are discouraged, unlike other versions.
+        let discouraged = VerifyFlags::P2SH
+            | VerifyFlags::WITNESS
+            | VerifyFlags::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM;
+        assert_eq!(verify_spent(&tx, &spent_outputs, discouraged), Ok(()));
+        let mut v2 = spent_outputs.clone();
+        v2[0].script_pubkey[0] = OP_PUSHNUM_2.to_u8();
+        assert_eq!(
+            verify_spent(&tx, &v2, discouraged),
+            Err(ScriptError::DiscourageUpgradableWitnessProgram)
+        );
+    }
+
+    #[test]
+    fn tapscript_signatures() {
+        let flags = VerifyFlags::STANDARD;
+        let keys: Vec<_> = (1..=3).map(x_only_key).collect();
+
+        let script = parse_script(&format!(
+            "0x20 0x{} CHECKSIG",
+            hex::encode(keys[0].1.serialize())
+        ));
+        let leaf_hash = TapLeafHash::from_script(script.as_bytes(), TAPROOT_LEAF_TAPSCRIPT);
+        let (spent, control) = script_path(&script, TAPROOT_LEAF_TAPSCRIPT, None);
+        let spent_outputs = [spent];
+        let mut tx =
+            spending_transaction(&ScriptBuf::from(spent_outputs[0].script_pubkey.clone()), 0);
+        let sig = sign_schnorr(
+            &tx,
+            &spent_outputs,
+            &keys[0].0,
+            None,
+            Some((leaf_hash, u32::MAX)),
+            SIGHASH_DEFAULT,
+        );
+        tx.input[0].witness = vec![sig, script.as_bytes().to_vec(), control.clone()];
+        assert_eq!(verify_spent(&tx, &spent_outputs, flags), Ok(()));
+
+        // An empty signature fails the check, any other invalid one fails the script.
+        tx.input[0].witness = vec![vec![], script.as_bytes().to_vec(), control.clone()];
+        assert_eq!(
+            verify_spent(&tx, &spent_outputs, flags),
+            Err(ScriptError::EvalFalse)
+        );
+        let sig = sign_schnorr(
+            &tx,
+            &spent_outputs,
+            &keys[1].0,
+            None,
+            Some((leaf_hash, u32::MAX)),
+            SIGHASH_DEFAULT,
+        );
+        tx.input[0].witness = vec![sig, script.as_bytes().to_vec(), control];
+        assert_eq!(
+            verify_spent(&tx, &spent_outputs, flags),
+            Err(ScriptError::SchnorrSig)
+        );
+
+        // 2-of-3 with OP_CHECKSIGADD.
+        let script = parse_script(&format!(
+            "0x20 0x{} CHECKSIG 0x20 0x{} CHECKSIGADD 0x20 0x{} CHECKSIGADD 2 NUMEQUAL",
+            hex::encode(keys[0].1.serialize()),
+            hex::encode(keys[1].1.serialize()),
+            hex::encode(keys[2].1.serialize()),
+        ));
+        let leaf_hash = TapLeafHash::from_script(script.as_bytes(), TAPROOT_LEAF_TAPSCRIPT);
+        let (spent, control) = script_path(&script, TAPROOT_LEAF_TAPSCRIPT, None);
+        let spent_outputs = [spent];
+        let mut tx =
+            spending_transaction(&ScriptBuf::from(spent_outputs[0].script_pubkey.clone()), 0);
+        let sigs: Vec<_> = keys
+            .iter()
+            .map(|(keypair, _)| {
+                sign_schnorr(
+                    &tx,
+                    &spent_outputs,
+                    keypair,
+                    None,
+                    Some((leaf_hash, u32::MAX)),
+                    SIGHASH_ALL,
+                )
+            })
+            .collect();
+        let witness = |sigs: [&[u8]; 3]| {
+            vec![
+                sigs[2].to_vec(),
+                sigs[1].to_vec(),
+                sigs[0].to_vec(),
+                script.as_bytes().to_vec(),
+                control.clone(),
+            ]
+        };
+        tx.input[0].witness = witness([&sigs[0], &[], &sigs[2]]);
+        assert_eq!(verify_spent(&tx, &spent_outputs, flags), Ok(()));
+        tx.input[0].witness = witness([&sigs[0], &[], &[]]);
+        assert_eq!(
+            verify_spent(&tx, &spent_outputs, flags),
+            Err(ScriptError::EvalFalse)
+        );
+
+        // OP_CHECKMULTISIG is disabled.
+        let script = parse_script(&format!(
+            "1 0x20 0x{} 1 CHECKMULTISIG",
+            hex::encode(keys[0].1.serialize())
+        ));
+        let (spent, control) = script_path(&script, TAPROOT_LEAF_TAPSCRIPT, None);
+        let spent_outputs = [spent];
+        let mut tx =
+            spending_transaction(&ScriptBuf::from(spent_outputs[0].script_pubkey.clone()), 0);
+        tx.input[0].witness = vec![vec![], vec![], script.as_bytes().to_vec(), control];
+        assert_eq!(
+            verify_spent(&tx, &spent_outputs, VerifyFlags::CONSENSUS),
+            Err(ScriptError::TapscriptCheckMultisig)
+        );
+
+        // The signature commits to the position of the last executed separator.
+        let script = parse_script(&format!(
+            "CODESEPARATOR 0x20 0x{} CHECKSIG",
+            hex::encode(keys[0].1.serialize())
+        ));
+        let leaf_hash = TapLeafHash::from_script(script.as_bytes(), TAPROOT_LEAF_TAPSCRIPT);
+        let (spent, control) = script_path(&script, TAPROOT_LEAF_TAPSCRIPT, None);
+        let spent_outputs = [spent];
+        let mut tx =
+            spending_transaction(&ScriptBuf::from(spent_outputs[0].script_pubkey.clone()), 0);
+        for (pos, expected) in [(0, Ok(())), (u32::MAX, Err(ScriptError::SchnorrSig))] {
+            let sig = sign_schnorr(
+                &tx,
+                &spent_outputs,
+                &keys[0].0,
+                None,
+                Some((leaf_hash, pos)),
+                SIGHASH_DEFAULT,
+            );
+            tx.input[0].witness = vec![sig, script.as_bytes().to_vec(), control.clone()];
+            assert_eq!(verify_spent(&tx, &spent_outputs, flags), expected);
+        }
+
+        // Keys other than 32 bytes are left for future soft forks, except empty ones.
+        for (pubkey, expected) in [
+            (vec![0x02; 33], Ok(())),
+            (vec![], Err(ScriptError::PubkeyType)),
+        ] {
+            let script =
+                ScriptBuf::from([script_sig(&[&pubkey]), vec![OP_CHECKSIG.to_u8()]].concat());
+            let (spent, control) = script_path(&script, TAPROOT_LEAF_TAPSCRIPT, None);
+            let spent_outputs = [spent];
+            let mut tx =
+                spending_transaction(&ScriptBuf::from(spent_outputs[0].script_pubkey.clone()), 0);
+            tx.input[0].witness = vec![vec![0x01], script.as_bytes().to_vec(), control];
+            assert_eq!(
+                verify_spent(&tx, &spent_outputs, VerifyFlags::CONSENSUS),
+                expected
+            );
+        }
+        let script =
+            ScriptBuf::from([script_sig(&[&[0x02; 33]]), vec![OP_CHECKSIG.to_u8()]].concat());
+        let (spent, control) = script_path(&script, TAPROOT_LEAF_TAPSCRIPT, None);
+        let spent_outputs = [spent];
+        let mut tx =
+            spending_transaction(&ScriptBuf::from(spent_outputs[0].script_pubkey.clone()), 0);
+        tx.input[0].witness = vec![vec![0x01], script.as_bytes().to_vec(), control];
+        assert_eq!(
+            verify_spent(&tx, &spent_outputs, flags),
+            Err(ScriptError::DiscourageUpgradablePubkeyType)
+        );
+    }
+
+    #[test]
+    fn tapscript_validation_weight() {
+        let (keypair, pubkey) = x_only_key(1);
+        // Each passing signature check costs 50 of a budget growing with the witness size,
+        // so repeating a check eventually exhausts it.
+        for (checks, expected) in [
+            (5, Ok(())),
+            (20, Err(ScriptError::TapscriptValidationWeight)),
+        ] {
+            let check = format!(
+                "DUP 0x20 0x{} CHECKSIGVERIFY ",
+                hex::encode(pubkey.serialize())
+            );
+            let script = parse_script(&format!(
+                "{}0x20 0x{} CHECKSIG",
+                check.repeat(checks),
+                hex::encode(pubkey.serialize())
+            ));
+            let leaf_hash = TapLeafHash::from_script(script.as_bytes(), TAPROOT_LEAF_TAPSCRIPT);
+            let (spent, control) = script_path(&script, TAPROOT_LEAF_TAPSCRIPT, None);
+            let spent_outputs = [spent];
+            let mut tx =
+                spending_transaction(&ScriptBuf::from(spent_outputs[0].script_pubkey.clone()), 0);
+            let sig = sign_schnorr(
+                &tx,
+                &spent_outputs,
+                &keypair,
+                None,
+                Some((leaf_hash, u32::MAX)),
+                SIGHASH_DEFAULT,
+            );
+            tx.input[0].witness = vec![sig, script.as_bytes().to_vec(), control];
+            assert_eq!(
+                verify_spent(&tx, &spent_outputs, VerifyFlags::STANDARD),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn tapscript_rules() {
+        let spend = |script: &ScriptBuf, mut witness: Stack, flags: VerifyFlags| {
+            let (spent, control) = script_path(script, TAPROOT_LEAF_TAPSCRIPT, None);
+            let spent_outputs = [spent];
+            let mut tx =
+                spending_transaction(&ScriptBuf::from(spent_outputs[0].script_pubkey.clone()), 0);
+            witness.extend([script.as_bytes().to_vec(), control]);
+            tx.input[0].witness = witness;
+            verify_spent(&tx, &spent_outputs, flags)
+        };
+
+        // MINIMALIF is a consensus rule.
+        let script = parse_script("IF 1 ELSE 0 ENDIF");
+        assert_eq!(
+            spend(&script, vec![vec![0x01]], VerifyFlags::CONSENSUS),
+            Ok(())
+        );
+        assert_eq!(
+            spend(&script, vec![vec![0x02]], VerifyFlags::CONSENSUS),
+            Err(ScriptError::TapscriptMinimalIf)
+        );
+
+        // Any OP_SUCCESSx makes the script succeed, even when it's never executed.
+        let script = ScriptBuf::from(vec![OP_RETURN.to_u8(), 0x50]);
+        assert_eq!(spend(&script, vec![], VerifyFlags::CONSENSUS), Ok(()));
+        assert_eq!(
+            spend(&script, vec![], VerifyFlags::STANDARD),
+            Err(ScriptError::DiscourageOpSuccess)
+        );
+        // As long as the script before it can be parsed.
+        let script = ScriptBuf::from(vec![0x4c]);
+        assert_eq!(
+            spend(&script, vec![], VerifyFlags::CONSENSUS),
+            Err(ScriptError::BadOpcode)
+        );
+
+        // The script and op count limits don't apply.
+        let script = ScriptBuf::from([vec![OP_NOP.to_u8(); 20_000], vec![0x51]].concat());
+        assert_eq!(spend(&script, vec![], VerifyFlags::STANDARD), Ok(()));
+
+        // The stack limit applies to the initial stack.
+        let script = ScriptBuf::from(vec![OP_DEPTH.to_u8(), OP_NIP.to_u8()]);
+        assert_eq!(
+            spend(&script, vec![vec![]; 999], VerifyFlags::STANDARD),
+            Err(ScriptError::CleanStack)
+        );
+        assert_eq!(
+            spend(&script, vec![vec![]; 1001], VerifyFlags::STANDARD),
+            Err(ScriptError::StackSize)
+        );
+    }
+
+    #[test]
+    fn taproot_script_path_commitment() {
+        let flags = VerifyFlags::STANDARD;
+        let script = parse_script("1");
+        let sibling = TapNodeHash::from(TapLeafHash::from_script(&[0x52], TAPROOT_LEAF_TAPSCRIPT));
+        let (spent, control) = script_path(&script, TAPROOT_LEAF_TAPSCRIPT, Some(sibling));
+        let spent_outputs = [spent];
+        let mut tx =
+            spending_transaction(&ScriptBuf::from(spent_outputs[0].script_pubkey.clone()), 0);
+        let mut spend = |control: &[u8]| {
+            tx.input[0].witness = vec![script.as_bytes().to_vec(), control.to_vec()];
+            verify_spent(&tx, &spent_outputs, flags)
+        };
+        assert_eq!(spend(&control), Ok(()));
+
+        let mut wrong_parity = control.clone();
+        wrong_parity[0] ^= 1;
+        assert_eq!(
+            spend(&wrong_parity),
+            Err(ScriptError::WitnessProgramMismatch)
+        );
+        assert_eq!(
+            spend(&control[..33]),
+            Err(ScriptError::WitnessProgramMismatch)
+        );
+        assert_eq!(
+            spend(&control[..64]),
+            Err(ScriptError::TaprootWrongControlSize)
+        );
+        assert_eq!(spend(&[]), Err(ScriptError::TaprootWrongControlSize));
+
+        // Unknown leaf versions are anyone-can-spend.
+        let script = parse_script("RETURN");
+        let (spent, control) = script_path(&script, 0xc2, None);
+        let spent_outputs = [spent];
+        let mut tx =
+            spending_transaction(&ScriptBuf::from(spent_outputs[0].script_pubkey.clone()), 0);
+        tx.input[0].witness = vec![script.as_bytes().to_vec(), control];
+        assert_eq!(
+            verify_spent(&tx, &spent_outputs, VerifyFlags::CONSENSUS),
+            Ok(())
+        );
+        assert_eq!(
+            verify_spent(&tx, &spent_outputs, flags),
+            Err(ScriptError::DiscourageUpgradableTaprootVersion)
+        );
+    }
+}
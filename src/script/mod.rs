@@ -1,5 +1,16 @@
+mod asm;
 pub mod builder;
+mod error;
 pub mod instructions;
+pub mod interpreter;
+mod multisig;
+mod num;
+#[allow(clippy::module_inception)]
 mod script;
-mod error;
+pub mod sigops;
+pub mod trace;
+pub use asm::ParseAsmError;
+pub use error::ScriptError;
+pub use multisig::{MultisigError, MAX_MULTISIG_KEYS};
+pub use num::ScriptNum;
 pub use script::*;
@@ -0,0 +1,364 @@
+//! Multisig scripts.
+//!
+//! An `m`-of-`n` multisig script, `m <key 1> ... <key n> n OP_CHECKMULTISIG`, can be
+//! used bare as an output script or as the redeem or witness script of P2SH, P2WSH and
+//! P2SH-P2WSH outputs, see [`Script::to_p2sh`] and its siblings.
+
+use super::builder::ScriptBuilder;
+use super::instructions::{Instruction, Instructions};
+use super::script::{is_public_key, Script, ScriptBuf};
+use crate::constants::MAX_PUBKEYS_PER_MULTISIG;
+use crate::key::PublicKey;
+use core::fmt;
+use opcodes::all::*;
+
+/// Largest number of keys of a multisig script, the most `OP_CHECKMULTISIG` takes.
+///
+/// Key counts above 16 are pushed as numbers rather than with `OP_1` to `OP_16`.
+pub const MAX_MULTISIG_KEYS: usize = MAX_PUBKEYS_PER_MULTISIG;
+
+/// Ways that creating a multisig script might fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MultisigError {
+    /// The number of required signatures is zero or more than the number of keys.
+    InvalidThreshold { required: usize, keys: usize },
+    /// There are more than [`MAX_MULTISIG_KEYS`] keys.
+    TooManyKeys { keys: usize },
+}
+
+impl fmt::Display for MultisigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MultisigError::InvalidThreshold { required, keys } => write!(
+                f,
+                "cannot require {} signatures from {} keys",
+                required, keys
+            ),
+            MultisigError::TooManyKeys { keys } => write!(
+                f,
+                "{} keys is more than the {} allowed in a multisig script",
+                keys, MAX_MULTISIG_KEYS
+            ),
+        }
+    }
+}
+
+impl ScriptBuf {
+    /// Creates a script requiring `required` signatures from `keys`, in the given order.
+    /// Each key is pushed compressed or uncompressed as it is serialized.
+    pub fn new_multisig(required: usize, keys: &[PublicKey]) -> Result<ScriptBuf, MultisigError> {
+        if keys.len() > MAX_MULTISIG_KEYS {
+            return Err(MultisigError::TooManyKeys { keys: keys.len() });
+        }
+        if required == 0 || required > keys.len() {
+            return Err(MultisigError::InvalidThreshold {
+                required,
+                keys: keys.len(),
+            });
+        }
+
+        let mut builder = ScriptBuilder::new();
+        builder.push_int(required as i64);
+        for key in keys {
+            builder.push_key(key);
+        }
+        builder
+            .push_int(keys.len() as i64)
+            .push_opcode(OP_CHECKMULTISIG);
+        Ok(builder.into_script())
+    }
+
+    /// Creates a script requiring `required` signatures from `keys`, sorted by their
+    /// serialization as in BIP67 so that the script doesn't depend on the key order.
+    /// BIP67 only allows compressed keys, uncompressed ones are sorted the same way.
+    pub fn new_sorted_multisig(
+        required: usize,
+        keys: &[PublicKey],
+    ) -> Result<ScriptBuf, MultisigError> {
+        let mut keys = keys.to_vec();
+        keys.sort_by_key(PublicKey::to_bytes);
+        ScriptBuf::new_multisig(required, &keys)
+    }
+}
+
+impl Script {
+    /// Returns the number of required signatures, the keys and the number of keys if the
+    /// script is `m <keys> n OP_CHECKMULTISIG` with `1 <= m <= n <= 20` and keys of a
+    /// valid size, like Bitcoin Core's `MatchMultisig`.
+    ///
+    /// `m` and `n` are `OP_1` to `OP_16` or minimal pushes of minimally encoded numbers,
+    /// as [`ScriptBuf::new_multisig`] creates them. The keys aren't checked to be on the
+    /// curve.
+    pub fn parse_multisig(&self) -> Option<(usize, Vec<&[u8]>, usize)> {
+        if self.as_bytes().last() != Some(&OP_CHECKMULTISIG.to_u8()) {
+            return None;
+        }
+        let (required, mut rest) = read_count(self.as_bytes())?;
+        let mut keys = vec![];
+        loop {
+            let mut instructions = Instructions::from(rest);
+            match instructions.next() {
+                Some(Ok(Instruction::PushBytes(key))) if is_public_key(key) => keys.push(key),
+                _ => break,
+            }
+            rest = instructions.data.as_slice();
+        }
+        let (total, rest) = read_count(rest)?;
+        if required > total || total != keys.len() || rest != [OP_CHECKMULTISIG.to_u8()] {
+            return None;
+        }
+        Some((required, keys, total))
+    }
+}
+
+/// Reads the number of signatures or keys of a multisig script at the start of `script`,
+/// returning it with the rest of the script, like Bitcoin Core's `GetScriptNumber`.
+fn read_count(script: &[u8]) -> Option<(usize, &[u8])> {
+    let mut instructions = Instructions {
+        data: script.iter(),
+        enforce_minimal: true,
+    };
+    let count = instructions.next()?.ok()?.script_num()?.to_i64();
+    if !(1..=MAX_MULTISIG_KEYS as i64).contains(&count) {
+        return None;
+    }
+    Some((count as usize, instructions.data.as_slice()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::address::BitcoinAddress;
+    use crate::script::ScriptError;
+    use crate::types::Network;
+    use core::str::FromStr;
+    use secp256k1::{Secp256k1, SecretKey};
+
+    fn keys() -> Vec<PublicKey> {
+        let secp = Secp256k1::new();
+        [0x33, 0x11, 0x22]
+            .iter()
+            .map(|&byte| {
+                let secret_key = SecretKey::from_slice(&[byte; 32]).unwrap();
+                PublicKey::new(secp256k1::PublicKey::from_secret_key(&secp, &secret_key))
+            })
+            .collect()
+    }
+
+    fn bitcoin_keys(keys: &[PublicKey]) -> Vec<bitcoin::PublicKey> {
+        keys.iter()
+            .map(|key| bitcoin::PublicKey::from_slice(&key.to_bytes()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn multisig_matches_rust_bitcoin() {
+        let keys = keys();
+        let script = ScriptBuf::new_multisig(2, &keys).unwrap();
+
+        let mut expected = bitcoin::script::Builder::new().push_int(2);
+        for key in bitcoin_keys(&keys) {
+            expected = expected.push_key(&key);
+        }
+        let expected = expected
+            .push_int(3)
+            .push_opcode(bitcoin::opcodes::all::OP_CHECKMULTISIG)
+            .into_script();
+        assert_eq!(script.as_bytes(), expected.as_bytes());
+        assert!(script.is_multisig());
+        assert_eq!(script.to_asm_string().split(' ').next(), Some("2"));
+
+        let (required, parsed_keys, total) = script.parse_multisig().unwrap();
+        assert_eq!((required, total), (2, 3));
+        let serialized: Vec<Vec<u8>> = keys.iter().map(PublicKey::to_bytes).collect();
+        assert_eq!(
+            parsed_keys,
+            serialized.iter().map(|key| &key[..]).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn sorted_multisig() {
+        let keys = keys();
+        let sorted = ScriptBuf::new_sorted_multisig(2, &keys).unwrap();
+        let mut reversed = keys.clone();
+        reversed.reverse();
+        assert_eq!(
+            ScriptBuf::new_sorted_multisig(2, &reversed).unwrap(),
+            sorted
+        );
+
+        let (_, parsed_keys, _) = sorted.parse_multisig().unwrap();
+        assert!(parsed_keys.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_ne!(ScriptBuf::new_multisig(2, &keys).unwrap(), sorted);
+    }
+
+    #[test]
+    fn uncompressed_multisig() {
+        let keys: Vec<PublicKey> = keys()
+            .iter()
+            .map(|key| PublicKey::new_uncompressed(key.inner))
+            .collect();
+        let script = ScriptBuf::new_multisig(1, &keys[..2]).unwrap();
+
+        let mut expected = bitcoin::script::Builder::new().push_int(1);
+        for key in bitcoin_keys(&keys[..2]) {
+            assert!(!key.compressed);
+            expected = expected.push_key(&key);
+        }
+        let expected = expected
+            .push_int(2)
+            .push_opcode(bitcoin::opcodes::all::OP_CHECKMULTISIG)
+            .into_script();
+        assert_eq!(script.as_bytes(), expected.as_bytes());
+        let (_, parsed_keys, _) = script.parse_multisig().unwrap();
+        assert!(parsed_keys.iter().all(|key| key.len() == 65));
+
+        let sorted = ScriptBuf::new_sorted_multisig(1, &keys).unwrap();
+        let (_, parsed_keys, _) = sorted.parse_multisig().unwrap();
+        assert!(parsed_keys.windows(2).all(|pair| pair[0] < pair[1]));
+        // Mixing both serializations is allowed too.
+        let mixed = [keys[0], PublicKey::new(keys[1].inner)];
+        let mixed = ScriptBuf::new_multisig(2, &mixed).unwrap();
+        let (_, parsed_keys, _) = mixed.parse_multisig().unwrap();
+        assert_eq!(
+            parsed_keys.iter().map(|key| key.len()).collect::<Vec<_>>(),
+            [65, 33]
+        );
+    }
+
+    #[test]
+    fn multisig_errors() {
+        let keys = keys();
+        assert_eq!(
+            ScriptBuf::new_multisig(0, &keys),
+            Err(MultisigError::InvalidThreshold {
+                required: 0,
+                keys: 3
+            })
+        );
+        assert_eq!(
+            ScriptBuf::new_multisig(4, &keys),
+            Err(MultisigError::InvalidThreshold {
+                required: 4,
+                keys: 3
+            })
+        );
+        assert_eq!(
+            ScriptBuf::new_multisig(1, &[]),
+            Err(MultisigError::InvalidThreshold {
+                required: 1,
+                keys: 0
+            })
+        );
+        let many_keys = [keys[0]; 21];
+        assert_eq!(
+            ScriptBuf::new_multisig(1, &many_keys)
+                .unwrap_err()
+                .to_string(),
+            "21 keys is more than the 20 allowed in a multisig script"
+        );
+        assert!(ScriptBuf::new_multisig(16, &many_keys[..16])
+            .unwrap()
+            .is_multisig());
+        // Counts above 16 are pushed as numbers.
+        let twenty = ScriptBuf::new_multisig(17, &many_keys[..20]).unwrap();
+        let mut expected = bitcoin::script::Builder::new().push_int(17);
+        for key in bitcoin_keys(&many_keys[..20]) {
+            expected = expected.push_key(&key);
+        }
+        let expected = expected
+            .push_int(20)
+            .push_opcode(bitcoin::opcodes::all::OP_CHECKMULTISIG)
+            .into_script();
+        assert_eq!(twenty.as_bytes(), expected.as_bytes());
+        assert!(twenty.is_multisig());
+        let (required, parsed_keys, total) = twenty.parse_multisig().unwrap();
+        assert_eq!((required, parsed_keys.len(), total), (17, 20, 20));
+        assert_eq!(ScriptBuf::new_p2pkh(&[0; 20]).parse_multisig(), None);
+
+        // Counts must be minimal pushes of minimal numbers between 1 and 20.
+        let key = keys[0].to_bytes();
+        let one_key = |required: &[u8], total: &[u8]| {
+            ScriptBuf::from([required, &[33], &key, total, &[0xae]].concat())
+        };
+        assert!(one_key(&[0x51], &[0x51]).is_multisig());
+        for (required, total) in [
+            (&[0x00][..], &[0x51][..]),
+            (&[0x4f], &[0x51]),
+            (&[0x01, 0x01], &[0x51]),
+            (&[0x51], &[0x01, 0x01]),
+            (&[0x4c, 0x01, 0x01], &[0x51]),
+            (&[0x02, 0x01, 0x00], &[0x51]),
+            (&[0x52], &[0x51]),
+            (&[0x51], &[0x52]),
+            (&[0x01, 0x15], &[0x01, 0x15]),
+        ] {
+            assert!(!one_key(required, total).is_multisig());
+        }
+        let mut trailing = one_key(&[0x51], &[0x51]).into_bytes();
+        trailing.push(0xae);
+        assert!(!ScriptBuf::from(trailing).is_multisig());
+    }
+
+    #[test]
+    fn wrapped_multisig_addresses() {
+        let keys = keys();
+        let script = ScriptBuf::new_multisig(2, &keys).unwrap();
+        let bitcoin_script = bitcoin::ScriptBuf::from_bytes(script.as_bytes().to_vec());
+        let network = bitcoin::Network::Bitcoin;
+
+        let cases = [
+            (
+                BitcoinAddress::p2sh(&script, Network::Bitcoin).unwrap(),
+                script.to_p2sh().unwrap(),
+                bitcoin::Address::p2sh(&bitcoin_script, network).unwrap(),
+            ),
+            (
+                BitcoinAddress::p2wsh(&script, Network::Bitcoin),
+                script.to_p2wsh(),
+                bitcoin::Address::p2wsh(&bitcoin_script, network),
+            ),
+            (
+                BitcoinAddress::p2shwsh(&script, Network::Bitcoin),
+                script.to_p2sh_p2wsh(),
+                bitcoin::Address::p2shwsh(&bitcoin_script, network),
+            ),
+        ];
+        for (address, script_pubkey, expected) in cases {
+            assert_eq!(
+                script_pubkey.as_bytes(),
+                expected.script_pubkey().as_bytes()
+            );
+            assert_eq!(address.to_script().unwrap(), script_pubkey);
+            assert_eq!(
+                address,
+                BitcoinAddress::from_str(&expected.to_string()).unwrap()
+            );
+        }
+        assert!(script.to_p2sh().unwrap().is_p2sh());
+        assert!(script.to_p2wsh().is_p2wsh());
+    }
+
+    #[test]
+    fn p2sh_redeem_script_limit() {
+        let many_keys = [keys()[0]; 16];
+        // 15 compressed keys are a 513-byte redeem script, 16 are 547 bytes.
+        let fifteen = ScriptBuf::new_multisig(1, &many_keys[..15]).unwrap();
+        assert_eq!(fifteen.len(), 513);
+        assert!(fifteen.to_p2sh().is_ok());
+        assert!(BitcoinAddress::p2sh(&fifteen, Network::Bitcoin).is_ok());
+
+        let sixteen = ScriptBuf::new_multisig(1, &many_keys).unwrap();
+        assert_eq!(sixteen.len(), 547);
+        assert_eq!(sixteen.to_p2sh(), Err(ScriptError::PushSize));
+        assert!(BitcoinAddress::p2sh(&sixteen, Network::Bitcoin).is_err());
+        // Only the 34-byte P2WSH output script is pushed when nested in P2SH.
+        assert!(sixteen.to_p2sh_p2wsh().is_p2sh());
+        assert!(BitcoinAddress::p2shwsh(&sixteen, Network::Bitcoin)
+            .to_script()
+            .unwrap()
+            .is_p2sh());
+    }
+}
@@ -0,0 +1,191 @@
+//! Script numbers.
+//!
+//! Numbers on the script stack are little-endian sign-magnitude byte vectors, with the
+//! top bit of the last byte as the sign, like Bitcoin Core's `CScriptNum`.
+
+use crate::script::error::ScriptError;
+use core::fmt;
+
+/// A number read from or written to the script stack.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ScriptNum(i64);
+
+impl ScriptNum {
+    /// Maximum size of the numbers taken by arithmetic opcodes.
+    pub const DEFAULT_MAX_SIZE: usize = 4;
+    /// Maximum size of the lock times taken by `OP_CHECKLOCKTIMEVERIFY` and
+    /// `OP_CHECKSEQUENCEVERIFY`, which may not fit in 4 bytes.
+    pub const LOCKTIME_MAX_SIZE: usize = 5;
+
+    /// Creates a script number.
+    pub fn new(value: i64) -> Self {
+        ScriptNum(value)
+    }
+
+    /// Decodes a number of at most [`DEFAULT_MAX_SIZE`](Self::DEFAULT_MAX_SIZE) bytes.
+    pub fn from_slice(bytes: &[u8], require_minimal: bool) -> Result<Self, ScriptError> {
+        ScriptNum::from_slice_with_max_size(bytes, require_minimal, Self::DEFAULT_MAX_SIZE)
+    }
+
+    /// Decodes a number of at most `max_size` bytes, which must not exceed 8.
+    ///
+    /// Fails with [`ScriptError::NumericOverflow`] if `bytes` is too long, and with
+    /// [`ScriptError::NonMinimalNumber`] if `require_minimal` is set and `bytes` isn't
+    /// [minimally encoded](Self::is_minimally_encoded).
+    pub fn from_slice_with_max_size(
+        bytes: &[u8],
+        require_minimal: bool,
+        max_size: usize,
+    ) -> Result<Self, ScriptError> {
+        if bytes.len() > max_size.min(8) {
+            return Err(ScriptError::NumericOverflow);
+        }
+        if require_minimal && !ScriptNum::is_minimally_encoded(bytes) {
+            return Err(ScriptError::NonMinimalNumber);
+        }
+        let (&last, rest) = match bytes.split_last() {
+            Some(split) => split,
+            None => return Ok(ScriptNum(0)),
+        };
+
+        let mut value: i64 = 0;
+        for (i, &byte) in bytes.iter().enumerate() {
+            value |= (byte as i64) << (8 * i);
+        }
+        if last & 0x80 != 0 {
+            Ok(ScriptNum(-(value & !(0x80 << (8 * rest.len())))))
+        } else {
+            Ok(ScriptNum(value))
+        }
+    }
+
+    /// Returns true if `bytes` is the shortest encoding of its number, in particular if
+    /// it isn't zero padded and isn't negative zero.
+    pub fn is_minimally_encoded(bytes: &[u8]) -> bool {
+        match bytes.split_last() {
+            // The most significant byte may only be zero (apart from the sign bit) if the
+            // next byte needs its top bit for the value.
+            Some((&last, rest)) => last & 0x7f != 0 || rest.last().is_some_and(|&b| b & 0x80 != 0),
+            None => true,
+        }
+    }
+
+    /// Encodes the number minimally, which takes 9 bytes for `i64::MIN`.
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut result = Vec::new();
+        let negative = self.0 < 0;
+        let mut abs = self.0.unsigned_abs();
+        while abs > 0 {
+            result.push(abs as u8);
+            abs >>= 8;
+        }
+        // Make room for the sign bit if the top bit is already used by the value.
+        if let Some(&last) = result.last() {
+            if last & 0x80 != 0 {
+                result.push(if negative { 0x80 } else { 0 });
+            } else if negative {
+                *result.last_mut().unwrap() |= 0x80;
+            }
+        }
+        result
+    }
+
+    /// Returns the number.
+    pub fn to_i64(self) -> i64 {
+        self.0
+    }
+
+    /// Returns the number clamped to the range of `i32`, like `CScriptNum::getint`.
+    pub fn to_i32(self) -> i32 {
+        self.0.clamp(i32::MIN as i64, i32::MAX as i64) as i32
+    }
+}
+
+impl From<i64> for ScriptNum {
+    fn from(value: i64) -> Self {
+        ScriptNum(value)
+    }
+}
+
+impl From<ScriptNum> for i64 {
+    fn from(num: ScriptNum) -> Self {
+        num.0
+    }
+}
+
+impl fmt::Display for ScriptNum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encoding() {
+        let cases: &[(i64, &[u8])] = &[
+            (0, &[]),
+            (1, &[0x01]),
+            (-1, &[0x81]),
+            (127, &[0x7f]),
+            (128, &[0x80, 0x00]),
+            (-128, &[0x80, 0x80]),
+            (255, &[0xff, 0x00]),
+            (256, &[0x00, 0x01]),
+            (-32768, &[0x00, 0x80, 0x80]),
+            (2147483647, &[0xff, 0xff, 0xff, 0x7f]),
+            (-2147483648, &[0x00, 0x00, 0x00, 0x80, 0x80]),
+            (i64::MAX, &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f]),
+        ];
+        for &(value, bytes) in cases {
+            assert_eq!(ScriptNum::new(value).to_bytes(), bytes);
+            assert!(ScriptNum::is_minimally_encoded(bytes));
+            assert_eq!(
+                ScriptNum::from_slice_with_max_size(bytes, true, 8),
+                Ok(ScriptNum(value))
+            );
+        }
+        assert_eq!(ScriptNum::new(i64::MIN).to_bytes().len(), 9);
+    }
+
+    #[test]
+    fn decoding() {
+        assert_eq!(ScriptNum::from_slice(&[0x00], false), Ok(ScriptNum(0)));
+        assert_eq!(ScriptNum::from_slice(&[0x80], false), Ok(ScriptNum(0)));
+        assert_eq!(
+            ScriptNum::from_slice(&[0x01, 0x80], false),
+            Ok(ScriptNum(-1))
+        );
+        assert_eq!(
+            ScriptNum::from_slice(&[0x00], true),
+            Err(ScriptError::NonMinimalNumber)
+        );
+        assert_eq!(
+            ScriptNum::from_slice(&[0x01, 0x00], true),
+            Err(ScriptError::NonMinimalNumber)
+        );
+        assert_eq!(
+            ScriptNum::from_slice(&[0x00; 5], false),
+            Err(ScriptError::NumericOverflow)
+        );
+        assert_eq!(
+            ScriptNum::from_slice_with_max_size(&[0xff; 5], true, ScriptNum::LOCKTIME_MAX_SIZE),
+            Ok(ScriptNum(-0x7f_ffff_ffff))
+        );
+        assert_eq!(
+            ScriptNum::from_slice_with_max_size(&[0x01; 9], false, 9),
+            Err(ScriptError::NumericOverflow)
+        );
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(ScriptNum::from(-5).to_i64(), -5);
+        assert_eq!(i64::from(ScriptNum::new(7)), 7);
+        assert_eq!(ScriptNum::new(1 << 40).to_i32(), i32::MAX);
+        assert_eq!(ScriptNum::new(-(1 << 40)).to_i32(), i32::MIN);
+        assert_eq!(ScriptNum::new(-300).to_string(), "-300");
+    }
+}
@@ -1,10 +1,355 @@
 use super::builder::ScriptBuilder;
+use super::error::ScriptError;
+use super::instructions::{decode_pushnum, Instruction, Instructions};
+use super::num::ScriptNum;
+use crate::constants::MAX_SCRIPT_ELEMENT_SIZE;
+use crate::key::{TweakedPublicKey, XOnlyPublicKey};
+use crate::taproot::TapNodeHash;
+use crate::types::WitnessVersion;
+use bitcoin_hashes::{hash160, hash_newtype, sha256, Hash};
+use core::borrow::{Borrow, BorrowMut};
+use core::fmt;
+use core::ops::{Deref, DerefMut};
 use opcodes::all::*;
+use opcodes::Opcode;
 
-#[derive(Debug)]
+hash_newtype! {
+    /// Hash160 of a script, as paid to by P2SH outputs.
+    pub struct ScriptHash(hash160::Hash);
+    /// SHA256 of a script, as paid to by P2WSH outputs.
+    pub struct WScriptHash(sha256::Hash);
+}
+
+/// A borrowed script, the unsized counterpart of [`ScriptBuf`] like `str` is to `String`.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct Script([u8]);
+
+impl Script {
+    /// Views `bytes` as a script.
+    pub fn from_bytes(bytes: &[u8]) -> &Script {
+        // SAFETY: `Script` is a `repr(transparent)` wrapper of `[u8]`.
+        unsafe { &*(bytes as *const [u8] as *const Script) }
+    }
+
+    /// Views `bytes` as a mutable script.
+    pub fn from_bytes_mut(bytes: &mut [u8]) -> &mut Script {
+        // SAFETY: `Script` is a `repr(transparent)` wrapper of `[u8]`.
+        unsafe { &mut *(bytes as *mut [u8] as *mut Script) }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn as_mut_bytes(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+
+    /// Returns the length of the script in bytes.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Iterates over the instructions of the script, stopping after the first error.
+    pub fn instructions(&self) -> Instructions<'_> {
+        Instructions::from(self.as_bytes())
+    }
+
+    /// Returns true if the script only pushes data, with the opcodes up to `OP_16`.
+    ///
+    /// Like Bitcoin Core, this treats `OP_RESERVED` as a push and fails on truncated pushes.
+    pub fn is_push_only(&self) -> bool {
+        self.instructions().all(|instruction| match instruction {
+            Ok(Instruction::PushBytes(_)) => true,
+            Ok(Instruction::Op(op)) => op.to_u8() <= OP_PUSHNUM_16.to_u8(),
+            Err(_) => false,
+        })
+    }
+
+    /// Returns true if the script is a pay to public key output script.
+    pub fn is_p2pk(&self) -> bool {
+        match self.0 {
+            [len, ref key @ .., op] if op == OP_CHECKSIG.to_u8() => {
+                len as usize == key.len() && is_public_key(key)
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns true if the script is a pay to public key hash output script.
+    pub fn is_p2pkh(&self) -> bool {
+        self.0.len() == 25
+            && self.0[0] == OP_DUP.to_u8()
+            && self.0[1] == OP_HASH160.to_u8()
+            && self.0[2] == OP_PUSHBYTES_20.to_u8()
+            && self.0[23] == OP_EQUALVERIFY.to_u8()
+            && self.0[24] == OP_CHECKSIG.to_u8()
+    }
+
+    /// Returns true if the script is a pay to script hash output script.
+    pub fn is_p2sh(&self) -> bool {
+        self.0.len() == 23
+            && self.0[0] == OP_HASH160.to_u8()
+            && self.0[1] == OP_PUSHBYTES_20.to_u8()
+            && self.0[22] == OP_EQUAL.to_u8()
+    }
+
+    /// Returns true if the script is a bare multisig output script, see
+    /// [`parse_multisig`](Self::parse_multisig).
+    pub fn is_multisig(&self) -> bool {
+        self.parse_multisig().is_some()
+    }
+
+    /// Returns true if the script is an `OP_RETURN` output script, which can't be spent.
+    pub fn is_op_return(&self) -> bool {
+        self.0.first() == Some(&OP_RETURN.to_u8())
+    }
+
+    /// Returns the data pushed after `OP_RETURN` if the script is a data carrier output
+    /// made only of pushes, as created by [`ScriptBuf::new_op_return`].
+    ///
+    /// Returns `None` if anything but a push follows `OP_RETURN`, including `OP_1` to
+    /// `OP_16`, or if a push is truncated.
+    pub fn op_return_data(&self) -> Option<Vec<&[u8]>> {
+        if !self.is_op_return() {
+            return None;
+        }
+        Instructions::from(&self.0[1..])
+            .map(|instruction| match instruction {
+                Ok(Instruction::PushBytes(data)) => Some(data),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns true if the script is a witness program of any version: a version opcode
+    /// followed by a single push of 2 to 40 bytes (BIP141).
+    pub fn is_witness_program(&self) -> bool {
+        self.witness_version().is_some()
+    }
+
+    /// Returns the version of the witness program if the script is one.
+    pub fn witness_version(&self) -> Option<WitnessVersion> {
+        if self.0.len() < 4 || self.0.len() > 42 || self.0[1] as usize + 2 != self.0.len() {
+            return None;
+        }
+        match self.0[0] {
+            0 => Some(WitnessVersion::V0),
+            op => decode_pushnum(Opcode::from(op)).and_then(WitnessVersion::from_num),
+        }
+    }
+
+    /// Returns the program of the witness program if the script is one.
+    pub fn witness_program(&self) -> Option<&[u8]> {
+        self.witness_version().map(|_| &self.0[2..])
+    }
+
+    /// Returns true if the script is a pay to witness public key hash output script.
+    pub fn is_p2wpkh(&self) -> bool {
+        self.witness_version() == Some(WitnessVersion::V0) && self.0.len() == 22
+    }
+
+    /// Returns true if the script is a pay to witness script hash output script.
+    pub fn is_p2wsh(&self) -> bool {
+        self.witness_version() == Some(WitnessVersion::V0) && self.0.len() == 34
+    }
+
+    /// Returns true if the script is a pay to taproot output script.
+    pub fn is_p2tr(&self) -> bool {
+        self.witness_version() == Some(WitnessVersion::V1) && self.0.len() == 34
+    }
+
+    /// Returns the hash of the script paid to by P2SH outputs.
+    pub fn script_hash(&self) -> ScriptHash {
+        ScriptHash::hash(self.as_bytes())
+    }
+
+    /// Returns the hash of the script paid to by P2WSH outputs.
+    pub fn wscript_hash(&self) -> WScriptHash {
+        WScriptHash::hash(self.as_bytes())
+    }
+
+    /// Returns the P2SH output script paying to this redeem script.
+    ///
+    /// # Errors
+    ///
+    /// [`ScriptError::PushSize`] if the redeem script is longer than
+    /// [`MAX_SCRIPT_ELEMENT_SIZE`], as it couldn't be pushed to spend the output.
+    pub fn to_p2sh(&self) -> Result<ScriptBuf, ScriptError> {
+        if self.len() > MAX_SCRIPT_ELEMENT_SIZE {
+            return Err(ScriptError::PushSize);
+        }
+        Ok(ScriptBuf::new_p2sh(&self.script_hash().to_byte_array()))
+    }
+
+    /// Returns the P2WSH output script paying to this witness script.
+    pub fn to_p2wsh(&self) -> ScriptBuf {
+        ScriptBuf::new_p2wsh(&self.wscript_hash().to_byte_array())
+    }
+
+    /// Returns the P2SH output script paying to the P2WSH of this witness script, whose
+    /// redeem script is [`to_p2wsh`](Self::to_p2wsh).
+    pub fn to_p2sh_p2wsh(&self) -> ScriptBuf {
+        self.to_p2wsh()
+            .to_p2sh()
+            .expect("P2WSH scripts are 34 bytes")
+    }
+
+    /// Returns the script in the compact ASM of Bitcoin Core's `decodescript`.
+    ///
+    /// Pushes of up to 4 bytes and `OP_0` to `OP_16` are written as numbers, longer pushes
+    /// as hex and other opcodes by their Core name. An invalid push ends the script with
+    /// `[error]`. Use [`Display`](fmt::Display) for an ASM keeping every push opcode.
+    pub fn to_asm_string(&self) -> String {
+        let mut asm = String::new();
+        for instruction in self.instructions() {
+            if !asm.is_empty() {
+                asm.push(' ');
+            }
+            match instruction {
+                Ok(Instruction::PushBytes(data)) if data.len() <= 4 => {
+                    // Core decodes short pushes as numbers, minimal or not.
+                    let value = ScriptNum::from_slice(data, false).unwrap();
+                    asm.push_str(&value.to_string());
+                }
+                Ok(Instruction::PushBytes(data)) => asm.push_str(&hex::encode(data)),
+                Ok(Instruction::Op(op)) => asm.push_str(&core_opcode_name(op)),
+                Err(_) => {
+                    asm.push_str("[error]");
+                    break;
+                }
+            }
+        }
+        asm
+    }
+
+    /// Writes the script as ASM, with every push preceded by its opcode.
+    fn fmt_asm(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut instructions = self.instructions();
+        let mut first = true;
+        loop {
+            let pos = self.len() - instructions.data.len();
+            let instruction = match instructions.next() {
+                Some(instruction) => instruction,
+                None => return Ok(()),
+            };
+            if !first {
+                f.write_str(" ")?;
+            }
+            first = false;
+            match instruction {
+                Ok(Instruction::PushBytes([])) => match Opcode::from(self.0[pos]) {
+                    OP_PUSHBYTES_0 => f.write_str("OP_0")?,
+                    // Empty pushes with OP_PUSHDATA1, 2 or 4 are displayed as they are.
+                    opcode => write!(f, "{} <>", opcode)?,
+                },
+                Ok(Instruction::PushBytes(data)) => {
+                    write!(f, "{} {}", Opcode::from(self.0[pos]), hex::encode(data))?
+                }
+                Ok(Instruction::Op(op)) => write!(f, "{}", op)?,
+                Err(_) => return f.write_str("<push past end>"),
+            }
+        }
+    }
+}
+
+/// Returns true if `key` has the size and prefix of a public key, like Bitcoin Core's
+/// `CPubKey::ValidSize`, without checking that it is on the curve.
+pub(super) fn is_public_key(key: &[u8]) -> bool {
+    match key.first() {
+        Some(0x02 | 0x03) => key.len() == 33,
+        Some(0x04 | 0x06 | 0x07) => key.len() == 65,
+        _ => false,
+    }
+}
+
+/// Returns the name Bitcoin Core gives to a non-push opcode.
+fn core_opcode_name(op: Opcode) -> String {
+    const UNKNOWN_START: u8 = OP_RETURN_187.to_u8();
+    const UNKNOWN_END: u8 = OP_RETURN_254.to_u8();
+    if let Some(n) = decode_pushnum(op) {
+        return n.to_string();
+    }
+    match op {
+        OP_PUSHNUM_NEG1 => "-1".to_string(),
+        OP_CLTV => "OP_CHECKLOCKTIMEVERIFY".to_string(),
+        OP_CSV => "OP_CHECKSEQUENCEVERIFY".to_string(),
+        _ => match op.to_u8() {
+            UNKNOWN_START..=UNKNOWN_END => "OP_UNKNOWN".to_string(),
+            _ => op.to_string(),
+        },
+    }
+}
+
+impl fmt::Display for Script {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_asm(f)
+    }
+}
+
+impl fmt::Debug for Script {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Script(")?;
+        self.fmt_asm(f)?;
+        f.write_str(")")
+    }
+}
+
+impl fmt::LowerHex for Script {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&hex::encode(self.as_bytes()))
+    }
+}
+
+impl fmt::UpperHex for Script {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&hex::encode_upper(self.as_bytes()))
+    }
+}
+
+impl AsRef<[u8]> for Script {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl AsRef<Script> for Script {
+    fn as_ref(&self) -> &Script {
+        self
+    }
+}
+
+impl<'a> From<&'a [u8]> for &'a Script {
+    fn from(bytes: &'a [u8]) -> Self {
+        Script::from_bytes(bytes)
+    }
+}
+
+impl ToOwned for Script {
+    type Owned = ScriptBuf;
+
+    fn to_owned(&self) -> ScriptBuf {
+        ScriptBuf(self.0.to_vec())
+    }
+}
+
+/// An owned script.
+///
+/// Dereferences to [`Script`], which has the methods reading the script.
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ScriptBuf(pub(in crate::script) Vec<u8>);
 
 impl ScriptBuf {
+    /// Creates an empty script.
+    pub fn new() -> Self {
+        ScriptBuf(Vec::new())
+    }
+
     pub(in crate::script) fn push_value(&mut self, data: u8) {
         self.0.push(data);
     }
@@ -13,8 +358,16 @@ impl ScriptBuf {
         self.0.extend_from_slice(data);
     }
 
-    pub fn as_bytes(&self) -> &[u8] {
-        &self.0
+    pub fn as_script(&self) -> &Script {
+        Script::from_bytes(&self.0)
+    }
+
+    pub fn as_mut_script(&mut self) -> &mut Script {
+        Script::from_bytes_mut(&mut self.0)
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
     }
 
     pub fn new_p2pkh(data: &[u8]) -> Self {
@@ -57,14 +410,131 @@ impl ScriptBuf {
         builder.into_script()
     }
 
-    pub fn new_p2tr(data: &[u8]) -> Self {
+    /// Creates a P2TR script paying to `internal_key` tweaked to commit to the script
+    /// tree with `merkle_root`, or to no scripts at all.
+    ///
+    /// Witness v1 programs that are not known to be keys, such as the ones decoded from
+    /// addresses, are built with [`ScriptBuf::new_witness_program`].
+    pub fn new_p2tr(internal_key: XOnlyPublicKey, merkle_root: Option<TapNodeHash>) -> Self {
+        let (output_key, _) = TweakedPublicKey::from_internal_key(internal_key, merkle_root);
+        ScriptBuf::new_p2tr_tweaked(output_key)
+    }
+
+    /// Creates a P2TR script paying to `output_key`.
+    pub fn new_p2tr_tweaked(output_key: TweakedPublicKey) -> Self {
         let mut builder = ScriptBuilder::new();
         builder
             .push_opcode(OP_PUSHNUM_1)
             .push_opcode(OP_PUSHBYTES_32)
-            .push_slice_only(data);
+            .push_slice_only(&output_key.serialize());
         builder.into_script()
     }
+
+    /// Creates a witness program of any version.
+    pub fn new_witness_program(version: WitnessVersion, program: &[u8]) -> Self {
+        let mut builder = ScriptBuilder::new();
+        builder
+            .push_int(version.to_num() as i64)
+            .push_slice_with_size(program);
+        builder.into_script()
+    }
+
+    /// Creates an unspendable output script carrying `data`, each chunk pushed with the
+    /// smallest push opcode after `OP_RETURN`.
+    ///
+    /// Nodes only relay transactions whose `OP_RETURN` scripts are at most 83 bytes by
+    /// default.
+    ///
+    /// Returns [`ScriptError::PushSize`] if a chunk is longer than
+    /// [`MAX_SCRIPT_ELEMENT_SIZE`], as no script can push it.
+    pub fn new_op_return(data: &[&[u8]]) -> Result<Self, ScriptError> {
+        if data
+            .iter()
+            .any(|chunk| chunk.len() > MAX_SCRIPT_ELEMENT_SIZE)
+        {
+            return Err(ScriptError::PushSize);
+        }
+        let mut builder = ScriptBuilder::new();
+        builder.push_opcode(OP_RETURN);
+        for chunk in data {
+            builder.push_slice_with_size(chunk);
+        }
+        Ok(builder.into_script())
+    }
+}
+
+impl Deref for ScriptBuf {
+    type Target = Script;
+
+    fn deref(&self) -> &Script {
+        self.as_script()
+    }
+}
+
+impl DerefMut for ScriptBuf {
+    fn deref_mut(&mut self) -> &mut Script {
+        self.as_mut_script()
+    }
+}
+
+impl Borrow<Script> for ScriptBuf {
+    fn borrow(&self) -> &Script {
+        self
+    }
+}
+
+impl BorrowMut<Script> for ScriptBuf {
+    fn borrow_mut(&mut self) -> &mut Script {
+        self
+    }
+}
+
+impl AsRef<Script> for ScriptBuf {
+    fn as_ref(&self) -> &Script {
+        self
+    }
+}
+
+impl AsRef<[u8]> for ScriptBuf {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl fmt::Display for ScriptBuf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.as_script(), f)
+    }
+}
+
+impl fmt::Debug for ScriptBuf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_script(), f)
+    }
+}
+
+impl fmt::LowerHex for ScriptBuf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::LowerHex::fmt(self.as_script(), f)
+    }
+}
+
+impl fmt::UpperHex for ScriptBuf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::UpperHex::fmt(self.as_script(), f)
+    }
+}
+
+impl From<&Script> for ScriptBuf {
+    fn from(script: &Script) -> Self {
+        script.to_owned()
+    }
+}
+
+impl From<ScriptBuf> for Vec<u8> {
+    fn from(script: ScriptBuf) -> Self {
+        script.0
+    }
 }
 
 impl From<&[u8]> for ScriptBuf {
@@ -73,6 +543,12 @@ impl From<&[u8]> for ScriptBuf {
     }
 }
 
+impl From<Vec<u8>> for ScriptBuf {
+    fn from(value: Vec<u8>) -> Self {
+        ScriptBuf(value)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -172,10 +648,13 @@ mod test {
 
     #[test]
     fn new_p2tr_works() {
-        let script = ScriptBuf::new_p2tr(&[
+        let output_key = XOnlyPublicKey::from_slice(&[
             255, 214, 52, 32, 30, 11, 213, 193, 28, 222, 135, 21, 217, 24, 92, 184, 95, 78, 48,
             116, 147, 14, 189, 212, 166, 230, 229, 110, 99, 32, 61, 76,
-        ]);
+        ])
+        .unwrap();
+        let script =
+            ScriptBuf::new_p2tr_tweaked(TweakedPublicKey::dangerous_assume_tweaked(output_key));
         assert_eq!(
             script.as_bytes(),
             [
@@ -184,4 +663,220 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn core_asm() {
+        let cases = [
+            (
+                "76a9141a047a70930d25e4262b50a408199768c927052088ac",
+                "OP_DUP OP_HASH160 1a047a70930d25e4262b50a408199768c9270520 OP_EQUALVERIFY OP_CHECKSIG",
+            ),
+            ("004f515f60", "0 -1 1 15 16"),
+            // Short pushes are numbers, even when not minimally encoded.
+            ("0100018001ff04ffffffff02000103ffff7f", "0 0 -127 -2147483647 256 8388607"),
+            ("050102030405", "0102030405"),
+            ("4c0401020304", "67305985"),
+            ("b0b1b2b9babbfe50ff", "OP_NOP1 OP_CHECKLOCKTIMEVERIFY OP_CHECKSEQUENCEVERIFY OP_NOP10 OP_CHECKSIGADD OP_UNKNOWN OP_UNKNOWN OP_RESERVED OP_INVALIDOPCODE"),
+            ("6a0201", "OP_RETURN [error]"),
+            ("4d01", "[error]"),
+            ("", ""),
+        ];
+        for (hex, asm) in cases {
+            let script = ScriptBuf::from(hex::decode(hex).unwrap());
+            assert_eq!(script.to_asm_string(), asm, "{}", hex);
+        }
+    }
+
+    #[test]
+    fn display_matches_rust_bitcoin() {
+        let scripts = [
+            "76a9141a047a70930d25e4262b50a408199768c927052088ac",
+            "00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262",
+            "004f515f60b1b2babbff",
+            "4c0201024d03000102034e01000000ff",
+            "6a0a68656c6c6f776f726c64",
+        ];
+        for hex in scripts {
+            let bytes = hex::decode(hex).unwrap();
+            let expected = bitcoin::Script::from_bytes(&bytes).to_asm_string();
+            assert_eq!(Script::from_bytes(&bytes).to_string(), expected);
+            assert_eq!(ScriptBuf::from(bytes).to_string(), expected);
+        }
+
+        let truncated = Script::from_bytes(&[0x51, 0x03, 0x01]);
+        assert_eq!(truncated.to_string(), "OP_PUSHNUM_1 <push past end>");
+    }
+
+    #[test]
+    fn debug_and_hex() {
+        let script = ScriptBuf::from(vec![0x00, 0x02, 0xab, 0xcd, 0x87]);
+        assert_eq!(
+            format!("{:?}", script),
+            "Script(OP_0 OP_PUSHBYTES_2 abcd OP_EQUAL)"
+        );
+        assert_eq!(format!("{:x}", script), "0002abcd87");
+        assert_eq!(format!("{:X}", script.as_script()), "0002ABCD87");
+    }
+
+    #[test]
+    fn borrowed_script() {
+        let bytes = [0x51, 0x52, 0x93];
+        let script = Script::from_bytes(&bytes);
+        assert_eq!(script.len(), 3);
+        assert!(!script.is_empty());
+        assert!(!script.is_push_only());
+
+        let owned: ScriptBuf = script.to_owned();
+        assert_eq!(owned.as_script(), script);
+        assert_eq!(&*owned, script);
+        assert_eq!(owned.instructions().count(), 3);
+        assert_eq!(owned.into_bytes(), bytes);
+        assert!(ScriptBuf::new().is_empty());
+    }
+
+    #[test]
+    fn templates() {
+        let key = [[0x02].as_slice(), &[0x11; 32]].concat();
+        let uncompressed_key = [[0x04].as_slice(), &[0x11; 64]].concat();
+        let multisig = |required: i64, keys: &[&[u8]], total: i64| {
+            let mut builder = ScriptBuilder::new();
+            builder.push_int(required);
+            for key in keys {
+                builder.push_slice_with_size(key);
+            }
+            builder.push_int(total).push_opcode(OP_CHECKMULTISIG);
+            builder.into_script()
+        };
+        let mut p2pk = ScriptBuilder::new();
+        p2pk.push_slice_with_size(&uncompressed_key)
+            .push_opcode(OP_CHECKSIG);
+        let p2pk = p2pk.into_script();
+
+        let check = |script: &ScriptBuf, expected: &str| {
+            let mut matches = Vec::new();
+            for (name, is) in [
+                ("p2pk", script.is_p2pk()),
+                ("p2pkh", script.is_p2pkh()),
+                ("p2sh", script.is_p2sh()),
+                ("multisig", script.is_multisig()),
+                ("op_return", script.is_op_return()),
+                ("witness", script.is_witness_program()),
+                ("p2wpkh", script.is_p2wpkh()),
+                ("p2wsh", script.is_p2wsh()),
+                ("p2tr", script.is_p2tr()),
+            ] {
+                if is {
+                    matches.push(name);
+                }
+            }
+            assert_eq!(matches.join(" "), expected, "{}", script);
+        };
+        check(&ScriptBuf::new(), "");
+        check(&ScriptBuf::from(vec![0x00]), "");
+        check(&p2pk, "p2pk");
+        check(&ScriptBuf::new_p2pkh(&[1; 20]), "p2pkh");
+        check(&ScriptBuf::new_p2sh(&[1; 20]), "p2sh");
+        check(&multisig(2, &[&key, &uncompressed_key], 2), "multisig");
+        check(&multisig(1, &[key.as_slice(); 16], 16), "multisig");
+        check(&multisig(3, &[&key, &key], 2), "");
+        check(&multisig(0, &[&key], 1), "");
+        check(&multisig(1, &[&key, &[0x05; 33]], 2), "");
+        check(&multisig(1, &[&key], 2), "");
+        check(&ScriptBuf::from(vec![0x6a, 0x01, 0x00]), "op_return");
+        check(&ScriptBuf::new_p2wpkh(&[1; 20]), "witness p2wpkh");
+        check(&ScriptBuf::new_p2wsh(&[1; 32]), "witness p2wsh");
+        check(
+            &ScriptBuf::new_witness_program(WitnessVersion::V1, &[1; 32]),
+            "witness p2tr",
+        );
+        check(
+            &ScriptBuf::new_witness_program(WitnessVersion::V0, &[1; 24]),
+            "witness",
+        );
+        check(
+            &ScriptBuf::new_witness_program(WitnessVersion::V1, &[1; 2]),
+            "witness",
+        );
+        check(
+            &ScriptBuf::new_witness_program(WitnessVersion::V16, &[1; 40]),
+            "witness",
+        );
+        check(&ScriptBuf::from(vec![0x51, 0x01, 0x01]), "");
+        check(
+            &ScriptBuf::from([vec![0x51, 0x29], vec![1; 41]].concat()),
+            "",
+        );
+        check(&ScriptBuf::from(vec![0x4f, 0x02, 0x01, 0x01]), "");
+
+        let program = ScriptBuf::new_witness_program(WitnessVersion::V2, &[7; 8]);
+        assert_eq!(program.witness_version(), Some(WitnessVersion::V2));
+        assert_eq!(program.witness_program(), Some(&[7; 8][..]));
+        assert_eq!(
+            bitcoin::Script::from_bytes(program.as_bytes()).witness_version(),
+            Some(bitcoin::WitnessVersion::V2)
+        );
+        assert_eq!(ScriptBuf::new_p2sh(&[1; 20]).witness_version(), None);
+        assert_eq!(ScriptBuf::new_p2sh(&[1; 20]).witness_program(), None);
+    }
+
+    #[test]
+    fn op_return() {
+        let commitment = [0xc0; 32];
+        let long = [0x11; 80];
+        let script = ScriptBuf::new_op_return(&[b"arch", &commitment, &long, &[]]).unwrap();
+        let expected = bitcoin::script::Builder::new()
+            .push_opcode(bitcoin::opcodes::all::OP_RETURN)
+            .push_slice(b"arch")
+            .push_slice(commitment)
+            .push_slice(<&bitcoin::script::PushBytes>::try_from(&long[..]).unwrap())
+            .push_slice([0u8; 0])
+            .into_script();
+        assert_eq!(script.as_bytes(), expected.as_bytes());
+        assert_eq!(script.as_bytes()[39..41], [OP_PUSHDATA1.to_u8(), 80]);
+        assert!(script.is_op_return());
+        assert_eq!(
+            script.op_return_data(),
+            Some(vec![&b"arch"[..], &commitment, &long, &[]])
+        );
+
+        assert_eq!(
+            ScriptBuf::new_op_return(&[]).unwrap().op_return_data(),
+            Some(vec![])
+        );
+        let max = [0x22; MAX_SCRIPT_ELEMENT_SIZE];
+        let script = ScriptBuf::new_op_return(&[&max]).unwrap();
+        assert_eq!(script.op_return_data(), Some(vec![&max[..]]));
+        assert_eq!(
+            ScriptBuf::new_op_return(&[b"arch", &[0x22; MAX_SCRIPT_ELEMENT_SIZE + 1]]),
+            Err(ScriptError::PushSize)
+        );
+        assert_eq!(ScriptBuf::from(vec![0x6a, 0x51]).op_return_data(), None);
+        assert_eq!(
+            ScriptBuf::from(vec![0x6a, 0x02, 0xab]).op_return_data(),
+            None
+        );
+        assert_eq!(ScriptBuf::new_p2pkh(&[0; 20]).op_return_data(), None);
+    }
+
+    #[test]
+    fn script_hashes() {
+        let script = ScriptBuf::from_asm("OP_1 OP_ADD OP_2 OP_EQUAL").unwrap();
+        let expected = bitcoin::Script::from_bytes(script.as_bytes());
+        assert_eq!(
+            script.script_hash().to_byte_array(),
+            expected.script_hash().to_byte_array()
+        );
+        assert_eq!(
+            script.wscript_hash().to_byte_array(),
+            expected.wscript_hash().to_byte_array()
+        );
+        assert_eq!(
+            script.to_p2sh(),
+            Ok(ScriptBuf::new_p2sh(&script.script_hash().to_byte_array()))
+        );
+        assert_eq!(
+            script.to_p2wsh().as_bytes(),
+            expected.to_p2wsh().as_bytes()
+        );
+    }
 }
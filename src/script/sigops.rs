@@ -0,0 +1,198 @@
+//! Signature operation counting.
+//!
+//! Blocks are limited to [`MAX_BLOCK_SIGOPS_COST`] signature operations, counted
+//! statically from scripts like Bitcoin Core does rather than by running them. Legacy
+//! sigops cost [`WITNESS_SCALE_FACTOR`] each and witness sigops 1, see
+//! [`Transaction::sigop_cost`](crate::transaction::Transaction::sigop_cost).
+//!
+//! [`MAX_BLOCK_SIGOPS_COST`]: crate::constants::MAX_BLOCK_SIGOPS_COST
+//! [`WITNESS_SCALE_FACTOR`]: crate::constants::WITNESS_SCALE_FACTOR
+
+use crate::constants::MAX_PUBKEYS_PER_MULTISIG;
+use crate::script::instructions::{decode_pushnum, Instruction};
+use crate::script::interpreter::VerifyFlags;
+use crate::script::Script;
+use opcodes::all::*;
+
+impl Script {
+    /// Counts the signature operations of the script.
+    ///
+    /// `OP_CHECKSIG` and `OP_CHECKSIGVERIFY` count 1. `OP_CHECKMULTISIG` and
+    /// `OP_CHECKMULTISIGVERIFY` count the number of keys if `accurate` is set and they
+    /// follow `OP_1` to `OP_16`, and 20 otherwise. Counting stops at an invalid push.
+    pub fn sigop_count(&self, accurate: bool) -> usize {
+        let mut count = 0;
+        let mut last_op = None;
+        for instruction in self.instructions() {
+            let op = match instruction {
+                Ok(Instruction::Op(op)) => op,
+                Ok(Instruction::PushBytes(_)) => {
+                    last_op = None;
+                    continue;
+                }
+                Err(_) => break,
+            };
+            count += match op {
+                OP_CHECKSIG | OP_CHECKSIGVERIFY => 1,
+                OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => {
+                    match last_op.and_then(decode_pushnum) {
+                        Some(n) if accurate => n as usize,
+                        _ => MAX_PUBKEYS_PER_MULTISIG,
+                    }
+                }
+                _ => 0,
+            };
+            last_op = Some(op);
+        }
+        count
+    }
+
+    /// Counts the signature operations of the redeem script pushed last by `script_sig`
+    /// if this is a P2SH output script, or the signature operations of this script
+    /// otherwise.
+    ///
+    /// Returns 0 if `script_sig` isn't push only, since such a spend is invalid anyway.
+    pub fn p2sh_sigop_count(&self, script_sig: &Script) -> usize {
+        if !self.is_p2sh() {
+            return self.sigop_count(true);
+        }
+        let mut redeem_script: &[u8] = &[];
+        for instruction in script_sig.instructions() {
+            match instruction {
+                Ok(Instruction::PushBytes(data)) => redeem_script = data,
+                Ok(Instruction::Op(op)) if op.to_u8() <= OP_PUSHNUM_16.to_u8() => {
+                    redeem_script = &[]
+                }
+                _ => return 0,
+            }
+        }
+        Script::from_bytes(redeem_script).sigop_count(true)
+    }
+}
+
+/// Counts the signature operations of the witness programs spent by an input, either
+/// directly by `script_pubkey` or nested in P2SH, like Bitcoin Core's
+/// `CountWitnessSigOps`.
+///
+/// P2WPKH counts 1 and P2WSH the accurate count of its witness script, the last element
+/// of `witness`. Other witness versions, including taproot, count 0 as their signature
+/// operations are limited per input instead.
+pub fn witness_sigop_count(
+    script_sig: &Script,
+    script_pubkey: &Script,
+    witness: &[Vec<u8>],
+    flags: VerifyFlags,
+) -> usize {
+    if !flags.contains(VerifyFlags::WITNESS) {
+        return 0;
+    }
+    if script_pubkey.is_witness_program() {
+        return witness_program_sigop_count(script_pubkey, witness);
+    }
+    if script_pubkey.is_p2sh() && script_sig.is_push_only() {
+        let redeem_script = match script_sig.instructions().last() {
+            Some(Ok(Instruction::PushBytes(data))) => data,
+            _ => &[],
+        };
+        let redeem_script = Script::from_bytes(redeem_script);
+        if redeem_script.is_witness_program() {
+            return witness_program_sigop_count(redeem_script, witness);
+        }
+    }
+    0
+}
+
+/// Counts the signature operations of spending `program`, a witness program.
+fn witness_program_sigop_count(program: &Script, witness: &[Vec<u8>]) -> usize {
+    if program.is_p2wpkh() {
+        1
+    } else if program.is_p2wsh() {
+        witness.last().map_or(0, |witness_script| {
+            Script::from_bytes(witness_script).sigop_count(true)
+        })
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::script::ScriptBuf;
+    use crate::types::WitnessVersion;
+
+    fn script(asm: &str) -> ScriptBuf {
+        ScriptBuf::from_asm(asm).unwrap()
+    }
+
+    #[test]
+    fn count_sigops() {
+        let cases = [
+            ("", 0, 0),
+            ("OP_CHECKSIG OP_CHECKSIGVERIFY OP_CHECKSIGADD", 2, 2),
+            ("OP_CHECKMULTISIG", 20, 20),
+            ("3 OP_CHECKMULTISIGVERIFY", 20, 3),
+            ("16 OP_CHECKMULTISIG 1 OP_CHECKMULTISIG", 40, 17),
+            ("0 OP_CHECKMULTISIG", 20, 20),
+            ("<abcd> OP_CHECKMULTISIG", 20, 20),
+            ("OP_CHECKSIG 0x4c", 1, 1),
+            ("OP_CHECKSIG 0x4c05ac", 1, 1),
+        ];
+        for (asm, inaccurate, accurate) in cases {
+            let script = script(asm);
+            assert_eq!(script.sigop_count(false), inaccurate, "{}", asm);
+            assert_eq!(script.sigop_count(true), accurate, "{}", asm);
+            let expected = bitcoin::Script::from_bytes(script.as_bytes());
+            assert_eq!(expected.count_sigops_legacy(), inaccurate, "{}", asm);
+            assert_eq!(expected.count_sigops(), accurate, "{}", asm);
+        }
+    }
+
+    #[test]
+    fn count_p2sh_sigops() {
+        let redeem_script = script("2 OP_CHECKSIG OP_CHECKSIG 3 OP_CHECKMULTISIG");
+        let script_pubkey = redeem_script.to_p2sh().unwrap();
+        let script_sig = script(&format!("0 <{}>", hex::encode(redeem_script.as_bytes())));
+        assert_eq!(script_pubkey.p2sh_sigop_count(&script_sig), 5);
+        assert_eq!(redeem_script.p2sh_sigop_count(&script_sig), 5);
+
+        let not_push_only = script(&format!(
+            "OP_NOP <{}>",
+            hex::encode(redeem_script.as_bytes())
+        ));
+        assert_eq!(script_pubkey.p2sh_sigop_count(&not_push_only), 0);
+        assert_eq!(script_pubkey.p2sh_sigop_count(&script("1")), 0);
+        assert_eq!(script_pubkey.p2sh_sigop_count(&ScriptBuf::new()), 0);
+    }
+
+    #[test]
+    fn count_witness_sigops() {
+        let witness_script = script("1 OP_CHECKSIG OP_CHECKSIG 2 OP_CHECKMULTISIG");
+        let witness = vec![vec![], witness_script.clone().into_bytes()];
+        let p2wsh = witness_script.to_p2wsh();
+        let p2wpkh = ScriptBuf::new_p2wpkh(&[0; 20]);
+        let empty = ScriptBuf::new();
+        let flags = VerifyFlags::P2SH | VerifyFlags::WITNESS;
+
+        assert_eq!(witness_sigop_count(&empty, &p2wsh, &witness, flags), 4);
+        assert_eq!(witness_sigop_count(&empty, &p2wsh, &[], flags), 0);
+        assert_eq!(witness_sigop_count(&empty, &p2wpkh, &[], flags), 1);
+        assert_eq!(
+            witness_sigop_count(&empty, &p2wsh, &witness, VerifyFlags::P2SH),
+            0
+        );
+
+        let nested_sig = script(&format!("<{}>", hex::encode(p2wsh.as_bytes())));
+        let nested = p2wsh.to_p2sh().unwrap();
+        assert_eq!(
+            witness_sigop_count(&nested_sig, &nested, &witness, flags),
+            4
+        );
+        let nested_sig = script(&format!("<{}>", hex::encode(p2wpkh.as_bytes())));
+        let nested = p2wpkh.to_p2sh().unwrap();
+        assert_eq!(witness_sigop_count(&nested_sig, &nested, &[], flags), 1);
+
+        let p2tr = ScriptBuf::new_witness_program(WitnessVersion::V1, &[1; 32]);
+        assert_eq!(witness_sigop_count(&empty, &p2tr, &witness, flags), 0);
+    }
+}
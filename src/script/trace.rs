@@ -0,0 +1,308 @@
+//! Script execution traces.
+//!
+//! A trace records the state of the interpreter before every instruction of the scripts
+//! run for a spend, so that failing spends can be diagnosed after the fact. See
+//! [`verify_script_with_trace`] and [`eval_script_with_trace`].
+//!
+//! Traces can be exported with borsh, for example to be inspected off-chain, with
+//! opcodes stored as their byte.
+//!
+//! [`verify_script_with_trace`]: crate::script::interpreter::verify_script_with_trace
+//! [`eval_script_with_trace`]: crate::script::interpreter::eval_script_with_trace
+
+use crate::script::error::ScriptError;
+use crate::script::interpreter::{SigVersion, Stack};
+use borsh::{io, BorshDeserialize, BorshSerialize};
+use core::fmt;
+use opcodes::Opcode;
+
+/// The state of the interpreter right before an instruction.
+#[derive(Debug, Clone, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+pub struct TraceStep {
+    /// Byte offset of the instruction in its script.
+    pub pos: usize,
+    /// The opcode of the instruction, including push opcodes.
+    #[borsh(
+        serialize_with = "serialize_opcode",
+        deserialize_with = "deserialize_opcode"
+    )]
+    pub opcode: Opcode,
+    /// The data pushed by the instruction, if it is a push.
+    pub push: Option<Vec<u8>>,
+    /// Whether the instruction runs, rather than being skipped by an untaken branch.
+    pub executed: bool,
+    /// The stack, with the top at the end.
+    pub stack: Stack,
+    /// The alt stack, with the top at the end.
+    pub altstack: Stack,
+    /// Whether each enclosing `OP_IF` or `OP_NOTIF` branch is taken, innermost last.
+    pub conditions: Vec<bool>,
+}
+
+/// The execution of a single script.
+#[derive(Debug, Clone, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+pub struct ScriptTrace {
+    /// The script that was run.
+    pub script: Vec<u8>,
+    /// The kind of script that was run.
+    pub sig_version: SigVersion,
+    /// One step per instruction reached, in order. The last one failed if the script did.
+    pub steps: Vec<TraceStep>,
+    /// The stack once the script finished or failed.
+    pub stack: Stack,
+    /// How the script ended.
+    pub result: Result<(), ScriptError>,
+}
+
+/// The scripts run to execute or verify a spend, in the order they ran.
+///
+/// Displays as a table with one row per step.
+#[derive(Debug, Clone, Default, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+pub struct ExecutionTrace {
+    pub scripts: Vec<ScriptTrace>,
+}
+
+fn serialize_opcode<W: io::Write>(opcode: &Opcode, writer: &mut W) -> io::Result<()> {
+    opcode.to_u8().serialize(writer)
+}
+
+fn deserialize_opcode<R: io::Read>(reader: &mut R) -> io::Result<Opcode> {
+    u8::deserialize_reader(reader).map(Opcode::from)
+}
+
+impl ExecutionTrace {
+    /// Creates an empty trace.
+    pub fn new() -> Self {
+        ExecutionTrace::default()
+    }
+
+    /// Returns the first script that failed, if any.
+    pub fn failed_script(&self) -> Option<&ScriptTrace> {
+        self.scripts.iter().find(|script| script.result.is_err())
+    }
+}
+
+impl fmt::Display for ScriptTrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:?} script {}",
+            self.sig_version,
+            hex::encode(&self.script)
+        )?;
+        writeln!(
+            f,
+            "{:>5}  {:<24}  {:<4}  {:<8}  {:<40}  altstack",
+            "pos", "instruction", "exec", "branches", "stack"
+        )?;
+        for step in &self.steps {
+            let instruction = match step.push {
+                Some(ref data) if !data.is_empty() => {
+                    format!("{} {}", step.opcode, hex::encode(data))
+                }
+                _ => step.opcode.to_string(),
+            };
+            let branches: String = step
+                .conditions
+                .iter()
+                .map(|&taken| if taken { '1' } else { '0' })
+                .collect();
+            write!(
+                f,
+                "{:>5}  {:<24}  {:<4}  {:<8}  ",
+                step.pos,
+                instruction,
+                if step.executed { "yes" } else { "no" },
+                branches
+            )?;
+            // Padding only applies to strings, not to any `Display` type.
+            let stack = StackDisplay(&step.stack).to_string();
+            writeln!(f, "{:<40}  {}", stack, StackDisplay(&step.altstack))?;
+        }
+        writeln!(f, "final stack: {}", StackDisplay(&self.stack))?;
+        match self.result {
+            Ok(()) => writeln!(f, "result: ok"),
+            Err(ref err) => writeln!(f, "result: {}", err),
+        }
+    }
+}
+
+impl fmt::Display for ExecutionTrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, script) in self.scripts.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            fmt::Display::fmt(script, f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Displays stack elements as hex on a single line, with the top last and `[]` for
+/// empty elements.
+struct StackDisplay<'a>(&'a Stack);
+
+impl fmt::Display for StackDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, elem) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            if elem.is_empty() {
+                f.write_str("[]")?;
+            } else {
+                f.write_str(&hex::encode(elem))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::script::interpreter::{
+        eval_script_with_trace, verify_script_with_trace, BaseSignatureChecker, VerifyFlags,
+    };
+    use crate::script::ScriptBuf;
+    use bitcoin_hashes::{hash160, Hash};
+    use opcodes::all::*;
+
+    #[test]
+    fn trace_failing_p2sh_spend() {
+        let redeem_script = vec![
+            OP_IF.to_u8(),
+            OP_PUSHNUM_2.to_u8(),
+            OP_ELSE.to_u8(),
+            OP_PUSHNUM_3.to_u8(),
+            OP_ENDIF.to_u8(),
+            OP_PUSHNUM_3.to_u8(),
+            OP_EQUALVERIFY.to_u8(),
+            OP_PUSHNUM_1.to_u8(),
+        ];
+        let script_sig = ScriptBuf::from(
+            [
+                vec![OP_PUSHNUM_1.to_u8(), redeem_script.len() as u8],
+                redeem_script.clone(),
+            ]
+            .concat(),
+        );
+        let script_pubkey =
+            ScriptBuf::new_p2sh(&hash160::Hash::hash(&redeem_script).to_byte_array());
+
+        let mut trace = ExecutionTrace::new();
+        let result = verify_script_with_trace(
+            &script_sig,
+            &script_pubkey,
+            &[],
+            VerifyFlags::P2SH,
+            &BaseSignatureChecker,
+            &mut trace,
+        );
+        assert_eq!(result, Err(ScriptError::EqualVerify));
+
+        // The scriptSig, the scriptPubKey and then the redeem script.
+        assert_eq!(trace.scripts.len(), 3);
+        assert!(trace.scripts[..2]
+            .iter()
+            .all(|script| script.result.is_ok()));
+        assert_eq!(trace.scripts[1].stack, vec![vec![1], vec![1]]);
+
+        let redeem = trace.failed_script().unwrap();
+        assert_eq!(redeem.script, redeem_script);
+        assert_eq!(redeem.sig_version, SigVersion::Base);
+        assert_eq!(redeem.result, Err(ScriptError::EqualVerify));
+        // The operands are popped before the check fails, so look at the last step instead.
+        assert!(redeem.stack.is_empty());
+        let opcodes: Vec<Opcode> = redeem.steps.iter().map(|step| step.opcode).collect();
+        assert_eq!(
+            opcodes,
+            [
+                OP_IF,
+                OP_PUSHNUM_2,
+                OP_ELSE,
+                OP_PUSHNUM_3,
+                OP_ENDIF,
+                OP_PUSHNUM_3,
+                OP_EQUALVERIFY
+            ]
+        );
+
+        let skipped = &redeem.steps[3];
+        assert_eq!(skipped.pos, 3);
+        assert!(!skipped.executed);
+        assert_eq!(skipped.conditions, [false]);
+        assert_eq!(skipped.stack, vec![vec![2]]);
+        assert_eq!(redeem.steps[1].conditions, [true]);
+        assert_eq!(redeem.steps[6].stack, vec![vec![2], vec![3]]);
+
+        let table = trace.to_string();
+        assert!(table.contains("    6  OP_EQUALVERIFY            yes"));
+        assert!(table.contains("    3  OP_PUSHNUM_3              no    0 "));
+        assert!(table.ends_with(&format!("result: {}\n", ScriptError::EqualVerify)));
+    }
+
+    #[test]
+    fn trace_pushes_and_altstack() {
+        let script = ScriptBuf::from(vec![
+            OP_PUSHBYTES_2.to_u8(),
+            0xab,
+            0xcd,
+            OP_TOALTSTACK.to_u8(),
+            OP_PUSHBYTES_0.to_u8(),
+        ]);
+        let mut trace = ExecutionTrace::new();
+        let mut stack = Vec::new();
+        let result = eval_script_with_trace(
+            &mut stack,
+            &script,
+            VerifyFlags::NONE,
+            &BaseSignatureChecker,
+            SigVersion::Base,
+            &mut trace,
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(trace.failed_script(), None);
+
+        let steps = &trace.scripts[0].steps;
+        assert_eq!(steps[0].push, Some(vec![0xab, 0xcd]));
+        assert_eq!(steps[1].pos, 3);
+        assert_eq!(steps[1].stack, vec![vec![0xab, 0xcd]]);
+        assert_eq!(steps[2].altstack, vec![vec![0xab, 0xcd]]);
+        assert_eq!(steps[2].push, Some(vec![]));
+        assert_eq!(trace.scripts[0].stack, vec![Vec::<u8>::new()]);
+
+        let table = trace.to_string();
+        assert!(table.contains("OP_PUSHBYTES_2 abcd"));
+        assert!(table.contains("final stack: []\n"));
+    }
+
+    #[test]
+    fn borsh_round_trip() {
+        let script = ScriptBuf::from(vec![
+            OP_PUSHBYTES_1.to_u8(),
+            0x07,
+            OP_IF.to_u8(),
+            OP_RETURN.to_u8(),
+            OP_ENDIF.to_u8(),
+        ]);
+        let mut trace = ExecutionTrace::new();
+        let result = eval_script_with_trace(
+            &mut Vec::new(),
+            &script,
+            VerifyFlags::NONE,
+            &BaseSignatureChecker,
+            SigVersion::WitnessV0,
+            &mut trace,
+        );
+        assert_eq!(result, Err(ScriptError::OpReturn));
+
+        let bytes = borsh::to_vec(&trace).unwrap();
+        assert_eq!(borsh::from_slice::<ExecutionTrace>(&bytes).unwrap(), trace);
+        // The first step starts with its position and then the opcode as one byte.
+        let step = borsh::to_vec(&trace.scripts[0].steps[0]).unwrap();
+        assert_eq!(step[..9], [0, 0, 0, 0, 0, 0, 0, 0, OP_PUSHBYTES_1.to_u8()]);
+        assert!(borsh::from_slice::<ExecutionTrace>(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,707 @@
+//! Signature hashes.
+//!
+//! Computes the message committed to by signatures in legacy, segwit version 0 and
+//! taproot scripts.
+
+use crate::script::instructions::{Instruction, Instructions};
+use crate::taproot::TapLeafHash;
+use crate::transaction::{encode_compact_size, Transaction, TxOut};
+use bitcoin_hashes::{hash_newtype, sha256, sha256d, sha256t_hash_newtype, Hash, HashEngine};
+use core::cell::OnceCell;
+use core::fmt;
+use opcodes::all::OP_CODESEPARATOR;
+
+hash_newtype! {
+    /// Hash of a transaction according to the legacy signature algorithm.
+    #[hash_newtype(forward)]
+    pub struct LegacySighash(sha256d::Hash);
+    /// Hash of a transaction according to the segwit version 0 signature algorithm (BIP143).
+    #[hash_newtype(forward)]
+    pub struct SegwitV0Sighash(sha256d::Hash);
+}
+
+sha256t_hash_newtype! {
+    pub struct TapSighashTag = hash_str("TapSighash");
+    /// Hash of a transaction according to the taproot signature algorithm (BIP341).
+    #[hash_newtype(forward)]
+    pub struct TapSighash(_);
+}
+
+/// Signs all inputs and outputs, only valid in taproot where it is implied by a
+/// 64 byte signature.
+pub const SIGHASH_DEFAULT: u32 = 0x00;
+
+/// Signs all inputs and outputs.
+pub const SIGHASH_ALL: u32 = 0x01;
+/// Signs all inputs and none of the outputs.
+pub const SIGHASH_NONE: u32 = 0x02;
+/// Signs all inputs and the output with the same index as the signed input.
+pub const SIGHASH_SINGLE: u32 = 0x03;
+/// Flag combined with one of the above to sign only the input being spent.
+pub const SIGHASH_ANYONECANPAY: u32 = 0x80;
+
+/// An input of the transaction spends an outpoint whose txid isn't 64 hex characters,
+/// so the outpoints signature hashes commit to can't be serialized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidTxidError {
+    /// The index of the first input with an invalid txid.
+    pub input: usize,
+}
+
+impl fmt::Display for InvalidTxidError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "input {} spends an outpoint with an invalid txid",
+            self.input
+        )
+    }
+}
+
+/// Computes signature hashes of a transaction's inputs, caching the intermediate
+/// hashes shared between inputs.
+#[derive(Debug)]
+pub struct SighashCache<'a> {
+    tx: &'a Transaction,
+    segwit: OnceCell<SegwitCache>,
+    taproot: OnceCell<TaprootCache>,
+}
+
+/// BIP143 hashes shared by all inputs of a transaction.
+#[derive(Debug)]
+struct SegwitCache {
+    prevouts: sha256d::Hash,
+    sequences: sha256d::Hash,
+    outputs: sha256d::Hash,
+}
+
+/// BIP341 hashes shared by all inputs of a transaction.
+#[derive(Debug)]
+struct TaprootCache {
+    prevouts: sha256::Hash,
+    amounts: sha256::Hash,
+    script_pubkeys: sha256::Hash,
+    sequences: sha256::Hash,
+    outputs: sha256::Hash,
+}
+
+impl<'a> SighashCache<'a> {
+    /// Creates a cache for computing the signature hashes of `tx`.
+    ///
+    /// Fails if the txid of any input isn't 64 hex characters, since every signature
+    /// hash commits to at least one outpoint.
+    pub fn new(tx: &'a Transaction) -> Result<Self, InvalidTxidError> {
+        if let Some(input) = tx.input.iter().position(|input| !input.has_valid_txid()) {
+            return Err(InvalidTxidError { input });
+        }
+        Ok(SighashCache {
+            tx,
+            segwit: OnceCell::new(),
+            taproot: OnceCell::new(),
+        })
+    }
+
+    /// Returns the transaction the signature hashes are computed for.
+    pub fn transaction(&self) -> &'a Transaction {
+        self.tx
+    }
+
+    /// Returns the legacy signature hash of input `input_index` for `script_code`.
+    ///
+    /// Any `OP_CODESEPARATOR` in `script_code` is removed before hashing. Like Bitcoin
+    /// Core, this returns the hash `0x00..01` when `input_index` is out of range or
+    /// when `SIGHASH_SINGLE` is used without a matching output.
+    pub fn legacy_signature_hash(
+        &self,
+        input_index: usize,
+        script_code: &[u8],
+        sighash_type: u32,
+    ) -> LegacySighash {
+        let tx = self.tx;
+        let base_type = sighash_type & 0x1f;
+        let anyone_can_pay = sighash_type & SIGHASH_ANYONECANPAY != 0;
+
+        if input_index >= tx.input.len()
+            || (base_type == SIGHASH_SINGLE && input_index >= tx.output.len())
+        {
+            let mut one = [0u8; 32];
+            one[0] = 1;
+            return LegacySighash::from_byte_array(one);
+        }
+
+        let mut engine = LegacySighash::engine();
+        engine.input(&tx.version.to_le_bytes());
+
+        let inputs: Vec<usize> = if anyone_can_pay {
+            vec![input_index]
+        } else {
+            (0..tx.input.len()).collect()
+        };
+        engine.input(&encode_compact_size(inputs.len()));
+        for index in inputs {
+            let input = &tx.input[index];
+            engine.input(&input.outpoint_bytes());
+            if index == input_index {
+                let script_code = remove_codeseparators(script_code);
+                engine.input(&encode_compact_size(script_code.len()));
+                engine.input(&script_code);
+            } else {
+                engine.input(&[0]);
+            }
+            if index != input_index && (base_type == SIGHASH_NONE || base_type == SIGHASH_SINGLE) {
+                engine.input(&0u32.to_le_bytes());
+            } else {
+                engine.input(&input.sequence.to_le_bytes());
+            }
+        }
+
+        match base_type {
+            SIGHASH_NONE => engine.input(&[0]),
+            SIGHASH_SINGLE => {
+                engine.input(&encode_compact_size(input_index + 1));
+                for _ in 0..input_index {
+                    // A "null" output: an amount of -1 and an empty script.
+                    engine.input(&u64::MAX.to_le_bytes());
+                    engine.input(&[0]);
+                }
+                engine.input(&tx.output[input_index].to_bytes());
+            }
+            _ => {
+                engine.input(&encode_compact_size(tx.output.len()));
+                for output in tx.output.iter() {
+                    engine.input(&output.to_bytes());
+                }
+            }
+        }
+
+        engine.input(&tx.lock_time.to_le_bytes());
+        engine.input(&sighash_type.to_le_bytes());
+        LegacySighash::from_engine(engine)
+    }
+
+    /// Returns the BIP143 signature hash of input `input_index`, which spends an
+    /// output worth `amount` satoshis.
+    ///
+    /// # Panics
+    ///
+    /// If `input_index` is out of range.
+    pub fn segwit_v0_signature_hash(
+        &self,
+        input_index: usize,
+        script_code: &[u8],
+        amount: u64,
+        sighash_type: u32,
+    ) -> SegwitV0Sighash {
+        let tx = self.tx;
+        let input = &tx.input[input_index];
+        let base_type = sighash_type & 0x1f;
+        let anyone_can_pay = sighash_type & SIGHASH_ANYONECANPAY != 0;
+        let cache = self.segwit_cache();
+        let zero = sha256d::Hash::all_zeros();
+
+        let prevouts = if anyone_can_pay { zero } else { cache.prevouts };
+        let sequences =
+            if anyone_can_pay || base_type == SIGHASH_SINGLE || base_type == SIGHASH_NONE {
+                zero
+            } else {
+                cache.sequences
+            };
+        let outputs = if base_type != SIGHASH_SINGLE && base_type != SIGHASH_NONE {
+            cache.outputs
+        } else if base_type == SIGHASH_SINGLE && input_index < tx.output.len() {
+            sha256d::Hash::hash(&tx.output[input_index].to_bytes())
+        } else {
+            zero
+        };
+
+        let mut engine = SegwitV0Sighash::engine();
+        engine.input(&tx.version.to_le_bytes());
+        engine.input(prevouts.as_byte_array());
+        engine.input(sequences.as_byte_array());
+        engine.input(&input.outpoint_bytes());
+        engine.input(&encode_compact_size(script_code.len()));
+        engine.input(script_code);
+        engine.input(&amount.to_le_bytes());
+        engine.input(&input.sequence.to_le_bytes());
+        engine.input(outputs.as_byte_array());
+        engine.input(&tx.lock_time.to_le_bytes());
+        engine.input(&sighash_type.to_le_bytes());
+        SegwitV0Sighash::from_engine(engine)
+    }
+
+    /// Returns the BIP341 signature hash of input `input_index`, given the outputs spent
+    /// by all inputs of the transaction.
+    ///
+    /// For script path spends, `leaf_hash_code_separator` is the hash of the executed leaf
+    /// and the opcode position of the last executed `OP_CODESEPARATOR`, or `u32::MAX`.
+    /// The annex includes its `0x50` prefix.
+    ///
+    /// Returns `None` if `sighash_type` is undefined, or if it is `SIGHASH_SINGLE` without
+    /// a matching output.
+    ///
+    /// # Panics
+    ///
+    /// If `input_index` is out of range, or if `prevouts` doesn't have one output per
+    /// input. The hashes of `prevouts` are cached, so it must be the same across calls.
+    pub fn taproot_signature_hash(
+        &self,
+        input_index: usize,
+        prevouts: &[TxOut],
+        annex: Option<&[u8]>,
+        leaf_hash_code_separator: Option<(TapLeafHash, u32)>,
+        sighash_type: u32,
+    ) -> Option<TapSighash> {
+        let tx = self.tx;
+        assert_eq!(prevouts.len(), tx.input.len());
+        let input = &tx.input[input_index];
+        if !matches!(sighash_type, 0x00..=0x03 | 0x81..=0x83) {
+            return None;
+        }
+        let output_type = if sighash_type == SIGHASH_DEFAULT {
+            SIGHASH_ALL
+        } else {
+            sighash_type & 0x03
+        };
+        let anyone_can_pay = sighash_type & SIGHASH_ANYONECANPAY != 0;
+        let cache = self.taproot_cache(prevouts);
+
+        let mut engine = TapSighash::engine();
+        // Epoch, allowing the algorithm to be replaced without colliding.
+        engine.input(&[0]);
+        engine.input(&[sighash_type as u8]);
+        engine.input(&tx.version.to_le_bytes());
+        engine.input(&tx.lock_time.to_le_bytes());
+        if !anyone_can_pay {
+            engine.input(cache.prevouts.as_byte_array());
+            engine.input(cache.amounts.as_byte_array());
+            engine.input(cache.script_pubkeys.as_byte_array());
+            engine.input(cache.sequences.as_byte_array());
+        }
+        if output_type == SIGHASH_ALL {
+            engine.input(cache.outputs.as_byte_array());
+        }
+
+        let ext_flag = leaf_hash_code_separator.is_some() as u8;
+        engine.input(&[(ext_flag << 1) | annex.is_some() as u8]);
+        if anyone_can_pay {
+            engine.input(&input.outpoint_bytes());
+            engine.input(&prevouts[input_index].to_bytes());
+            engine.input(&input.sequence.to_le_bytes());
+        } else {
+            engine.input(&(input_index as u32).to_le_bytes());
+        }
+        if let Some(annex) = annex {
+            let mut annex_engine = sha256::Hash::engine();
+            annex_engine.input(&encode_compact_size(annex.len()));
+            annex_engine.input(annex);
+            engine.input(sha256::Hash::from_engine(annex_engine).as_byte_array());
+        }
+        if output_type == SIGHASH_SINGLE {
+            let output = tx.output.get(input_index)?;
+            engine.input(sha256::Hash::hash(&output.to_bytes()).as_byte_array());
+        }
+
+        if let Some((leaf_hash, code_separator_pos)) = leaf_hash_code_separator {
+            engine.input(leaf_hash.as_byte_array());
+            // Key version, for future public key types.
+            engine.input(&[0]);
+            engine.input(&code_separator_pos.to_le_bytes());
+        }
+        Some(TapSighash::from_engine(engine))
+    }
+
+    fn segwit_cache(&self) -> &SegwitCache {
+        self.segwit.get_or_init(|| {
+            let mut prevouts = sha256d::Hash::engine();
+            let mut sequences = sha256d::Hash::engine();
+            for input in self.tx.input.iter() {
+                prevouts.input(&input.outpoint_bytes());
+                sequences.input(&input.sequence.to_le_bytes());
+            }
+            let mut outputs = sha256d::Hash::engine();
+            for output in self.tx.output.iter() {
+                outputs.input(&output.to_bytes());
+            }
+
+            SegwitCache {
+                prevouts: sha256d::Hash::from_engine(prevouts),
+                sequences: sha256d::Hash::from_engine(sequences),
+                outputs: sha256d::Hash::from_engine(outputs),
+            }
+        })
+    }
+
+    fn taproot_cache(&self, prevouts: &[TxOut]) -> &TaprootCache {
+        self.taproot.get_or_init(|| {
+            let mut outpoints = sha256::Hash::engine();
+            let mut sequences = sha256::Hash::engine();
+            for input in self.tx.input.iter() {
+                outpoints.input(&input.outpoint_bytes());
+                sequences.input(&input.sequence.to_le_bytes());
+            }
+            let mut amounts = sha256::Hash::engine();
+            let mut script_pubkeys = sha256::Hash::engine();
+            for prevout in prevouts {
+                amounts.input(&prevout.amount.to_le_bytes());
+                script_pubkeys.input(&encode_compact_size(prevout.script_pubkey.len()));
+                script_pubkeys.input(&prevout.script_pubkey);
+            }
+            let mut outputs = sha256::Hash::engine();
+            for output in self.tx.output.iter() {
+                outputs.input(&output.to_bytes());
+            }
+
+            TaprootCache {
+                prevouts: sha256::Hash::from_engine(outpoints),
+                amounts: sha256::Hash::from_engine(amounts),
+                script_pubkeys: sha256::Hash::from_engine(script_pubkeys),
+                sequences: sha256::Hash::from_engine(sequences),
+                outputs: sha256::Hash::from_engine(outputs),
+            }
+        })
+    }
+}
+
+/// Removes every `OP_CODESEPARATOR` from `script`, keeping anything after a parse
+/// error untouched.
+fn remove_codeseparators(script: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(script.len());
+    let mut instructions = Instructions::from(script);
+    let mut start = 0;
+    loop {
+        let pos = script.len() - instructions.data.len();
+        match instructions.next() {
+            Some(Ok(Instruction::Op(OP_CODESEPARATOR))) => {
+                result.extend_from_slice(&script[start..pos]);
+                start = pos + 1;
+            }
+            Some(Ok(_)) => {}
+            Some(Err(_)) | None => break,
+        }
+    }
+    result.extend_from_slice(&script[start..]);
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transaction::{TxIn, TxOut};
+    use bitcoin::consensus::deserialize;
+    use bitcoin::sighash::{EcdsaSighashType, SighashCache as BitcoinSighashCache};
+
+    fn test_transaction() -> Transaction {
+        let input = |txid: &str, vout: u32, sequence: u32| TxIn {
+            txid: txid.to_string(),
+            vout,
+            script_sig: vec![0x51],
+            sequence,
+            witness: vec![],
+        };
+        let output = |amount: u64, script_pubkey: &[u8]| TxOut {
+            amount,
+            script_pubkey: script_pubkey.to_vec(),
+        };
+
+        Transaction {
+            version: 2,
+            input: vec![
+                input(
+                    "9f96ade4b41d5433f4eda31e1738ec2b36f6e7d1420d94a6af99801a88f7f7ff",
+                    0,
+                    0xffffffee,
+                ),
+                input(
+                    "8ac60eb9575db5b2d987e29f301b5b819ea83a5c6579d282d189cc04b8e151ef",
+                    1,
+                    0xffffffff,
+                ),
+                input(
+                    "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16",
+                    7,
+                    0x00000010,
+                ),
+            ],
+            output: vec![
+                output(
+                    112340000,
+                    &hex::decode("76a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac").unwrap(),
+                ),
+                output(
+                    223450000,
+                    &hex::decode("76a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac").unwrap(),
+                ),
+            ],
+            lock_time: 17,
+        }
+    }
+
+    fn to_bitcoin(tx: &Transaction) -> bitcoin::Transaction {
+        deserialize(&tx.to_bytes_without_witness()).unwrap()
+    }
+
+    #[test]
+    fn legacy_sighash_matches_rust_bitcoin() {
+        let tx = test_transaction();
+        let expected_tx = to_bitcoin(&tx);
+        let cache = SighashCache::new(&tx).unwrap();
+        let expected_cache = BitcoinSighashCache::new(&expected_tx);
+        let script_code =
+            hex::decode("76a914000102030405060708090a0b0c0d0e0f1011121388acab51").unwrap();
+        // rust-bitcoin doesn't remove code separators itself.
+        let stripped_code = remove_codeseparators(&script_code);
+
+        let mut one = [0u8; 32];
+        one[0] = 1;
+
+        for input_index in 0..3 {
+            for sighash_type in [
+                0x00, 0x01, 0x02, 0x03, 0x04, 0x81, 0x82, 0x83, 0x41, 0xdeadbe03,
+            ] {
+                let expected = if sighash_type & 0x1f == SIGHASH_SINGLE && input_index >= 2 {
+                    one
+                } else {
+                    expected_cache
+                        .legacy_signature_hash(
+                            input_index,
+                            bitcoin::Script::from_bytes(&stripped_code),
+                            sighash_type,
+                        )
+                        .unwrap()
+                        .to_byte_array()
+                };
+
+                let sighash = cache.legacy_signature_hash(input_index, &script_code, sighash_type);
+                assert_eq!(sighash.to_byte_array(), expected);
+            }
+        }
+
+        let sighash = cache.legacy_signature_hash(3, &script_code, SIGHASH_ALL);
+        assert_eq!(sighash.to_byte_array(), one);
+    }
+
+    #[test]
+    fn segwit_v0_sighash_matches_rust_bitcoin() {
+        let tx = test_transaction();
+        let expected_tx = to_bitcoin(&tx);
+        let cache = SighashCache::new(&tx).unwrap();
+        let mut expected_cache = BitcoinSighashCache::new(&expected_tx);
+        let script_code =
+            hex::decode("76a9141d0f172a0ecb48aee1be1f2687d2963ae33f71a188ac").unwrap();
+
+        for input_index in 0..3 {
+            for sighash_type in [0x01, 0x02, 0x03, 0x81, 0x82, 0x83] {
+                let expected = expected_cache
+                    .p2wsh_signature_hash(
+                        input_index,
+                        bitcoin::Script::from_bytes(&script_code),
+                        bitcoin::Amount::from_sat(600_000_000),
+                        EcdsaSighashType::from_consensus(sighash_type),
+                    )
+                    .unwrap();
+
+                let sighash = cache.segwit_v0_signature_hash(
+                    input_index,
+                    &script_code,
+                    600_000_000,
+                    sighash_type,
+                );
+                assert_eq!(sighash.to_byte_array(), expected.to_byte_array());
+            }
+        }
+    }
+
+    #[test]
+    fn bip143_native_p2wpkh() {
+        // Second input of the native P2WPKH example in BIP143.
+        let tx = Transaction {
+            version: 1,
+            input: vec![
+                TxIn {
+                    txid: "9f96ade4b41d5433f4eda31e1738ec2b36f6e7d1420d94a6af99801a88f7f7ff"
+                        .to_string(),
+                    vout: 0,
+                    script_sig: vec![],
+                    sequence: 0xffffffee,
+                    witness: vec![],
+                },
+                TxIn {
+                    txid: "8ac60eb9575db5b2d987e29f301b5b819ea83a5c6579d282d189cc04b8e151ef"
+                        .to_string(),
+                    vout: 1,
+                    script_sig: vec![],
+                    sequence: 0xffffffff,
+                    witness: vec![],
+                },
+            ],
+            output: vec![
+                TxOut {
+                    amount: 112340000,
+                    script_pubkey: hex::decode(
+                        "76a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac",
+                    )
+                    .unwrap(),
+                },
+                TxOut {
+                    amount: 223450000,
+                    script_pubkey: hex::decode(
+                        "76a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac",
+                    )
+                    .unwrap(),
+                },
+            ],
+            lock_time: 17,
+        };
+        let script_code =
+            hex::decode("76a9141d0f172a0ecb48aee1be1f2687d2963ae33f71a188ac").unwrap();
+
+        let sighash = SighashCache::new(&tx).unwrap().segwit_v0_signature_hash(
+            1,
+            &script_code,
+            600_000_000,
+            SIGHASH_ALL,
+        );
+        assert_eq!(
+            hex::encode(sighash.to_byte_array()),
+            "c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670"
+        );
+    }
+
+    #[test]
+    fn bip341_key_path_sighashes() {
+        let vectors: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/data/bip341_tests.json")).unwrap();
+
+        for vector in vectors["keyPathSpending"].as_array().unwrap() {
+            let tx = Transaction::from_hex(vector["auxiliary"]["fullySignedTx"].as_str().unwrap());
+            let prevouts: Vec<TxOut> = vector["given"]["utxosSpent"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|utxo| TxOut {
+                    amount: utxo["amountSats"].as_u64().unwrap(),
+                    script_pubkey: hex::decode(utxo["scriptPubKey"].as_str().unwrap()).unwrap(),
+                })
+                .collect();
+
+            let cache = SighashCache::new(&tx).unwrap();
+            for input in vector["inputSpending"].as_array().unwrap() {
+                let input_index = input["given"]["txinIndex"].as_u64().unwrap() as usize;
+                let sighash_type = input["given"]["hashType"].as_u64().unwrap() as u32;
+                let sighash = cache
+                    .taproot_signature_hash(input_index, &prevouts, None, None, sighash_type)
+                    .unwrap();
+                assert_eq!(
+                    hex::encode(sighash.to_byte_array()),
+                    input["intermediary"]["sigHash"].as_str().unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn taproot_sighash_matches_rust_bitcoin() {
+        use bitcoin::sighash::{Annex, Prevouts, TapSighashType};
+        use bitcoin::taproot::TapLeafHash as BitcoinLeafHash;
+
+        let tx = test_transaction();
+        let bitcoin_tx = to_bitcoin(&tx);
+        let prevouts: Vec<TxOut> = (0..tx.input.len())
+            .map(|i| TxOut {
+                amount: 100_000 * (i as u64 + 1),
+                script_pubkey: [&[0x51, 0x20][..], &[i as u8; 32]].concat(),
+            })
+            .collect();
+        let bitcoin_prevouts: Vec<bitcoin::TxOut> = prevouts
+            .iter()
+            .map(|prevout| deserialize(&prevout.to_bytes()).unwrap())
+            .collect();
+
+        let leaf_hash = TapLeafHash::from_script(&[0x51], 0xc0);
+        let annex = [0x50, 0x01, 0x02];
+        let cache = SighashCache::new(&tx).unwrap();
+        let mut bitcoin_cache = BitcoinSighashCache::new(&bitcoin_tx);
+        for sighash_type in [0x00, 0x01, 0x02, 0x03, 0x81, 0x82, 0x83] {
+            for input_index in 0..tx.input.len() {
+                for (annex, leaf) in [
+                    (None, None),
+                    (Some(&annex[..]), None),
+                    (None, Some((leaf_hash, u32::MAX))),
+                    (Some(&annex[..]), Some((leaf_hash, 7))),
+                ] {
+                    let sighash = cache.taproot_signature_hash(
+                        input_index,
+                        &prevouts,
+                        annex,
+                        leaf,
+                        sighash_type,
+                    );
+                    let expected = bitcoin_cache
+                        .taproot_signature_hash(
+                            input_index,
+                            &Prevouts::All(&bitcoin_prevouts),
+                            annex.map(|annex| Annex::new(annex).unwrap()),
+                            leaf.map(|(leaf_hash, pos)| {
+                                (
+                                    BitcoinLeafHash::from_byte_array(leaf_hash.to_byte_array()),
+                                    pos,
+                                )
+                            }),
+                            TapSighashType::from_consensus_u8(sighash_type as u8).unwrap(),
+                        )
+                        .ok();
+                    // Both refuse SIGHASH_SINGLE without a matching output.
+                    assert_eq!(
+                        sighash.map(|sighash| sighash.to_byte_array()),
+                        expected.map(|sighash| sighash.to_byte_array())
+                    );
+                }
+            }
+        }
+
+        // Undefined types, and SIGHASH_SINGLE without a matching output, have no sighash.
+        assert_eq!(
+            cache.taproot_signature_hash(0, &prevouts, None, None, 0x04),
+            None
+        );
+        assert_eq!(
+            cache.taproot_signature_hash(2, &prevouts, None, None, 0x03),
+            None
+        );
+    }
+
+    #[test]
+    fn invalid_txids() {
+        let mut tx = test_transaction();
+        for txid in [
+            "".to_string(),
+            "11".repeat(31),
+            "11".repeat(33),
+            "zz".repeat(32),
+        ] {
+            tx.input[1].txid = txid;
+            assert_eq!(
+                SighashCache::new(&tx).unwrap_err(),
+                InvalidTxidError { input: 1 }
+            );
+        }
+        tx.input[1].txid = "AB".repeat(32);
+        assert!(SighashCache::new(&tx).is_ok());
+    }
+
+    #[test]
+    fn codeseparators_are_removed() {
+        let script = hex::decode("51ab52abab4c01ab").unwrap();
+        assert_eq!(
+            remove_codeseparators(&script),
+            hex::decode("51524c01ab").unwrap()
+        );
+
+        // Truncated pushes are kept as they are.
+        let script = hex::decode("ab4cffab").unwrap();
+        assert_eq!(
+            remove_codeseparators(&script),
+            hex::decode("4cffab").unwrap()
+        );
+    }
+}
@@ -0,0 +1,203 @@
+//! Block subsidy and money supply calculations.
+
+use crate::constants::{COIN_VALUE, MAX_MONEY, SUBSIDY_HALVING_INTERVAL};
+use crate::params::Params;
+use crate::transaction::Transaction;
+use crate::types::Network;
+use core::fmt;
+
+/// Subsidy of the blocks before the first halving, in satoshis.
+const INITIAL_SUBSIDY: u64 = 50 * COIN_VALUE;
+
+/// Returns the new coins a block at `height` may create, in satoshis.
+pub fn block_subsidy(height: u32, network: Network) -> u64 {
+    subsidy_with_interval(height, Params::new(network).subsidy_halving_interval)
+}
+
+/// Returns the total number of satoshis created by the subsidies of all blocks up to
+/// and including `height`, following the mainnet halving schedule.
+///
+/// This includes the (unspendable) output of the genesis block.
+pub fn total_supply_at(height: u32) -> u64 {
+    let blocks = height as u64 + 1;
+    let interval = SUBSIDY_HALVING_INTERVAL as u64;
+
+    let mut total = 0;
+    let mut era_start = 0;
+    while era_start < blocks {
+        let subsidy = subsidy_with_interval(era_start as u32, SUBSIDY_HALVING_INTERVAL);
+        if subsidy == 0 {
+            break;
+        }
+        let era_blocks = interval.min(blocks - era_start);
+        total += era_blocks * subsidy;
+        era_start += interval;
+    }
+    total
+}
+
+fn subsidy_with_interval(height: u32, interval: u32) -> u64 {
+    let halvings = height / interval;
+    // Force the subsidy to zero once the right shift would be undefined.
+    if halvings >= 64 {
+        return 0;
+    }
+    INITIAL_SUBSIDY >> halvings
+}
+
+/// Ways a coinbase transaction might claim an invalid amount.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CoinbaseError {
+    /// The transaction is not a coinbase.
+    NotCoinbase,
+    /// An output, or the sum of all outputs, is above [`MAX_MONEY`].
+    ValueOutOfRange,
+    /// The outputs claim more than the block subsidy plus fees.
+    ExceedsAllowed { claimed: u64, allowed: u64 },
+}
+
+impl fmt::Display for CoinbaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CoinbaseError::NotCoinbase => f.write_str("transaction is not a coinbase"),
+            CoinbaseError::ValueOutOfRange => f.write_str("coinbase output value out of range"),
+            CoinbaseError::ExceedsAllowed { claimed, allowed } => write!(
+                f,
+                "coinbase claims {} satoshis but only {} are allowed",
+                claimed, allowed
+            ),
+        }
+    }
+}
+
+/// Checks that a coinbase at `height` doesn't pay out more than the block subsidy plus
+/// the `fees` of the other transactions in its block.
+pub fn check_coinbase_value(
+    coinbase: &Transaction,
+    height: u32,
+    fees: u64,
+    network: Network,
+) -> Result<(), CoinbaseError> {
+    if !coinbase.is_coinbase() {
+        return Err(CoinbaseError::NotCoinbase);
+    }
+
+    let mut claimed: u64 = 0;
+    for output in coinbase.output.iter() {
+        if output.amount > MAX_MONEY {
+            return Err(CoinbaseError::ValueOutOfRange);
+        }
+        claimed += output.amount;
+        if claimed > MAX_MONEY {
+            return Err(CoinbaseError::ValueOutOfRange);
+        }
+    }
+
+    let allowed = block_subsidy(height, network).saturating_add(fees);
+    if claimed > allowed {
+        return Err(CoinbaseError::ExceedsAllowed { claimed, allowed });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transaction::{TxIn, TxOut};
+
+    fn coinbase(amounts: &[u64]) -> Transaction {
+        Transaction {
+            version: 2,
+            input: vec![TxIn {
+                txid: "00".repeat(32),
+                vout: u32::MAX,
+                script_sig: vec![3, 0x40, 0x0d, 0x03],
+                sequence: u32::MAX,
+                witness: vec![vec![0; 32]],
+            }],
+            output: amounts
+                .iter()
+                .map(|amount| TxOut {
+                    amount: *amount,
+                    script_pubkey: vec![0x6a],
+                })
+                .collect(),
+            lock_time: 0,
+        }
+    }
+
+    #[test]
+    fn block_subsidy_halves() {
+        let test_cases = vec![
+            (0, 5_000_000_000),
+            (209_999, 5_000_000_000),
+            (210_000, 2_500_000_000),
+            (420_000, 1_250_000_000),
+            (630_000, 625_000_000),
+            (840_000, 312_500_000),
+            (6_929_999, 1),
+            (6_930_000, 0),
+            (u32::MAX, 0),
+        ];
+
+        for (height, expected_subsidy) in test_cases {
+            assert_eq!(block_subsidy(height, Network::Bitcoin), expected_subsidy);
+        }
+
+        assert_eq!(block_subsidy(149, Network::Regtest), 5_000_000_000);
+        assert_eq!(block_subsidy(150, Network::Regtest), 2_500_000_000);
+        assert_eq!(block_subsidy(150, Network::Testnet), 5_000_000_000);
+    }
+
+    #[test]
+    fn total_supply() {
+        assert_eq!(total_supply_at(0), 50 * COIN_VALUE);
+        assert_eq!(total_supply_at(209_999), 210_000 * 50 * COIN_VALUE);
+        assert_eq!(
+            total_supply_at(210_000),
+            210_000 * 50 * COIN_VALUE + 25 * COIN_VALUE
+        );
+        assert_eq!(total_supply_at(u32::MAX), 2_099_999_997_690_000);
+        assert!(total_supply_at(u32::MAX) <= MAX_MONEY);
+    }
+
+    #[test]
+    fn coinbase_value() {
+        let height = 840_000;
+        let subsidy = block_subsidy(height, Network::Bitcoin);
+
+        let tx = coinbase(&[subsidy - 1000, 1000]);
+        assert_eq!(
+            check_coinbase_value(&tx, height, 0, Network::Bitcoin),
+            Ok(())
+        );
+
+        let tx = coinbase(&[subsidy, 500]);
+        assert_eq!(
+            check_coinbase_value(&tx, height, 0, Network::Bitcoin),
+            Err(CoinbaseError::ExceedsAllowed {
+                claimed: subsidy + 500,
+                allowed: subsidy
+            })
+        );
+        assert_eq!(
+            check_coinbase_value(&tx, height, 500, Network::Bitcoin),
+            Ok(())
+        );
+
+        let tx = coinbase(&[MAX_MONEY, 1]);
+        assert_eq!(
+            check_coinbase_value(&tx, height, u64::MAX, Network::Bitcoin),
+            Err(CoinbaseError::ValueOutOfRange)
+        );
+
+        let mut tx = coinbase(&[subsidy]);
+        tx.input[0].vout = 0;
+        assert_eq!(
+            check_coinbase_value(&tx, height, 0, Network::Bitcoin),
+            Err(CoinbaseError::NotCoinbase)
+        );
+    }
+}
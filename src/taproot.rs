@@ -0,0 +1,1046 @@
+//! Taproot.
+//!
+//! Tagged hashes and constants of BIP341 used to commit to script trees,
+//! [`TaprootBuilder`] to build a tree and the control blocks spending its leaves, and the
+//! BIP340 [`Signature`]s of taproot spends.
+
+use bitcoin_hashes::{sha256t_hash_newtype, Hash, HashEngine};
+use core::cmp::Reverse;
+use core::fmt;
+#[cfg(feature = "signing")]
+use secp256k1::Keypair;
+use secp256k1::{schnorr, Message, Parity, Scalar, Secp256k1, XOnlyPublicKey};
+use std::collections::BinaryHeap;
+
+#[cfg(feature = "signing")]
+use crate::key::PrivateKey;
+use crate::key::TweakedPublicKey;
+use crate::script::{Script, ScriptBuf};
+use crate::sighash::SIGHASH_DEFAULT;
+use crate::transaction::encode_compact_size;
+
+/// Leaf version of tapscript (BIP342).
+pub const TAPROOT_LEAF_TAPSCRIPT: u8 = 0xc0;
+/// Mask of the leaf version in the first byte of a control block, the rest being the
+/// parity of the output key.
+pub const TAPROOT_LEAF_MASK: u8 = 0xfe;
+/// First byte of the annex, an optional last witness element reserved for future use.
+pub const TAPROOT_ANNEX_PREFIX: u8 = 0x50;
+/// Size of a control block without any merkle path node.
+pub const TAPROOT_CONTROL_BASE_SIZE: usize = 33;
+/// Size of a merkle path node in a control block.
+pub const TAPROOT_CONTROL_NODE_SIZE: usize = 32;
+/// Maximum number of merkle path nodes in a control block.
+pub const TAPROOT_CONTROL_MAX_NODE_COUNT: usize = 128;
+/// Maximum size of a control block.
+pub const TAPROOT_CONTROL_MAX_SIZE: usize =
+    TAPROOT_CONTROL_BASE_SIZE + TAPROOT_CONTROL_NODE_SIZE * TAPROOT_CONTROL_MAX_NODE_COUNT;
+
+sha256t_hash_newtype! {
+    pub struct TapLeafTag = hash_str("TapLeaf");
+    /// Hash of a leaf script and its version.
+    #[hash_newtype(forward)]
+    pub struct TapLeafHash(_);
+
+    pub struct TapBranchTag = hash_str("TapBranch");
+    /// Hash of a node in a script tree, either a leaf or a branch of two nodes.
+    #[hash_newtype(forward)]
+    pub struct TapNodeHash(_);
+
+    pub struct TapTweakTag = hash_str("TapTweak");
+    /// Hash tweaking an internal key into an output key.
+    #[hash_newtype(forward)]
+    pub struct TapTweakHash(_);
+}
+
+impl TapLeafHash {
+    /// Computes the leaf hash of `script` with `leaf_version`.
+    pub fn from_script(script: &[u8], leaf_version: u8) -> TapLeafHash {
+        let mut engine = TapLeafHash::engine();
+        engine.input(&[leaf_version]);
+        engine.input(&encode_compact_size(script.len()));
+        engine.input(script);
+        TapLeafHash::from_engine(engine)
+    }
+}
+
+impl TapNodeHash {
+    /// Computes the hash of the branch with children `a` and `b`, which is independent of
+    /// their order.
+    pub fn from_node_hashes(a: TapNodeHash, b: TapNodeHash) -> TapNodeHash {
+        let mut engine = TapNodeHash::engine();
+        if a < b {
+            engine.input(a.as_byte_array());
+            engine.input(b.as_byte_array());
+        } else {
+            engine.input(b.as_byte_array());
+            engine.input(a.as_byte_array());
+        }
+        TapNodeHash::from_engine(engine)
+    }
+}
+
+impl From<TapLeafHash> for TapNodeHash {
+    fn from(leaf: TapLeafHash) -> TapNodeHash {
+        TapNodeHash::from_byte_array(leaf.to_byte_array())
+    }
+}
+
+impl TapTweakHash {
+    /// Computes the tweak of `internal_key` committing to the script tree with
+    /// `merkle_root`, or to no scripts at all.
+    pub fn from_key_and_tweak(
+        internal_key: &XOnlyPublicKey,
+        merkle_root: Option<TapNodeHash>,
+    ) -> TapTweakHash {
+        let mut engine = TapTweakHash::engine();
+        engine.input(&internal_key.serialize());
+        if let Some(merkle_root) = merkle_root {
+            engine.input(merkle_root.as_byte_array());
+        }
+        TapTweakHash::from_engine(engine)
+    }
+
+    /// Returns the tweak as a scalar to add to the internal key.
+    ///
+    /// # Panics
+    ///
+    /// If the hash isn't less than the curve order, which happens with negligible
+    /// probability.
+    pub fn to_scalar(self) -> Scalar {
+        Scalar::from_be_bytes(self.to_byte_array()).expect("hash is less than the curve order")
+    }
+}
+
+/// Ways that building a script tree might fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TaprootBuilderError {
+    /// A leaf is deeper than the [`TAPROOT_CONTROL_MAX_NODE_COUNT`] levels a control
+    /// block can prove.
+    InvalidDepth { depth: usize },
+    /// A leaf was added shallower than an unfinished branch, so the leaves aren't in
+    /// depth-first order.
+    NotInDfsOrder,
+    /// A leaf was added to a tree that is already complete.
+    OverCompleteTree,
+    /// The tree was finalized with branches missing a child.
+    IncompleteTree,
+    /// A Huffman tree was built from no leaves.
+    EmptyTree,
+}
+
+impl fmt::Display for TaprootBuilderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TaprootBuilderError::InvalidDepth { depth } => write!(
+                f,
+                "leaf depth {} is more than the maximum of {}",
+                depth, TAPROOT_CONTROL_MAX_NODE_COUNT
+            ),
+            TaprootBuilderError::NotInDfsOrder => {
+                f.write_str("leaves are not added in depth-first order")
+            }
+            TaprootBuilderError::OverCompleteTree => f.write_str("script tree is already complete"),
+            TaprootBuilderError::IncompleteTree => f.write_str("script tree is not complete"),
+            TaprootBuilderError::EmptyTree => f.write_str("script tree has no leaves"),
+        }
+    }
+}
+
+/// A leaf script of a tree along with the hashes proving it is part of the tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptLeaf {
+    pub script: ScriptBuf,
+    pub leaf_version: u8,
+    /// Hashes of the siblings of the leaf and of its ancestors, from the leaf up.
+    pub merkle_branch: Vec<TapNodeHash>,
+}
+
+impl ScriptLeaf {
+    /// Returns the hash of the leaf.
+    pub fn leaf_hash(&self) -> TapLeafHash {
+        TapLeafHash::from_script(self.script.as_bytes(), self.leaf_version)
+    }
+}
+
+/// A node of a tree under construction, with the leaves below it.
+#[derive(Debug, Clone)]
+struct NodeInfo {
+    hash: TapNodeHash,
+    leaves: Vec<ScriptLeaf>,
+}
+
+impl NodeInfo {
+    fn new_leaf(script: ScriptBuf, leaf_version: u8) -> NodeInfo {
+        let leaf = ScriptLeaf {
+            script,
+            leaf_version,
+            merkle_branch: Vec::new(),
+        };
+        NodeInfo {
+            hash: leaf.leaf_hash().into(),
+            leaves: vec![leaf],
+        }
+    }
+
+    fn combine(a: NodeInfo, b: NodeInfo) -> Result<NodeInfo, TaprootBuilderError> {
+        let hash = TapNodeHash::from_node_hashes(a.hash, b.hash);
+        let mut leaves = Vec::with_capacity(a.leaves.len() + b.leaves.len());
+        for (mut leaf, sibling) in a
+            .leaves
+            .into_iter()
+            .map(|leaf| (leaf, b.hash))
+            .chain(b.leaves.into_iter().map(|leaf| (leaf, a.hash)))
+        {
+            if leaf.merkle_branch.len() >= TAPROOT_CONTROL_MAX_NODE_COUNT {
+                return Err(TaprootBuilderError::InvalidDepth {
+                    depth: leaf.merkle_branch.len() + 1,
+                });
+            }
+            leaf.merkle_branch.push(sibling);
+            leaves.push(leaf);
+        }
+        Ok(NodeInfo { hash, leaves })
+    }
+}
+
+/// Builds a script tree from leaves given in depth-first order with their depths, or
+/// from their weights with [`with_huffman_tree`](Self::with_huffman_tree).
+#[derive(Debug, Clone, Default)]
+pub struct TaprootBuilder {
+    /// Nodes still waiting for a sibling, indexed by depth.
+    branch: Vec<Option<NodeInfo>>,
+}
+
+impl TaprootBuilder {
+    /// Creates a builder of an empty tree.
+    pub fn new() -> Self {
+        TaprootBuilder::default()
+    }
+
+    /// Builds a tree placing the leaves with the largest weights, their expected
+    /// frequency of use, closest to the root so that spending them is cheaper.
+    pub fn with_huffman_tree<I>(leaves: I) -> Result<Self, TaprootBuilderError>
+    where
+        I: IntoIterator<Item = (u32, ScriptBuf)>,
+    {
+        let mut nodes = Vec::new();
+        let mut heap = BinaryHeap::new();
+        for (weight, script) in leaves {
+            heap.push((Reverse(weight as u64), Reverse(nodes.len())));
+            nodes.push(Some(NodeInfo::new_leaf(script, TAPROOT_LEAF_TAPSCRIPT)));
+        }
+        // Each node is popped from the heap, and so taken, exactly once.
+        fn take(nodes: &mut [Option<NodeInfo>], index: usize) -> NodeInfo {
+            nodes[index].take().unwrap()
+        }
+        let root = loop {
+            let (Reverse(weight), Reverse(index)) =
+                heap.pop().ok_or(TaprootBuilderError::EmptyTree)?;
+            let (Reverse(other_weight), Reverse(other_index)) = match heap.pop() {
+                Some(other) => other,
+                None => break take(&mut nodes, index),
+            };
+            let node = NodeInfo::combine(take(&mut nodes, index), take(&mut nodes, other_index))?;
+            heap.push((Reverse(weight + other_weight), Reverse(nodes.len())));
+            nodes.push(Some(node));
+        };
+        Ok(TaprootBuilder {
+            branch: vec![Some(root)],
+        })
+    }
+
+    /// Adds a tapscript leaf at `depth`, where the root is at depth 0.
+    pub fn add_leaf(
+        &mut self,
+        depth: usize,
+        script: ScriptBuf,
+    ) -> Result<&mut Self, TaprootBuilderError> {
+        self.add_leaf_with_version(depth, script, TAPROOT_LEAF_TAPSCRIPT)
+    }
+
+    /// Adds a leaf with `leaf_version` at `depth`, where the root is at depth 0.
+    pub fn add_leaf_with_version(
+        &mut self,
+        depth: usize,
+        script: ScriptBuf,
+        leaf_version: u8,
+    ) -> Result<&mut Self, TaprootBuilderError> {
+        self.insert(NodeInfo::new_leaf(script, leaf_version), depth)?;
+        Ok(self)
+    }
+
+    /// Returns true if the tree is complete, or empty.
+    pub fn is_finalizable(&self) -> bool {
+        self.branch.len() <= 1 && self.branch.iter().all(Option::is_some)
+    }
+
+    /// Commits `internal_key` to the tree, or to no scripts if the tree is empty.
+    pub fn finalize(
+        self,
+        internal_key: XOnlyPublicKey,
+    ) -> Result<TaprootSpendInfo, TaprootBuilderError> {
+        if !self.is_finalizable() {
+            return Err(TaprootBuilderError::IncompleteTree);
+        }
+        Ok(match self.branch.into_iter().next().flatten() {
+            Some(root) => TaprootSpendInfo {
+                leaves: root.leaves,
+                ..TaprootSpendInfo::new_key_spend(internal_key, Some(root.hash))
+            },
+            None => TaprootSpendInfo::new_key_spend(internal_key, None),
+        })
+    }
+
+    fn insert(&mut self, mut node: NodeInfo, mut depth: usize) -> Result<(), TaprootBuilderError> {
+        if depth > TAPROOT_CONTROL_MAX_NODE_COUNT {
+            return Err(TaprootBuilderError::InvalidDepth { depth });
+        }
+        // Only a complete tree collapses to a single node at the root.
+        if let [Some(_)] = self.branch.as_slice() {
+            return Err(TaprootBuilderError::OverCompleteTree);
+        }
+        // A deeper branch still waiting for a sibling would be left unfinished.
+        if depth + 1 < self.branch.len() {
+            return Err(TaprootBuilderError::NotInDfsOrder);
+        }
+        // Combine the node with its waiting sibling, and so on up the tree.
+        while self.branch.len() == depth + 1 {
+            let sibling = match self.branch.pop().flatten() {
+                Some(sibling) => sibling,
+                None => {
+                    self.branch.push(None);
+                    break;
+                }
+            };
+            node = NodeInfo::combine(sibling, node)?;
+            depth -= 1;
+        }
+        if self.branch.len() < depth + 1 {
+            self.branch.resize(depth + 1, None);
+        }
+        self.branch[depth] = Some(node);
+        Ok(())
+    }
+}
+
+/// An internal key committed to a script tree, with what is needed to spend the output
+/// by key or by any of the scripts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaprootSpendInfo {
+    internal_key: XOnlyPublicKey,
+    merkle_root: Option<TapNodeHash>,
+    output_key: TweakedPublicKey,
+    output_key_parity: Parity,
+    leaves: Vec<ScriptLeaf>,
+}
+
+impl TaprootSpendInfo {
+    /// Commits `internal_key` to the tree with `merkle_root` without knowing its
+    /// scripts, or to no scripts at all.
+    pub fn new_key_spend(
+        internal_key: XOnlyPublicKey,
+        merkle_root: Option<TapNodeHash>,
+    ) -> TaprootSpendInfo {
+        let (output_key, output_key_parity) =
+            TweakedPublicKey::from_internal_key(internal_key, merkle_root);
+        TaprootSpendInfo {
+            internal_key,
+            merkle_root,
+            output_key,
+            output_key_parity,
+            leaves: Vec::new(),
+        }
+    }
+
+    /// Returns the untweaked key.
+    pub fn internal_key(&self) -> XOnlyPublicKey {
+        self.internal_key
+    }
+
+    /// Returns the root of the script tree, if any.
+    pub fn merkle_root(&self) -> Option<TapNodeHash> {
+        self.merkle_root
+    }
+
+    /// Returns the tweaked key paid to by the output.
+    pub fn output_key(&self) -> TweakedPublicKey {
+        self.output_key
+    }
+
+    /// Returns the parity of the full output key, which control blocks commit to.
+    pub fn output_key_parity(&self) -> Parity {
+        self.output_key_parity
+    }
+
+    /// Returns the leaves of the script tree in depth-first order, if it was built from
+    /// its scripts.
+    pub fn leaves(&self) -> &[ScriptLeaf] {
+        &self.leaves
+    }
+
+    /// Returns the P2TR output script paying to the output key.
+    pub fn script_pubkey(&self) -> ScriptBuf {
+        ScriptBuf::new_p2tr_tweaked(self.output_key)
+    }
+
+    /// Returns the control block to spend the output with `script`, or `None` if the
+    /// tree has no such leaf.
+    pub fn control_block(&self, script: &Script, leaf_version: u8) -> Option<ControlBlock> {
+        let leaf = self
+            .leaves
+            .iter()
+            .find(|leaf| leaf.script.as_script() == script && leaf.leaf_version == leaf_version)?;
+        Some(ControlBlock {
+            leaf_version,
+            output_key_parity: self.output_key_parity,
+            internal_key: self.internal_key,
+            merkle_branch: leaf.merkle_branch.clone(),
+        })
+    }
+}
+
+/// Returns the annex of a taproot witness, the last of at least two elements if it
+/// starts with [`TAPROOT_ANNEX_PREFIX`].
+pub fn annex(witness: &[Vec<u8>]) -> Option<&[u8]> {
+    match witness {
+        [_, .., last] if last.first() == Some(&TAPROOT_ANNEX_PREFIX) => Some(last),
+        _ => None,
+    }
+}
+
+/// Ways that decoding a control block might fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ControlBlockError {
+    /// The size isn't 33 bytes plus up to [`TAPROOT_CONTROL_MAX_NODE_COUNT`] nodes of 32
+    /// bytes.
+    InvalidSize { size: usize },
+    /// The internal key isn't a valid x-only key.
+    InvalidInternalKey,
+}
+
+impl fmt::Display for ControlBlockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ControlBlockError::InvalidSize { size } => {
+                write!(f, "invalid control block size {}", size)
+            }
+            ControlBlockError::InvalidInternalKey => {
+                f.write_str("invalid internal key in control block")
+            }
+        }
+    }
+}
+
+/// The proof that a leaf is committed to by an output key, the last witness element of
+/// a script path spend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControlBlock {
+    pub leaf_version: u8,
+    pub output_key_parity: Parity,
+    pub internal_key: XOnlyPublicKey,
+    /// Hashes of the siblings of the leaf and of its ancestors, from the leaf up.
+    pub merkle_branch: Vec<TapNodeHash>,
+}
+
+impl ControlBlock {
+    /// Decodes a control block.
+    pub fn decode(data: &[u8]) -> Result<ControlBlock, ControlBlockError> {
+        if data.len() < TAPROOT_CONTROL_BASE_SIZE
+            || data.len() > TAPROOT_CONTROL_MAX_SIZE
+            || !(data.len() - TAPROOT_CONTROL_BASE_SIZE).is_multiple_of(TAPROOT_CONTROL_NODE_SIZE)
+        {
+            return Err(ControlBlockError::InvalidSize { size: data.len() });
+        }
+        let internal_key = XOnlyPublicKey::from_slice(&data[1..TAPROOT_CONTROL_BASE_SIZE])
+            .map_err(|_| ControlBlockError::InvalidInternalKey)?;
+        let output_key_parity = if data[0] & !TAPROOT_LEAF_MASK == 1 {
+            Parity::Odd
+        } else {
+            Parity::Even
+        };
+        let merkle_branch = data[TAPROOT_CONTROL_BASE_SIZE..]
+            .chunks(TAPROOT_CONTROL_NODE_SIZE)
+            .map(|node| TapNodeHash::from_slice(node).unwrap())
+            .collect();
+        Ok(ControlBlock {
+            leaf_version: data[0] & TAPROOT_LEAF_MASK,
+            output_key_parity,
+            internal_key,
+            merkle_branch,
+        })
+    }
+
+    /// Returns true if `script`, as a leaf of the control block's version, is committed
+    /// to by `output_key`.
+    pub fn verify_taproot_commitment(&self, output_key: &XOnlyPublicKey, script: &Script) -> bool {
+        let leaf_hash = TapLeafHash::from_script(script.as_bytes(), self.leaf_version);
+        self.verify_leaf_hash(output_key, leaf_hash)
+    }
+
+    /// Returns true if the leaf with `leaf_hash` is committed to by `output_key`.
+    pub fn verify_leaf_hash(&self, output_key: &XOnlyPublicKey, leaf_hash: TapLeafHash) -> bool {
+        let merkle_root = self
+            .merkle_branch
+            .iter()
+            .fold(TapNodeHash::from(leaf_hash), |node, &sibling| {
+                TapNodeHash::from_node_hashes(node, sibling)
+            });
+        let tweak = TapTweakHash::from_key_and_tweak(&self.internal_key, Some(merkle_root));
+        let tweak = match Scalar::from_be_bytes(tweak.to_byte_array()) {
+            Ok(tweak) => tweak,
+            Err(_) => return false,
+        };
+        self.internal_key.tweak_add_check(
+            &Secp256k1::verification_only(),
+            output_key,
+            self.output_key_parity,
+            tweak,
+        )
+    }
+
+    /// Serializes the control block as a witness element.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(
+            TAPROOT_CONTROL_BASE_SIZE + TAPROOT_CONTROL_NODE_SIZE * self.merkle_branch.len(),
+        );
+        result.push(self.leaf_version | self.output_key_parity.to_u8());
+        result.extend_from_slice(&self.internal_key.serialize());
+        for node in &self.merkle_branch {
+            result.extend_from_slice(node.as_byte_array());
+        }
+        result
+    }
+}
+
+/// A script path spend of a taproot output, split from its witness.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptPathSpend<'a> {
+    /// The initial stack of the script.
+    pub stack: &'a [Vec<u8>],
+    pub script: &'a Script,
+    pub control_block: ControlBlock,
+    pub annex: Option<&'a [u8]>,
+}
+
+impl<'a> ScriptPathSpend<'a> {
+    /// Splits a taproot witness into the script, its stack and the decoded control block,
+    /// or returns `None` if the witness is a key path spend of a single signature.
+    pub fn from_witness(witness: &'a [Vec<u8>]) -> Result<Option<Self>, ControlBlockError> {
+        let annex = annex(witness);
+        let stack = match annex {
+            Some(_) => &witness[..witness.len() - 1],
+            None => witness,
+        };
+        let (control_block, script, stack) = match stack {
+            [stack @ .., script, control_block] => (control_block, script, stack),
+            _ => return Ok(None),
+        };
+        Ok(Some(ScriptPathSpend {
+            stack,
+            script: Script::from_bytes(script),
+            control_block: ControlBlock::decode(control_block)?,
+            annex,
+        }))
+    }
+
+    /// Returns the hash of the spent leaf.
+    pub fn leaf_hash(&self) -> TapLeafHash {
+        TapLeafHash::from_script(self.script.as_bytes(), self.control_block.leaf_version)
+    }
+}
+
+/// Ways that parsing a signature might fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SignatureError {
+    /// The signature isn't 64 bytes, or 65 with a sighash type.
+    InvalidLength(usize),
+    /// The sighash type is undefined, or `SIGHASH_DEFAULT` given explicitly, which would
+    /// make the signature malleable.
+    InvalidSighashType(u32),
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SignatureError::InvalidLength(len) => write!(f, "invalid signature length {}", len),
+            SignatureError::InvalidSighashType(sighash_type) => {
+                write!(f, "invalid sighash type {:#x}", sighash_type)
+            }
+        }
+    }
+}
+
+/// A BIP340 signature along with the sighash type it commits to, as taken by taproot
+/// key path spends and tapscript.
+///
+/// Verifying only needs a verification context, which unlike signing doesn't precompute
+/// tables. Turning off the default `signing` feature leaves out [`Signature::sign`] along
+/// with the crate's other signing code, though not secp256k1's, which has no such feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signature {
+    pub signature: schnorr::Signature,
+    pub sighash_type: u32,
+}
+
+impl Signature {
+    /// Signs `sighash` with `key`, mixing `aux_rand` into the nonce as BIP340 recommends
+    /// to protect against side channels.
+    ///
+    /// For a key path spend `key` must be tweaked first, see [`PrivateKey::tap_tweak`].
+    #[cfg(feature = "signing")]
+    pub fn sign(
+        sighash: &[u8; 32],
+        key: &PrivateKey,
+        aux_rand: &[u8; 32],
+        sighash_type: u32,
+    ) -> Self {
+        let secp = Secp256k1::signing_only();
+        let keypair = Keypair::from_secret_key(&secp, &key.inner);
+        let signature =
+            secp.sign_schnorr_with_aux_rand(&Message::from_digest(*sighash), &keypair, aux_rand);
+        Signature {
+            signature,
+            sighash_type,
+        }
+    }
+
+    /// Parses a 64-byte signature committing to `SIGHASH_DEFAULT`, or a 65-byte one
+    /// followed by another sighash type.
+    pub fn from_slice(data: &[u8]) -> Result<Self, SignatureError> {
+        let (signature, sighash_type) = match data.len() {
+            64 => (data, SIGHASH_DEFAULT),
+            65 => match data[64] as u32 {
+                sighash_type @ (0x01..=0x03 | 0x81..=0x83) => (&data[..64], sighash_type),
+                sighash_type => return Err(SignatureError::InvalidSighashType(sighash_type)),
+            },
+            len => return Err(SignatureError::InvalidLength(len)),
+        };
+        // Whether the signature is valid for any key is only known when verifying it.
+        let signature = schnorr::Signature::from_slice(signature).expect("64 bytes");
+        Ok(Signature {
+            signature,
+            sighash_type,
+        })
+    }
+
+    /// Serializes the signature, in 64 bytes for `SIGHASH_DEFAULT` and 65 bytes with the
+    /// sighash type otherwise.
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut result = self.signature.as_ref().to_vec();
+        if self.sighash_type != SIGHASH_DEFAULT {
+            result.push(self.sighash_type as u8);
+        }
+        result
+    }
+
+    /// Returns true if the signature is valid for `sighash` and `key`.
+    pub fn verify(&self, sighash: &[u8; 32], key: &XOnlyPublicKey) -> bool {
+        Secp256k1::verification_only()
+            .verify_schnorr(&self.signature, &Message::from_digest(*sighash), key)
+            .is_ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::key::PrivateKey;
+
+    #[test]
+    fn tagged_hashes_match_rust_bitcoin() {
+        use bitcoin::taproot::{
+            LeafVersion, TapLeafHash as BitcoinLeaf, TapNodeHash as BitcoinNode,
+        };
+
+        let script =
+            hex::decode("20d85a959b0290bf19bb89ed43c916be835475d013da4b362117393e25a48229b8ac")
+                .unwrap();
+        let leaf = TapLeafHash::from_script(&script, TAPROOT_LEAF_TAPSCRIPT);
+        let expected =
+            BitcoinLeaf::from_script(bitcoin::Script::from_bytes(&script), LeafVersion::TapScript);
+        assert_eq!(leaf.to_byte_array(), expected.to_byte_array());
+
+        let other = TapLeafHash::from_script(&[0x51], TAPROOT_LEAF_TAPSCRIPT);
+        let node = TapNodeHash::from_node_hashes(leaf.into(), other.into());
+        assert_eq!(
+            node,
+            TapNodeHash::from_node_hashes(other.into(), leaf.into())
+        );
+        let expected = BitcoinNode::from_node_hashes(
+            expected.into(),
+            BitcoinLeaf::from_script(bitcoin::Script::from_bytes(&[0x51]), LeafVersion::TapScript)
+                .into(),
+        );
+        assert_eq!(node.to_byte_array(), expected.to_byte_array());
+    }
+
+    fn key(hex_key: &str) -> XOnlyPublicKey {
+        XOnlyPublicKey::from_slice(&hex::decode(hex_key).unwrap()).unwrap()
+    }
+
+    /// Adds the leaves of a BIP341 test vector tree depth-first, returning their ids.
+    fn add_leaves(
+        builder: &mut TaprootBuilder,
+        tree: &serde_json::Value,
+        depth: usize,
+        ids: &mut Vec<u64>,
+    ) {
+        match tree {
+            serde_json::Value::Array(children) => {
+                for child in children {
+                    add_leaves(builder, child, depth + 1, ids);
+                }
+            }
+            leaf => {
+                let script =
+                    ScriptBuf::from(hex::decode(leaf["script"].as_str().unwrap()).unwrap());
+                let leaf_version = leaf["leafVersion"].as_u64().unwrap() as u8;
+                builder
+                    .add_leaf_with_version(depth, script, leaf_version)
+                    .unwrap();
+                ids.push(leaf["id"].as_u64().unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn bip341_script_trees() {
+        let data: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/data/bip341_tests.json")).unwrap();
+        for vector in data["scriptPubKey"].as_array().unwrap() {
+            let (given, intermediary, expected) = (
+                &vector["given"],
+                &vector["intermediary"],
+                &vector["expected"],
+            );
+            let mut builder = TaprootBuilder::new();
+            let mut ids = Vec::new();
+            if !given["scriptTree"].is_null() {
+                add_leaves(&mut builder, &given["scriptTree"], 0, &mut ids);
+            }
+            let info = builder
+                .finalize(key(given["internalPubkey"].as_str().unwrap()))
+                .unwrap();
+
+            assert_eq!(
+                info.merkle_root()
+                    .map(|root| hex::encode(root.to_byte_array())),
+                intermediary["merkleRoot"].as_str().map(str::to_owned)
+            );
+            assert_eq!(
+                info.output_key().to_inner(),
+                key(intermediary["tweakedPubkey"].as_str().unwrap())
+            );
+            assert_eq!(
+                hex::encode(info.script_pubkey().as_bytes()),
+                expected["scriptPubKey"].as_str().unwrap()
+            );
+
+            for (leaf, &id) in info.leaves().iter().zip(&ids) {
+                let id = id as usize;
+                assert_eq!(
+                    hex::encode(leaf.leaf_hash().to_byte_array()),
+                    intermediary["leafHashes"][id].as_str().unwrap()
+                );
+                let control_block = info.control_block(&leaf.script, leaf.leaf_version).unwrap();
+                assert_eq!(
+                    hex::encode(control_block.serialize()),
+                    expected["scriptPathControlBlocks"][id].as_str().unwrap()
+                );
+                assert_eq!(
+                    ControlBlock::decode(&control_block.serialize()),
+                    Ok(control_block.clone())
+                );
+                assert!(control_block
+                    .verify_taproot_commitment(&info.output_key().to_inner(), &leaf.script));
+                assert!(
+                    !control_block.verify_taproot_commitment(&info.internal_key(), &leaf.script)
+                );
+            }
+            assert_eq!(info.leaves().len(), ids.len());
+        }
+    }
+
+    #[test]
+    fn huffman_tree() {
+        let internal_key = key("d6889cb081036e0faefa3a35157ad71086b123b2b144b649798b494c300a961d");
+        let weights = [1, 2, 4, 8, 16];
+        let scripts: Vec<ScriptBuf> = (1..=weights.len() as u8)
+            .map(|n| ScriptBuf::from(vec![0x50 + n]))
+            .collect();
+        let info = TaprootBuilder::with_huffman_tree(weights.into_iter().zip(scripts.clone()))
+            .unwrap()
+            .finalize(internal_key)
+            .unwrap();
+
+        // The heaviest leaf is the closest to the root, the two lightest share a branch.
+        for (script, depth) in scripts.iter().zip([4, 4, 3, 2, 1]) {
+            let control_block = info.control_block(script, TAPROOT_LEAF_TAPSCRIPT).unwrap();
+            assert_eq!(control_block.merkle_branch.len(), depth);
+        }
+
+        let secp = bitcoin::secp256k1::Secp256k1::verification_only();
+        let expected = bitcoin::taproot::TaprootBuilder::with_huffman_tree(
+            weights.into_iter().zip(
+                scripts
+                    .iter()
+                    .map(|script| bitcoin::ScriptBuf::from(script.as_bytes().to_vec())),
+            ),
+        )
+        .unwrap()
+        .finalize(
+            &secp,
+            bitcoin::key::XOnlyPublicKey::from_slice(&internal_key.serialize()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            info.merkle_root().unwrap().to_byte_array(),
+            expected.merkle_root().unwrap().to_byte_array()
+        );
+
+        assert_eq!(
+            TaprootBuilder::with_huffman_tree(vec![]).unwrap_err(),
+            TaprootBuilderError::EmptyTree
+        );
+        let single = TaprootBuilder::with_huffman_tree(vec![(1, scripts[0].clone())])
+            .unwrap()
+            .finalize(internal_key)
+            .unwrap();
+        assert_eq!(
+            single.merkle_root(),
+            Some(TapLeafHash::from_script(scripts[0].as_bytes(), TAPROOT_LEAF_TAPSCRIPT).into())
+        );
+    }
+
+    #[test]
+    fn builder_errors() {
+        let script = ScriptBuf::from(vec![0x51]);
+        let internal_key = key("d6889cb081036e0faefa3a35157ad71086b123b2b144b649798b494c300a961d");
+
+        let mut builder = TaprootBuilder::new();
+        assert!(builder.is_finalizable());
+        builder.add_leaf(1, script.clone()).unwrap();
+        assert!(!builder.is_finalizable());
+        assert_eq!(
+            builder.clone().finalize(internal_key).unwrap_err(),
+            TaprootBuilderError::IncompleteTree
+        );
+        builder.add_leaf(2, script.clone()).unwrap();
+        assert_eq!(
+            builder.add_leaf(1, script.clone()).unwrap_err(),
+            TaprootBuilderError::NotInDfsOrder
+        );
+        builder.add_leaf(2, script.clone()).unwrap();
+        assert!(builder.is_finalizable());
+        assert_eq!(
+            builder.add_leaf(1, script.clone()).unwrap_err(),
+            TaprootBuilderError::OverCompleteTree
+        );
+        assert_eq!(builder.finalize(internal_key).unwrap().leaves().len(), 3);
+
+        assert_eq!(
+            TaprootBuilder::new()
+                .add_leaf(129, script.clone())
+                .unwrap_err(),
+            TaprootBuilderError::InvalidDepth { depth: 129 }
+        );
+        let mut builder = TaprootBuilder::new();
+        builder.add_leaf(128, script.clone()).unwrap();
+        builder.add_leaf(128, script).unwrap();
+        assert!(!builder.is_finalizable());
+
+        let key_only = TaprootBuilder::new().finalize(internal_key).unwrap();
+        assert_eq!(
+            key_only,
+            TaprootSpendInfo::new_key_spend(internal_key, None)
+        );
+        assert_eq!(
+            key_only.control_block(&ScriptBuf::new(), TAPROOT_LEAF_TAPSCRIPT),
+            None
+        );
+    }
+
+    #[test]
+    fn control_block_decoding() {
+        let internal_key = key("d6889cb081036e0faefa3a35157ad71086b123b2b144b649798b494c300a961d");
+        let mut control_block = vec![TAPROOT_LEAF_TAPSCRIPT | 1];
+        control_block.extend_from_slice(&internal_key.serialize());
+        control_block.extend_from_slice(&[0xab; 32]);
+
+        let decoded = ControlBlock::decode(&control_block).unwrap();
+        assert_eq!(decoded.leaf_version, TAPROOT_LEAF_TAPSCRIPT);
+        assert_eq!(decoded.output_key_parity, Parity::Odd);
+        assert_eq!(decoded.internal_key, internal_key);
+        assert_eq!(
+            decoded.merkle_branch,
+            [TapNodeHash::from_byte_array([0xab; 32])]
+        );
+        assert_eq!(decoded.serialize(), control_block);
+
+        for size in [0, 32, 34, 64, TAPROOT_CONTROL_MAX_SIZE + 32] {
+            let mut data = control_block.clone();
+            data.resize(size, 0);
+            assert_eq!(
+                ControlBlock::decode(&data),
+                Err(ControlBlockError::InvalidSize { size })
+            );
+        }
+        let mut data = control_block.clone();
+        data.resize(TAPROOT_CONTROL_MAX_SIZE, 0);
+        assert_eq!(
+            ControlBlock::decode(&data).unwrap().merkle_branch.len(),
+            TAPROOT_CONTROL_MAX_NODE_COUNT
+        );
+        // Zero isn't the x coordinate of a point on the curve.
+        data[1..TAPROOT_CONTROL_BASE_SIZE].fill(0);
+        assert_eq!(
+            ControlBlock::decode(&data),
+            Err(ControlBlockError::InvalidInternalKey)
+        );
+    }
+
+    #[test]
+    fn script_path_witness() {
+        let internal_key = key("d6889cb081036e0faefa3a35157ad71086b123b2b144b649798b494c300a961d");
+        let script = ScriptBuf::from(vec![0x51]);
+        let mut builder = TaprootBuilder::new();
+        builder.add_leaf(0, script.clone()).unwrap();
+        let info = builder.finalize(internal_key).unwrap();
+        let control_block = info.control_block(&script, TAPROOT_LEAF_TAPSCRIPT).unwrap();
+        let annex_element = vec![TAPROOT_ANNEX_PREFIX, 0x01];
+
+        let mut witness = vec![
+            vec![0x02],
+            script.as_bytes().to_vec(),
+            control_block.serialize(),
+        ];
+        let spend = ScriptPathSpend::from_witness(&witness).unwrap().unwrap();
+        assert_eq!(spend.stack, [vec![0x02]]);
+        assert_eq!(spend.script, script.as_script());
+        assert_eq!(spend.control_block, control_block);
+        assert_eq!(spend.annex, None);
+        assert_eq!(Some(spend.leaf_hash().into()), info.merkle_root());
+
+        witness.push(annex_element.clone());
+        assert_eq!(annex(&witness), Some(&annex_element[..]));
+        let spend = ScriptPathSpend::from_witness(&witness).unwrap().unwrap();
+        assert_eq!(spend.control_block, control_block);
+        assert_eq!(spend.annex, Some(&annex_element[..]));
+
+        // A single element starting with the annex prefix is a signature.
+        let key_path = vec![annex_element.clone()];
+        assert_eq!(annex(&key_path), None);
+        assert_eq!(ScriptPathSpend::from_witness(&key_path), Ok(None));
+        let key_path = vec![vec![0x01; 64], annex_element];
+        assert_eq!(ScriptPathSpend::from_witness(&key_path), Ok(None));
+        assert_eq!(ScriptPathSpend::from_witness(&[]), Ok(None));
+
+        let bad_control = vec![script.as_bytes().to_vec(), vec![TAPROOT_LEAF_TAPSCRIPT; 40]];
+        assert_eq!(
+            ScriptPathSpend::from_witness(&bad_control),
+            Err(ControlBlockError::InvalidSize { size: 40 })
+        );
+    }
+
+    #[test]
+    fn bip340_vectors() {
+        let vectors = include_str!("../tests/data/bip340_test_vectors.csv");
+        let mut skipped = vec![];
+        for line in vectors.lines().skip(1) {
+            let fields: Vec<&str> = line.split(',').collect();
+            let (index, secret_key, public_key, message, signature, result) = (
+                fields[0], fields[1], fields[2], fields[4], fields[5], fields[6],
+            );
+            let message = hex::decode(message).unwrap();
+            // Taproot only signs 32-byte sighashes.
+            let sighash: [u8; 32] = match message.try_into() {
+                Ok(sighash) => sighash,
+                Err(_) => {
+                    skipped.push(index);
+                    continue;
+                }
+            };
+            let signature = Signature::from_slice(&hex::decode(signature).unwrap()).unwrap();
+            assert_eq!(signature.sighash_type, SIGHASH_DEFAULT);
+
+            if !secret_key.is_empty() {
+                let key = PrivateKey::from_slice(&hex::decode(secret_key).unwrap()).unwrap();
+                let (x_only, _) = key.public_key().inner.x_only_public_key();
+                assert_eq!(
+                    x_only.serialize().to_vec(),
+                    hex::decode(public_key).unwrap()
+                );
+                #[cfg(feature = "signing")]
+                {
+                    let aux_rand: [u8; 32] = hex::decode(fields[3]).unwrap().try_into().unwrap();
+                    assert_eq!(
+                        Signature::sign(&sighash, &key, &aux_rand, SIGHASH_DEFAULT),
+                        signature,
+                        "vector {}",
+                        index
+                    );
+                }
+            }
+
+            let valid = XOnlyPublicKey::from_slice(&hex::decode(public_key).unwrap())
+                .is_ok_and(|key| signature.verify(&sighash, &key));
+            assert_eq!(valid, result == "TRUE", "vector {}", index);
+        }
+        // The vectors with messages of 0, 1, 17 and 100 bytes.
+        assert_eq!(skipped, ["15", "16", "17", "18"]);
+    }
+
+    #[test]
+    #[cfg(feature = "signing")]
+    fn signature_encoding() {
+        let key = PrivateKey::from_slice(&[0x42; 32]).unwrap();
+        let sig = Signature::sign(&[0x17; 32], &key, &[0; 32], SIGHASH_DEFAULT);
+        let bytes = sig.to_vec();
+        assert_eq!(bytes.len(), 64);
+        assert_eq!(Signature::from_slice(&bytes), Ok(sig));
+
+        let sig = Signature {
+            sighash_type: 0x83,
+            ..sig
+        };
+        let bytes = sig.to_vec();
+        assert_eq!(bytes.len(), 65);
+        assert_eq!(Signature::from_slice(&bytes), Ok(sig));
+
+        let with_type = |sighash_type: u8| [&bytes[..64], &[sighash_type]].concat();
+        for sighash_type in [0x00, 0x04, 0x80, 0x84] {
+            assert_eq!(
+                Signature::from_slice(&with_type(sighash_type)),
+                Err(SignatureError::InvalidSighashType(sighash_type as u32))
+            );
+        }
+        assert_eq!(
+            Signature::from_slice(&bytes[..63]),
+            Err(SignatureError::InvalidLength(63))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "signing")]
+    fn key_path_signature() {
+        let key = PrivateKey::from_slice(&[0x42; 32]).unwrap();
+        let (internal_key, _) = key.public_key().inner.x_only_public_key();
+        let merkle_root = Some(TapNodeHash::from_byte_array([0x01; 32]));
+        let info = TaprootSpendInfo::new_key_spend(internal_key, merkle_root);
+
+        let tweaked = key.tap_tweak(merkle_root);
+        assert_eq!(
+            tweaked.public_key().inner.x_only_public_key().0,
+            info.output_key().to_inner()
+        );
+        let sighash = [0x17; 32];
+        let sig = Signature::sign(&sighash, &tweaked, &[0; 32], SIGHASH_DEFAULT);
+        assert!(sig.verify(&sighash, &info.output_key().to_inner()));
+        assert!(!sig.verify(&sighash, &internal_key));
+        assert!(!sig.verify(&[0x18; 32], &info.output_key().to_inner()));
+    }
+}
@@ -1,5 +1,17 @@
+use crate::constants::WITNESS_SCALE_FACTOR;
+use crate::script::interpreter::{verify_script, TransactionSignatureChecker, VerifyFlags};
+use crate::script::sigops::witness_sigop_count;
+use crate::script::{Script, ScriptError};
+use crate::sighash::SighashCache;
+#[cfg(feature = "signing")]
+use crate::sighash::{
+    InvalidTxidError, SIGHASH_ALL, SIGHASH_ANYONECANPAY, SIGHASH_NONE, SIGHASH_SINGLE,
+};
+#[cfg(feature = "signing")]
+use crate::{ecdsa, key::PrivateKey, script::builder::ScriptBuilder, script::ScriptBuf};
+use bitcoin_hashes::{sha256d, Hash};
 use borsh::{BorshDeserialize, BorshSerialize};
-use std::convert::TryFrom;
+use core::fmt;
 
 #[derive(Debug, Clone, BorshDeserialize, BorshSerialize)]
 pub struct TxIn {
@@ -24,70 +36,425 @@ pub struct Transaction {
     pub lock_time: u32,
 }
 
+/// Ways the inputs of a transaction might fail to verify against the outputs they spend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TxVerifyError {
+    /// The flags are a combination [`verify_script`] doesn't support, see
+    /// [`VerifyFlags::is_supported`].
+    UnsupportedFlags(VerifyFlags),
+    /// The number of spent outputs differs from the number of inputs.
+    SpentOutputsCount { inputs: usize, spent_outputs: usize },
+    /// The txid of the outpoint the input spends isn't 64 hex characters.
+    InvalidTxid { input: usize },
+    /// The input spends an `OP_RETURN` output, which can never be spent.
+    UnspendableOutput { input: usize },
+    /// The input spends an output that isn't of a standard type, see [`ScriptType`](crate::types::ScriptType).
+    UnsupportedScript { input: usize },
+    /// The scriptSig and witness of the input don't satisfy the output it spends.
+    Script { input: usize, error: ScriptError },
+}
+
+impl fmt::Display for TxVerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TxVerifyError::UnsupportedFlags(flags) => {
+                write!(f, "unsupported verification flags {:#x}", flags.bits())
+            }
+            TxVerifyError::SpentOutputsCount {
+                inputs,
+                spent_outputs,
+            } => write!(
+                f,
+                "{} spent outputs given for {} inputs",
+                spent_outputs, inputs
+            ),
+            TxVerifyError::InvalidTxid { input } => {
+                write!(f, "input {} spends an outpoint with an invalid txid", input)
+            }
+            TxVerifyError::UnspendableOutput { input } => {
+                write!(f, "input {} spends an unspendable output", input)
+            }
+            TxVerifyError::UnsupportedScript { input } => {
+                write!(f, "input {} spends an unsupported script", input)
+            }
+            TxVerifyError::Script { input, error } => {
+                write!(f, "input {} failed to verify: {}", input, error)
+            }
+        }
+    }
+}
+
+/// Ways signing an input of a transaction might fail.
+#[cfg(feature = "signing")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SignError {
+    /// The transaction has no input at the index.
+    InputIndexOutOfRange { index: usize, inputs: usize },
+    /// The txid of the outpoint an input spends isn't 64 hex characters.
+    InvalidTxid { input: usize },
+    /// The sighash type isn't `SIGHASH_ALL`, `SIGHASH_NONE` or `SIGHASH_SINGLE`, optionally
+    /// combined with `SIGHASH_ANYONECANPAY`.
+    InvalidSighashType(u32),
+    /// `SIGHASH_SINGLE` was used in a legacy input without an output at the same index.
+    /// The signature hash is then the constant `0x00..01`, so the signature would be
+    /// valid for any transaction.
+    SighashSingleWithoutOutput { index: usize, outputs: usize },
+    /// The key is uncompressed, which segwit doesn't allow.
+    UncompressedKey,
+}
+
+#[cfg(feature = "signing")]
+impl fmt::Display for SignError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SignError::InputIndexOutOfRange { index, inputs } => write!(
+                f,
+                "input index {} out of range for {} inputs",
+                index, inputs
+            ),
+            SignError::InvalidTxid { input } => {
+                write!(f, "input {} spends an outpoint with an invalid txid", input)
+            }
+            SignError::InvalidSighashType(sighash_type) => {
+                write!(f, "invalid sighash type {:#x}", sighash_type)
+            }
+            SignError::SighashSingleWithoutOutput { index, outputs } => write!(
+                f,
+                "SIGHASH_SINGLE input {} has no matching output among {} outputs",
+                index, outputs
+            ),
+            SignError::UncompressedKey => write!(f, "segwit doesn't allow uncompressed keys"),
+        }
+    }
+}
+
+#[cfg(feature = "signing")]
+impl From<InvalidTxidError> for SignError {
+    fn from(error: InvalidTxidError) -> Self {
+        SignError::InvalidTxid { input: error.input }
+    }
+}
+
 pub fn decode_compact_size(byte_tx: &mut Vec<u8>) -> u64 {
-    let mut integer: u64 =
-        u8::from_le_bytes(byte_tx.drain(..1).collect::<Vec<u8>>().try_into().unwrap()) as u64;
-
-    if integer == 0xFD {
-        integer =
-            u16::from_le_bytes(byte_tx.drain(..2).collect::<Vec<u8>>().try_into().unwrap()) as u64;
-    } else if integer == 0xFF {
-        integer =
-            u32::from_le_bytes(byte_tx.drain(..4).collect::<Vec<u8>>().try_into().unwrap()) as u64;
-    } else if integer == 0xFF {
-        integer =
-            u64::from_le_bytes(byte_tx.drain(..8).collect::<Vec<u8>>().try_into().unwrap()) as u64;
-    } else if integer > 0xFC {
-        panic!("transaction can't have this much inputs!")
-    }
-
-    integer
+    let prefix = u8::from_le_bytes(byte_tx.drain(..1).collect::<Vec<u8>>().try_into().unwrap());
+
+    match prefix {
+        0xFD => {
+            u16::from_le_bytes(byte_tx.drain(..2).collect::<Vec<u8>>().try_into().unwrap()) as u64
+        }
+        0xFE => {
+            u32::from_le_bytes(byte_tx.drain(..4).collect::<Vec<u8>>().try_into().unwrap()) as u64
+        }
+        0xFF => u64::from_le_bytes(byte_tx.drain(..8).collect::<Vec<u8>>().try_into().unwrap()),
+        _ => prefix as u64,
+    }
 }
 
 pub fn encode_compact_size(integer: usize) -> Vec<u8> {
     if integer <= 0xFC {
-        u8::try_from(integer).ok().unwrap().to_le_bytes().to_vec()
-    } else if integer == 0xFD {
-        u16::try_from(integer).ok().unwrap().to_le_bytes().to_vec()
-    } else if integer == 0xFF {
-        u32::try_from(integer).ok().unwrap().to_le_bytes().to_vec()
-    } else if integer == 0xFF {
-        u64::try_from(integer).ok().unwrap().to_le_bytes().to_vec()
+        vec![integer as u8]
+    } else if integer <= 0xFFFF {
+        let mut bytes = vec![0xFD];
+        bytes.extend_from_slice(&(integer as u16).to_le_bytes());
+        bytes
+    } else if integer <= 0xFFFF_FFFF {
+        let mut bytes = vec![0xFE];
+        bytes.extend_from_slice(&(integer as u32).to_le_bytes());
+        bytes
     } else {
-        panic!("transaction can't have this much inputs!")
+        let mut bytes = vec![0xFF];
+        bytes.extend_from_slice(&(integer as u64).to_le_bytes());
+        bytes
+    }
+}
+
+impl TxIn {
+    /// Returns true if [`TxIn::txid`] is 64 hex characters, as the outpoint serialization
+    /// requires.
+    pub(crate) fn has_valid_txid(&self) -> bool {
+        self.txid.len() == 64 && self.txid.bytes().all(|c| c.is_ascii_hexdigit())
+    }
+
+    /// Returns the 36 byte serialization of the outpoint spent by this input.
+    ///
+    /// # Panics
+    ///
+    /// If the txid isn't valid, see [`TxIn::has_valid_txid`].
+    pub(crate) fn outpoint_bytes(&self) -> Vec<u8> {
+        assert!(self.has_valid_txid(), "invalid txid {:?}", self.txid);
+        let mut bytes: Vec<u8> = hex::decode(&self.txid).unwrap().into_iter().rev().collect();
+        bytes.extend_from_slice(&self.vout.to_le_bytes());
+        bytes
+    }
+}
+
+impl TxOut {
+    /// Returns the consensus serialization of the output.
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.amount.to_le_bytes().to_vec();
+        bytes.append(&mut encode_compact_size(self.script_pubkey.len()));
+        bytes.extend_from_slice(&self.script_pubkey);
+        bytes
     }
 }
 
 impl Transaction {
+    /// Returns the serialization of the transaction without witness data, as used
+    /// to compute the txid.
+    ///
+    /// # Panics
+    ///
+    /// If the txid of an input isn't 64 hex characters.
+    pub fn to_bytes_without_witness(&self) -> Vec<u8> {
+        let mut bytes = self.version.to_le_bytes().to_vec();
+
+        bytes.append(&mut encode_compact_size(self.input.len()));
+        for input in self.input.iter() {
+            bytes.append(&mut input.outpoint_bytes());
+            bytes.append(&mut encode_compact_size(input.script_sig.len()));
+            bytes.extend_from_slice(&input.script_sig);
+            bytes.extend_from_slice(&input.sequence.to_le_bytes());
+        }
+
+        bytes.append(&mut encode_compact_size(self.output.len()));
+        for output in self.output.iter() {
+            bytes.append(&mut output.to_bytes());
+        }
+
+        bytes.extend_from_slice(&self.lock_time.to_le_bytes());
+        bytes
+    }
+
+    /// Returns the transaction id as a hex string, in the same byte order as [`TxIn::txid`].
+    pub fn txid(&self) -> String {
+        sha256d::Hash::hash(&self.to_bytes_without_witness()).to_string()
+    }
+
+    /// Verifies the scriptSig and witness of every input against the output it spends,
+    /// with `spent_outputs` in the same order as the inputs.
+    ///
+    /// Every input is checked and the failures of all of them are returned, in input
+    /// order. Signatures commit to every outpoint, so if any txid is invalid only those
+    /// errors are returned. Only P2PKH, P2SH (including nested segwit), P2WPKH, P2WSH and
+    /// P2TR outputs can be spent; spending P2PK, bare multisig or witness programs of
+    /// other versions and sizes fails with [`TxVerifyError::UnsupportedScript`].
+    pub fn verify(
+        &self,
+        spent_outputs: &[TxOut],
+        flags: VerifyFlags,
+    ) -> Result<(), Vec<TxVerifyError>> {
+        if !flags.is_supported() {
+            return Err(vec![TxVerifyError::UnsupportedFlags(flags)]);
+        }
+        if spent_outputs.len() != self.input.len() {
+            return Err(vec![TxVerifyError::SpentOutputsCount {
+                inputs: self.input.len(),
+                spent_outputs: spent_outputs.len(),
+            }]);
+        }
+
+        let invalid_txids: Vec<_> = self
+            .input
+            .iter()
+            .enumerate()
+            .filter(|(_, input)| !input.has_valid_txid())
+            .map(|(index, _)| TxVerifyError::InvalidTxid { input: index })
+            .collect();
+        if !invalid_txids.is_empty() {
+            return Err(invalid_txids);
+        }
+
+        let cache = SighashCache::new(self).expect("txids were checked above");
+        let mut errors = vec![];
+        for (index, (input, spent_output)) in self.input.iter().zip(spent_outputs).enumerate() {
+            let script_pubkey = Script::from_bytes(&spent_output.script_pubkey);
+            if script_pubkey.is_op_return() {
+                errors.push(TxVerifyError::UnspendableOutput { input: index });
+                continue;
+            }
+            if !(script_pubkey.is_p2pkh()
+                || script_pubkey.is_p2sh()
+                || script_pubkey.is_p2wpkh()
+                || script_pubkey.is_p2wsh()
+                || script_pubkey.is_p2tr())
+            {
+                errors.push(TxVerifyError::UnsupportedScript { input: index });
+                continue;
+            }
+
+            let checker =
+                TransactionSignatureChecker::with_spent_outputs(&cache, index, spent_outputs);
+            if let Err(error) = verify_script(
+                Script::from_bytes(&input.script_sig),
+                script_pubkey,
+                &input.witness,
+                flags,
+                &checker,
+            ) {
+                errors.push(TxVerifyError::Script {
+                    input: index,
+                    error,
+                });
+            }
+        }
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+
+    /// Returns the signature operation cost of the transaction, which blocks limit to
+    /// [`MAX_BLOCK_SIGOPS_COST`](crate::constants::MAX_BLOCK_SIGOPS_COST), like Bitcoin Core's `GetTransactionSigOpCost` with
+    /// P2SH and segwit active.
+    ///
+    /// Legacy signature operations of the scriptSigs, scriptPubKeys and P2SH redeem
+    /// scripts cost [`WITNESS_SCALE_FACTOR`] each and witness ones 1. `spent_outputs` are
+    /// the outputs spent by each input, ignored for coinbase transactions.
+    pub fn sigop_cost(&self, spent_outputs: &[TxOut]) -> Result<i64, TxVerifyError> {
+        let mut legacy = 0;
+        for input in &self.input {
+            legacy += Script::from_bytes(&input.script_sig).sigop_count(false);
+        }
+        for output in &self.output {
+            legacy += Script::from_bytes(&output.script_pubkey).sigop_count(false);
+        }
+        let mut cost = (legacy * WITNESS_SCALE_FACTOR) as i64;
+        if self.is_coinbase() {
+            return Ok(cost);
+        }
+
+        if spent_outputs.len() != self.input.len() {
+            return Err(TxVerifyError::SpentOutputsCount {
+                inputs: self.input.len(),
+                spent_outputs: spent_outputs.len(),
+            });
+        }
+        for (input, spent_output) in self.input.iter().zip(spent_outputs) {
+            let script_sig = Script::from_bytes(&input.script_sig);
+            let script_pubkey = Script::from_bytes(&spent_output.script_pubkey);
+            if script_pubkey.is_p2sh() {
+                let p2sh = script_pubkey.p2sh_sigop_count(script_sig);
+                cost += (p2sh * WITNESS_SCALE_FACTOR) as i64;
+            }
+            let flags = VerifyFlags::P2SH | VerifyFlags::WITNESS;
+            cost += witness_sigop_count(script_sig, script_pubkey, &input.witness, flags) as i64;
+        }
+        Ok(cost)
+    }
+
+    /// Signs input `input_index`, which spends a P2PKH output paying to `key`, setting
+    /// its scriptSig to the signature and the public key.
+    ///
+    /// Fails if `sighash_type` is `SIGHASH_SINGLE` and there is no output at `input_index`.
+    #[cfg(feature = "signing")]
+    pub fn sign_p2pkh_input(
+        &mut self,
+        input_index: usize,
+        key: &PrivateKey,
+        sighash_type: u32,
+    ) -> Result<(), SignError> {
+        self.check_signable(input_index, sighash_type)?;
+        if sighash_type & !SIGHASH_ANYONECANPAY == SIGHASH_SINGLE
+            && input_index >= self.output.len()
+        {
+            return Err(SignError::SighashSingleWithoutOutput {
+                index: input_index,
+                outputs: self.output.len(),
+            });
+        }
+        let public_key = key.public_key();
+        let script_code = ScriptBuf::new_p2pkh(&public_key.pubkey_hash().to_byte_array());
+        let sighash = SighashCache::new(self)?.legacy_signature_hash(
+            input_index,
+            script_code.as_bytes(),
+            sighash_type,
+        );
+        let sig = ecdsa::Signature::sign(sighash.as_byte_array(), key, sighash_type);
+
+        let mut script_sig = ScriptBuilder::new();
+        script_sig
+            .push_slice_with_size(&sig.to_vec())
+            .push_slice_with_size(&public_key.to_bytes());
+        self.input[input_index].script_sig = script_sig.into_script().into_bytes();
+        Ok(())
+    }
+
+    /// Signs input `input_index`, which spends a P2WPKH output of `amount` satoshis
+    /// paying to `key`, setting its witness to the signature and the public key.
+    ///
+    /// Fails if `key` is uncompressed, which segwit doesn't allow.
+    #[cfg(feature = "signing")]
+    pub fn sign_p2wpkh_input(
+        &mut self,
+        input_index: usize,
+        key: &PrivateKey,
+        amount: u64,
+        sighash_type: u32,
+    ) -> Result<(), SignError> {
+        self.check_signable(input_index, sighash_type)?;
+        let public_key = key.public_key();
+        let pubkey_hash = public_key
+            .wpubkey_hash()
+            .ok_or(SignError::UncompressedKey)?;
+        // BIP143 signs P2WPKH spends with the script code of the matching P2PKH.
+        let script_code = ScriptBuf::new_p2pkh(&pubkey_hash.to_byte_array());
+        let sighash = SighashCache::new(self)?.segwit_v0_signature_hash(
+            input_index,
+            script_code.as_bytes(),
+            amount,
+            sighash_type,
+        );
+        let sig = ecdsa::Signature::sign(sighash.as_byte_array(), key, sighash_type);
+        self.input[input_index].witness = vec![sig.to_vec(), public_key.to_bytes()];
+        Ok(())
+    }
+
+    /// Checks the input index and the sighash type.
+    #[cfg(feature = "signing")]
+    fn check_signable(&self, index: usize, sighash_type: u32) -> Result<(), SignError> {
+        if index >= self.input.len() {
+            return Err(SignError::InputIndexOutOfRange {
+                index,
+                inputs: self.input.len(),
+            });
+        }
+        let base_type = sighash_type & !SIGHASH_ANYONECANPAY;
+        if !matches!(base_type, SIGHASH_ALL | SIGHASH_NONE | SIGHASH_SINGLE) {
+            return Err(SignError::InvalidSighashType(sighash_type));
+        }
+        Ok(())
+    }
+
+    /// Returns true if this is a coinbase transaction, which has a single input
+    /// spending the null outpoint.
+    pub fn is_coinbase(&self) -> bool {
+        self.input.len() == 1
+            && self.input[0].vout == u32::MAX
+            && self.input[0].txid.bytes().all(|c| c == b'0')
+    }
+
     pub fn from_hex(hex_tx: &str) -> Transaction {
         let mut byte_tx = hex::decode(hex_tx).unwrap();
 
         let version =
             u32::from_le_bytes(byte_tx.drain(..4).collect::<Vec<u8>>().try_into().unwrap());
-        let marker = u8::from_le_bytes(byte_tx.drain(..1).collect::<Vec<u8>>().try_into().unwrap());
-        let flag = u8::from_le_bytes(byte_tx.drain(..1).collect::<Vec<u8>>().try_into().unwrap());
+        // skip the segwit marker and flag bytes
+        byte_tx.drain(..2);
 
         let input_counts = decode_compact_size(&mut byte_tx);
 
         let mut inputs = Vec::new();
 
         for _ in 0..input_counts {
-            let txid = hex::encode::<Vec<u8>>(
-                byte_tx
-                    .drain(..32)
-                    .rev()
-                    .collect::<Vec<u8>>()
-                    .try_into()
-                    .unwrap(),
-            );
+            let txid = hex::encode(byte_tx.drain(..32).rev().collect::<Vec<u8>>());
             let vout =
                 u32::from_le_bytes(byte_tx.drain(..4).collect::<Vec<u8>>().try_into().unwrap());
             let script_sig_size = decode_compact_size(&mut byte_tx);
             let script_sig = byte_tx
                 .drain(..script_sig_size as usize)
-                .collect::<Vec<u8>>()
-                .try_into()
-                .unwrap();
+                .collect::<Vec<u8>>();
             let sequence =
                 u32::from_le_bytes(byte_tx.drain(..4).collect::<Vec<u8>>().try_into().unwrap());
 
@@ -110,9 +477,7 @@ impl Transaction {
             let script_pubkey_size = decode_compact_size(&mut byte_tx);
             let script_pubkey: Vec<u8> = byte_tx
                 .drain(..script_pubkey_size as usize)
-                .collect::<Vec<u8>>()
-                .try_into()
-                .unwrap();
+                .collect::<Vec<u8>>();
 
             outputs.push(TxOut {
                 amount,
@@ -124,11 +489,7 @@ impl Transaction {
             let stack_items = decode_compact_size(&mut byte_tx);
             for _ in 0..stack_items {
                 let size = decode_compact_size(&mut byte_tx);
-                let item: Vec<u8> = byte_tx
-                    .drain(..size as usize)
-                    .collect::<Vec<u8>>()
-                    .try_into()
-                    .unwrap();
+                let item: Vec<u8> = byte_tx.drain(..size as usize).collect::<Vec<u8>>();
                 inputs[i as usize].witness.push(item);
             }
         }
@@ -186,6 +547,7 @@ impl Transaction {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::script::ScriptBuf;
 
     #[test]
     fn decode_encode_hex() {
@@ -195,4 +557,482 @@ mod tests {
 
         assert_eq!(hex_tx, &tx.to_hex())
     }
+
+    #[test]
+    fn compact_size() {
+        let cases: [(u64, &str); 9] = [
+            (0, "00"),
+            (0xfc, "fc"),
+            (0xfd, "fdfd00"),
+            (0xffff, "fdffff"),
+            (0x1_0000, "fe00000100"),
+            (0xffff_ffff, "feffffffff"),
+            (0x1_0000_0000, "ff0000000001000000"),
+            (0x0123_4567_89ab_cdef, "ffefcdab8967452301"),
+            (u64::MAX, "ffffffffffffffffff"),
+        ];
+        for (value, encoding) in cases {
+            assert_eq!(hex::encode(encode_compact_size(value as usize)), encoding);
+
+            let mut bytes = hex::decode(encoding).unwrap();
+            bytes.push(0xaa);
+            assert_eq!(decode_compact_size(&mut bytes), value);
+            assert_eq!(bytes, [0xaa]);
+        }
+    }
+
+    #[test]
+    fn txid() {
+        // The first bitcoin transaction between two people, in block 170.
+        let legacy_hex = "0100000001c997a5e56e104102fa209c6a852dd90660a20b2d9c352423edce25857fcd3704000000004847304402204e45e16932b8af514961a1d3a1a25fdf3f4f7732e9d624c6c61548ab5fb8cd410220181522ec8eca07de4860a4acdd12909d831cc56cbbac4622082221a8768d1d0901ffffffff0200ca9a3b00000000434104ae1a62fe09c5f51b13905f07f06b99a2f7159b2225f374cd378d71302fa28414e7aab37397f554a7df5f142c21c1b7303b8a0626f1baded5c72a704f7e6cd84cac00286bee0000000043410411db93e1dcdb8a016b49840f8c53bc1eb68a382e97b1482ecad7b148a6909a5cb2e0eaddfb84ccf9744464f82e160bfa9b8b64f9d4c03f999b8643f656b412a3ac00000000";
+        // `from_hex` expects the segwit serialization, so add a marker and an empty witness.
+        let len = legacy_hex.len();
+        let segwit_hex = format!(
+            "{}0001{}00{}",
+            &legacy_hex[..8],
+            &legacy_hex[8..len - 8],
+            &legacy_hex[len - 8..]
+        );
+        let tx = Transaction::from_hex(&segwit_hex);
+
+        assert_eq!(hex::encode(tx.to_bytes_without_witness()), legacy_hex);
+        assert_eq!(
+            tx.txid(),
+            "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16"
+        );
+        assert!(!tx.is_coinbase());
+    }
+
+    fn bip341_transaction() -> (Transaction, Vec<TxOut>) {
+        let vectors: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/data/bip341_tests.json")).unwrap();
+        let vector = &vectors["keyPathSpending"][0];
+        let tx = Transaction::from_hex(vector["auxiliary"]["fullySignedTx"].as_str().unwrap());
+        let spent_outputs = vector["given"]["utxosSpent"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|utxo| TxOut {
+                amount: utxo["amountSats"].as_u64().unwrap(),
+                script_pubkey: hex::decode(utxo["scriptPubKey"].as_str().unwrap()).unwrap(),
+            })
+            .collect();
+        (tx, spent_outputs)
+    }
+
+    #[test]
+    fn verify() {
+        // Spends P2TR, P2PKH and P2WPKH outputs.
+        let (mut tx, mut spent_outputs) = bip341_transaction();
+        assert_eq!(tx.verify(&spent_outputs, VerifyFlags::STANDARD), Ok(()));
+
+        tx.input[2].script_sig.clear();
+        assert_eq!(
+            tx.verify(&spent_outputs, VerifyFlags::STANDARD),
+            Err(vec![TxVerifyError::Script {
+                input: 2,
+                error: ScriptError::InvalidStackOperation
+            }])
+        );
+
+        // Every failing input is reported. Taproot signatures commit to the amounts of
+        // all spent outputs, so changing one fails every P2TR input.
+        spent_outputs[0].amount += 1;
+        let schnorr = |input| TxVerifyError::Script {
+            input,
+            error: ScriptError::SchnorrSig,
+        };
+        assert_eq!(
+            tx.verify(&spent_outputs, VerifyFlags::STANDARD),
+            Err(vec![
+                schnorr(0),
+                TxVerifyError::Script {
+                    input: 2,
+                    error: ScriptError::InvalidStackOperation
+                },
+                schnorr(3),
+                schnorr(4),
+                schnorr(6)
+            ])
+        );
+
+        // Invalid txids are reported without panicking, before any scripts are run.
+        tx.input[1].txid.truncate(62);
+        tx.input[4].txid.replace_range(..2, "zz");
+        assert_eq!(
+            tx.verify(&spent_outputs, VerifyFlags::STANDARD),
+            Err(vec![
+                TxVerifyError::InvalidTxid { input: 1 },
+                TxVerifyError::InvalidTxid { input: 4 }
+            ])
+        );
+
+        spent_outputs.pop();
+        assert_eq!(
+            tx.verify(&spent_outputs, VerifyFlags::STANDARD),
+            Err(vec![TxVerifyError::SpentOutputsCount {
+                inputs: 9,
+                spent_outputs: 8
+            }])
+        );
+
+        // Checked before anything else, instead of panicking in verify_script.
+        for flags in [
+            VerifyFlags::CLEANSTACK | VerifyFlags::P2SH,
+            VerifyFlags::CLEANSTACK | VerifyFlags::WITNESS,
+            VerifyFlags::WITNESS,
+        ] {
+            assert!(!flags.is_supported());
+            assert_eq!(
+                tx.verify(&spent_outputs, flags),
+                Err(vec![TxVerifyError::UnsupportedFlags(flags)])
+            );
+        }
+        for flags in [
+            VerifyFlags::NONE,
+            VerifyFlags::CONSENSUS,
+            VerifyFlags::STANDARD,
+        ] {
+            assert!(flags.is_supported());
+        }
+    }
+
+    #[test]
+    fn verify_nested_and_script_hash_spends() {
+        use crate::sighash::SIGHASH_ALL;
+        use bitcoin_hashes::{hash160, sha256};
+        use secp256k1::{Message, Secp256k1, SecretKey};
+
+        let secp = Secp256k1::new();
+        let secret = SecretKey::from_slice(&[1; 32]).unwrap();
+        let pubkey = secret.public_key(&secp).serialize();
+        let pubkey_hash = hash160::Hash::hash(&pubkey).to_byte_array();
+
+        // P2SH-P2WPKH and a P2WSH paying to a public key.
+        let p2wpkh = ScriptBuf::new_p2wpkh(&pubkey_hash);
+        let witness_script = [&[0x21][..], &pubkey, &[0xac]].concat();
+        let spent_outputs = vec![
+            TxOut {
+                amount: 50_000,
+                script_pubkey: ScriptBuf::new_p2sh(
+                    &hash160::Hash::hash(p2wpkh.as_bytes()).to_byte_array(),
+                )
+                .as_bytes()
+                .to_vec(),
+            },
+            TxOut {
+                amount: 70_000,
+                script_pubkey: ScriptBuf::new_p2wsh(
+                    &sha256::Hash::hash(&witness_script).to_byte_array(),
+                )
+                .as_bytes()
+                .to_vec(),
+            },
+        ];
+        let input = |vout: u32| TxIn {
+            txid: "11".repeat(32),
+            vout,
+            script_sig: vec![],
+            sequence: u32::MAX,
+            witness: vec![],
+        };
+        let mut tx = Transaction {
+            version: 2,
+            input: vec![input(0), input(1)],
+            output: vec![TxOut {
+                amount: 100_000,
+                script_pubkey: p2wpkh.as_bytes().to_vec(),
+            }],
+            lock_time: 0,
+        };
+
+        let script_codes = [
+            ScriptBuf::new_p2pkh(&pubkey_hash).as_bytes().to_vec(),
+            witness_script.clone(),
+        ];
+        let sigs: Vec<Vec<u8>> = {
+            let cache = SighashCache::new(&tx).unwrap();
+            (0..2)
+                .map(|index| {
+                    let sighash = cache.segwit_v0_signature_hash(
+                        index,
+                        &script_codes[index],
+                        spent_outputs[index].amount,
+                        SIGHASH_ALL,
+                    );
+                    let sig =
+                        secp.sign_ecdsa(&Message::from_digest(sighash.to_byte_array()), &secret);
+                    [&sig.serialize_der()[..], &[SIGHASH_ALL as u8]].concat()
+                })
+                .collect()
+        };
+        tx.input[0].script_sig = [&[0x16][..], p2wpkh.as_bytes()].concat();
+        tx.input[0].witness = vec![sigs[0].clone(), pubkey.to_vec()];
+        tx.input[1].witness = vec![sigs[1].clone(), witness_script];
+        assert_eq!(tx.verify(&spent_outputs, VerifyFlags::STANDARD), Ok(()));
+
+        // The second input commits to its own amount.
+        let mut other_outputs = spent_outputs.clone();
+        other_outputs[1].amount -= 1;
+        assert_eq!(
+            tx.verify(&other_outputs, VerifyFlags::STANDARD),
+            Err(vec![TxVerifyError::Script {
+                input: 1,
+                error: ScriptError::SigNullFail
+            }])
+        );
+
+        let mut other_outputs = spent_outputs.clone();
+        other_outputs[1].script_pubkey = vec![0x6a, 0x01, 0x00];
+        assert_eq!(
+            tx.verify(&other_outputs, VerifyFlags::STANDARD),
+            Err(vec![TxVerifyError::UnspendableOutput { input: 1 }])
+        );
+        other_outputs[1].script_pubkey = vec![0x51];
+        assert_eq!(
+            tx.verify(&other_outputs, VerifyFlags::STANDARD),
+            Err(vec![TxVerifyError::UnsupportedScript { input: 1 }])
+        );
+        // Neither P2PK, bare multisig nor witness programs without meaning yet.
+        let unsupported = [
+            ScriptBuf::from([&[0x21][..], &pubkey, &[0xac]].concat()),
+            ScriptBuf::from([&[0x51, 0x21][..], &pubkey, &[0x51, 0xae]].concat()),
+            ScriptBuf::from([&[0x52, 0x20][..], &[7; 32]].concat()),
+            ScriptBuf::from([&[0x51, 0x02][..], &[7; 2]].concat()),
+        ];
+        for script_pubkey in unsupported {
+            other_outputs[1].script_pubkey = script_pubkey.into_bytes();
+            assert_eq!(
+                tx.verify(&other_outputs, VerifyFlags::STANDARD),
+                Err(vec![TxVerifyError::UnsupportedScript { input: 1 }])
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "signing")]
+    fn sign_inputs() {
+        use crate::key::PrivateKey;
+        use crate::sighash::{SIGHASH_ALL, SIGHASH_ANYONECANPAY, SIGHASH_SINGLE};
+
+        let key = PrivateKey::from_slice(&[7; 32]).unwrap();
+        let uncompressed = PrivateKey::new_uncompressed(key.inner);
+        let p2pkh = |key: &PrivateKey| {
+            ScriptBuf::new_p2pkh(&key.public_key().pubkey_hash().to_byte_array())
+        };
+        let p2wpkh =
+            ScriptBuf::new_p2wpkh(&key.public_key().wpubkey_hash().unwrap().to_byte_array());
+        let spent_outputs = vec![
+            TxOut {
+                amount: 10_000,
+                script_pubkey: p2pkh(&uncompressed).into_bytes(),
+            },
+            TxOut {
+                amount: 20_000,
+                script_pubkey: p2wpkh.as_bytes().to_vec(),
+            },
+            TxOut {
+                amount: 30_000,
+                script_pubkey: p2pkh(&key).into_bytes(),
+            },
+        ];
+        let input = |vout: u32| TxIn {
+            txid: "22".repeat(32),
+            vout,
+            script_sig: vec![],
+            sequence: u32::MAX,
+            witness: vec![],
+        };
+        let mut tx = Transaction {
+            version: 2,
+            input: vec![input(0), input(1), input(2)],
+            output: vec![TxOut {
+                amount: 55_000,
+                script_pubkey: p2wpkh.as_bytes().to_vec(),
+            }],
+            lock_time: 0,
+        };
+
+        tx.sign_p2pkh_input(0, &uncompressed, SIGHASH_ALL).unwrap();
+        tx.sign_p2wpkh_input(1, &key, 20_000, SIGHASH_SINGLE | SIGHASH_ANYONECANPAY)
+            .unwrap();
+        tx.sign_p2pkh_input(2, &key, SIGHASH_ALL).unwrap();
+        assert_eq!(tx.verify(&spent_outputs, VerifyFlags::STANDARD), Ok(()));
+        assert_eq!(tx.input[1].witness[0].last(), Some(&0x83));
+        assert!(tx.input[1].script_sig.is_empty());
+
+        // Signing with the wrong amount commits to a different sighash.
+        tx.sign_p2wpkh_input(1, &key, 20_001, SIGHASH_ALL).unwrap();
+        assert_eq!(
+            tx.verify(&spent_outputs, VerifyFlags::STANDARD),
+            Err(vec![TxVerifyError::Script {
+                input: 1,
+                error: ScriptError::SigNullFail
+            }])
+        );
+        assert_eq!(
+            tx.sign_p2wpkh_input(1, &uncompressed, 20_000, SIGHASH_ALL),
+            Err(SignError::UncompressedKey)
+        );
+        let out_of_range = Err(SignError::InputIndexOutOfRange {
+            index: 3,
+            inputs: 3,
+        });
+        assert_eq!(tx.sign_p2pkh_input(3, &key, SIGHASH_ALL), out_of_range);
+        assert_eq!(
+            tx.sign_p2wpkh_input(3, &key, 20_000, SIGHASH_ALL),
+            out_of_range
+        );
+
+        // Only ALL, NONE and SINGLE are valid, with or without ANYONECANPAY.
+        for sighash_type in [0x00, 0x04, 0x21, 0x40 | SIGHASH_ALL, SIGHASH_ANYONECANPAY] {
+            let invalid = Err(SignError::InvalidSighashType(sighash_type));
+            assert_eq!(tx.sign_p2pkh_input(2, &key, sighash_type), invalid);
+            assert_eq!(tx.sign_p2wpkh_input(1, &key, 20_000, sighash_type), invalid);
+        }
+
+        // A legacy SIGHASH_SINGLE signature without a matching output signs `0x00..01`.
+        for sighash_type in [SIGHASH_SINGLE, SIGHASH_SINGLE | SIGHASH_ANYONECANPAY] {
+            assert_eq!(
+                tx.sign_p2pkh_input(2, &key, sighash_type),
+                Err(SignError::SighashSingleWithoutOutput {
+                    index: 2,
+                    outputs: 1
+                })
+            );
+        }
+
+        // Every input commits to all outpoints, so one invalid txid prevents signing.
+        tx.input[0].txid = "xx".repeat(32);
+        let invalid_txid = Err(SignError::InvalidTxid { input: 0 });
+        assert_eq!(tx.sign_p2pkh_input(2, &key, SIGHASH_ALL), invalid_txid);
+        assert_eq!(
+            tx.sign_p2wpkh_input(1, &key, 20_000, SIGHASH_ALL),
+            invalid_txid
+        );
+        tx.input[0].txid = "22".repeat(31);
+        assert_eq!(tx.sign_p2pkh_input(2, &key, SIGHASH_ALL), invalid_txid);
+    }
+
+    #[test]
+    fn sigop_cost() {
+        use crate::constants::MAX_BLOCK_SIGOPS_COST;
+        use bitcoin::consensus::encode::deserialize;
+
+        let key = |byte: u8| [&[0x02][..], &[byte; 32]].concat();
+        let multisig = |required: usize, keys: &[Vec<u8>]| {
+            let keys: Vec<String> = keys
+                .iter()
+                .map(|key| format!("<{}>", hex::encode(key)))
+                .collect();
+            let asm = format!(
+                "{} {} {} OP_CHECKMULTISIG",
+                required,
+                keys.join(" "),
+                keys.len()
+            );
+            ScriptBuf::from_asm(&asm).unwrap()
+        };
+        let push = |data: &[u8]| {
+            ScriptBuf::from_asm(&format!("<{}>", hex::encode(data)))
+                .unwrap()
+                .into_bytes()
+        };
+        let sig = vec![0x30; 71];
+
+        let redeem_script = multisig(2, &[key(1), key(2), key(3)]);
+        let witness_script = multisig(1, &[key(1), key(2)]);
+        let p2wpkh = ScriptBuf::new_p2wpkh(&[4; 20]);
+        let spent_outputs: Vec<TxOut> = [
+            redeem_script.to_p2sh().unwrap(),
+            witness_script.to_p2wsh(),
+            p2wpkh.to_p2sh().unwrap(),
+            ScriptBuf::new_p2pkh(&[5; 20]),
+        ]
+        .into_iter()
+        .map(|script_pubkey| TxOut {
+            amount: 10_000,
+            script_pubkey: script_pubkey.into_bytes(),
+        })
+        .collect();
+
+        let input = |vout: u32, script_sig: Vec<u8>, witness: Vec<Vec<u8>>| TxIn {
+            txid: "ab".repeat(32),
+            vout,
+            script_sig,
+            sequence: u32::MAX,
+            witness,
+        };
+        let tx = Transaction {
+            version: 2,
+            input: vec![
+                input(
+                    0,
+                    [
+                        vec![0],
+                        push(&sig),
+                        push(&sig),
+                        push(redeem_script.as_bytes()),
+                    ]
+                    .concat(),
+                    vec![],
+                ),
+                input(
+                    1,
+                    vec![],
+                    vec![vec![], sig.clone(), witness_script.into_bytes()],
+                ),
+                input(2, push(p2wpkh.as_bytes()), vec![sig.clone(), key(4)]),
+                input(3, [push(&sig), push(&key(5))].concat(), vec![]),
+            ],
+            output: vec![
+                TxOut {
+                    amount: 20_000,
+                    script_pubkey: ScriptBuf::new_p2pkh(&[6; 20]).into_bytes(),
+                },
+                TxOut {
+                    amount: 10_000,
+                    script_pubkey: multisig(1, &[key(7), key(8)]).into_bytes(),
+                },
+            ],
+            lock_time: 0,
+        };
+
+        // Outputs: (1 + 20) * 4, P2SH multisig: 3 * 4, P2WSH: 2, P2SH-P2WPKH: 1.
+        assert_eq!(tx.sigop_cost(&spent_outputs), Ok(99));
+        assert!(tx.sigop_cost(&spent_outputs).unwrap() < MAX_BLOCK_SIGOPS_COST);
+
+        let expected: bitcoin::Transaction =
+            deserialize(&hex::decode(tx.clone().to_hex()).unwrap()).unwrap();
+        let expected_cost = expected.total_sigop_cost(|outpoint| {
+            let spent_output = &spent_outputs[outpoint.vout as usize];
+            Some(bitcoin::TxOut {
+                value: bitcoin::Amount::from_sat(spent_output.amount),
+                script_pubkey: bitcoin::ScriptBuf::from_bytes(spent_output.script_pubkey.clone()),
+            })
+        });
+        assert_eq!(expected_cost, 99);
+
+        assert_eq!(
+            tx.sigop_cost(&spent_outputs[1..]),
+            Err(TxVerifyError::SpentOutputsCount {
+                inputs: 4,
+                spent_outputs: 3
+            })
+        );
+
+        let coinbase = Transaction {
+            version: 1,
+            input: vec![TxIn {
+                txid: "00".repeat(32),
+                vout: u32::MAX,
+                script_sig: vec![0x01, 0xac],
+                sequence: u32::MAX,
+                witness: vec![],
+            }],
+            output: tx.output.clone(),
+            lock_time: 0,
+        };
+        assert_eq!(coinbase.sigop_cost(&[]), Ok(84));
+    }
 }
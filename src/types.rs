@@ -10,7 +10,7 @@ pub const SEGWIT_V0_PUBKEY_HASH_LEN: usize = 20;
 pub const SEGWIT_V0_SCRIPT_HASH_LEN: usize = 32;
 
 #[non_exhaustive]
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum Network {
     Bitcoin,
     Testnet,
@@ -47,28 +47,57 @@ impl WitnessProgram {
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum WitnessVersion {
     V0,
     V1,
+    V2,
+    V3,
+    V4,
+    V5,
+    V6,
+    V7,
+    V8,
+    V9,
+    V10,
+    V11,
+    V12,
+    V13,
+    V14,
+    V15,
+    V16,
 }
 
 impl WitnessVersion {
     pub fn from_fe32(val: Fe32) -> Self {
-        match val.to_u8() {
-            0 => WitnessVersion::V0,
-            1 => WitnessVersion::V1,
-            _ => panic!("invalid witness script"),
-        }
+        WitnessVersion::from_num(val.to_u8()).expect("invalid witness script")
+    }
+
+    /// Returns the witness version numbered `num`, if it is at most 16.
+    pub fn from_num(num: u8) -> Option<Self> {
+        use WitnessVersion::*;
+        const VERSIONS: [WitnessVersion; 17] = [
+            V0, V1, V2, V3, V4, V5, V6, V7, V8, V9, V10, V11, V12, V13, V14, V15, V16,
+        ];
+        VERSIONS.get(num as usize).copied()
+    }
+
+    /// Returns the number of the witness version.
+    pub fn to_num(self) -> u8 {
+        self as u8
     }
 }
 
 #[derive(Eq, PartialEq, Debug)]
 pub enum ScriptType {
+    P2PK,
     P2PKH,
     P2SH,
+    Multisig,
     OPReturn,
     P2WPKH,
     P2WSH,
     P2TR,
+    /// A witness program of a version or size without meaning yet.
+    WitnessUnknown,
 }
@@ -1,51 +1,38 @@
-use crate::types::ScriptType;
-use opcodes::all::*;
+use crate::script::Script;
+use crate::types::{ScriptType, WitnessVersion};
 
+/// Returns the standard type of `script` with its payload: the public key of P2PK, the
+/// hash of P2PKH and P2SH, the program of witness programs, the unparsed script after
+/// `OP_RETURN` and nothing for bare multisig.
+///
+/// The pushes of an `OP_RETURN` payload can be read with [`Script::op_return_data`].
 pub fn get_script_type_with_payload(script: &[u8]) -> Result<(ScriptType, Vec<u8>), &'static str> {
-    // check if script is p2pkh
-    if script.len() == 25
-        && script[0] == OP_DUP.to_u8()
-        && script[1] == OP_HASH160.to_u8()
-        && script[2] == OP_PUSHBYTES_20.to_u8()
-        && script[23] == OP_EQUALVERIFY.to_u8()
-        && script[24] == OP_CHECKSIG.to_u8()
+    let script = Script::from_bytes(script);
+    let bytes = script.as_bytes();
+    if script.is_p2pk() {
+        Ok((ScriptType::P2PK, bytes[1..bytes.len() - 1].to_vec()))
+    } else if script.is_p2pkh() {
+        Ok((ScriptType::P2PKH, bytes[3..23].to_vec()))
+    } else if script.is_p2sh() {
+        Ok((ScriptType::P2SH, bytes[2..22].to_vec()))
+    } else if script.is_multisig() {
+        Ok((ScriptType::Multisig, vec![]))
+    } else if script.is_op_return() {
+        Ok((ScriptType::OPReturn, bytes[1..].to_vec()))
+    } else if let (Some(version), Some(program)) =
+        (script.witness_version(), script.witness_program())
     {
-        return Ok((ScriptType::P2PKH, script[3..23].to_vec()));
+        let script_type = match (version, program.len()) {
+            (WitnessVersion::V0, 20) => ScriptType::P2WPKH,
+            (WitnessVersion::V0, 32) => ScriptType::P2WSH,
+            (WitnessVersion::V0, _) => return Err("invalid script"),
+            (WitnessVersion::V1, 32) => ScriptType::P2TR,
+            _ => ScriptType::WitnessUnknown,
+        };
+        Ok((script_type, program.to_vec()))
+    } else {
+        Err("invalid script")
     }
-
-    // check if script is p2sh
-    if script.len() == 23
-        && script[0] == OP_HASH160.to_u8()
-        && script[1] == OP_PUSHBYTES_20.to_u8()
-        && script[22] == OP_EQUAL.to_u8()
-    {
-        return Ok((ScriptType::P2SH, script[2..22].to_vec()));
-    }
-
-    // check if script is p2wpkh
-    if script.len() == 22 && script[0] == 0 && script[1] == OP_PUSHBYTES_20.to_u8() {
-        return Ok((ScriptType::P2WPKH, script[2..].to_vec()));
-    }
-
-    // check if script is p2wsh
-    if script.len() == 34 && script[0] == 0 && script[1] == OP_PUSHBYTES_32.to_u8() {
-        return Ok((ScriptType::P2WSH, script[2..].to_vec()));
-    }
-
-    // check if script is p2tr
-    if script.len() == 34
-        && script[0] == OP_PUSHNUM_1.to_u8()
-        && script[1] == OP_PUSHBYTES_32.to_u8()
-    {
-        return Ok((ScriptType::P2TR, script[2..].to_vec()));
-    }
-
-    // check if script is op_return
-    if script[0] == OP_RETURN.to_u8() {
-        return Ok((ScriptType::OPReturn, vec![]));
-    }
-
-    Err("invalid script")
 }
 
 #[cfg(test)]
@@ -94,4 +81,45 @@ mod test {
             assert_eq!(actual_result, expected_result);
         }
     }
+
+    #[test]
+    fn get_script_type_with_payload_other_types() {
+        let key = [[0x03].as_slice(), &[0x22; 32]].concat();
+        let p2pk = [vec![33], key.clone(), vec![0xac]].concat();
+        let multisig = [vec![0x51, 33], key.clone(), vec![0x51, 0xae]].concat();
+        let witness_v2 = vec![0x52, 0x02, 0xab, 0xcd];
+
+        assert_eq!(
+            get_script_type_with_payload(&p2pk),
+            Ok((ScriptType::P2PK, key))
+        );
+        assert_eq!(
+            get_script_type_with_payload(&multisig),
+            Ok((ScriptType::Multisig, vec![]))
+        );
+        assert_eq!(
+            get_script_type_with_payload(&[0x6a]),
+            Ok((ScriptType::OPReturn, vec![]))
+        );
+        // The pushes keep their boundaries, and scripts that aren't only pushes or are
+        // truncated are returned as they are.
+        for data in [
+            vec![0x01, 0xaa, 0x02, 0xbb, 0xcc],
+            vec![0x02, 0xaa, 0xbb, 0x01, 0xcc],
+            vec![0x51],
+            vec![0x02, 0xaa],
+        ] {
+            assert_eq!(
+                get_script_type_with_payload(&[&[0x6a][..], &data].concat()),
+                Ok((ScriptType::OPReturn, data))
+            );
+        }
+        assert_eq!(
+            get_script_type_with_payload(&witness_v2),
+            Ok((ScriptType::WitnessUnknown, vec![0xab, 0xcd]))
+        );
+        assert!(get_script_type_with_payload(&[0x00, 0x02, 0xab, 0xcd]).is_err());
+        assert!(get_script_type_with_payload(&[]).is_err());
+        assert!(get_script_type_with_payload(&[0x00]).is_err());
+    }
 }